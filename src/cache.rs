@@ -0,0 +1,253 @@
+use crate::calendar::{Event, Note, Todo};
+use crate::paths;
+use anyhow::{Context, Result};
+use chrono::{Local, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+// Bumped when what is cached changes, older caches are dropped
+const VERSION: u32 = 3;
+
+// What a file holds once parsed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Parsed {
+    // Every VEVENT of the file, with the warnings of their RRULE, given each
+    // time it is read
+    Events(Vec<Event>, Vec<String>),
+    Todo(Todo),
+    Note(Note),
+    // Anything else, like a VFREEBUSY
+    Other,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    // Modification time in nanoseconds, and size, of the file when parsed
+    mtime: u64,
+    size: u64,
+    parsed: Parsed,
+}
+
+// The parsed ICS files, which stay the source of truth: an entry is only used
+// while its file keeps the modification time and size it had. The times of
+// floating events are read in the local timezone, so the cache of another
+// timezone is dropped.
+#[derive(Serialize, Deserialize)]
+struct Cache {
+    version: u32,
+    timezone: String,
+    files: HashMap<String, Entry>,
+    #[serde(skip)]
+    changed: bool,
+}
+
+static CACHE: Mutex<Option<Cache>> = Mutex::new(None);
+
+impl Default for Cache {
+    fn default() -> Self {
+        Cache {
+            version: VERSION,
+            timezone: timezone(),
+            files: HashMap::new(),
+            changed: false,
+        }
+    }
+}
+
+impl Cache {
+    // A cache that cannot be read is started again, it only costs a parse
+    // of the files
+    fn load(path: &Path) -> Self {
+        fs::read(path)
+            .ok()
+            .and_then(|content| serde_json::from_slice::<Cache>(&content).ok())
+            .filter(|cache| cache.version == VERSION && cache.timezone == timezone())
+            .unwrap_or_default()
+    }
+
+    fn get(&self, path: &Path, metadata: &fs::Metadata) -> Option<Parsed> {
+        let entry = self.files.get(path.to_str()?)?;
+        let (mtime, size) = stamp(metadata)?;
+        (entry.mtime == mtime && entry.size == size).then(|| entry.parsed.clone())
+    }
+
+    fn insert(&mut self, path: &Path, metadata: &fs::Metadata, parsed: &Parsed) {
+        let (Some(key), Some((mtime, size))) = (path.to_str(), stamp(metadata)) else {
+            return;
+        };
+        if let Parsed::Events(events, _) = parsed {
+            if !events.iter().all(keeps_times) {
+                return;
+            }
+        }
+        let parsed = parsed.clone();
+        self.files.insert(
+            key.to_string(),
+            Entry {
+                mtime,
+                size,
+                parsed,
+            },
+        );
+        self.changed = true;
+    }
+
+    // Written aside then moved in place, for another command reading it at
+    // the same time not to get half of it. The files removed since are
+    // dropped.
+    fn save(&mut self, path: &Path) -> Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+        self.files.retain(|file, _| Path::new(file).is_file());
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let temporary = path.with_extension(format!("json.{}", std::process::id()));
+        fs::write(&temporary, serde_json::to_vec(self)?)
+            .and_then(|()| fs::rename(&temporary, path))
+            .with_context(|| format!("Failed to write the cache '{}'", path.display()))?;
+        self.changed = false;
+        Ok(())
+    }
+}
+
+// The cache, read from its file the first time
+fn with_cache<T>(f: impl FnOnce(&mut Cache) -> T) -> Option<T> {
+    let mut cache = CACHE.lock().ok()?;
+    if cache.is_none() {
+        *cache = Some(Cache::load(&paths::cache_file().ok()?));
+    }
+    cache.as_mut().map(f)
+}
+
+// The parsed content of a file, when it did not change since it was cached
+pub fn get(path: &Path, metadata: &fs::Metadata) -> Option<Parsed> {
+    with_cache(|cache| cache.get(path, metadata)).flatten()
+}
+
+pub fn insert(path: &Path, metadata: &fs::Metadata, parsed: &Parsed) {
+    with_cache(|cache| cache.insert(path, metadata, parsed));
+}
+
+// Writes the files parsed since the cache was read, if any
+pub fn save() -> Result<()> {
+    let Ok(mut cache) = CACHE.lock() else {
+        return Ok(());
+    };
+    match cache.as_mut() {
+        Some(cache) => cache.save(&paths::cache_file()?),
+        None => Ok(()),
+    }
+}
+
+fn stamp(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((modified.as_nanos() as u64, metadata.len()))
+}
+
+// The offsets of the local time through the year, which tell timezones apart
+// well enough to notice a change of it
+fn timezone() -> String {
+    let offsets: Vec<String> = (1..=12)
+        .filter_map(|month| NaiveDate::from_ymd_opt(2000, month, 1))
+        .map(|day| Local.offset_from_utc_datetime(&day.and_time(NaiveTime::MIN)))
+        .map(|offset| offset.fix().to_string())
+        .collect();
+    offsets.join(",")
+}
+
+// Times skipped by a change to summer time are written with the UTC offset,
+// and would not read back the same: their events are parsed each time
+fn keeps_times(event: &Event) -> bool {
+    let until = event
+        .recurrence
+        .as_ref()
+        .and_then(|recurrence| recurrence.until);
+    [
+        Some(event.start),
+        Some(event.end),
+        event.recurrence_id,
+        until,
+    ]
+    .into_iter()
+    .flatten()
+    .all(|time: NaiveDateTime| Local.from_local_datetime(&time).earliest().is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event() -> Parsed {
+        let start = NaiveDate::from_ymd_opt(2026, 10, 16)
+            .unwrap()
+            .and_hms_opt(10, 0, 0)
+            .unwrap();
+        let event = Event::builder("Review", start, start + chrono::Duration::hours(1))
+            .build()
+            .unwrap();
+        Parsed::Events(vec![event], vec!["a warning".to_string()])
+    }
+
+    #[test]
+    fn entries_are_used_while_their_file_is_unchanged() {
+        let dir = std::env::temp_dir().join(format!("calendar-rs-cache-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("event.ics");
+        let cache_file = dir.join("cache.json");
+        fs::write(&file, "BEGIN:VCALENDAR\r\n").unwrap();
+
+        let mut cache = Cache::default();
+        cache.insert(&file, &fs::metadata(&file).unwrap(), &event());
+        cache.save(&cache_file).unwrap();
+
+        let cache = Cache::load(&cache_file);
+        let parsed = cache.get(&file, &fs::metadata(&file).unwrap());
+        assert!(matches!(
+            parsed,
+            Some(Parsed::Events(events, warnings)) if events[0].name == "Review" && warnings.len() == 1
+        ));
+
+        fs::write(&file, "BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n").unwrap();
+        assert!(cache.get(&file, &fs::metadata(&file).unwrap()).is_none());
+
+        // Removed files are dropped when the cache is written again
+        let mut cache = Cache::load(&cache_file);
+        let other = dir.join("other.ics");
+        fs::write(&other, "BEGIN:VCALENDAR\r\n").unwrap();
+        cache.insert(&other, &fs::metadata(&other).unwrap(), &Parsed::Other);
+        fs::remove_file(&file).unwrap();
+        cache.save(&cache_file).unwrap();
+        let cache = Cache::load(&cache_file);
+        assert_eq!(cache.files.len(), 1);
+        assert!(cache.files.contains_key(other.to_str().unwrap()));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn caches_of_another_version_are_dropped() {
+        let dir =
+            std::env::temp_dir().join(format!("calendar-rs-cache-version-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("event.ics");
+        let cache_file = dir.join("cache.json");
+        fs::write(&file, "BEGIN:VCALENDAR\r\n").unwrap();
+
+        let mut cache = Cache::default();
+        cache.insert(&file, &fs::metadata(&file).unwrap(), &event());
+        cache.version = VERSION + 1;
+        cache.save(&cache_file).unwrap();
+        assert!(Cache::load(&cache_file).files.is_empty());
+
+        fs::write(&cache_file, "not json").unwrap();
+        assert!(Cache::load(&cache_file).files.is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
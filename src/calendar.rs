@@ -1,23 +1,204 @@
 use anyhow::{anyhow, Result};
-use chrono::NaiveDateTime;
+use chrono::{Datelike, Duration, Local, NaiveDate, NaiveDateTime, Weekday};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::path::PathBuf;
+use uuid::Uuid;
 
+use crate::cli::RepeatFrequency;
+use crate::error::CalendarError;
 use crate::storage;
 
-#[derive(Debug, Clone)]
+// Upper bound on the number of occurrences a single recurring event can produce
+pub const MAX_OCCURRENCES: u32 = 10_000;
+
+// Calendars and their events serialize to the JSON of --json, times being in
+// RFC 3339 with the local offset, see local_time
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Calendar {
+    pub name: String,
     pub path: PathBuf,
     pub events: Vec<Event>,
+    pub todos: Vec<Todo>,
+    pub notes: Vec<Note>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Event {
     pub id: String,
+    pub calendar: String,
     pub name: String,
+    #[serde(with = "local_time")]
     pub start: NaiveDateTime,
+    #[serde(with = "local_time")]
     pub end: NaiveDateTime,
+    #[serde(default)]
     pub location: Option<String>,
+    #[serde(default)]
     pub description: Option<String>,
+    // URL of the event, like the page of a meeting
+    #[serde(default)]
+    pub url: Option<String>,
+    // Email addresses of the ORGANIZER and the ATTENDEEs of a meeting
+    #[serde(default)]
+    pub organizer: Option<String>,
+    #[serde(default)]
+    pub attendees: Vec<String>,
+    #[serde(default)]
+    pub all_day: bool,
+    // Taskwarrior tasks shown at their due date, they are read only
+    #[serde(default)]
+    pub task: bool,
+    // Shown in views, but not counted as busy time (TRANSP:TRANSPARENT)
+    #[serde(default)]
+    pub transparent: bool,
+    // CATEGORIES of the event
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub recurrence: Option<Recurrence>,
+    // The occurrence of a recurring event it overrides, for the exceptions
+    // other clients write to files of their own (RECURRENCE-ID)
+    #[serde(default, with = "local_time::option")]
+    pub recurrence_id: Option<NaiveDateTime>,
+    // Whether its occurrences were attended, as recorded with attend
+    #[serde(default)]
+    pub attendance: Vec<Attendance>,
+    #[serde(default, with = "local_time::option")]
+    pub created: Option<NaiveDateTime>,
+    #[serde(default, with = "local_time::option")]
+    pub last_modified: Option<NaiveDateTime>,
+    // The file the event was read from, None until it is written. It is not
+    // always named after the UID, like the files written by other clients.
+    #[serde(default)]
+    pub file: Option<PathBuf>,
+}
+
+// The attendance of an occurrence, stored as an X-CALENDAR-RS-ATTENDED or
+// X-CALENDAR-RS-ABSENT property of the event
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Attendance {
+    // The start of the occurrence
+    pub occurrence: NaiveDateTime,
+    pub attended: bool,
+    pub recorded: NaiveDateTime,
+}
+
+// A VTODO, stored next to the events of its calendar
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Todo {
+    pub id: String,
+    pub calendar: String,
+    pub name: String,
+    #[serde(default, with = "local_time::option")]
+    pub due: Option<NaiveDateTime>,
+    // The due date has no time part
+    pub all_day: bool,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub done: bool,
+    #[serde(default, with = "local_time::option")]
+    pub completed: Option<NaiveDateTime>,
+    #[serde(default, with = "local_time::option")]
+    pub created: Option<NaiveDateTime>,
+    #[serde(default, with = "local_time::option")]
+    pub last_modified: Option<NaiveDateTime>,
+}
+
+// A VJOURNAL, free text attached to a day
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Note {
+    pub id: String,
+    pub calendar: String,
+    pub date: NaiveDate,
+    pub text: String,
+    #[serde(default, with = "local_time::option")]
+    pub created: Option<NaiveDateTime>,
+    #[serde(default, with = "local_time::option")]
+    pub last_modified: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recurrence {
+    pub frequency: RepeatFrequency,
+    pub interval: u32,
+    #[serde(default)]
+    pub count: Option<u32>,
+    #[serde(default, with = "local_time::option")]
+    pub until: Option<NaiveDateTime>,
+    // BYDAY of daily and weekly rules, empty for every day or the day of the
+    // start
+    pub by_day: Vec<Weekday>,
+    // Monthly and yearly rules on a day some months do not have, like the
+    // 31st, fall on the last day of these months rather than skip them.
+    // Written as the days from the 28th with BYSETPOS=-1.
+    #[serde(default)]
+    pub month_end: bool,
+    // The starts of the occurrences removed from the series (EXDATE)
+    #[serde(default)]
+    pub excluded: Vec<NaiveDateTime>,
+}
+
+// Events are stored in local time, and serialized in RFC 3339 with the local
+// offset. Times without an offset are read as local ones too.
+pub mod local_time {
+    use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn rfc3339(datetime: NaiveDateTime) -> String {
+        Local
+            .from_local_datetime(&datetime)
+            .earliest()
+            .unwrap_or_else(|| Local.from_utc_datetime(&datetime))
+            .to_rfc3339()
+    }
+
+    pub fn parse(text: &str) -> Option<NaiveDateTime> {
+        match DateTime::parse_from_rfc3339(text) {
+            Ok(datetime) => Some(datetime.with_timezone(&Local).naive_local()),
+            Err(_) => text.parse().ok(),
+        }
+    }
+
+    pub fn serialize<S: Serializer>(
+        datetime: &NaiveDateTime,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&rfc3339(*datetime))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<NaiveDateTime, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        parse(&text).ok_or_else(|| serde::de::Error::custom(format!("invalid time '{}'", text)))
+    }
+
+    pub mod option {
+        use chrono::NaiveDateTime;
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(
+            datetime: &Option<NaiveDateTime>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            match datetime {
+                Some(datetime) => super::serialize(datetime, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<NaiveDateTime>, D::Error> {
+            match Option::<String>::deserialize(deserializer)? {
+                Some(text) => super::parse(&text)
+                    .map(Some)
+                    .ok_or_else(|| serde::de::Error::custom(format!("invalid time '{}'", text))),
+                None => Ok(None),
+            }
+        }
+    }
 }
 
 pub fn load_all() -> Result<Vec<Calendar>> {
@@ -28,91 +209,807 @@ pub fn load(name: &str) -> Result<Calendar> {
     storage::load_calendar(name)
 }
 
+// The calendars with only the events that may occur between from and to,
+// the files of the others being skipped before they are parsed
+pub fn load_all_between(from: NaiveDateTime, to: NaiveDateTime) -> Result<Vec<Calendar>> {
+    storage::load_calendars_in(Some((from, to)))
+}
+
+pub fn load_between(name: &str, from: NaiveDateTime, to: NaiveDateTime) -> Result<Calendar> {
+    storage::load_calendar_in(name, Some((from, to)))
+}
+
+// The event of an id, or else the only event whose id starts with it, like the
+// short ids shown by list -i
+pub fn event_by_id<'a>(events: &'a [Event], id: &str) -> Result<&'a Event> {
+    let describe = |event: &Event| {
+        format!(
+            "{} {} - {}",
+            event.start.format("%Y-%m-%d"),
+            event.time_range(),
+            event.name
+        )
+    };
+    let event = by_id(events, id, |event| &event.id, describe, "event")?;
+    // A series rather than one of its exceptions written to a file of its own
+    Ok(events
+        .iter()
+        .find(|master| master.id == event.id && master.recurrence_id.is_none())
+        .unwrap_or(event))
+}
+
+fn by_id<'a, T>(
+    items: &'a [T],
+    id: &str,
+    item_id: impl Fn(&T) -> &str,
+    describe: impl Fn(&T) -> String,
+    what: &str,
+) -> Result<&'a T> {
+    if let Some(item) = items.iter().find(|item| item_id(item) == id) {
+        return Ok(item);
+    }
+
+    let mut matches: Vec<&T> = items
+        .iter()
+        .filter(|item| !id.is_empty() && item_id(item).starts_with(id))
+        .collect();
+    matches.sort_by(|a, b| item_id(a).cmp(item_id(b)));
+    matches.dedup_by(|a, b| item_id(a) == item_id(b));
+    match matches.as_slice() {
+        [] => {
+            Err(CalendarError::NotFound(format!("Could not find {} with this uuid", what)).into())
+        }
+        [item] => Ok(item),
+        _ => {
+            let candidates: Vec<String> = matches
+                .iter()
+                .map(|item| format!("  {}  {}", item_id(item), describe(item)))
+                .collect();
+            Err(CalendarError::Ambiguous(format!(
+                "'{}' matches several {}s, did you mean:\n{}",
+                id,
+                what,
+                candidates.join("\n")
+            ))
+            .into())
+        }
+    }
+}
+
 impl Calendar {
-    pub fn add_event(
-        &mut self,
-        name: String,
-        start: NaiveDateTime,
-        end: NaiveDateTime,
-        location: Option<String>,
-        description: Option<String>,
-    ) -> Result<()> {
-        let event = Event::new(name, start, end, location, description);
+    pub fn add_event(&mut self, event: Event) -> Result<()> {
+        let mut batch = storage::Batch::default();
+        self.stage_event(&mut batch, event)?;
+        batch.commit()
+    }
 
-        storage::write_event(&self.path, &event)?;
+    // Like add_event, the event being written when the batch is committed
+    pub fn stage_event(&self, batch: &mut storage::Batch, mut event: Event) -> Result<()> {
+        let now = Local::now().naive_local();
+        event.calendar = self.name.clone();
+        event.created = Some(now);
+        event.last_modified = Some(now);
 
-        Ok(())
+        batch.write_event(&self.path, &event)
     }
 
     pub fn remove_event(&mut self, event_id: String) -> Result<()> {
-        let _ = self
-            .get_event(event_id.clone())
-            .ok_or_else(|| anyhow!("Could not find event with this uuid"))?;
+        let event = self.get_event(event_id.clone()).ok_or_else(|| {
+            CalendarError::NotFound("Could not find event with this uuid".to_string())
+        })?;
 
-        storage::delete_event(&self.path, event_id)?;
+        storage::trash_event(&self.path, event)?;
 
         Ok(())
     }
 
-    pub fn edit_event(
-        &mut self,
-        id: String,
-        name: Option<String>,
-        start: Option<NaiveDateTime>,
-        end: Option<NaiveDateTime>,
-        location: Option<String>,
-        description: Option<String>,
-    ) -> Result<()> {
+    pub fn update_event(&mut self, event: Event) -> Result<()> {
+        let mut batch = storage::Batch::default();
+        self.stage_update(&mut batch, event)?;
+        batch.commit()
+    }
+
+    // Like update_event, the event being written when the batch is committed
+    pub fn stage_update(&mut self, batch: &mut storage::Batch, mut event: Event) -> Result<()> {
         let path = self.path.clone();
+        event.last_modified = Some(Local::now().naive_local());
 
-        let event = self
-            .get_event_mut(id)
-            .ok_or_else(|| anyhow!("Could not find event with this uuid"))?;
-        if let Some(new_name) = name {
-            event.name = new_name;
+        let existing = self.get_event_mut(event.id.clone()).ok_or_else(|| {
+            CalendarError::NotFound("Could not find event with this uuid".to_string())
+        })?;
+        if event.file.is_none() {
+            event.file = existing.file.clone();
         }
-        if let Some(new_start) = start {
-            event.start = new_start;
+        let old_name = std::mem::replace(existing, event.clone()).name;
+
+        // The exceptions of a series are renamed with it, the ones in its file
+        // by write_event, the ones other clients wrote to files of their own
+        // here
+        batch.write_event(&path, &event)?;
+        if old_name != event.name {
+            let others = self
+                .events
+                .iter()
+                .filter(|other| other.id == event.id && other.recurrence_id.is_some())
+                .filter(|other| other.file != event.file)
+                .filter_map(|other| other.file.as_deref());
+            for file in others {
+                batch.rename_exceptions(file, &event.id, &old_name, &event.name)?;
+            }
         }
-        if let Some(new_end) = end {
-            event.end = new_end;
+        Ok(())
+    }
+
+    pub fn get_event(&self, id: String) -> Option<&Event> {
+        let index = self.event_index(&id)?;
+        self.events.get(index)
+    }
+
+    fn get_event_mut(&mut self, id: String) -> Option<&mut Event> {
+        let index = self.event_index(&id)?;
+        self.events.get_mut(index)
+    }
+
+    // The series of an id before the exceptions of it other clients wrote to
+    // files of their own
+    fn event_index(&self, id: &str) -> Option<usize> {
+        self.events
+            .iter()
+            .position(|event| event.id == id && event.recurrence_id.is_none())
+            .or_else(|| self.events.iter().position(|event| event.id == id))
+    }
+
+    pub fn add_todo(&mut self, mut todo: Todo) -> Result<()> {
+        let now = Local::now().naive_local();
+        todo.calendar = self.name.clone();
+        todo.created = Some(now);
+        todo.last_modified = Some(now);
+
+        storage::write_todo(&self.path, &todo)?;
+
+        Ok(())
+    }
+
+    pub fn complete_todo(&mut self, todo_id: String) -> Result<()> {
+        let path = self.path.clone();
+        let now = Local::now().naive_local();
+
+        let todo = self
+            .todos
+            .iter_mut()
+            .find(|t| t.id == todo_id)
+            .ok_or_else(|| {
+                CalendarError::NotFound("Could not find todo with this uuid".to_string())
+            })?;
+        todo.done = true;
+        todo.completed = Some(now);
+        todo.last_modified = Some(now);
+
+        storage::write_todo(&path, todo)?;
+
+        Ok(())
+    }
+
+    // The todo of an id, or of a prefix of it
+    pub fn todo_by_id(&self, id: &str) -> Result<&Todo> {
+        by_id(
+            &self.todos,
+            id,
+            |todo| &todo.id,
+            |todo| todo.name.clone(),
+            "todo",
+        )
+    }
+
+    pub fn add_note(&mut self, mut note: Note) -> Result<()> {
+        let now = Local::now().naive_local();
+        note.calendar = self.name.clone();
+        note.created = Some(now);
+        note.last_modified = Some(now);
+
+        storage::write_note(&self.path, &note)?;
+
+        Ok(())
+    }
+}
+
+// Builds a new event with a random id, the fields left out being empty.
+// build() checks that the event has a name and does not end before it starts.
+#[derive(Debug)]
+pub struct EventBuilder {
+    event: Event,
+}
+
+impl EventBuilder {
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.event.id = id.into();
+        self
+    }
+
+    pub fn calendar(mut self, calendar: impl Into<String>) -> Self {
+        self.event.calendar = calendar.into();
+        self
+    }
+
+    pub fn location(mut self, location: Option<String>) -> Self {
+        self.event.location = location;
+        self
+    }
+
+    pub fn description(mut self, description: Option<String>) -> Self {
+        self.event.description = description;
+        self
+    }
+
+    pub fn all_day(mut self, all_day: bool) -> Self {
+        self.event.all_day = all_day;
+        self
+    }
+
+    pub fn task(mut self, task: bool) -> Self {
+        self.event.task = task;
+        self
+    }
+
+    pub fn transparent(mut self, transparent: bool) -> Self {
+        self.event.transparent = transparent;
+        self
+    }
+
+    pub fn tags(mut self, tags: Vec<String>) -> Self {
+        self.event.tags = tags;
+        self
+    }
+
+    pub fn recurrence(mut self, recurrence: Option<Recurrence>) -> Self {
+        self.event.recurrence = recurrence;
+        self
+    }
+
+    // Kept from the events of other services
+    #[cfg(feature = "graph")]
+    pub fn created(mut self, created: Option<NaiveDateTime>) -> Self {
+        self.event.created = created;
+        self
+    }
+
+    #[cfg(feature = "graph")]
+    pub fn last_modified(mut self, last_modified: Option<NaiveDateTime>) -> Self {
+        self.event.last_modified = last_modified;
+        self
+    }
+
+    pub fn build(self) -> Result<Event> {
+        if self.event.name.trim().is_empty() {
+            return Err(anyhow!("Name cannot be empty"));
         }
-        if let Some(new_location) = location {
-            event.location = Some(new_location);
+        if self.event.end < self.event.start {
+            return Err(anyhow!("End time must be after start time"));
         }
-        if let Some(new_description) = description {
-            event.description = Some(new_description);
+        Ok(self.event)
+    }
+}
+
+impl Event {
+    pub fn builder(
+        name: impl Into<String>,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+    ) -> EventBuilder {
+        EventBuilder {
+            event: Event {
+                id: Uuid::new_v4().to_string(),
+                calendar: String::new(),
+                name: name.into(),
+                start,
+                end,
+                location: None,
+                description: None,
+                url: None,
+                organizer: None,
+                attendees: Vec::new(),
+                all_day: false,
+                task: false,
+                transparent: false,
+                tags: Vec::new(),
+                recurrence: None,
+                recurrence_id: None,
+                attendance: Vec::new(),
+                created: None,
+                last_modified: None,
+                file: None,
+            },
         }
+    }
+
+    // Started and not over yet
+    pub fn is_ongoing(&self, at: NaiveDateTime) -> bool {
+        self.start <= at && at < self.end
+    }
 
-        storage::write_event(&path, &event)?;
+    pub fn attendance_of(&self, occurrence: NaiveDateTime) -> Option<&Attendance> {
+        self.attendance
+            .iter()
+            .find(|attendance| attendance.occurrence == occurrence)
+    }
 
-        Ok(())
+    // Records the attendance of an occurrence, replacing the previous one
+    pub fn record_attendance(&mut self, occurrence: NaiveDateTime, attended: bool) {
+        self.attendance
+            .retain(|attendance| attendance.occurrence != occurrence);
+        self.attendance.push(Attendance {
+            occurrence,
+            attended,
+            recorded: Local::now().naive_local(),
+        });
+        self.attendance
+            .sort_by_key(|attendance| attendance.occurrence);
     }
 
-    pub fn get_event(&self, id: String) -> Option<&Event> {
-        self.events.iter().find(|e| e.id == id)
+    // Hours shown in views, all-day events have none
+    pub fn time_range(&self) -> String {
+        if self.task {
+            format!("due {}", self.start.format("%H:%M"))
+        } else if self.all_day {
+            "all day".to_string()
+        } else {
+            format!(
+                "{}-{}",
+                self.start.format("%H:%M"),
+                self.end.format("%H:%M")
+            )
+        }
     }
 
-    fn get_event_mut(&mut self, id: String) -> Option<&mut Event> {
-        self.events.iter_mut().find(|e| e.id == id)
+    // Whether every occurrence ended before a time, never the case of a series
+    // without an end
+    pub fn ended_before(&self, time: NaiveDateTime) -> bool {
+        if let Some(recurrence) = &self.recurrence {
+            if recurrence.count.is_none() && recurrence.until.is_none() {
+                return false;
+            }
+        }
+        self.occurrences(time - (self.end - self.start), NaiveDateTime::MAX)
+            .is_empty()
+    }
+
+    // Returns the occurrences of this event starting between from and to
+    pub fn occurrences(&self, from: NaiveDateTime, to: NaiveDateTime) -> Vec<Event> {
+        let recurrence = match &self.recurrence {
+            Some(recurrence) => recurrence,
+            None if self.start >= from && self.start <= to => return vec![self.clone()],
+            None => return Vec::new(),
+        };
+
+        let count = recurrence
+            .count
+            .map_or(i64::MAX, |count| count.min(MAX_OCCURRENCES) as i64);
+        let duration = self.end - self.start;
+
+        // Jump close to the window instead of walking from the first occurrence.
+        // Skipped dates give no occurrence, counting them needs a walk.
+        let mut n = if from > self.start
+            && (recurrence.count.is_none() || !recurrence.skips_dates(self.start))
+        {
+            recurrence.index_before(self.start, from).max(0)
+        } else {
+            0
+        };
+
+        let mut index = n;
+
+        let mut occurrences = Vec::new();
+        while index < count && occurrences.len() < MAX_OCCURRENCES as usize {
+            let start = match recurrence.nth_start(self.start, n) {
+                Some(Some(start)) => start,
+                Some(None) => {
+                    n += 1;
+                    continue;
+                }
+                None => break,
+            };
+            n += 1;
+
+            if start > to || recurrence.until.is_some_and(|until| start > until) {
+                break;
+            }
+
+            if start >= from && !recurrence.excluded.contains(&start) {
+                let mut occurrence = self.clone();
+                occurrence.start = start;
+                occurrence.end = start + duration;
+                occurrences.push(occurrence);
+            }
+            index += 1;
+        }
+
+        occurrences
     }
 }
 
-impl Event {
+impl Todo {
     pub fn new(
         name: String,
-        start: NaiveDateTime,
-        end: NaiveDateTime,
-        location: Option<String>,
+        due: Option<NaiveDateTime>,
+        all_day: bool,
         description: Option<String>,
     ) -> Self {
-        Event {
-            id: String::new(),
+        Todo {
+            id: Uuid::new_v4().to_string(),
+            calendar: String::new(),
             name,
-            start,
-            end,
-            location,
+            due,
+            all_day,
             description,
+            done: false,
+            completed: None,
+            created: None,
+            last_modified: None,
+        }
+    }
+}
+
+impl Note {
+    pub fn new(date: NaiveDate, text: String) -> Self {
+        Note {
+            id: Uuid::new_v4().to_string(),
+            calendar: String::new(),
+            date,
+            text,
+            created: None,
+            last_modified: None,
+        }
+    }
+}
+
+impl Recurrence {
+    // Weekly rules on several days go through the series one day at a time
+    fn steps_by_day(&self) -> bool {
+        matches!(self.frequency, RepeatFrequency::Weekly) && !self.by_day.is_empty()
+    }
+
+    // An n of nth_start no further than the first occurrence from from
+    fn index_before(&self, start: NaiveDateTime, from: NaiveDateTime) -> i64 {
+        if self.steps_by_day() {
+            return (from - start).num_days() - 1;
+        }
+        self.periods_between(start, from) / self.interval.max(1) as i64 - 1
+    }
+
+    fn periods_between(&self, from: NaiveDateTime, to: NaiveDateTime) -> i64 {
+        match self.frequency {
+            RepeatFrequency::Daily => (to - from).num_days(),
+            RepeatFrequency::Weekly => (to - from).num_weeks(),
+            RepeatFrequency::Monthly => {
+                (to.year() - from.year()) as i64 * 12 + to.month() as i64 - from.month() as i64
+            }
+            RepeatFrequency::Yearly => (to.year() - from.year()) as i64,
+        }
+    }
+
+    // Whether some periods have no such date, like the 31st or February 29
+    fn skips_dates(&self, start: NaiveDateTime) -> bool {
+        !self.by_day.is_empty() || (self.has_short_months(start) && !self.month_end)
+    }
+
+    // Whether some months do not have the day of the start of the series
+    pub fn has_short_months(&self, start: NaiveDateTime) -> bool {
+        match self.frequency {
+            RepeatFrequency::Daily | RepeatFrequency::Weekly => false,
+            RepeatFrequency::Monthly => start.day() > 28,
+            RepeatFrequency::Yearly => start.month() == 2 && start.day() == 29,
+        }
+    }
+
+    // The day of the start in a month, or its last day with month_end
+    fn day_in(&self, start: NaiveDateTime, year: i32, month: u32) -> Option<NaiveDateTime> {
+        let last = if self.month_end { 28 } else { start.day() };
+        (last..=start.day())
+            .rev()
+            .find_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+            .map(|date| date.and_time(start.time()))
+    }
+
+    // None once the date range is exhausted, Some(None) for dates that do not
+    // exist (like the 31st of a short month), which RFC 5545 says to skip
+    fn nth_start(&self, start: NaiveDateTime, n: i64) -> Option<Option<NaiveDateTime>> {
+        let on_day =
+            |date: NaiveDateTime| self.by_day.is_empty() || self.by_day.contains(&date.weekday());
+
+        if self.steps_by_day() {
+            // Weeks start on monday, the default WKST
+            let date = start.checked_add_signed(Duration::try_days(n)?)?;
+            let week = |date: NaiveDateTime| date.date().week(Weekday::Mon).first_day();
+            let weeks = (week(date) - week(start)).num_weeks();
+            return Some(
+                (weeks % self.interval.max(1) as i64 == 0 && on_day(date)).then_some(date),
+            );
+        }
+
+        let periods = n.checked_mul(self.interval.max(1) as i64)?;
+        match self.frequency {
+            RepeatFrequency::Daily => start
+                .checked_add_signed(Duration::try_days(periods)?)
+                .map(|date| on_day(date).then_some(date)),
+            RepeatFrequency::Weekly => start
+                .checked_add_signed(Duration::try_weeks(periods)?)
+                .map(Some),
+            RepeatFrequency::Monthly => {
+                let months = start.month0() as i64 + periods;
+                let year = i32::try_from(start.year() as i64 + months.div_euclid(12)).ok()?;
+                let month = months.rem_euclid(12) as u32 + 1;
+                NaiveDate::from_ymd_opt(year, 1, 1)?;
+                Some(self.day_in(start, year, month))
+            }
+            RepeatFrequency::Yearly => {
+                let year = i32::try_from(start.year() as i64 + periods).ok()?;
+                NaiveDate::from_ymd_opt(year, 1, 1)?;
+                Some(self.day_in(start, year, start.month()))
+            }
         }
     }
 }
+
+pub fn expand(events: Vec<Event>, from: NaiveDateTime, to: NaiveDateTime) -> Vec<Event> {
+    let overridden = overridden(&events);
+    events
+        .iter()
+        .flat_map(|event| event.occurrences(from, to))
+        .filter(|occurrence| !is_overridden(&overridden, occurrence))
+        .collect()
+}
+
+// The occurrences going on between from and to, the ones started before from
+// that have not ended yet included, however long they last
+pub fn expand_overlapping(
+    events: Vec<Event>,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+) -> Vec<Event> {
+    let overridden = overridden(&events);
+    events
+        .iter()
+        .flat_map(|event| event.occurrences(from - (event.end - event.start), to))
+        .filter(|occurrence| occurrence.end > from || occurrence.start >= from)
+        .filter(|occurrence| !is_overridden(&overridden, occurrence))
+        .collect()
+}
+
+// The occurrences of series that an exception written by another client
+// replaces, by UID and start
+fn overridden(events: &[Event]) -> HashSet<(String, NaiveDateTime)> {
+    events
+        .iter()
+        .filter_map(|event| Some((event.id.clone(), event.recurrence_id?)))
+        .collect()
+}
+
+fn is_overridden(overridden: &HashSet<(String, NaiveDateTime)>, occurrence: &Event) -> bool {
+    occurrence.recurrence.is_some()
+        && overridden.contains(&(occurrence.id.clone(), occurrence.start))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(year: i32, month: u32, day: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(year, month, day)
+            .unwrap()
+            .and_hms_opt(10, 0, 0)
+            .unwrap()
+    }
+
+    fn series(start: NaiveDateTime, frequency: RepeatFrequency, count: Option<u32>) -> Event {
+        Event::builder("Series", start, start + Duration::hours(1))
+            .recurrence(Some(Recurrence {
+                frequency,
+                interval: 1,
+                count,
+                until: None,
+                by_day: Vec::new(),
+                month_end: false,
+                excluded: Vec::new(),
+            }))
+            .build()
+            .unwrap()
+    }
+
+    fn starts(event: &Event, from: NaiveDateTime, to: NaiveDateTime) -> Vec<NaiveDateTime> {
+        event
+            .occurrences(from, to)
+            .iter()
+            .map(|occurrence| occurrence.start)
+            .collect()
+    }
+
+    #[test]
+    fn builder_checks_the_name_and_times() {
+        let start = at(2026, 1, 1);
+        assert!(Event::builder(" ", start, start).build().is_err());
+        assert!(Event::builder("Late", start, start - Duration::hours(1))
+            .build()
+            .is_err());
+        let event = Event::builder("Due", start, start)
+            .calendar("personal")
+            .build()
+            .unwrap();
+        assert_eq!(event.calendar, "personal");
+    }
+
+    #[test]
+    fn ids_can_be_shortened_to_a_unique_prefix() {
+        let start = at(2026, 1, 1);
+        let event = |id: &str| Event::builder(id, start, start).id(id).build().unwrap();
+        let events = [event("abc1"), event("abc2"), event("abd9")];
+        assert_eq!(event_by_id(&events, "abc2").unwrap().id, "abc2");
+        assert_eq!(event_by_id(&events, "abd").unwrap().id, "abd9");
+        assert_eq!(
+            event_by_id(&events, "abc")
+                .unwrap_err()
+                .to_string()
+                .lines()
+                .count(),
+            3
+        );
+        assert!(event_by_id(&events, "x").is_err());
+    }
+
+    #[test]
+    fn only_series_with_an_end_are_over() {
+        let event = series(at(2026, 1, 1), RepeatFrequency::Daily, Some(3));
+        assert!(event.ended_before(at(2026, 1, 4)));
+        assert!(!event.ended_before(at(2026, 1, 3)));
+        let endless = series(at(2020, 1, 1), RepeatFrequency::Yearly, None);
+        assert!(!endless.ended_before(at(2026, 1, 1)));
+    }
+
+    #[test]
+    fn events_read_back_their_json() {
+        let mut event = series(at(2026, 1, 1), RepeatFrequency::Weekly, Some(3));
+        event.created = Some(at(2025, 12, 1));
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["start"], local_time::rfc3339(at(2026, 1, 1)));
+        assert_eq!(json["recurrence"]["frequency"], "weekly");
+
+        let read: Event = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&read).unwrap(), json);
+        // Times without an offset are local ones
+        let naive: Event = serde_json::from_str(
+            r#"{"id": "a", "calendar": "work", "name": "Sync",
+                "start": "2026-01-01T10:00:00", "end": "2026-01-01T11:00:00"}"#,
+        )
+        .unwrap();
+        assert_eq!(naive.start, at(2026, 1, 1));
+    }
+
+    #[test]
+    fn count_limits_the_occurrences() {
+        let event = series(at(2026, 1, 1), RepeatFrequency::Daily, Some(3));
+        assert_eq!(
+            starts(&event, at(2025, 1, 1), at(2027, 1, 1)),
+            [at(2026, 1, 1), at(2026, 1, 2), at(2026, 1, 3)]
+        );
+        assert!(starts(&event, at(2026, 1, 4), at(2027, 1, 1)).is_empty());
+    }
+
+    #[test]
+    fn skipped_dates_do_not_count() {
+        let event = series(at(2027, 1, 31), RepeatFrequency::Monthly, Some(4));
+        let all = [
+            at(2027, 1, 31),
+            at(2027, 3, 31),
+            at(2027, 5, 31),
+            at(2027, 7, 31),
+        ];
+        assert_eq!(starts(&event, at(2027, 1, 1), at(2028, 12, 31)), all);
+        // Without walking from the start, the count would be off
+        assert_eq!(starts(&event, at(2027, 6, 1), at(2028, 12, 31)), all[3..]);
+
+        let event = series(at(2024, 2, 29), RepeatFrequency::Yearly, Some(2));
+        assert_eq!(
+            starts(&event, at(2024, 1, 1), at(2040, 1, 1)),
+            [at(2024, 2, 29), at(2028, 2, 29)]
+        );
+    }
+
+    #[test]
+    fn month_end_falls_on_the_last_day_of_short_months() {
+        let mut event = series(at(2027, 1, 31), RepeatFrequency::Monthly, Some(4));
+        event.recurrence.as_mut().unwrap().month_end = true;
+        let all = [
+            at(2027, 1, 31),
+            at(2027, 2, 28),
+            at(2027, 3, 31),
+            at(2027, 4, 30),
+        ];
+        assert_eq!(starts(&event, at(2027, 1, 1), at(2028, 12, 31)), all);
+        assert_eq!(starts(&event, at(2027, 3, 1), at(2028, 12, 31)), all[2..]);
+
+        let mut event = series(at(2027, 1, 30), RepeatFrequency::Monthly, Some(3));
+        event.recurrence.as_mut().unwrap().month_end = true;
+        assert_eq!(
+            starts(&event, at(2027, 1, 1), at(2028, 12, 31)),
+            [at(2027, 1, 30), at(2027, 2, 28), at(2027, 3, 30)]
+        );
+
+        let mut event = series(at(2024, 2, 29), RepeatFrequency::Yearly, Some(2));
+        event.recurrence.as_mut().unwrap().month_end = true;
+        assert_eq!(
+            starts(&event, at(2024, 1, 1), at(2040, 1, 1)),
+            [at(2024, 2, 29), at(2025, 2, 28)]
+        );
+    }
+
+    #[test]
+    fn until_is_the_last_start() {
+        let mut event = series(at(2026, 1, 1), RepeatFrequency::Weekly, None);
+        event.recurrence.as_mut().unwrap().until = Some(at(2026, 1, 15));
+        assert_eq!(
+            starts(&event, at(2025, 1, 1), at(2027, 1, 1)),
+            [at(2026, 1, 1), at(2026, 1, 8), at(2026, 1, 15)]
+        );
+    }
+
+    #[test]
+    fn weekly_rules_repeat_on_each_day() {
+        // Monday 5 January 2026, on weekdays every other week
+        let mut event = series(at(2026, 1, 5), RepeatFrequency::Weekly, Some(7));
+        let recurrence = event.recurrence.as_mut().unwrap();
+        recurrence.interval = 2;
+        recurrence.by_day = vec![Weekday::Mon, Weekday::Wed, Weekday::Fri];
+        let all = [
+            at(2026, 1, 5),
+            at(2026, 1, 7),
+            at(2026, 1, 9),
+            at(2026, 1, 19),
+            at(2026, 1, 21),
+            at(2026, 1, 23),
+            at(2026, 2, 2),
+        ];
+        assert_eq!(starts(&event, at(2026, 1, 1), at(2026, 12, 31)), all);
+        assert_eq!(starts(&event, at(2026, 1, 20), at(2026, 12, 31)), all[4..]);
+
+        event.recurrence.as_mut().unwrap().count = None;
+        assert_eq!(
+            starts(&event, at(2026, 3, 1), at(2026, 3, 7)),
+            [at(2026, 3, 2), at(2026, 3, 4), at(2026, 3, 6)]
+        );
+    }
+
+    #[test]
+    fn far_windows_keep_the_rhythm_of_the_series() {
+        let mut event = series(at(2000, 1, 3), RepeatFrequency::Weekly, None);
+        event.recurrence.as_mut().unwrap().interval = 2;
+        assert_eq!(
+            starts(&event, at(2026, 10, 6), at(2026, 11, 16)),
+            [at(2026, 10, 19), at(2026, 11, 2), at(2026, 11, 16)]
+        );
+
+        let event = series(at(2000, 1, 31), RepeatFrequency::Monthly, None);
+        assert_eq!(
+            starts(&event, at(2026, 9, 1), at(2026, 12, 31)),
+            [at(2026, 10, 31), at(2026, 12, 31)]
+        );
+    }
+
+    #[test]
+    fn occurrences_started_before_the_window_are_kept_while_going_on() {
+        let trip = Event::builder("Trip", at(2026, 10, 1), at(2026, 10, 12))
+            .build()
+            .unwrap();
+        let done = Event::builder("Done", at(2026, 10, 8), at(2026, 10, 10))
+            .build()
+            .unwrap();
+        let mut weekly = series(at(2026, 9, 1), RepeatFrequency::Weekly, None);
+        weekly.end = weekly.start + Duration::days(3);
+
+        let starts: Vec<_> =
+            expand_overlapping(vec![trip, done, weekly], at(2026, 10, 10), at(2026, 10, 14))
+                .iter()
+                .map(|event| event.start)
+                .collect();
+        // Done ends as the window starts, the weekly occurrence of the 6th before
+        assert_eq!(starts, [at(2026, 10, 1), at(2026, 10, 13)]);
+    }
+}
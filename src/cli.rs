@@ -1,7 +1,15 @@
-use crate::date::{CalendarDate, CalendarDateTime};
-use anyhow::{anyhow, Result};
-use chrono::{Duration, NaiveDate, NaiveDateTime};
+use crate::config;
+use crate::date::{self, CalendarDate, CalendarDateTime, CalendarTime};
+use crate::display;
+use crate::paths;
+use crate::storage;
+use anyhow::{anyhow, Context, Result};
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
 use clap::{Parser, Subcommand};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
 use std::str::FromStr;
 
 #[derive(Parser)]
@@ -10,12 +18,37 @@ pub struct Cli {
     #[command(subcommand)]
     pub command: Option<Commands>,
 
-    #[arg(short, long, help = "View mode: day, week, month")]
+    #[arg(short, long, help = "View mode: day, week, month, year")]
     mode: Option<String>,
     #[arg(short, long, help = "Specify the calendar to view")]
     calendar: Option<String>,
     #[arg(short, long, help = "Show n times")]
     number: Option<u32>,
+    #[arg(
+        long,
+        global = true,
+        help = "Output list, show and view results as JSON"
+    )]
+    json: bool,
+    #[arg(
+        long,
+        global = true,
+        value_name = "DIR",
+        help = "Use the calendars of this directory, like the one of someone you manage"
+    )]
+    root: Option<PathBuf>,
+    #[arg(
+        long,
+        global = true,
+        help = "Output without colors or styles, and the events of the month view under the grid"
+    )]
+    plain: bool,
+    #[arg(
+        long,
+        global = true,
+        help = "Fail on the files that cannot be read instead of skipping them"
+    )]
+    strict: bool,
 }
 
 #[derive(Subcommand)]
@@ -24,28 +57,145 @@ pub enum Commands {
     List(ListArgs),
     #[command(about = "Add a new event to a calendar")]
     Add(AddArgs),
+    #[command(about = "Block a time of the day that does not count as busy")]
+    Protect(ProtectArgs),
     #[command(about = "Edit an existing event")]
     Edit(EditArgs),
     #[command(about = "Delete an event")]
     Delete(DeleteArgs),
+    #[command(about = "Move an event to another calendar")]
+    Move(MoveArgs),
     #[command(about = "Show details of a specific event")]
     Show(ShowArgs),
+    #[command(about = "Open the link or the location of an event")]
+    Open(OpenArgs),
+    #[command(about = "Record whether you attended an event")]
+    Attend(AttendArgs),
     #[command(about = "Display calendar in various formats (daily, weekly, monthly)")]
     View(ViewArgs),
     #[command(about = "Synchronize calendars using vdirsyncer")]
     Sync(SyncArgs),
+    #[command(about = "Move calendars from ~/.calendars to the XDG data directory")]
+    Migrate(MigrateArgs),
+    #[command(about = "Set up the vdirsyncer config for a CalDAV account")]
+    Setup(SetupArgs),
+    #[command(about = "Import events from an ICS or CSV file")]
+    Import(ImportArgs),
+    #[command(about = "Export events to a single ICS file")]
+    Export(ExportArgs),
+    #[command(about = "Print the agenda of a day, as text or as an email")]
+    Digest(DigestArgs),
+    #[command(about = "Print the events of the next days, grouped by day")]
+    Agenda(AgendaArgs),
+    #[command(about = "Print the busy times of all calendars")]
+    Freebusy(FreeBusyArgs),
+    #[command(
+        about = "Print the attendance rate of each recurring event, or the booked work hours"
+    )]
+    Stats(StatsArgs),
+    #[command(about = "Find free slots across all calendars")]
+    Free(FreeArgs),
+    #[command(about = "List the days with nothing planned in the evening")]
+    FreeEvenings(FreeEveningsArgs),
+    #[command(about = "Find times free for you and the attendees of a meeting")]
+    Schedule(ScheduleArgs),
+    #[command(about = "Answer an invitation and add its event to a calendar")]
+    Rsvp(RsvpArgs),
+    #[command(about = "Print the current or next event, for a status line")]
+    Status(StatusArgs),
+    #[command(about = "Print the next event, or exit with 1 when there is none")]
+    Next(NextArgs),
+    #[command(about = "List the events going on now")]
+    Now(NowArgs),
+    #[command(about = "Print a box with today's events, for a login message")]
+    Motd(MotdArgs),
+    #[command(about = "Print the coming events as lines to choose from with rofi or dmenu")]
+    Menu(MenuArgs),
+    #[command(about = "Keep the events in memory to answer calendar client, for status bars")]
+    Daemon(DaemonArgs),
+    #[command(about = "Ask the daemon for the next event, the agenda or the status line")]
+    Client(ClientArgs),
+    #[command(about = "Serve the events over HTTP, as JSON and as an ICS feed")]
+    Serve(ServeArgs),
+    #[command(about = "Manage the todos of a calendar")]
+    Todo(TodoArgs),
+    #[command(about = "Find the rooms of a shared calendar free at a time")]
+    Rooms(RoomsArgs),
+    #[command(about = "Attach a note to a day")]
+    Note(NoteArgs),
+    #[command(about = "Read the notes of a range of days")]
+    Notes(NotesArgs),
+    #[command(about = "Search events by name, description or location")]
+    Search(SearchArgs),
+    #[command(about = "Tell when an event is, or was")]
+    When(WhenArgs),
+    #[cfg(feature = "sqlite")]
+    #[command(about = "Update the SQLite index of the events")]
+    Index(IndexArgs),
+    #[command(about = "List, restore or empty the deleted events")]
+    Trash(TrashArgs),
+    #[command(about = "Move the events older than an age to the trash")]
+    Purge(PurgeArgs),
+    #[command(about = "Remove the copies of events added twice")]
+    Dedupe(DedupeArgs),
+    #[command(about = "Check the ICS files of the calendars, and repair them")]
+    Doctor(DoctorArgs),
+    #[command(about = "Print the completion script of a shell: bash, zsh or fish")]
+    Completion(CompletionArgs),
+    // The candidates completed by the scripts, like the names of the calendars
+    #[command(name = "__complete", hide = true)]
+    Complete(CompleteArgs),
+}
+
+#[derive(Subcommand)]
+pub enum TodoCommands {
+    #[command(about = "Add a new todo to a calendar")]
+    Add(TodoAddArgs),
+    #[command(about = "List the todos of all or specific calendars")]
+    List(TodoListArgs),
+    #[command(about = "Mark a todo as done")]
+    Done(TodoDoneArgs),
+}
+
+#[derive(Subcommand)]
+pub enum TrashCommands {
+    #[command(about = "List the deleted events")]
+    List(TrashListArgs),
+    #[command(about = "Put a deleted event back in its calendar")]
+    Restore(TrashRestoreArgs),
+    #[command(about = "Delete the events of the trash for good")]
+    Empty(TrashEmptyArgs),
+}
+
+#[cfg(feature = "sqlite")]
+#[derive(Subcommand)]
+pub enum IndexCommands {
+    #[command(about = "Drop the index and read every file again")]
+    Rebuild,
+}
+
+#[derive(Subcommand)]
+pub enum RoomsCommands {
+    #[command(about = "List the rooms free for a whole slot")]
+    Free(RoomsFreeArgs),
 }
 
 // Validated structs for each command
 
 #[derive(Debug)]
 pub struct CalendarListArgs {
-    pub query: Option<String>,
+    pub query: Option<ListQuery>,
+    pub fields: Vec<SearchField>,
     pub from: NaiveDate,
     pub to: NaiveDate,
     pub limit: Option<usize>,
     pub id: bool,
     pub calendar: Option<String>,
+    pub format: Option<String>,
+    pub output: OutputFormat,
+    pub backend: Option<String>,
+    pub count: bool,
+    pub exists: bool,
 }
 
 #[derive(Debug)]
@@ -59,30 +209,98 @@ pub struct CalendarAddArgs {
     pub repeat: Option<RepeatFrequency>,
     pub every: Option<u32>,
     pub until: Option<NaiveDate>,
+    pub all_day: bool,
+    pub create: bool,
+    pub force: bool,
+    pub attendees: Vec<String>,
+    pub invite: Option<String>,
+    pub send: bool,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct CalendarProtectArgs {
+    pub calendar: String,
+    pub name: String,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    pub days: Vec<Weekday>,
+    pub from: NaiveDate,
+    pub until: Option<NaiveDate>,
+    pub create: bool,
+}
+
+// The events a command changes: one by id or name, or all the ones whose name
+// contains a text, with an occurrence in a range of days
+#[derive(Debug)]
+pub enum EventSelection {
+    Id(String),
+    Matching {
+        query: String,
+        from: NaiveDate,
+        to: Option<NaiveDate>,
+    },
 }
 
 #[derive(Debug)]
 pub struct CalendarEditArgs {
-    pub event_id: String,
+    pub selection: EventSelection,
     pub calendar: String,
     pub name: Option<String>,
     pub start: Option<NaiveDateTime>,
     pub end: Option<NaiveDateTime>,
+    // Moves the start and the end, before the duration is set
+    pub shift: Option<Duration>,
+    // Sets the end after the start, the new one or the current one
+    pub duration: Option<Duration>,
     pub loc: Option<String>,
     pub desc: Option<String>,
+    pub yes: bool,
 }
 
 #[derive(Debug)]
 pub struct CalendarDeleteArgs {
-    pub event_id: String,
+    pub selection: EventSelection,
     pub calendar: String,
     pub force: bool,
 }
 
+#[derive(Debug)]
+pub struct CalendarMoveArgs {
+    pub event_id: String,
+    pub calendar: String,
+    pub target: String,
+    pub create: bool,
+}
+
 #[derive(Debug)]
 pub struct CalendarShowArgs {
     pub event_id: String,
     pub calendar: String,
+    pub output: OutputFormat,
+}
+
+#[derive(Debug)]
+pub struct CalendarOpenArgs {
+    pub event_id: String,
+    pub calendar: String,
+    pub dry_run: bool,
+}
+
+#[derive(Debug)]
+pub struct CalendarAttendArgs {
+    pub event_id: String,
+    pub calendar: String,
+    pub date: Option<NaiveDate>,
+    pub absent: bool,
+}
+
+#[derive(Debug)]
+pub struct CalendarStatsArgs {
+    pub calendar: Option<String>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub utilization: bool,
 }
 
 #[derive(Debug)]
@@ -91,367 +309,2959 @@ pub struct CalendarViewArgs {
     pub mode: ViewMode,
     pub calendar: Option<String>,
     pub number: u32,
+    pub output: OutputFormat,
+    pub backend: Option<String>,
+    pub focus: Option<NaiveDate>,
+    pub grid: bool,
+    pub timeline: bool,
+    pub heatmap: bool,
+    pub watch: bool,
 }
 
 #[derive(Debug)]
 pub struct CalendarSyncArgs {
     pub calendar: Option<String>,
+    pub pair: Option<String>,
+    pub backend: SyncBackend,
+    pub dry_run: bool,
+    pub status: bool,
 }
 
-// Enums for specific types
+#[derive(Debug)]
+pub struct CalendarMigrateArgs {}
 
-#[derive(Debug, Clone, Copy)]
-pub enum RepeatFrequency {
-    Daily,
-    Weekly,
-    Monthly,
-    Yearly,
-}
+#[derive(Debug)]
+pub struct CalendarSetupArgs {}
 
 #[derive(Debug)]
-pub enum SearchField {
-    Name,
-    Description,
-    Location,
+pub struct CalendarImportArgs {
+    pub file: Option<String>,
+    pub from: Option<ImportTool>,
+    pub takeout: bool,
+    // None for takeout archives, the calendars are asked for
+    pub calendar: Option<String>,
+    pub format: ImportFormat,
+    pub map: Vec<(CsvField, String)>,
+    pub on_conflict: ConflictStrategy,
 }
 
 #[derive(Debug)]
-pub enum ViewMode {
-    Day,
-    Week,
-    Month,
+pub struct CalendarExportArgs {
+    pub calendar: Option<String>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub format: ExportFormat,
+    pub mode: HtmlMode,
+    // Months or days of the HTML page
+    pub number: u32,
 }
 
-// Implementation of FromStr for custom enums
-
-impl FromStr for RepeatFrequency {
-    type Err = anyhow::Error;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_lowercase().as_str() {
-            "daily" => Ok(RepeatFrequency::Daily),
-            "weekly" => Ok(RepeatFrequency::Weekly),
-            "monthly" => Ok(RepeatFrequency::Monthly),
-            "yearly" => Ok(RepeatFrequency::Yearly),
-            _ => Err(anyhow!("Invalid repeat frequency")),
-        }
-    }
+#[derive(Debug)]
+pub struct CalendarDigestArgs {
+    pub date: NaiveDate,
+    pub calendar: Option<String>,
+    pub format: DigestFormat,
 }
 
-impl FromStr for SearchField {
-    type Err = anyhow::Error;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_lowercase().as_str() {
-            "name" => Ok(SearchField::Name),
-            "description" => Ok(SearchField::Description),
-            "location" => Ok(SearchField::Location),
-            _ => Err(anyhow!("Invalid search field")),
-        }
-    }
+#[derive(Debug)]
+pub struct CalendarAgendaArgs {
+    pub days: u32,
+    pub calendar: Option<String>,
+    pub watch: bool,
 }
 
-impl FromStr for ViewMode {
-    type Err = anyhow::Error;
+#[derive(Debug)]
+pub struct CalendarFreeEveningsArgs {
+    pub calendar: Option<String>,
+    pub weeks: u32,
+    pub after: Option<NaiveTime>,
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_lowercase().as_str() {
-            "day" => Ok(ViewMode::Day),
-            "week" => Ok(ViewMode::Week),
-            "month" => Ok(ViewMode::Month),
-            _ => Err(anyhow!("Invalid view mode")),
-        }
-    }
+#[derive(Debug)]
+pub struct CalendarFreeArgs {
+    pub calendar: Option<String>,
+    pub duration: Duration,
+    pub between: Option<(NaiveTime, NaiveTime)>,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
 }
 
-// Argument structs for each command
+#[derive(Debug)]
+pub struct CalendarScheduleArgs {
+    pub with: Vec<String>,
+    pub duration: Duration,
+    pub between: Option<(NaiveTime, NaiveTime)>,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub name: Option<String>,
+    pub calendar: String,
+    pub invite: Option<String>,
+    pub send: bool,
+    pub attendees: Vec<String>,
+}
 
-#[derive(Parser)]
-pub struct ListArgs {
-    #[arg(help = "Query terms in the fzf search")]
-    pub query: Vec<String>,
-    #[arg(short, long, help = "Specify the calendar to list (default: all)")]
-    calendar: Option<String>,
-    #[arg(short, long, help = "Start date for listing (default: today)")]
-    from: Option<String>,
-    #[arg(
-        short,
-        long,
-        help = "End date for listing (default: 1 month from today)"
-    )]
-    to: Option<String>,
-    #[arg(short, long, help = "Limit the number of events shown")]
-    limit: Option<usize>,
-    #[arg(
-        short,
-        long,
-        help = "Show the uuid of the tasks for future modification"
-    )]
-    id: bool,
+#[derive(Debug)]
+pub struct CalendarRsvpArgs {
+    pub file: String,
+    pub answer: RsvpAnswer,
+    pub calendar: String,
+    pub address: Option<String>,
+    pub reply: Option<String>,
+    pub send: bool,
 }
 
-#[derive(Parser)]
-pub struct AddArgs {
-    #[arg(required = true, help = "Name of the event")]
-    pub name: Vec<String>,
-    #[arg(
-        short,
-        long,
-        help = "Event start time (eg. tom@21 14-jul@12:30 2024/08/06@08:00)"
-    )]
-    pub at: String,
-    #[arg(short, long, help = "Event end time (default: 1 hour after start)")]
-    pub to: Option<String>,
-    #[arg(
-        short,
-        long,
-        help = "The calendar to add the event to (default: personal)"
-    )]
+#[derive(Debug)]
+pub struct CalendarFreeBusyArgs {
     pub calendar: Option<String>,
-    #[arg(short, long, help = "Event location")]
-    pub loc: Option<String>,
-    #[arg(short, long, help = "Event description")]
-    pub desc: Option<String>,
-    #[arg(
-        short,
-        long,
-        help = "Repeat frequency (daily, weekly, monthly, yearly)"
-    )]
-    pub repeat: Option<String>,
-    #[arg(short, long, help = "Repeat every N days/weeks/months/years")]
-    pub every: Option<u32>,
-    #[arg(short, long, help = "Repeat until this date")]
-    pub until: Option<String>,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub ics: bool,
 }
 
-#[derive(Parser)]
-pub struct EditArgs {
-    pub event_id: String,
-    #[arg(
-        short,
-        long,
-        help = "The calendar to edit the event from (default: personal)"
-    )]
-    calendar: Option<String>,
-    #[arg(short, long, help = "Name of the event")]
-    name: Option<String>,
-    #[arg(short, long, help = "New event start time")]
-    at: Option<String>,
-    #[arg(short, long, help = "New event end time")]
-    to: Option<String>,
-    #[arg(short, long, help = "New event location")]
-    loc: Option<String>,
-    #[arg(short, long, help = "New event description")]
-    desc: Option<String>,
+#[derive(Debug)]
+pub struct CalendarStatusArgs {
+    pub calendar: Option<String>,
+    pub format: StatusFormat,
 }
 
-#[derive(Parser)]
-pub struct DeleteArgs {
-    pub event_id: String,
-    #[arg(short, long, help = "Specify the calendar")]
-    calendar: Option<String>,
-    #[arg(short, long, help = "Delete without confirmation")]
-    force: bool,
+#[derive(Debug)]
+pub struct CalendarNowArgs {
+    pub calendar: Option<String>,
 }
 
-#[derive(Parser)]
-pub struct ShowArgs {
-    pub event_id: String,
-    #[arg(short, long, help = "Specify the calendar to show from")]
-    calendar: Option<String>,
+#[derive(Debug)]
+pub struct CalendarNextArgs {
+    pub calendar: Option<String>,
+    pub within: Option<Duration>,
+    pub format: Option<String>,
 }
 
-#[derive(Parser)]
-pub struct ViewArgs {
-    #[arg(help = "Specify the date for which the calendar will be run")]
-    pub date: Option<String>,
-    #[arg(
-        short,
-        long,
-        default_value = "month",
-        help = "View mode: day, week, month"
-    )]
-    mode: String,
-    #[arg(short, long, help = "Specify the calendar to view")]
-    calendar: Option<String>,
-    #[arg(short, long, help = "Show n times")]
-    number: Option<u32>,
+#[derive(Debug)]
+pub struct CalendarMotdArgs {
+    pub calendar: Option<String>,
+    pub max: usize,
+    pub width: usize,
 }
 
-#[derive(Parser)]
-pub struct SyncArgs {
-    #[arg(long, help = "Specify the calendar to sync")]
-    calendar: Option<String>,
+#[derive(Debug)]
+pub struct CalendarMenuArgs {
+    pub selection: Option<String>,
+    pub action: Option<MenuAction>,
+    pub changes: Vec<String>,
+    pub calendar: Option<String>,
+    pub days: u32,
 }
 
-// Helper functions
+#[derive(Debug)]
+pub struct CalendarDaemonArgs {}
 
-fn parse_date(date_str: &str) -> Result<NaiveDate> {
-    Ok(CalendarDate::parse(date_str)?.inner())
+#[derive(Debug)]
+pub struct CalendarClientArgs {
+    pub query: Vec<String>,
 }
 
-fn parse_datetime(datetime_str: &str) -> Result<NaiveDateTime> {
-    Ok(CalendarDateTime::parse(datetime_str)?.inner())
+#[derive(Debug)]
+pub struct CalendarServeArgs {
+    pub address: SocketAddr,
+    pub allow_origin: Option<String>,
 }
 
-impl ListArgs {
-    pub fn validate(self) -> Result<CalendarListArgs> {
-        let query: Option<String> = Some(self.query.join(" "))
-            .filter(|s| !s.trim().is_empty())
+// The queries answered by the daemon, validated like their commands
+#[derive(Debug)]
+pub enum CalendarQuery {
+    Next(CalendarNextArgs),
+    Agenda(CalendarAgendaArgs),
+    Status(CalendarStatusArgs),
+}
+
+#[derive(Debug)]
+pub struct CalendarRoomsFreeArgs {
+    pub calendar: String,
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+}
+
+#[derive(Debug)]
+pub struct CalendarTodoAddArgs {
+    pub calendar: String,
+    pub name: String,
+    pub due: Option<NaiveDateTime>,
+    pub all_day: bool,
+    pub desc: Option<String>,
+    pub create: bool,
+}
+
+#[derive(Debug)]
+pub struct CalendarTodoListArgs {
+    pub calendar: Option<String>,
+    pub all: bool,
+    pub id: bool,
+}
+
+#[derive(Debug)]
+pub struct CalendarTodoDoneArgs {
+    pub todo_id: String,
+    pub calendar: String,
+}
+
+#[derive(Debug)]
+pub struct CalendarPurgeArgs {
+    // None for the retention of the config
+    pub before: Option<NaiveDate>,
+    pub calendar: Option<String>,
+    pub dry_run: bool,
+    pub force: bool,
+}
+
+#[derive(Debug)]
+pub struct CalendarDedupeArgs {
+    pub calendar: Option<String>,
+    pub force: bool,
+}
+
+#[derive(Debug)]
+pub struct CalendarDoctorArgs {
+    pub calendar: Option<String>,
+    pub fix: bool,
+}
+
+#[derive(Debug)]
+pub struct CalendarCompletionArgs {
+    pub shell: Shell,
+}
+
+#[derive(Debug)]
+pub struct CalendarCompleteArgs {
+    pub candidates: Candidates,
+}
+
+#[derive(Debug)]
+pub struct CalendarTrashListArgs {
+    pub calendar: Option<String>,
+    pub id: bool,
+}
+
+#[derive(Debug)]
+pub struct CalendarTrashRestoreArgs {
+    pub event_id: String,
+    pub calendar: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct CalendarTrashEmptyArgs {
+    pub calendar: Option<String>,
+    pub force: bool,
+}
+
+#[derive(Debug)]
+pub struct CalendarNoteArgs {
+    pub calendar: String,
+    pub date: NaiveDate,
+    pub text: String,
+    pub create: bool,
+}
+
+#[derive(Debug)]
+pub struct CalendarNotesArgs {
+    pub calendar: Option<String>,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+}
+
+#[derive(Debug)]
+pub struct CalendarSearchArgs {
+    pub words: Vec<String>,
+    pub calendar: Option<String>,
+    // None for all time
+    pub range: Option<(NaiveDate, NaiveDate)>,
+    pub limit: Option<usize>,
+    pub id: bool,
+}
+
+#[derive(Debug)]
+pub struct CalendarWhenArgs {
+    pub words: Vec<String>,
+    pub calendar: Option<String>,
+}
+
+#[cfg(feature = "sqlite")]
+#[derive(Debug)]
+pub struct CalendarIndexArgs {
+    pub rebuild: bool,
+}
+
+// Enums for specific types
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RepeatFrequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SearchField {
+    Name,
+    Description,
+    Location,
+}
+
+// The names kept by list, fuzzily matched or matched by a regex
+#[derive(Debug)]
+pub enum ListQuery {
+    Fuzzy(String),
+    Regex(Regex),
+}
+
+#[derive(Debug)]
+pub enum ViewMode {
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Csv,
+    Tsv,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportFormat {
+    Ics,
+    Csv,
+    // An email with calendar attachments, given with --from-email
+    Email,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConflictStrategy {
+    Skip,
+    Overwrite,
+    NewUid,
+    Ask,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportTool {
+    Khal,
+    Calcurse,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CsvField {
+    Name,
+    StartDate,
+    StartTime,
+    EndDate,
+    EndTime,
+    AllDay,
+    Location,
+    Description,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncBackend {
+    Vdirsyncer,
+    Graph,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Ics,
+    Html,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HtmlMode {
+    Month,
+    Agenda,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DigestFormat {
+    Text,
+    Email,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StatusFormat {
+    Text,
+    Tmux,
+    Waybar,
+    Polybar,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MenuAction {
+    Show,
+    Edit,
+    Delete,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+// What the completion scripts ask for: the calendars, or the events of one
+#[derive(Debug)]
+pub enum Candidates {
+    Calendars,
+    Events(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RsvpAnswer {
+    Accept,
+    Decline,
+    Tentative,
+}
+
+impl OutputFormat {
+    fn from_json_flag(json: bool) -> Self {
+        if json {
+            OutputFormat::Json
+        } else {
+            OutputFormat::Text
+        }
+    }
+}
+
+impl RsvpAnswer {
+    // The participation status of an attendee giving this answer
+    pub fn partstat(self) -> &'static str {
+        match self {
+            RsvpAnswer::Accept => "ACCEPTED",
+            RsvpAnswer::Decline => "DECLINED",
+            RsvpAnswer::Tentative => "TENTATIVE",
+        }
+    }
+}
+
+// Implementation of FromStr for custom enums
+
+impl FromStr for RepeatFrequency {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "daily" => Ok(RepeatFrequency::Daily),
+            "weekly" => Ok(RepeatFrequency::Weekly),
+            "monthly" => Ok(RepeatFrequency::Monthly),
+            "yearly" => Ok(RepeatFrequency::Yearly),
+            _ => Err(anyhow!("Invalid repeat frequency")),
+        }
+    }
+}
+
+impl FromStr for SearchField {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "name" => Ok(SearchField::Name),
+            "description" => Ok(SearchField::Description),
+            "location" => Ok(SearchField::Location),
+            _ => Err(anyhow!(
+                "Invalid search field '{}', expected name, location or description",
+                s
+            )),
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            "tsv" => Ok(OutputFormat::Tsv),
+            _ => Err(anyhow!("Invalid output format")),
+        }
+    }
+}
+
+impl FromStr for ImportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ics" => Ok(ImportFormat::Ics),
+            "csv" => Ok(ImportFormat::Csv),
+            _ => Err(anyhow!("Invalid import format")),
+        }
+    }
+}
+
+impl FromStr for ConflictStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "skip" => Ok(ConflictStrategy::Skip),
+            "overwrite" => Ok(ConflictStrategy::Overwrite),
+            "new-uid" => Ok(ConflictStrategy::NewUid),
+            "ask" => Ok(ConflictStrategy::Ask),
+            _ => Err(anyhow!("Invalid conflict strategy")),
+        }
+    }
+}
+
+impl FromStr for ImportTool {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "khal" => Ok(ImportTool::Khal),
+            "calcurse" => Ok(ImportTool::Calcurse),
+            _ => Err(anyhow!("Invalid tool to import from")),
+        }
+    }
+}
+
+impl FromStr for CsvField {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "name" => Ok(CsvField::Name),
+            "start-date" => Ok(CsvField::StartDate),
+            "start-time" => Ok(CsvField::StartTime),
+            "end-date" => Ok(CsvField::EndDate),
+            "end-time" => Ok(CsvField::EndTime),
+            "all-day" => Ok(CsvField::AllDay),
+            "location" => Ok(CsvField::Location),
+            "description" => Ok(CsvField::Description),
+            _ => Err(anyhow!("Invalid CSV field '{}'", s)),
+        }
+    }
+}
+
+impl FromStr for SyncBackend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "vdirsyncer" => Ok(SyncBackend::Vdirsyncer),
+            "graph" => Ok(SyncBackend::Graph),
+            _ => Err(anyhow!("Invalid sync backend")),
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ics" => Ok(ExportFormat::Ics),
+            "html" => Ok(ExportFormat::Html),
+            _ => Err(anyhow!("Invalid export format")),
+        }
+    }
+}
+
+impl FromStr for HtmlMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "month" => Ok(HtmlMode::Month),
+            "agenda" => Ok(HtmlMode::Agenda),
+            _ => Err(anyhow!("Invalid HTML mode")),
+        }
+    }
+}
+
+impl FromStr for DigestFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(DigestFormat::Text),
+            "email" => Ok(DigestFormat::Email),
+            _ => Err(anyhow!("Invalid digest format")),
+        }
+    }
+}
+
+impl FromStr for StatusFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(StatusFormat::Text),
+            "tmux" => Ok(StatusFormat::Tmux),
+            "waybar" => Ok(StatusFormat::Waybar),
+            "polybar" => Ok(StatusFormat::Polybar),
+            _ => Err(anyhow!("Invalid status format")),
+        }
+    }
+}
+
+impl FromStr for MenuAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "show" => Ok(MenuAction::Show),
+            "edit" => Ok(MenuAction::Edit),
+            "delete" => Ok(MenuAction::Delete),
+            _ => Err(anyhow!(
+                "Invalid action '{}', expected show, edit or delete",
+                s
+            )),
+        }
+    }
+}
+
+impl FromStr for Shell {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            _ => Err(anyhow!("Invalid shell '{}', expected bash, zsh or fish", s)),
+        }
+    }
+}
+
+impl FromStr for ViewMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "day" => Ok(ViewMode::Day),
+            "week" => Ok(ViewMode::Week),
+            "month" => Ok(ViewMode::Month),
+            "year" => Ok(ViewMode::Year),
+            _ => Err(anyhow!("Invalid view mode")),
+        }
+    }
+}
+
+// Argument structs for each command
+
+#[derive(Parser)]
+pub struct ListArgs {
+    #[arg(help = "Query terms in the fzf search")]
+    pub query: Vec<String>,
+    #[arg(
+        long,
+        help = "Take the query as a regular expression matched against the names"
+    )]
+    regex: bool,
+    #[arg(
+        long = "in",
+        value_name = "FIELDS",
+        help = "Fields the query is matched against: name, location, description, separated by commas (default: name)"
+    )]
+    fields: Option<String>,
+    #[arg(short, long, help = "Specify the calendar to list (default: all)")]
+    calendar: Option<String>,
+    #[arg(short, long, help = "Start date for listing (default: today)")]
+    from: Option<String>,
+    #[arg(
+        short,
+        long,
+        help = "End date for listing (default: 1 month from today)"
+    )]
+    to: Option<String>,
+    #[arg(
+        long,
+        help = "A named range of days of the config, instead of --from and --to"
+    )]
+    range: Option<String>,
+    #[arg(short, long, help = "Limit the number of events shown")]
+    limit: Option<usize>,
+    #[arg(
+        short,
+        long,
+        help = "Show the uuid of the tasks for future modification"
+    )]
+    id: bool,
+    #[arg(
+        long,
+        help = "Output format, either a named format from the config or a template (eg. \"{start:%H:%M} {name}\")"
+    )]
+    format: Option<String>,
+    #[arg(
+        short,
+        long,
+        help = "Output type: text, json, csv, tsv (default: text)"
+    )]
+    output: Option<String>,
+    #[arg(
+        short,
+        long,
+        help = "Display backend used for text output (default: text)"
+    )]
+    backend: Option<String>,
+    #[arg(long, help = "Print the number of matching events only")]
+    count: bool,
+    #[arg(long, help = "Print nothing, exit with status 1 when no event matches")]
+    exists: bool,
+}
+
+#[derive(Parser)]
+pub struct ProtectArgs {
+    #[arg(help = "Name of the block (eg. lunch)")]
+    pub name: String,
+    #[arg(help = "Hours of the block (eg. 12:30-13:30)")]
+    pub hours: String,
+    #[arg(
+        help = "Days of the block: daily, weekdays, weekends or a list (eg. mon,wed,fri) (default: weekdays)"
+    )]
+    pub days: Option<String>,
+    #[arg(
+        short,
+        long,
+        help = "The calendar to add the block to (default: personal)"
+    )]
+    pub calendar: Option<String>,
+    #[arg(short, long, help = "First day of the block (default: today)")]
+    pub from: Option<String>,
+    #[arg(short, long, help = "Last day of the block (default: forever)")]
+    pub until: Option<String>,
+    #[arg(long, help = "Create the calendar if it does not exist")]
+    pub create: bool,
+}
+
+#[derive(Parser)]
+pub struct AddArgs {
+    #[arg(required = true, help = "Name of the event")]
+    pub name: Vec<String>,
+    #[arg(
+        short,
+        long,
+        help = "Event start time (eg. tom@21 14-jul@12:30 2024/08/06@08:00)"
+    )]
+    pub at: Option<String>,
+    #[arg(long, help = "Day of an all-day event (eg. tom 14-jul 1)")]
+    pub on: Option<String>,
+    #[arg(
+        short,
+        long,
+        help = "Event end time, or last day for all-day events, or +DURATION after the start (default: the default duration of the config, else 1 hour)"
+    )]
+    pub to: Option<String>,
+    #[arg(
+        long = "for",
+        value_name = "DURATION",
+        help = "Event duration, instead of an end time (eg. 90m 2h30m 3d)"
+    )]
+    pub duration: Option<String>,
+    #[arg(
+        short,
+        long,
+        help = "The calendar to add the event to (default: personal)"
+    )]
+    pub calendar: Option<String>,
+    #[arg(short, long, help = "Event location")]
+    pub loc: Option<String>,
+    #[arg(short, long, help = "Event description")]
+    pub desc: Option<String>,
+    #[arg(
+        short,
+        long,
+        help = "Repeat frequency (daily, weekly, monthly, yearly)"
+    )]
+    pub repeat: Option<String>,
+    #[arg(short, long, help = "Repeat every N days/weeks/months/years")]
+    pub every: Option<u32>,
+    #[arg(short, long, help = "Repeat until this date")]
+    pub until: Option<String>,
+    #[arg(long, help = "Make the event last the whole day")]
+    pub all_day: bool,
+    #[arg(long, help = "Create the calendar if it does not exist")]
+    pub create: bool,
+    #[arg(short, long, help = "Add the event even if it overlaps other events")]
+    pub force: bool,
+    #[arg(long, help = "Email address of an attendee invited to the event")]
+    pub attendee: Vec<String>,
+    #[arg(long, help = "Write an invitation to the event to this file")]
+    pub invite: Option<String>,
+    #[arg(long, help = "Send the invitation to the attendees with sendmail")]
+    pub send: bool,
+    #[arg(
+        long,
+        help = "Tag of the event, can be repeated, its defaults in the config apply"
+    )]
+    pub tag: Vec<String>,
+}
+
+#[derive(Parser)]
+pub struct EditArgs {
+    pub event_id: Option<String>,
+    #[arg(
+        short,
+        long,
+        help = "The calendar to edit the event from (default: personal)"
+    )]
+    calendar: Option<String>,
+    #[arg(short, long, help = "Name of the event")]
+    name: Option<String>,
+    #[arg(short, long, help = "New event start time")]
+    at: Option<String>,
+    #[arg(short, long, help = "New event end time, or +DURATION after the start")]
+    to: Option<String>,
+    #[arg(
+        long = "for",
+        value_name = "DURATION",
+        help = "New event duration, instead of an end time (eg. 90m 2h30m)"
+    )]
+    duration: Option<String>,
+    #[arg(
+        long,
+        allow_hyphen_values = true,
+        help = "Move the event by a duration, later or earlier (eg. +1h -30m 1d)"
+    )]
+    shift: Option<String>,
+    #[arg(short, long, help = "New event location")]
+    loc: Option<String>,
+    #[arg(short, long, help = "New event description")]
+    desc: Option<String>,
+    #[arg(short, long, help = "Save the changes without confirmation")]
+    yes: bool,
+    #[arg(
+        short,
+        long,
+        help = "Edit the events whose name contains this text, instead of one event"
+    )]
+    matching: Option<String>,
+    #[arg(
+        long,
+        help = "First day of the events edited with --matching (default: today)"
+    )]
+    from: Option<String>,
+    #[arg(
+        long,
+        help = "Last day of the events edited with --matching (default: no limit)"
+    )]
+    until: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct MoveArgs {
+    pub event_id: String,
+    #[arg(
+        short,
+        long,
+        help = "The calendar to move the event from (default: personal)"
+    )]
+    calendar: Option<String>,
+    #[arg(
+        short,
+        long,
+        value_name = "CALENDAR",
+        help = "The calendar to move the event to"
+    )]
+    to: String,
+    #[arg(long, help = "Create the calendar if it does not exist")]
+    create: bool,
+}
+
+#[derive(Parser)]
+pub struct DeleteArgs {
+    pub event_id: Option<String>,
+    #[arg(short, long, help = "Specify the calendar")]
+    calendar: Option<String>,
+    #[arg(short, long, help = "Delete without confirmation")]
+    force: bool,
+    #[arg(
+        short,
+        long,
+        help = "Delete the events whose name contains this text, instead of one event"
+    )]
+    matching: Option<String>,
+    #[arg(
+        long,
+        help = "First day of the events deleted with --matching (default: today)"
+    )]
+    from: Option<String>,
+    #[arg(
+        long,
+        help = "Last day of the events deleted with --matching (default: no limit)"
+    )]
+    to: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct ShowArgs {
+    pub event_id: String,
+    #[arg(short, long, help = "Specify the calendar to show from")]
+    calendar: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct OpenArgs {
+    pub event_id: String,
+    #[arg(
+        short,
+        long,
+        help = "Specify the calendar of the event (default: personal)"
+    )]
+    calendar: Option<String>,
+    #[arg(long, help = "Print what would be opened")]
+    dry_run: bool,
+}
+
+#[derive(Parser)]
+pub struct AttendArgs {
+    pub event_id: String,
+    #[arg(
+        short,
+        long,
+        help = "Specify the calendar of the event (default: personal)"
+    )]
+    calendar: Option<String>,
+    #[arg(
+        long,
+        help = "Day of the occurrence (default: the last one that started)"
+    )]
+    on: Option<String>,
+    #[arg(long, help = "Record that you did not attend")]
+    absent: bool,
+}
+
+#[derive(Parser)]
+pub struct StatsArgs {
+    #[arg(short, long, help = "Specify the calendar to use (default: all)")]
+    calendar: Option<String>,
+    #[arg(
+        short,
+        long,
+        help = "First day (default: the first recorded occurrence)"
+    )]
+    from: Option<String>,
+    #[arg(short, long, help = "Last day (default: today)")]
+    to: Option<String>,
+    #[arg(
+        long,
+        help = "A named range of days of the config, instead of --from and --to"
+    )]
+    range: Option<String>,
+    #[arg(
+        long,
+        help = "Print the share of the work hours of each week taken by events instead"
+    )]
+    utilization: bool,
+}
+
+#[derive(Parser)]
+pub struct ViewArgs {
+    #[arg(help = "Specify the date for which the calendar will be run")]
+    pub date: Option<String>,
+    #[arg(
+        short,
+        long,
+        default_value = "month",
+        help = "View mode: day, week, month, year"
+    )]
+    mode: String,
+    #[arg(short, long, help = "Specify the calendar to view")]
+    calendar: Option<String>,
+    #[arg(short, long, help = "Show n times")]
+    number: Option<u32>,
+    #[arg(short, long, help = "Display backend (default: text)")]
+    backend: Option<String>,
+    #[arg(long, help = "Highlight this date in the month grid")]
+    focus: Option<String>,
+    #[arg(short, long, help = "Draw the week view as a grid of hours and days")]
+    grid: bool,
+    #[arg(long, help = "Draw the day view as a column of hours")]
+    timeline: bool,
+    #[arg(
+        long,
+        help = "Color the days of the year view by their number of events"
+    )]
+    heatmap: bool,
+    #[arg(
+        short,
+        long,
+        help = "Draw the view again when the calendars change, and each minute"
+    )]
+    watch: bool,
+}
+
+#[derive(Parser)]
+pub struct SyncArgs {
+    #[arg(help = "status to show the last sync of each calendar instead")]
+    mode: Option<String>,
+    #[arg(long, help = "Specify the calendar to sync")]
+    calendar: Option<String>,
+    #[arg(long, help = "Specify the vdirsyncer pair to sync")]
+    pair: Option<String>,
+    #[arg(long, help = "Sync backend: vdirsyncer, graph (default: vdirsyncer)")]
+    backend: Option<String>,
+    #[arg(long, help = "Print what would be synced without syncing")]
+    dry_run: bool,
+}
+
+#[derive(Parser)]
+pub struct MigrateArgs {}
+
+#[derive(Parser)]
+pub struct SetupArgs {}
+
+#[derive(Parser)]
+pub struct ImportArgs {
+    #[arg(
+        help = "File to import, or - to read from stdin. With --from, the khal config or calcurse directory"
+    )]
+    pub file: Option<String>,
+    #[arg(long, help = "Import the calendars of another tool: khal, calcurse")]
+    from: Option<String>,
+    #[arg(
+        long,
+        help = "FILE is a Google Takeout or Outlook zip archive of ICS files"
+    )]
+    takeout: bool,
+    #[arg(
+        long,
+        help = "FILE is an email, its calendar attachments are imported (eg. an invitation)"
+    )]
+    from_email: bool,
+    #[arg(
+        short,
+        long,
+        help = "The calendar to import the events to (default: personal)"
+    )]
+    calendar: Option<String>,
+    #[arg(short, long, help = "Format of the file: ics, csv (default: ics)")]
+    format: Option<String>,
+    #[arg(
+        short,
+        long,
+        help = "Read a field from a CSV column (eg. name=Title start-date=\"Begin Date\")"
+    )]
+    map: Vec<String>,
+    #[arg(
+        long,
+        help = "What to do with events whose UID exists: skip, overwrite, new-uid, ask (default: skip)"
+    )]
+    on_conflict: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct ExportArgs {
+    #[arg(short, long, help = "Specify the calendar to export (default: all)")]
+    calendar: Option<String>,
+    #[arg(short, long, help = "Export events from this date (default: all)")]
+    from: Option<String>,
+    #[arg(short, long, help = "Export events until this date (default: all)")]
+    to: Option<String>,
+    #[arg(
+        long,
+        help = "A named range of days of the config, instead of --from and --to"
+    )]
+    range: Option<String>,
+    #[arg(long, help = "Output format: ics, html (default: ics)")]
+    format: Option<String>,
+    #[arg(
+        short,
+        long,
+        help = "View of the HTML page: month, agenda (default: month)"
+    )]
+    mode: Option<String>,
+    #[arg(
+        short,
+        long,
+        help = "Number of months, or of days of the agenda, of the HTML page (default: 1 month, 7 days)"
+    )]
+    number: Option<u32>,
+}
+
+#[derive(Parser)]
+pub struct DigestArgs {
+    #[arg(help = "Specify the day of the agenda (default: today)")]
+    pub date: Option<String>,
+    #[arg(long, help = "Use tomorrow's agenda")]
+    tomorrow: bool,
+    #[arg(short, long, help = "Specify the calendar to use (default: all)")]
+    calendar: Option<String>,
+    #[arg(short, long, help = "Output format: text, email (default: text)")]
+    format: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct AgendaArgs {
+    #[arg(help = "Number of days to show, from today (default: 7)")]
+    days: Option<u32>,
+    #[arg(short, long, help = "Specify the calendar to use (default: all)")]
+    calendar: Option<String>,
+    #[arg(
+        short,
+        long,
+        help = "Print the agenda again when the calendars change, and each minute"
+    )]
+    watch: bool,
+}
+
+#[derive(Parser)]
+pub struct FreeArgs {
+    #[arg(short, long, help = "Specify the calendar to use (default: all)")]
+    calendar: Option<String>,
+    #[arg(
+        short,
+        long,
+        help = "Length of the slots (eg. 1h, 30m, 1h30m, default: 1h)"
+    )]
+    duration: Option<String>,
+    #[arg(
+        short,
+        long,
+        help = "Hours to look in (eg. 9:00-18:00, default: the work hours)"
+    )]
+    between: Option<String>,
+    #[arg(short, long, help = "First day (default: today)")]
+    from: Option<String>,
+    #[arg(short, long, help = "Last day (default: 6 days after the first)")]
+    to: Option<String>,
+    #[arg(
+        long,
+        help = "A named range of days of the config, instead of --from and --to"
+    )]
+    range: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct FreeEveningsArgs {
+    #[arg(short, long, help = "Specify the calendar to use (default: all)")]
+    calendar: Option<String>,
+    #[arg(
+        short,
+        long,
+        help = "Number of weeks to look in, from today (default: 2)"
+    )]
+    weeks: Option<u32>,
+    #[arg(
+        short,
+        long,
+        help = "Time the evening starts at (eg. 19:00, default: the evening of the config)"
+    )]
+    after: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct ScheduleArgs {
+    #[arg(
+        short,
+        long,
+        num_args = 1..,
+        required = true,
+        help = "ICS files of the attendees, with their events or a VFREEBUSY"
+    )]
+    with: Vec<String>,
+    #[arg(
+        short,
+        long,
+        help = "Length of the meeting (eg. 1h, 30m, 1h30m, default: 1h)"
+    )]
+    duration: Option<String>,
+    #[arg(
+        short,
+        long,
+        help = "Hours to look in (eg. 9:00-18:00, default: the work hours)"
+    )]
+    between: Option<String>,
+    #[arg(short, long, help = "First day (default: today)")]
+    from: Option<String>,
+    #[arg(short, long, help = "Last day (default: 6 days after the first)")]
+    to: Option<String>,
+    #[arg(
+        long,
+        help = "A named range of days of the config, instead of --from and --to"
+    )]
+    range: Option<String>,
+    #[arg(
+        short,
+        long,
+        help = "Add an event with this name at the first free time"
+    )]
+    name: Option<String>,
+    #[arg(
+        short,
+        long,
+        help = "The calendar to add the event to (default: personal)"
+    )]
+    calendar: Option<String>,
+    #[arg(long, help = "Write an invitation to the event to this file")]
+    invite: Option<String>,
+    #[arg(long, help = "Send the invitation to the attendees with sendmail")]
+    send: bool,
+    #[arg(
+        short,
+        long,
+        help = "Email address of an attendee invited to the event"
+    )]
+    attendee: Vec<String>,
+}
+
+#[derive(Parser)]
+pub struct RsvpArgs {
+    #[arg(help = "ICS file of the invitation, - for stdin")]
+    file: String,
+    #[arg(long, help = "Accept the invitation")]
+    accept: bool,
+    #[arg(long, help = "Decline the invitation")]
+    decline: bool,
+    #[arg(long, help = "Accept the invitation tentatively")]
+    tentative: bool,
+    #[arg(
+        short,
+        long,
+        help = "The calendar to add the event to (default: personal)"
+    )]
+    calendar: Option<String>,
+    #[arg(
+        long = "as",
+        value_name = "ADDRESS",
+        help = "Your address among the attendees (default: the one of [invite] from)"
+    )]
+    address: Option<String>,
+    #[arg(long, help = "Write the reply to the organizer to this file")]
+    reply: Option<String>,
+    #[arg(long, help = "Send the reply to the organizer with sendmail")]
+    send: bool,
+}
+
+#[derive(Parser)]
+pub struct FreeBusyArgs {
+    #[arg(short, long, help = "Specify the calendar to use (default: all)")]
+    calendar: Option<String>,
+    #[arg(short, long, help = "First day (default: today)")]
+    from: Option<String>,
+    #[arg(short, long, help = "Last day (default: 6 days after the first)")]
+    to: Option<String>,
+    #[arg(
+        long,
+        help = "A named range of days of the config, instead of --from and --to"
+    )]
+    range: Option<String>,
+    #[arg(long, help = "Print a VFREEBUSY calendar to send to others")]
+    ics: bool,
+}
+
+#[derive(Parser)]
+pub struct StatusArgs {
+    #[arg(short, long, help = "Specify the calendar to use (default: all)")]
+    calendar: Option<String>,
+    #[arg(
+        short,
+        long,
+        help = "Output format: text, tmux, waybar, polybar (default: text)"
+    )]
+    format: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct MenuArgs {
+    #[arg(help = "A line chosen from the menu, or - to read it from stdin")]
+    selection: Option<String>,
+    #[arg(
+        short,
+        long,
+        value_name = "ACTION",
+        help = "Act on the chosen event: show, edit, delete"
+    )]
+    pick: Option<String>,
+    #[arg(short, long, help = "Specify the calendar to use (default: all)")]
+    calendar: Option<String>,
+    #[arg(short, long, help = "Number of days of events to list (default: 7)")]
+    days: Option<u32>,
+    #[arg(
+        last = true,
+        value_name = "CHANGES",
+        help = "The options of edit giving the changes of --pick edit"
+    )]
+    changes: Vec<String>,
+}
+
+#[derive(Parser)]
+pub struct DaemonArgs {}
+
+#[derive(Parser)]
+pub struct ClientArgs {
+    #[arg(
+        required = true,
+        trailing_var_arg = true,
+        allow_hyphen_values = true,
+        help = "The query: next, agenda or status, with the options of the command"
+    )]
+    query: Vec<String>,
+}
+
+#[derive(Parser)]
+pub struct ServeArgs {
+    #[arg(short, long, help = "Port to listen on (default: 8080)")]
+    port: Option<u16>,
+    #[arg(
+        short,
+        long,
+        help = "Address to listen on, 0.0.0.0 for every network (default: 127.0.0.1)"
+    )]
+    address: Option<String>,
+    #[arg(
+        long,
+        value_name = "ORIGIN",
+        help = "Let the pages of this origin read the events from a browser, or * for any"
+    )]
+    allow_origin: Option<String>,
+}
+
+// The words of a query of calendar client, parsed by the client to check
+// them and by the daemon to answer them
+#[derive(Parser)]
+#[command(name = "calendar client", no_binary_name = true)]
+pub enum ClientQuery {
+    #[command(about = "The next event, like calendar next")]
+    Next(NextArgs),
+    #[command(about = "The agenda of the next days, like calendar agenda")]
+    Agenda(AgendaArgs),
+    #[command(about = "The current or next event, like calendar status")]
+    Status(StatusArgs),
+}
+
+#[derive(Parser)]
+pub struct NowArgs {
+    #[arg(short, long, help = "Specify the calendar to use (default: all)")]
+    calendar: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct NextArgs {
+    #[arg(short, long, help = "Specify the calendar to use (default: all)")]
+    calendar: Option<String>,
+    #[arg(
+        short,
+        long,
+        help = "Only an event starting within this time (eg. 4h, 30m)"
+    )]
+    within: Option<String>,
+    #[arg(
+        short,
+        long,
+        help = "Template or name of a format of the config, like list --format"
+    )]
+    format: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct MotdArgs {
+    #[arg(short, long, help = "Specify the calendar to use (default: all)")]
+    calendar: Option<String>,
+    #[arg(short, long, help = "Maximum number of events shown (default: 5)")]
+    max: Option<usize>,
+    #[arg(short, long, help = "Width of the box in columns (default: 60)")]
+    width: Option<usize>,
+}
+
+// Helper functions
+
+fn parse_date(date_str: &str) -> Result<NaiveDate> {
+    Ok(CalendarDate::parse(date_str)?.inner())
+}
+
+fn parse_past_date(date_str: &str) -> Result<NaiveDate> {
+    Ok(CalendarDate::parse_past(date_str)?.inner())
+}
+
+fn parse_datetime(datetime_str: &str) -> Result<NaiveDateTime> {
+    Ok(CalendarDateTime::parse(datetime_str)?.inner())
+}
+
+// An end time given as a duration after the start, like +2h or +45m
+fn relative_end(to: &str) -> Result<Option<Duration>> {
+    to.strip_prefix('+').map(date::parse_duration).transpose()
+}
+
+// Email addresses of attendees, needed by invitations
+fn validate_attendees(attendees: Vec<String>, invite: bool) -> Result<Vec<String>> {
+    if invite && attendees.is_empty() {
+        return Err(anyhow!("Invitations need at least one --attendee"));
+    }
+    if let Some(attendee) = attendees.iter().find(|a| !a.contains('@')) {
+        return Err(anyhow!("Invalid email address '{}'", attendee));
+    }
+    Ok(attendees)
+}
+
+// The first and last day of a named range of the config given with --range,
+// as "S1 = 2024-09-02..2025-01-17"
+fn named_range(
+    range: Option<String>,
+    from: &Option<String>,
+    to: &Option<String>,
+) -> Result<Option<(NaiveDate, NaiveDate)>> {
+    let Some(name) = range else {
+        return Ok(None);
+    };
+    if from.is_some() || to.is_some() {
+        return Err(anyhow!("Cannot use 'range' with 'from' or 'to'"));
+    }
+
+    let ranges = config::load()?.ranges;
+    let range = ranges.get(&name).ok_or_else(|| {
+        let mut names: Vec<&str> = ranges.keys().map(String::as_str).collect();
+        names.sort();
+        match names.is_empty() {
+            true => anyhow!("Unknown range '{}', none are defined in the config", name),
+            false => anyhow!(
+                "Unknown range '{}', expected one of {}",
+                name,
+                names.join(", ")
+            ),
+        }
+    })?;
+    let (first, last) = range
+        .split_once("..")
+        .ok_or_else(|| anyhow!("Invalid range '{}', expected FIRST..LAST", name))?;
+    let first = parse_date(first.trim()).with_context(|| format!("Invalid range '{}'", name))?;
+    let last = parse_date(last.trim()).with_context(|| format!("Invalid range '{}'", name))?;
+    if last < first {
+        return Err(anyhow!(
+            "Invalid range '{}', it ends before it starts",
+            name
+        ));
+    }
+    Ok(Some((first, last)))
+}
+
+// The duration and the calendar of the first tags having them in the config,
+// as "[tag.gym] duration = "1h30" calendar = "personal""
+fn tag_defaults(tags: &[String]) -> Result<(Option<Duration>, Option<String>)> {
+    if tags.is_empty() {
+        return Ok((None, None));
+    }
+
+    let config = config::load()?.tags;
+    let defaults: Vec<_> = tags
+        .iter()
+        .filter_map(|tag| config.get(tag).map(|defaults| (tag, defaults)))
+        .collect();
+    let duration = defaults
+        .iter()
+        .find_map(|(tag, defaults)| defaults.duration.as_deref().map(|d| (tag, d)))
+        .map(|(tag, d)| {
+            date::parse_duration(d).with_context(|| format!("Invalid duration of tag '{}'", tag))
+        })
+        .transpose()?;
+    let calendar = defaults
+        .iter()
+        .find_map(|(_, defaults)| defaults.calendar.clone());
+    Ok((duration, calendar))
+}
+
+// The duration of the events added to a calendar without an end, from the
+// config of the calendar, else the global one, else an hour
+fn default_duration(calendar: &str) -> Result<Duration> {
+    let config = config::load()?;
+    let (duration, source) = match config
+        .calendars
+        .get(calendar)
+        .and_then(|c| c.default_duration.as_deref())
+    {
+        Some(duration) => (Some(duration), format!("calendar '{}'", calendar)),
+        None => (config.default_duration.as_deref(), "config".to_string()),
+    };
+    match duration {
+        Some(d) => date::parse_duration(d)
+            .with_context(|| format!("Invalid default_duration of {}", source)),
+        None => Ok(Duration::hours(1)),
+    }
+}
+
+// The end of an all-day event is the day after its last day
+fn all_day_range(
+    day: NaiveDate,
+    to: Option<String>,
+    duration: Option<Duration>,
+) -> Result<(NaiveDateTime, NaiveDateTime)> {
+    let duration = to
+        .as_deref()
+        .map(relative_end)
+        .transpose()?
+        .flatten()
+        .or(duration);
+    let last_day = match duration {
+        Some(duration) => {
+            later(day.and_time(NaiveTime::MIN), whole_days(duration)?)?.date() - Duration::days(1)
+        }
+        None => to.map(|t| parse_date(&t)).transpose()?.unwrap_or(day),
+    };
+    let end = last_day
+        .succ_opt()
+        .ok_or_else(|| anyhow!("Date is out of the supported range"))?;
+    Ok((day.and_time(NaiveTime::MIN), end.and_time(NaiveTime::MIN)))
+}
+
+// A time some duration after another, refused past the dates supported
+// rather than overflowing
+fn later(time: NaiveDateTime, duration: Duration) -> Result<NaiveDateTime> {
+    time.checked_add_signed(duration)
+        .ok_or_else(|| anyhow!("Date is out of the supported range"))
+}
+
+// All-day events last whole days
+pub fn whole_days(duration: Duration) -> Result<Duration> {
+    match duration.num_seconds() % Duration::days(1).num_seconds() {
+        0 => Ok(duration),
+        _ => Err(anyhow!("All-day events last whole days (eg. 1d, 3d)")),
+    }
+}
+
+impl ListArgs {
+    pub fn validate(self, json: bool) -> Result<CalendarListArgs> {
+        let query: Option<String> = Some(self.query.join(" "))
+            .filter(|s| !s.trim().is_empty())
             .map(|s| s.trim().to_owned());
+        let query = match (query, self.regex) {
+            (Some(query), true) => Some(ListQuery::Regex(
+                Regex::new(&query).with_context(|| format!("Invalid regex '{}'", query))?,
+            )),
+            (Some(query), false) => Some(ListQuery::Fuzzy(query)),
+            (None, true) => return Err(anyhow!("Cannot use 'regex' without a query")),
+            (None, false) => None,
+        };
+        let fields = match self.fields {
+            Some(_) if query.is_none() => return Err(anyhow!("Cannot use 'in' without a query")),
+            Some(fields) => fields
+                .split(',')
+                .map(|field| SearchField::from_str(field.trim()))
+                .collect::<Result<Vec<_>>>()?,
+            None => vec![SearchField::Name],
+        };
+
+        let now = chrono::Local::now().naive_local().date();
+        let range = named_range(self.range, &self.from, &self.to)?;
+        let from = self
+            .from
+            .map(|d| parse_date(&d))
+            .transpose()?
+            .or(range.map(|(from, _)| from))
+            .unwrap_or(now);
+        let to = self
+            .to
+            .map(|d| parse_date(&d))
+            .transpose()?
+            .or(range.map(|(_, to)| to))
+            .unwrap_or(now + Duration::days(30));
+
+        if to < from {
+            return Err(anyhow!("'to' date must be after 'from' date"));
+        }
+
+        let output = match self.output {
+            Some(output) => {
+                let output = OutputFormat::from_str(&output)?;
+                if json && output != OutputFormat::Json {
+                    return Err(anyhow!("Cannot use --json with another --output"));
+                }
+                output
+            }
+            None => OutputFormat::from_json_flag(json),
+        };
+
+        if self.count && self.exists {
+            return Err(anyhow!("Cannot use both 'count' and 'exists'"));
+        }
+        if (self.count || self.exists)
+            && (output != OutputFormat::Text
+                || self.format.is_some()
+                || self.id
+                || self.backend.is_some())
+        {
+            return Err(anyhow!(
+                "Cannot use 'count' or 'exists' with 'output', 'format', 'id' or 'backend'"
+            ));
+        }
+
+        Ok(CalendarListArgs {
+            query,
+            fields,
+            from,
+            to,
+            limit: self.limit,
+            id: self.id,
+            calendar: self.calendar,
+            format: self.format,
+            output,
+            backend: self.backend,
+            count: self.count,
+            exists: self.exists,
+        })
+    }
+}
+
+impl AddArgs {
+    pub fn validate(self) -> Result<CalendarAddArgs> {
+        let name = self.name.join(" ");
+
+        if name.trim().is_empty() {
+            return Err(anyhow!("Name cannot be empty"));
+        }
+
+        let tags: Vec<String> = self.tag.iter().map(|t| t.trim().to_string()).collect();
+        if tags.iter().any(|t| t.is_empty()) {
+            return Err(anyhow!("Tag cannot be empty"));
+        }
+        let (tag_duration, tag_calendar) = tag_defaults(&tags)?;
+        let calendar = self
+            .calendar
+            .or(tag_calendar)
+            .unwrap_or_else(|| "personal".to_string());
+
+        if self.to.is_some() && self.duration.is_some() {
+            return Err(anyhow!("Cannot use both 'to' and 'for'"));
+        }
+        let duration = self
+            .duration
+            .map(|d| date::parse_duration(&d))
+            .transpose()?;
+
+        let all_day = self.all_day || self.on.is_some();
+        let (start, end) = match (self.at, self.on) {
+            (Some(_), Some(_)) => return Err(anyhow!("Cannot use both 'at' and 'on'")),
+            (None, None) => return Err(anyhow!("Either 'at' or 'on' must be specified")),
+            (Some(at), None) if !all_day => {
+                let start = parse_datetime(&at)?;
+                let end = match self.to {
+                    Some(to) => match relative_end(&to)? {
+                        Some(relative) => later(start, relative)?,
+                        None => parse_datetime(&to)?,
+                    },
+                    None => match duration.or(tag_duration) {
+                        Some(duration) => later(start, duration)?,
+                        None => later(start, default_duration(&calendar)?)?,
+                    },
+                };
+                (start, end)
+            }
+            (Some(at), None) => {
+                // All-day events may be given a time, only the date is kept
+                let day = parse_datetime(&at)
+                    .map(|start| start.date())
+                    .or_else(|_| parse_date(&at))?;
+                all_day_range(day, self.to, duration)?
+            }
+            (None, Some(on)) => all_day_range(parse_date(&on)?, self.to, duration)?,
+        };
+
+        if end < start {
+            return Err(anyhow!("End time must be after start time"));
+        }
+
+        let repeat = self
+            .repeat
+            .map(|r| RepeatFrequency::from_str(&r))
+            .transpose()?;
+        let until = self.until.map(|u| parse_date(&u)).transpose()?;
+
+        if self.every == Some(0) {
+            return Err(anyhow!("'every' must be at least 1"));
+        }
+
+        let every = if repeat.is_some() {
+            Some(self.every.unwrap_or(1))
+        } else {
+            None
+        };
+
+        if repeat.is_none() {
+            if self.every.is_some() {
+                return Err(anyhow!("'repeat' must be specified when using 'every'"));
+            }
+            if until.is_some() {
+                return Err(anyhow!("'repeat' must be specified when using 'until'"));
+            }
+        }
+
+        Ok(CalendarAddArgs {
+            calendar,
+            name,
+            start,
+            end,
+            loc: self.loc,
+            desc: self.desc,
+            repeat,
+            every,
+            until,
+            all_day,
+            create: self.create,
+            force: self.force,
+            attendees: validate_attendees(self.attendee, self.invite.is_some() || self.send)?,
+            invite: self.invite,
+            send: self.send,
+            tags,
+        })
+    }
+}
+
+impl ProtectArgs {
+    pub fn validate(self) -> Result<CalendarProtectArgs> {
+        let calendar = self.calendar.unwrap_or_else(|| "personal".to_string());
+
+        if self.name.trim().is_empty() {
+            return Err(anyhow!("Name cannot be empty"));
+        }
+
+        let (start, end) = date::parse_hours(&self.hours)?;
+        let days = date::parse_days(self.days.as_deref().unwrap_or("weekdays"))?;
+
+        let from = self
+            .from
+            .map(|d| parse_date(&d))
+            .transpose()?
+            .unwrap_or_else(|| chrono::Local::now().naive_local().date());
+        let until = self.until.map(|u| parse_date(&u)).transpose()?;
+        if until.is_some_and(|until| until < from) {
+            return Err(anyhow!("'until' date must be after 'from' date"));
+        }
+
+        Ok(CalendarProtectArgs {
+            calendar,
+            name: self.name,
+            start,
+            end,
+            days,
+            from,
+            until,
+            create: self.create,
+        })
+    }
+}
+
+// An event id, or a text to match with the range of days to look in
+fn event_selection(
+    event_id: Option<String>,
+    matching: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    to_name: &str,
+) -> Result<EventSelection> {
+    match (event_id, matching) {
+        (Some(_), Some(_)) => Err(anyhow!("Cannot use both an event id and 'matching'")),
+        (None, None) => Err(anyhow!(
+            "Either an event id or 'matching' must be specified"
+        )),
+        (Some(_), None) if from.is_some() || to.is_some() => Err(anyhow!(
+            "Cannot use 'from' or '{}' without 'matching'",
+            to_name
+        )),
+        (Some(id), None) => Ok(EventSelection::Id(id)),
+        (None, Some(query)) => {
+            if query.trim().is_empty() {
+                return Err(anyhow!("'matching' cannot be empty"));
+            }
+            let from = from
+                .map(|d| parse_date(&d))
+                .transpose()?
+                .unwrap_or_else(|| chrono::Local::now().naive_local().date());
+            let to = to.map(|d| parse_date(&d)).transpose()?;
+            if to.is_some_and(|to| to < from) {
+                return Err(anyhow!("'{}' date must be after 'from' date", to_name));
+            }
+            Ok(EventSelection::Matching {
+                query: query.trim().to_string(),
+                from,
+                to,
+            })
+        }
+    }
+}
+
+// A duration to move events by, later or earlier with a leading -
+fn parse_shift(shift: &str) -> Result<Duration> {
+    match shift.strip_prefix('-') {
+        Some(earlier) => Ok(-date::parse_duration(earlier)?),
+        None => date::parse_duration(shift.strip_prefix('+').unwrap_or(shift)),
+    }
+}
+
+impl EditArgs {
+    pub fn validate(self) -> Result<CalendarEditArgs> {
+        let calendar = self.calendar.unwrap_or_else(|| "personal".to_string());
+
+        let selection =
+            event_selection(self.event_id, self.matching, self.from, self.until, "until")?;
+        if matches!(selection, EventSelection::Matching { .. })
+            && (self.at.is_some() || self.to.as_deref().is_some_and(|to| !to.starts_with('+')))
+        {
+            return Err(anyhow!(
+                "Cannot use 'at' or 'to' with 'matching', use 'shift' or 'for'"
+            ));
+        }
+        if self.shift.is_some() && (self.at.is_some() || self.to.is_some()) {
+            return Err(anyhow!("Cannot use 'shift' with 'at' or 'to'"));
+        }
+        let shift = self.shift.map(|s| parse_shift(&s)).transpose()?;
+        let start = self.at.map(|w| parse_datetime(&w)).transpose()?;
+        if self.to.is_some() && self.duration.is_some() {
+            return Err(anyhow!("Cannot use both 'to' and 'for'"));
+        }
+        // A relative end is a duration after the start, the new or current one
+        let relative = self.to.as_deref().map(relative_end).transpose()?.flatten();
+        let end = match relative {
+            Some(_) => None,
+            None => self.to.map(|t| parse_datetime(&t)).transpose()?,
+        };
+        let duration = self
+            .duration
+            .map(|d| date::parse_duration(&d))
+            .transpose()?
+            .or(relative);
+
+        if let (Some(start), Some(end)) = (start, end) {
+            if end < start {
+                return Err(anyhow!("End time must be after start time"));
+            }
+        }
+
+        Ok(CalendarEditArgs {
+            selection,
+            calendar,
+            name: self.name,
+            start,
+            end,
+            shift,
+            duration,
+            loc: self.loc,
+            desc: self.desc,
+            yes: self.yes,
+        })
+    }
+}
+
+impl MoveArgs {
+    pub fn validate(self) -> Result<CalendarMoveArgs> {
+        let calendar = self.calendar.unwrap_or_else(|| "personal".to_string());
+        if self.to == calendar {
+            return Err(anyhow!("The event is already in '{}'", calendar));
+        }
+        Ok(CalendarMoveArgs {
+            event_id: self.event_id,
+            calendar,
+            target: self.to,
+            create: self.create,
+        })
+    }
+}
+
+impl DeleteArgs {
+    pub fn validate(self) -> Result<CalendarDeleteArgs> {
+        let calendar = self.calendar.unwrap_or_else(|| "personal".to_string());
+        let selection = event_selection(self.event_id, self.matching, self.from, self.to, "to")?;
+        Ok(CalendarDeleteArgs {
+            selection,
+            calendar,
+            force: self.force,
+        })
+    }
+}
+
+#[derive(Parser)]
+pub struct TodoArgs {
+    #[command(subcommand)]
+    pub command: TodoCommands,
+}
+
+#[derive(Parser)]
+pub struct TrashArgs {
+    #[command(subcommand)]
+    pub command: TrashCommands,
+}
+
+#[derive(Parser)]
+pub struct RoomsArgs {
+    #[command(subcommand)]
+    pub command: RoomsCommands,
+}
+
+#[derive(Parser)]
+pub struct RoomsFreeArgs {
+    #[arg(
+        short,
+        long,
+        required = true,
+        help = "Start of the slot (eg. tom@14 14-jul@12:30)"
+    )]
+    at: String,
+    #[arg(
+        short,
+        long = "for",
+        help = "Length of the slot (eg. 1h, 30m, 1h30m, default: 1h)"
+    )]
+    duration: Option<String>,
+    #[arg(short, long, help = "The calendar of the rooms (default: rooms)")]
+    calendar: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct TodoAddArgs {
+    #[arg(required = true, help = "Name of the todo")]
+    pub name: Vec<String>,
+    #[arg(
+        long,
+        help = "Due date, with an optional time (eg. fri tom@18 2024/08/06)"
+    )]
+    pub due: Option<String>,
+    #[arg(
+        short,
+        long,
+        help = "The calendar to add the todo to (default: personal)"
+    )]
+    pub calendar: Option<String>,
+    #[arg(short, long, help = "Todo description")]
+    pub desc: Option<String>,
+    #[arg(long, help = "Create the calendar if it does not exist")]
+    pub create: bool,
+}
+
+#[derive(Parser)]
+pub struct TodoListArgs {
+    #[arg(short, long, help = "Specify the calendar to list (default: all)")]
+    calendar: Option<String>,
+    #[arg(short, long, help = "Also show the todos that are done")]
+    all: bool,
+    #[arg(short, long, help = "Show the uuid of the todos to mark them as done")]
+    id: bool,
+}
+
+#[derive(Parser)]
+pub struct TodoDoneArgs {
+    pub todo_id: String,
+    #[arg(short, long, help = "Specify the calendar (default: personal)")]
+    calendar: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct PurgeArgs {
+    #[arg(
+        long,
+        help = "Age of the events to purge, like 90d or 2y (default: the retention of the config)"
+    )]
+    older_than: Option<String>,
+    #[arg(short, long, help = "Specify the calendar (default: all)")]
+    calendar: Option<String>,
+    #[arg(
+        long,
+        help = "List the events that would be purged, without purging them"
+    )]
+    dry_run: bool,
+    #[arg(short, long, help = "Purge without confirmation")]
+    force: bool,
+}
+
+#[derive(Parser)]
+pub struct DedupeArgs {
+    #[arg(short, long, help = "Specify the calendar (default: all)")]
+    calendar: Option<String>,
+    #[arg(short, long, help = "Remove the duplicates without confirmation")]
+    force: bool,
+}
+
+#[derive(Parser)]
+pub struct DoctorArgs {
+    #[arg(short, long, help = "Specify the calendar to check (default: all)")]
+    calendar: Option<String>,
+    #[arg(long, help = "Repair the problems that are safe to repair")]
+    fix: bool,
+}
+
+#[derive(Parser)]
+pub struct CompletionArgs {
+    #[arg(help = "The shell: bash, zsh or fish")]
+    shell: String,
+}
+
+#[derive(Parser)]
+pub struct CompleteArgs {
+    kind: String,
+    calendar: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct TrashListArgs {
+    #[arg(short, long, help = "Specify the calendar (default: all)")]
+    calendar: Option<String>,
+    #[arg(short, long, help = "Show the uuid of the events to restore them")]
+    id: bool,
+}
+
+#[derive(Parser)]
+pub struct TrashRestoreArgs {
+    #[arg(help = "Id or name of the deleted event")]
+    pub event_id: String,
+    #[arg(short, long, help = "Specify the calendar (default: all)")]
+    calendar: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct TrashEmptyArgs {
+    #[arg(short, long, help = "Specify the calendar (default: all)")]
+    calendar: Option<String>,
+    #[arg(short, long, help = "Empty the trash without confirmation")]
+    force: bool,
+}
+
+#[derive(Parser)]
+pub struct NoteArgs {
+    #[arg(help = "Day of the note (eg. today yes fri 14-jul)")]
+    pub date: String,
+    #[arg(required = true, help = "Text of the note")]
+    pub text: Vec<String>,
+    #[arg(
+        short,
+        long,
+        help = "The calendar to add the note to (default: personal)"
+    )]
+    calendar: Option<String>,
+    #[arg(long, help = "Create the calendar if it does not exist")]
+    create: bool,
+}
+
+#[derive(Parser)]
+pub struct NotesArgs {
+    #[arg(short, long, help = "Specify the calendar to read (default: all)")]
+    calendar: Option<String>,
+    #[arg(
+        short,
+        long,
+        help = "First day, weekdays are the last ones (default: a week ago)"
+    )]
+    from: Option<String>,
+    #[arg(short, long, help = "Last day (default: today)")]
+    to: Option<String>,
+    #[arg(
+        long,
+        help = "A named range of days of the config, instead of --from and --to"
+    )]
+    range: Option<String>,
+}
+
+#[derive(Parser)]
+pub struct SearchArgs {
+    #[arg(required = true, help = "Words to find, all of them must match")]
+    pub query: Vec<String>,
+    #[arg(short, long, help = "Specify the calendar to search (default: all)")]
+    calendar: Option<String>,
+    #[arg(short, long, help = "Start date of the search (default: a year ago)")]
+    from: Option<String>,
+    #[arg(short, long, help = "End date of the search (default: in a year)")]
+    to: Option<String>,
+    #[arg(
+        long,
+        help = "A named range of days of the config, instead of --from and --to"
+    )]
+    range: Option<String>,
+    #[arg(short, long, help = "Search the whole history and future")]
+    all_time: bool,
+    #[arg(short, long, help = "Limit the number of events shown")]
+    limit: Option<usize>,
+    #[arg(short, long, help = "Show the uuid of the events")]
+    id: bool,
+}
+
+#[derive(Parser)]
+pub struct WhenArgs {
+    #[arg(required = true, help = "Words to find in the event")]
+    pub query: Vec<String>,
+    #[arg(short, long, help = "Specify the calendar to search (default: all)")]
+    calendar: Option<String>,
+}
+
+#[cfg(feature = "sqlite")]
+#[derive(Parser)]
+pub struct IndexArgs {
+    #[command(subcommand)]
+    pub command: Option<IndexCommands>,
+}
+
+impl NoteArgs {
+    pub fn validate(self) -> Result<CalendarNoteArgs> {
+        let calendar = self.calendar.unwrap_or_else(|| "personal".to_string());
+        let text = self.text.join(" ");
+
+        if text.trim().is_empty() {
+            return Err(anyhow!("Text cannot be empty"));
+        }
+
+        Ok(CalendarNoteArgs {
+            calendar,
+            date: parse_past_date(&self.date)?,
+            text,
+            create: self.create,
+        })
+    }
+}
+
+impl NotesArgs {
+    pub fn validate(self) -> Result<CalendarNotesArgs> {
+        let today = chrono::Local::now().naive_local().date();
+        let range = named_range(self.range, &self.from, &self.to)?;
+        let from = self
+            .from
+            .map(|d| parse_past_date(&d))
+            .transpose()?
+            .or(range.map(|(from, _)| from))
+            .unwrap_or(today - Duration::weeks(1));
+        let to = self
+            .to
+            .map(|d| parse_past_date(&d))
+            .transpose()?
+            .or(range.map(|(_, to)| to))
+            .unwrap_or(today);
+
+        if to < from {
+            return Err(anyhow!("'to' date must be after 'from' date"));
+        }
+
+        Ok(CalendarNotesArgs {
+            calendar: self.calendar,
+            from,
+            to,
+        })
+    }
+}
+
+impl SearchArgs {
+    pub fn validate(self) -> Result<CalendarSearchArgs> {
+        let words: Vec<String> = self
+            .query
+            .iter()
+            .flat_map(|word| word.split_whitespace())
+            .map(str::to_string)
+            .collect();
+        if words.is_empty() {
+            return Err(anyhow!("Query cannot be empty"));
+        }
+
+        let range = if self.all_time {
+            if self.from.is_some() || self.to.is_some() || self.range.is_some() {
+                return Err(anyhow!(
+                    "Cannot use 'all-time' with 'from', 'to' or 'range'"
+                ));
+            }
+            None
+        } else {
+            let today = chrono::Local::now().naive_local().date();
+            let range = named_range(self.range, &self.from, &self.to)?;
+            let from = self
+                .from
+                .map(|d| parse_past_date(&d))
+                .transpose()?
+                .or(range.map(|(from, _)| from))
+                .unwrap_or(today - Duration::days(365));
+            let to = self
+                .to
+                .map(|d| parse_date(&d))
+                .transpose()?
+                .or(range.map(|(_, to)| to))
+                .unwrap_or(today + Duration::days(365));
+            if to < from {
+                return Err(anyhow!("'to' date must be after 'from' date"));
+            }
+            Some((from, to))
+        };
+
+        Ok(CalendarSearchArgs {
+            words,
+            calendar: self.calendar,
+            range,
+            limit: self.limit,
+            id: self.id,
+        })
+    }
+}
+
+impl WhenArgs {
+    pub fn validate(self) -> Result<CalendarWhenArgs> {
+        let words: Vec<String> = self
+            .query
+            .iter()
+            .flat_map(|word| word.split_whitespace())
+            .map(str::to_string)
+            .collect();
+        if words.is_empty() {
+            return Err(anyhow!("Query cannot be empty"));
+        }
+
+        Ok(CalendarWhenArgs {
+            words,
+            calendar: self.calendar,
+        })
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl IndexArgs {
+    pub fn validate(self) -> Result<CalendarIndexArgs> {
+        Ok(CalendarIndexArgs {
+            rebuild: matches!(self.command, Some(IndexCommands::Rebuild)),
+        })
+    }
+}
+
+impl RoomsFreeArgs {
+    pub fn validate(self) -> Result<CalendarRoomsFreeArgs> {
+        let start = parse_datetime(&self.at)?;
+        let duration = date::parse_duration(self.duration.as_deref().unwrap_or("1h"))?;
+
+        Ok(CalendarRoomsFreeArgs {
+            calendar: self.calendar.unwrap_or_else(|| "rooms".to_string()),
+            start,
+            end: start + duration,
+        })
+    }
+}
+
+impl TodoAddArgs {
+    pub fn validate(self) -> Result<CalendarTodoAddArgs> {
+        let calendar = self.calendar.unwrap_or_else(|| "personal".to_string());
+        let name = self.name.join(" ");
+
+        if name.trim().is_empty() {
+            return Err(anyhow!("Name cannot be empty"));
+        }
+
+        // A due date without a time is stored as a DATE value
+        let (due, all_day) = match self.due {
+            Some(due) if due.contains('@') => (Some(parse_datetime(&due)?), false),
+            Some(due) => (Some(parse_date(&due)?.and_time(NaiveTime::MIN)), true),
+            None => (None, false),
+        };
+
+        Ok(CalendarTodoAddArgs {
+            calendar,
+            name,
+            due,
+            all_day,
+            desc: self.desc,
+            create: self.create,
+        })
+    }
+}
+
+impl TodoListArgs {
+    pub fn validate(self) -> Result<CalendarTodoListArgs> {
+        Ok(CalendarTodoListArgs {
+            calendar: self.calendar,
+            all: self.all,
+            id: self.id,
+        })
+    }
+}
+
+impl PurgeArgs {
+    pub fn validate(self) -> Result<CalendarPurgeArgs> {
+        let today = chrono::Local::now().naive_local().date();
+        let before = self
+            .older_than
+            .map(|age| date::parse_age(&age, today))
+            .transpose()?;
+        Ok(CalendarPurgeArgs {
+            before,
+            calendar: self.calendar,
+            dry_run: self.dry_run,
+            force: self.force,
+        })
+    }
+}
+
+impl DedupeArgs {
+    pub fn validate(self) -> Result<CalendarDedupeArgs> {
+        Ok(CalendarDedupeArgs {
+            calendar: self.calendar,
+            force: self.force,
+        })
+    }
+}
+
+impl DoctorArgs {
+    pub fn validate(self) -> Result<CalendarDoctorArgs> {
+        Ok(CalendarDoctorArgs {
+            calendar: self.calendar,
+            fix: self.fix,
+        })
+    }
+}
+
+impl CompletionArgs {
+    pub fn validate(self) -> Result<CalendarCompletionArgs> {
+        Ok(CalendarCompletionArgs {
+            shell: self.shell.parse()?,
+        })
+    }
+}
+
+impl CompleteArgs {
+    pub fn validate(self) -> Result<CalendarCompleteArgs> {
+        let candidates = match self.kind.as_str() {
+            "calendars" => Candidates::Calendars,
+            // The default calendar of the commands taking an event
+            "events" => Candidates::Events(self.calendar.unwrap_or_else(|| "personal".to_string())),
+            kind => return Err(anyhow!("Unknown completion '{}'", kind)),
+        };
+
+        Ok(CalendarCompleteArgs { candidates })
+    }
+}
+
+impl TrashListArgs {
+    pub fn validate(self) -> Result<CalendarTrashListArgs> {
+        Ok(CalendarTrashListArgs {
+            calendar: self.calendar,
+            id: self.id,
+        })
+    }
+}
+
+impl TrashRestoreArgs {
+    pub fn validate(self) -> Result<CalendarTrashRestoreArgs> {
+        Ok(CalendarTrashRestoreArgs {
+            event_id: self.event_id,
+            calendar: self.calendar,
+        })
+    }
+}
+
+impl TrashEmptyArgs {
+    pub fn validate(self) -> Result<CalendarTrashEmptyArgs> {
+        Ok(CalendarTrashEmptyArgs {
+            calendar: self.calendar,
+            force: self.force,
+        })
+    }
+}
+
+impl TodoDoneArgs {
+    pub fn validate(self) -> Result<CalendarTodoDoneArgs> {
+        Ok(CalendarTodoDoneArgs {
+            todo_id: self.todo_id,
+            calendar: self.calendar.unwrap_or_else(|| "personal".to_string()),
+        })
+    }
+}
+
+impl ViewArgs {
+    pub fn validate(self, json: bool) -> Result<CalendarViewArgs> {
+        let date = self
+            .date
+            .map(|d| parse_date(&d))
+            .transpose()?
+            .unwrap_or_else(|| chrono::Local::now().naive_local().date());
+        let mode = ViewMode::from_str(&self.mode)?;
+        let focus = self.focus.map(|d| parse_date(&d)).transpose()?;
+
+        let number = self.number.unwrap_or(1);
+
+        if number == 0 {
+            return Err(anyhow!("Number must be at least 1"));
+        }
+        if self.grid && !matches!(mode, ViewMode::Week) {
+            return Err(anyhow!("--grid only applies to the week view"));
+        }
+        if self.timeline && !matches!(mode, ViewMode::Day) {
+            return Err(anyhow!("--timeline only applies to the day view"));
+        }
+        if self.heatmap && !matches!(mode, ViewMode::Year) {
+            return Err(anyhow!("--heatmap only applies to the year view"));
+        }
+        if self.watch && json {
+            return Err(anyhow!("Cannot use 'watch' with 'json'"));
+        }
+
+        Ok(CalendarViewArgs {
+            date,
+            mode,
+            calendar: self.calendar,
+            number,
+            output: OutputFormat::from_json_flag(json),
+            backend: self.backend,
+            focus,
+            grid: self.grid,
+            timeline: self.timeline,
+            heatmap: self.heatmap,
+            watch: self.watch,
+        })
+    }
+}
+
+impl ShowArgs {
+    pub fn validate(self, json: bool) -> Result<CalendarShowArgs> {
+        let calendar = self.calendar.unwrap_or_else(|| "personal".to_string());
+        let event_id = self.event_id;
+        Ok(CalendarShowArgs {
+            event_id,
+            calendar,
+            output: OutputFormat::from_json_flag(json),
+        })
+    }
+}
+
+impl OpenArgs {
+    pub fn validate(self) -> Result<CalendarOpenArgs> {
+        Ok(CalendarOpenArgs {
+            event_id: self.event_id,
+            calendar: self.calendar.unwrap_or_else(|| "personal".to_string()),
+            dry_run: self.dry_run,
+        })
+    }
+}
+
+impl AttendArgs {
+    pub fn validate(self) -> Result<CalendarAttendArgs> {
+        Ok(CalendarAttendArgs {
+            event_id: self.event_id,
+            calendar: self.calendar.unwrap_or_else(|| "personal".to_string()),
+            date: self.on.map(|d| parse_past_date(&d)).transpose()?,
+            absent: self.absent,
+        })
+    }
+}
+
+impl StatsArgs {
+    pub fn validate(self) -> Result<CalendarStatsArgs> {
+        let range = named_range(self.range, &self.from, &self.to)?;
+        let from = self
+            .from
+            .map(|d| parse_past_date(&d))
+            .transpose()?
+            .or(range.map(|(from, _)| from));
+        let to = self
+            .to
+            .map(|d| parse_past_date(&d))
+            .transpose()?
+            .or(range.map(|(_, to)| to));
+        if let (Some(from), Some(to)) = (from, to) {
+            if to < from {
+                return Err(anyhow!("The last day is before the first one"));
+            }
+        }
 
-        let now = chrono::Local::now().naive_local().date();
+        Ok(CalendarStatsArgs {
+            calendar: self.calendar,
+            from,
+            to,
+            utilization: self.utilization,
+        })
+    }
+}
+
+impl SyncArgs {
+    pub fn validate(self) -> Result<CalendarSyncArgs> {
+        let backend = self
+            .backend
+            .map(|b| SyncBackend::from_str(&b))
+            .transpose()?
+            .unwrap_or(SyncBackend::Vdirsyncer);
+
+        let status = match self.mode.as_deref() {
+            None => false,
+            Some("status") => true,
+            Some(mode) => return Err(anyhow!("Unknown sync mode '{}', expected status", mode)),
+        };
+
+        if backend == SyncBackend::Graph && (self.calendar.is_some() || self.pair.is_some()) {
+            return Err(anyhow!(
+                "The graph backend syncs the calendar of the config, 'calendar' and 'pair' cannot be used"
+            ));
+        }
+        if self.calendar.is_some() && self.pair.is_some() {
+            return Err(anyhow!("Cannot use both 'calendar' and 'pair'"));
+        }
+
+        Ok(CalendarSyncArgs {
+            calendar: self.calendar,
+            pair: self.pair,
+            backend,
+            dry_run: self.dry_run,
+            status,
+        })
+    }
+}
+
+impl MigrateArgs {
+    pub fn validate(self) -> Result<CalendarMigrateArgs> {
+        Ok(CalendarMigrateArgs {})
+    }
+}
+
+impl SetupArgs {
+    pub fn validate(self) -> Result<CalendarSetupArgs> {
+        Ok(CalendarSetupArgs {})
+    }
+}
+
+impl ImportArgs {
+    pub fn validate(self) -> Result<CalendarImportArgs> {
+        let from = self.from.map(|f| ImportTool::from_str(&f)).transpose()?;
+
+        if from.is_some() && self.format.is_some() {
+            return Err(anyhow!("Cannot use both 'from' and 'format'"));
+        }
+        if from.is_none() && self.file.is_none() {
+            return Err(anyhow!("A file to import must be specified"));
+        }
+        if self.takeout && (from.is_some() || self.format.is_some()) {
+            return Err(anyhow!("Cannot use 'takeout' with 'from' or 'format'"));
+        }
+        if self.from_email && (from.is_some() || self.format.is_some() || self.takeout) {
+            return Err(anyhow!(
+                "Cannot use 'from-email' with 'from', 'format' or 'takeout'"
+            ));
+        }
+
+        let calendar = match self.calendar {
+            Some(calendar) => Some(calendar),
+            None if self.takeout => None,
+            None => Some("personal".to_string()),
+        };
+
+        let format = match self.format {
+            Some(format) => ImportFormat::from_str(&format)?,
+            None if self.from_email => ImportFormat::Email,
+            None => ImportFormat::Ics,
+        };
+
+        if format != ImportFormat::Csv && !self.map.is_empty() {
+            return Err(anyhow!("'map' can only be used with the csv format"));
+        }
+
+        let map = self
+            .map
+            .iter()
+            .map(|mapping| {
+                let (field, column) = mapping.split_once('=').ok_or_else(|| {
+                    anyhow!("Invalid mapping '{}', expected field=column", mapping)
+                })?;
+                Ok((CsvField::from_str(field.trim())?, column.trim().to_string()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let on_conflict = self
+            .on_conflict
+            .map(|c| ConflictStrategy::from_str(&c))
+            .transpose()?
+            .unwrap_or(ConflictStrategy::Skip);
+
+        Ok(CalendarImportArgs {
+            file: self.file,
+            from,
+            takeout: self.takeout,
+            calendar,
+            format,
+            map,
+            on_conflict,
+        })
+    }
+}
+
+impl ExportArgs {
+    pub fn validate(self) -> Result<CalendarExportArgs> {
+        let range = named_range(self.range, &self.from, &self.to)?;
         let from = self
             .from
             .map(|d| parse_date(&d))
             .transpose()?
-            .unwrap_or(now);
+            .or(range.map(|(from, _)| from));
         let to = self
             .to
             .map(|d| parse_date(&d))
             .transpose()?
-            .unwrap_or(now + Duration::days(30));
+            .or(range.map(|(_, to)| to));
+
+        if let (Some(from), Some(to)) = (from, to) {
+            if to < from {
+                return Err(anyhow!("'to' date must be after 'from' date"));
+            }
+        }
+
+        let format = self
+            .format
+            .map(|format| ExportFormat::from_str(&format))
+            .transpose()?
+            .unwrap_or(ExportFormat::Ics);
+        let mode = self
+            .mode
+            .as_deref()
+            .map(HtmlMode::from_str)
+            .transpose()?
+            .unwrap_or(HtmlMode::Month);
+        match format {
+            // The page shows its number of months or days from its first one
+            ExportFormat::Html if to.is_some() => {
+                return Err(anyhow!("Cannot use 'to' or 'range' with 'format html'"))
+            }
+            ExportFormat::Ics if self.mode.is_some() || self.number.is_some() => {
+                return Err(anyhow!(
+                    "Cannot use 'mode' or 'number' without 'format html'"
+                ))
+            }
+            _ => {}
+        }
+        let number = self.number.unwrap_or(match mode {
+            HtmlMode::Month => 1,
+            HtmlMode::Agenda => 7,
+        });
+        if number == 0 {
+            return Err(anyhow!("'number' must be at least 1"));
+        }
+
+        Ok(CalendarExportArgs {
+            calendar: self.calendar,
+            from,
+            to,
+            format,
+            mode,
+            number,
+        })
+    }
+}
+
+impl FreeEveningsArgs {
+    pub fn validate(self) -> Result<CalendarFreeEveningsArgs> {
+        let weeks = self.weeks.unwrap_or(2);
+        if !(1..=52).contains(&weeks) {
+            return Err(anyhow!("Look in 1 to 52 weeks"));
+        }
+
+        Ok(CalendarFreeEveningsArgs {
+            calendar: self.calendar,
+            weeks,
+            after: self
+                .after
+                .map(|after| CalendarTime::parse(&after).map(|time| time.inner()))
+                .transpose()?,
+        })
+    }
+}
+
+impl FreeArgs {
+    pub fn validate(self) -> Result<CalendarFreeArgs> {
+        let duration = date::parse_duration(self.duration.as_deref().unwrap_or("1h"))?;
+        let between = self.between.map(|b| date::parse_hours(&b)).transpose()?;
+
+        let today = chrono::Local::now().naive_local().date();
+        let range = named_range(self.range, &self.from, &self.to)?;
+        let from = self
+            .from
+            .map(|d| parse_date(&d))
+            .transpose()?
+            .or(range.map(|(from, _)| from))
+            .unwrap_or(today);
+        let to = self
+            .to
+            .map(|d| parse_date(&d))
+            .transpose()?
+            .or(range.map(|(_, to)| to))
+            .unwrap_or(from + Duration::days(6));
 
         if to < from {
             return Err(anyhow!("'to' date must be after 'from' date"));
         }
 
-        Ok(CalendarListArgs {
-            query,
+        Ok(CalendarFreeArgs {
+            calendar: self.calendar,
+            duration,
+            between,
             from,
             to,
-            limit: self.limit,
-            id: self.id,
-            calendar: self.calendar,
         })
     }
 }
 
-impl AddArgs {
-    pub fn validate(self) -> Result<CalendarAddArgs> {
-        let calendar = self.calendar.unwrap_or_else(|| "personal".to_string());
-        let name = self.name.join(" ");
+impl ScheduleArgs {
+    pub fn validate(self) -> Result<CalendarScheduleArgs> {
+        let duration = date::parse_duration(self.duration.as_deref().unwrap_or("1h"))?;
+        let between = self.between.map(|b| date::parse_hours(&b)).transpose()?;
 
-        if name.trim().is_empty() {
+        let today = chrono::Local::now().naive_local().date();
+        let range = named_range(self.range, &self.from, &self.to)?;
+        let from = self
+            .from
+            .map(|d| parse_date(&d))
+            .transpose()?
+            .or(range.map(|(from, _)| from))
+            .unwrap_or(today);
+        let to = self
+            .to
+            .map(|d| parse_date(&d))
+            .transpose()?
+            .or(range.map(|(_, to)| to))
+            .unwrap_or(from + Duration::days(6));
+
+        if to < from {
+            return Err(anyhow!("'to' date must be after 'from' date"));
+        }
+
+        let name = self.name.map(|n| n.trim().to_string());
+        if name.as_ref().is_some_and(|n| n.is_empty()) {
             return Err(anyhow!("Name cannot be empty"));
         }
+        if name.is_none()
+            && (self.invite.is_some()
+                || self.send
+                || self.calendar.is_some()
+                || !self.attendee.is_empty())
+        {
+            return Err(anyhow!("The event must be named to be added"));
+        }
+        let attendees = validate_attendees(self.attendee, self.invite.is_some() || self.send)?;
 
-        let start = parse_datetime(&self.at)?;
-        let end = self
+        Ok(CalendarScheduleArgs {
+            with: self.with,
+            duration,
+            between,
+            from,
+            to,
+            name,
+            calendar: self.calendar.unwrap_or_else(|| "personal".to_string()),
+            invite: self.invite,
+            send: self.send,
+            attendees,
+        })
+    }
+}
+
+impl FreeBusyArgs {
+    pub fn validate(self) -> Result<CalendarFreeBusyArgs> {
+        let today = chrono::Local::now().naive_local().date();
+        let range = named_range(self.range, &self.from, &self.to)?;
+        let from = self
+            .from
+            .map(|d| parse_date(&d))
+            .transpose()?
+            .or(range.map(|(from, _)| from))
+            .unwrap_or(today);
+        let to = self
             .to
-            .map(|t| parse_datetime(&t))
+            .map(|d| parse_date(&d))
             .transpose()?
-            .unwrap_or(start + Duration::hours(1));
+            .or(range.map(|(_, to)| to))
+            .unwrap_or(from + Duration::days(6));
 
-        if end < start {
-            return Err(anyhow!("End time must be after start time"));
+        if to < from {
+            return Err(anyhow!("'to' date must be after 'from' date"));
         }
 
-        let repeat = self
-            .repeat
-            .map(|r| RepeatFrequency::from_str(&r))
-            .transpose()?;
-        let until = self.until.map(|u| parse_date(&u)).transpose()?;
+        Ok(CalendarFreeBusyArgs {
+            calendar: self.calendar,
+            from,
+            to,
+            ics: self.ics,
+        })
+    }
+}
 
-        let every = if repeat.is_some() {
-            Some(self.every.unwrap_or(1))
-        } else {
-            None
+impl RsvpArgs {
+    pub fn validate(self) -> Result<CalendarRsvpArgs> {
+        let answer = match (self.accept, self.decline, self.tentative) {
+            (true, false, false) => RsvpAnswer::Accept,
+            (false, true, false) => RsvpAnswer::Decline,
+            (false, false, true) => RsvpAnswer::Tentative,
+            _ => {
+                return Err(anyhow!(
+                    "Answer with one of --accept, --decline or --tentative"
+                ))
+            }
         };
+        if let Some(address) = self.address.as_ref().filter(|a| !a.contains('@')) {
+            return Err(anyhow!("Invalid email address '{}'", address));
+        }
 
-        if repeat.is_none() {
-            if self.every.is_some() {
-                return Err(anyhow!("'repeat' must be specified when using 'every'"));
+        Ok(CalendarRsvpArgs {
+            file: self.file,
+            answer,
+            calendar: self.calendar.unwrap_or_else(|| "personal".to_string()),
+            address: self.address,
+            reply: self.reply,
+            send: self.send,
+        })
+    }
+}
+
+impl StatusArgs {
+    pub fn validate(self) -> Result<CalendarStatusArgs> {
+        let format = self
+            .format
+            .map(|f| StatusFormat::from_str(&f))
+            .transpose()?
+            .unwrap_or(StatusFormat::Text);
+
+        Ok(CalendarStatusArgs {
+            calendar: self.calendar,
+            format,
+        })
+    }
+}
+
+impl MenuArgs {
+    pub fn validate(self) -> Result<CalendarMenuArgs> {
+        let action = self.pick.map(|a| MenuAction::from_str(&a)).transpose()?;
+        let days = self.days.unwrap_or(7);
+        if !(1..=366).contains(&days) {
+            return Err(anyhow!("The menu lists from 1 to 366 days"));
+        }
+
+        match action {
+            Some(_) if self.selection.is_none() => {
+                return Err(anyhow!("Cannot use 'pick' without a selection"))
             }
-            if until.is_some() {
-                return Err(anyhow!("'repeat' must be specified when using 'until'"));
+            Some(MenuAction::Edit) if self.changes.is_empty() => {
+                return Err(anyhow!(
+                    "Give the changes of the event after --, like -- --at tom@15"
+                ))
+            }
+            Some(MenuAction::Edit) => {}
+            _ if !self.changes.is_empty() => {
+                return Err(anyhow!("Cannot use changes without 'pick edit'"))
             }
+            _ => {}
         }
 
-        Ok(CalendarAddArgs {
-            calendar,
-            name,
-            start,
-            end,
-            loc: self.loc,
-            desc: self.desc,
-            repeat,
-            every,
-            until,
+        Ok(CalendarMenuArgs {
+            selection: self.selection,
+            action,
+            changes: self.changes,
+            calendar: self.calendar,
+            days,
         })
     }
 }
 
-impl EditArgs {
-    pub fn validate(self) -> Result<CalendarEditArgs> {
-        let calendar = self.calendar.unwrap_or_else(|| "personal".to_string());
+impl DaemonArgs {
+    pub fn validate(self) -> Result<CalendarDaemonArgs> {
+        Ok(CalendarDaemonArgs {})
+    }
+}
 
-        let event_id = self.event_id;
-        let start = self.at.map(|w| parse_datetime(&w)).transpose()?;
-        let end = self.to.map(|t| parse_datetime(&t)).transpose()?;
+impl ClientArgs {
+    pub fn validate(self) -> Result<CalendarClientArgs> {
+        // Help and usage errors are printed like the ones of the commands
+        ClientQuery::try_parse_from(&self.query)
+            .unwrap_or_else(|err| err.exit())
+            .validate()?;
+        Ok(CalendarClientArgs { query: self.query })
+    }
+}
 
-        if let (Some(start), Some(end)) = (start, end) {
-            if end < start {
-                return Err(anyhow!("End time must be after start time"));
+impl ServeArgs {
+    pub fn validate(self) -> Result<CalendarServeArgs> {
+        let ip = match self.address {
+            Some(address) => IpAddr::from_str(&address)
+                .map_err(|_| anyhow!("Invalid address '{}', expected an IP", address))?,
+            None => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        };
+        Ok(CalendarServeArgs {
+            address: SocketAddr::new(ip, self.port.unwrap_or(8080)),
+            allow_origin: self.allow_origin,
+        })
+    }
+}
+
+impl ClientQuery {
+    pub fn validate(self) -> Result<CalendarQuery> {
+        match self {
+            ClientQuery::Next(args) => args.validate().map(CalendarQuery::Next),
+            ClientQuery::Agenda(args) if args.watch => {
+                Err(anyhow!("Cannot use 'watch' with the daemon"))
             }
+            ClientQuery::Agenda(args) => args.validate().map(CalendarQuery::Agenda),
+            ClientQuery::Status(args) => args.validate().map(CalendarQuery::Status),
         }
+    }
+}
 
-        Ok(CalendarEditArgs {
-            event_id,
-            calendar,
-            name: self.name,
-            start,
-            end,
-            loc: self.loc,
-            desc: self.desc,
+impl NowArgs {
+    pub fn validate(self) -> Result<CalendarNowArgs> {
+        Ok(CalendarNowArgs {
+            calendar: self.calendar,
         })
     }
 }
 
-impl DeleteArgs {
-    pub fn validate(self) -> Result<CalendarDeleteArgs> {
-        let calendar = self.calendar.unwrap_or_else(|| "personal".to_string());
-        let event_id = self.event_id;
-        Ok(CalendarDeleteArgs {
-            event_id,
-            calendar,
-            force: self.force,
+impl NextArgs {
+    pub fn validate(self) -> Result<CalendarNextArgs> {
+        Ok(CalendarNextArgs {
+            calendar: self.calendar,
+            within: self
+                .within
+                .map(|within| date::parse_duration(&within))
+                .transpose()?,
+            format: self.format,
         })
     }
 }
 
-impl ViewArgs {
-    pub fn validate(self) -> Result<CalendarViewArgs> {
-        let date = self
-            .date
-            .map(|d| parse_date(&d))
-            .transpose()?
-            .unwrap_or_else(|| chrono::Local::now().naive_local().date());
-        let mode = ViewMode::from_str(&self.mode)?;
-
-        let number = self.number.unwrap_or(1);
+impl MotdArgs {
+    pub fn validate(self) -> Result<CalendarMotdArgs> {
+        let width = self.width.unwrap_or(60);
+        if width < 20 {
+            return Err(anyhow!("Width must be at least 20"));
+        }
+        let max = self.max.unwrap_or(5);
+        if max < 1 {
+            return Err(anyhow!("Maximum number of events must be at least 1"));
+        }
 
-        Ok(CalendarViewArgs {
-            date,
-            mode,
+        Ok(CalendarMotdArgs {
             calendar: self.calendar,
-            number,
+            max,
+            width,
         })
     }
 }
 
-impl ShowArgs {
-    pub fn validate(self) -> Result<CalendarShowArgs> {
-        let calendar = self.calendar.unwrap_or_else(|| "personal".to_string());
-        let event_id = self.event_id;
-        Ok(CalendarShowArgs { event_id, calendar })
+impl DigestArgs {
+    pub fn validate(self) -> Result<CalendarDigestArgs> {
+        let today = chrono::Local::now().naive_local().date();
+        let date = match (self.date, self.tomorrow) {
+            (Some(_), true) => return Err(anyhow!("Cannot use both a date and 'tomorrow'")),
+            (Some(date), false) => parse_date(&date)?,
+            (None, true) => today + Duration::days(1),
+            (None, false) => today,
+        };
+
+        let format = self
+            .format
+            .map(|f| DigestFormat::from_str(&f))
+            .transpose()?
+            .unwrap_or(DigestFormat::Text);
+
+        Ok(CalendarDigestArgs {
+            date,
+            calendar: self.calendar,
+            format,
+        })
     }
 }
 
-impl SyncArgs {
-    pub fn validate(self) -> Result<CalendarSyncArgs> {
-        Ok(CalendarSyncArgs {
+impl AgendaArgs {
+    pub fn validate(self) -> Result<CalendarAgendaArgs> {
+        let days = self.days.unwrap_or(7);
+        if !(1..=366).contains(&days) {
+            return Err(anyhow!("The agenda shows from 1 to 366 days"));
+        }
+
+        Ok(CalendarAgendaArgs {
+            days,
             calendar: self.calendar,
+            watch: self.watch,
         })
     }
 }
@@ -465,28 +3275,139 @@ pub fn parse_cli() -> Result<CalendarCommand> {
         return Err(anyhow!("Cannot use command and view arguments"));
     }
 
+    if let Some(root) = &cli.root {
+        // These change the setup of your own calendars
+        if matches!(cli.command, Some(Commands::Setup(_) | Commands::Migrate(_))) {
+            return Err(anyhow!("Cannot use --root with setup or migrate"));
+        }
+        paths::set_root(root)?;
+    }
+
+    if cli.plain {
+        display::set_plain();
+    }
+    if cli.strict {
+        storage::set_strict();
+    }
+
     match cli.command.unwrap_or(Commands::View(ViewArgs {
         date: None,
         mode: cli.mode.unwrap_or("month".to_string()),
         calendar: cli.calendar,
         number: cli.number,
+        backend: None,
+        focus: None,
+        grid: false,
+        timeline: false,
+        heatmap: false,
+        watch: false,
     })) {
-        Commands::List(args) => args.validate().map(CalendarCommand::List),
+        Commands::List(args) => args.validate(cli.json).map(CalendarCommand::List),
         Commands::Add(args) => args.validate().map(CalendarCommand::Add),
+        Commands::Protect(args) => args.validate().map(CalendarCommand::Protect),
         Commands::Edit(args) => args.validate().map(CalendarCommand::Edit),
         Commands::Delete(args) => args.validate().map(CalendarCommand::Delete),
-        Commands::View(args) => args.validate().map(CalendarCommand::View),
-        Commands::Show(args) => args.validate().map(CalendarCommand::Show),
+        Commands::Move(args) => args.validate().map(CalendarCommand::Move),
+        Commands::View(args) => args.validate(cli.json).map(CalendarCommand::View),
+        Commands::Show(args) => args.validate(cli.json).map(CalendarCommand::Show),
+        Commands::Open(args) => args.validate().map(CalendarCommand::Open),
+        Commands::Attend(args) => args.validate().map(CalendarCommand::Attend),
+        Commands::Stats(args) => args.validate().map(CalendarCommand::Stats),
         Commands::Sync(args) => args.validate().map(CalendarCommand::Sync),
+        Commands::Migrate(args) => args.validate().map(CalendarCommand::Migrate),
+        Commands::Setup(args) => args.validate().map(CalendarCommand::Setup),
+        Commands::Import(args) => args.validate().map(CalendarCommand::Import),
+        Commands::Export(args) => args.validate().map(CalendarCommand::Export),
+        Commands::Digest(args) => args.validate().map(CalendarCommand::Digest),
+        Commands::Agenda(args) => args.validate().map(CalendarCommand::Agenda),
+        Commands::Free(args) => args.validate().map(CalendarCommand::Free),
+        Commands::FreeEvenings(args) => args.validate().map(CalendarCommand::FreeEvenings),
+        Commands::Schedule(args) => args.validate().map(CalendarCommand::Schedule),
+        Commands::Rsvp(args) => args.validate().map(CalendarCommand::Rsvp),
+        Commands::Freebusy(args) => args.validate().map(CalendarCommand::FreeBusy),
+        Commands::Status(args) => args.validate().map(CalendarCommand::Status),
+        Commands::Next(args) => args.validate().map(CalendarCommand::Next),
+        Commands::Now(args) => args.validate().map(CalendarCommand::Now),
+        Commands::Motd(args) => args.validate().map(CalendarCommand::Motd),
+        Commands::Menu(args) => args.validate().map(CalendarCommand::Menu),
+        Commands::Daemon(args) => args.validate().map(CalendarCommand::Daemon),
+        Commands::Client(args) => args.validate().map(CalendarCommand::Client),
+        Commands::Serve(args) => args.validate().map(CalendarCommand::Serve),
+        Commands::Todo(args) => match args.command {
+            TodoCommands::Add(args) => args.validate().map(CalendarCommand::TodoAdd),
+            TodoCommands::List(args) => args.validate().map(CalendarCommand::TodoList),
+            TodoCommands::Done(args) => args.validate().map(CalendarCommand::TodoDone),
+        },
+        Commands::Rooms(args) => match args.command {
+            RoomsCommands::Free(args) => args.validate().map(CalendarCommand::RoomsFree),
+        },
+        Commands::Note(args) => args.validate().map(CalendarCommand::Note),
+        Commands::Notes(args) => args.validate().map(CalendarCommand::Notes),
+        Commands::Search(args) => args.validate().map(CalendarCommand::Search),
+        Commands::When(args) => args.validate().map(CalendarCommand::When),
+        #[cfg(feature = "sqlite")]
+        Commands::Index(args) => args.validate().map(CalendarCommand::Index),
+        Commands::Trash(args) => match args.command {
+            TrashCommands::List(args) => args.validate().map(CalendarCommand::TrashList),
+            TrashCommands::Restore(args) => args.validate().map(CalendarCommand::TrashRestore),
+            TrashCommands::Empty(args) => args.validate().map(CalendarCommand::TrashEmpty),
+        },
+        Commands::Purge(args) => args.validate().map(CalendarCommand::Purge),
+        Commands::Dedupe(args) => args.validate().map(CalendarCommand::Dedupe),
+        Commands::Doctor(args) => args.validate().map(CalendarCommand::Doctor),
+        Commands::Completion(args) => args.validate().map(CalendarCommand::Completion),
+        Commands::Complete(args) => args.validate().map(CalendarCommand::Complete),
     }
 }
 
 pub enum CalendarCommand {
     List(CalendarListArgs),
     Add(CalendarAddArgs),
+    Protect(CalendarProtectArgs),
     Edit(CalendarEditArgs),
     Delete(CalendarDeleteArgs),
+    Move(CalendarMoveArgs),
     Show(CalendarShowArgs),
+    Open(CalendarOpenArgs),
+    Attend(CalendarAttendArgs),
+    Stats(CalendarStatsArgs),
     View(CalendarViewArgs),
     Sync(CalendarSyncArgs),
+    Migrate(CalendarMigrateArgs),
+    Setup(CalendarSetupArgs),
+    Import(CalendarImportArgs),
+    Export(CalendarExportArgs),
+    Digest(CalendarDigestArgs),
+    Agenda(CalendarAgendaArgs),
+    Free(CalendarFreeArgs),
+    FreeEvenings(CalendarFreeEveningsArgs),
+    Schedule(CalendarScheduleArgs),
+    Rsvp(CalendarRsvpArgs),
+    FreeBusy(CalendarFreeBusyArgs),
+    Status(CalendarStatusArgs),
+    Next(CalendarNextArgs),
+    Now(CalendarNowArgs),
+    Motd(CalendarMotdArgs),
+    Menu(CalendarMenuArgs),
+    Daemon(CalendarDaemonArgs),
+    Client(CalendarClientArgs),
+    Serve(CalendarServeArgs),
+    TodoAdd(CalendarTodoAddArgs),
+    TodoList(CalendarTodoListArgs),
+    TodoDone(CalendarTodoDoneArgs),
+    RoomsFree(CalendarRoomsFreeArgs),
+    Note(CalendarNoteArgs),
+    Notes(CalendarNotesArgs),
+    Search(CalendarSearchArgs),
+    When(CalendarWhenArgs),
+    #[cfg(feature = "sqlite")]
+    Index(CalendarIndexArgs),
+    TrashList(CalendarTrashListArgs),
+    TrashRestore(CalendarTrashRestoreArgs),
+    TrashEmpty(CalendarTrashEmptyArgs),
+    Purge(CalendarPurgeArgs),
+    Dedupe(CalendarDedupeArgs),
+    Doctor(CalendarDoctorArgs),
+    Completion(CalendarCompletionArgs),
+    Complete(CalendarCompleteArgs),
 }
@@ -0,0 +1,434 @@
+use crate::calendar;
+use crate::cli::{self, Candidates, Shell};
+use crate::event;
+use crate::storage;
+use anyhow::Result;
+use chrono::{Duration, Local, NaiveTime};
+use clap::{Command, CommandFactory};
+use std::collections::HashSet;
+
+// The events completed by id are the ones around today, the ones a command
+// is most likely given
+const RECENT_DAYS: i64 = 30;
+
+// The kind of the value of an option, completed with the calendars, or else
+// with files, like the ones of import, as there is no telling a path from a
+// date by the option
+#[derive(PartialEq)]
+enum Value {
+    None,
+    Calendar,
+    File,
+}
+
+struct CommandOption {
+    flags: Vec<String>,
+    help: String,
+    value: Value,
+}
+
+// A command as seen by the scripts: its path of subcommands, like "todo add",
+// and what follows it
+struct Entry {
+    path: String,
+    commands: Vec<(String, String)>,
+    options: Vec<CommandOption>,
+    // Whether it takes the id or the name of an event
+    events: bool,
+}
+
+pub fn script(cmd: cli::CalendarCompletionArgs) -> Result<()> {
+    let mut command = cli::Cli::command();
+    // Spreads the global options to the subcommands
+    command.build();
+    let mut entries = Vec::new();
+    collect(&command, "", &mut entries);
+
+    let script = match cmd.shell {
+        Shell::Bash => bash(&entries),
+        Shell::Zsh => zsh(&entries),
+        Shell::Fish => fish(&entries),
+    };
+    print!("{}", script);
+    Ok(())
+}
+
+// Prints the candidates asked for by the scripts, one per line, the events
+// as their id and their name separated by a tab
+pub fn complete(cmd: cli::CalendarCompleteArgs) -> Result<()> {
+    match cmd.candidates {
+        Candidates::Calendars => {
+            for name in storage::list_calendars()? {
+                println!("{}", name);
+            }
+        }
+        Candidates::Events(calendar_name) => {
+            let today = Local::now().naive_local().date().and_time(NaiveTime::MIN);
+            let from = today - Duration::days(RECENT_DAYS);
+            let to = today + Duration::days(RECENT_DAYS);
+            let events = event::load_events(Some(calendar_name), from, to, false)?;
+            let mut events = calendar::expand(events, from, to);
+            events.sort_by_key(|event| event.start);
+
+            let mut seen = HashSet::new();
+            for event in events.iter().filter(|event| seen.insert(&event.id)) {
+                println!("{}\t{}", event.id, event.name.replace(['\t', '\n'], " "));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn collect(command: &Command, path: &str, entries: &mut Vec<Entry>) {
+    let subcommands: Vec<&Command> = command
+        .get_subcommands()
+        .filter(|subcommand| !subcommand.is_hide_set())
+        .collect();
+
+    let mut options = Vec::new();
+    let mut events = false;
+    for arg in command.get_arguments().filter(|arg| !arg.is_hide_set()) {
+        if arg.is_positional() {
+            // The ids of the trash are not the ones of the calendars
+            events |= arg.get_id() == "event_id" && !path.starts_with("trash");
+            continue;
+        }
+
+        let mut flags = Vec::new();
+        if let Some(short) = arg.get_short() {
+            flags.push(format!("-{}", short));
+        }
+        if let Some(long) = arg.get_long() {
+            flags.push(format!("--{}", long));
+        }
+        let names_calendar = arg.get_id() == "calendar"
+            || arg
+                .get_value_names()
+                .is_some_and(|names| names.iter().any(|name| name == "CALENDAR"));
+        let value = match arg.get_action().takes_values() {
+            false => Value::None,
+            true if names_calendar => Value::Calendar,
+            true => Value::File,
+        };
+        options.push(CommandOption {
+            flags,
+            help: arg
+                .get_help()
+                .map(|help| help.to_string())
+                .unwrap_or_default(),
+            value,
+        });
+    }
+
+    entries.push(Entry {
+        path: path.to_string(),
+        commands: subcommands
+            .iter()
+            .map(|subcommand| (subcommand.get_name().to_string(), about(subcommand)))
+            .collect(),
+        options,
+        events,
+    });
+    for subcommand in subcommands {
+        let path = match path {
+            "" => subcommand.get_name().to_string(),
+            path => format!("{} {}", path, subcommand.get_name()),
+        };
+        collect(subcommand, &path, entries);
+    }
+}
+
+fn about(command: &Command) -> String {
+    command
+        .get_about()
+        .map(|about| about.to_string())
+        .unwrap_or_default()
+}
+
+// The case patterns of the words naming a subcommand, as the path before it
+// and the word, like 'todo:add'
+fn command_patterns(entries: &[Entry]) -> String {
+    entries
+        .iter()
+        .flat_map(|entry| {
+            entry
+                .commands
+                .iter()
+                .map(move |(name, _)| single_quote(&format!("{}:{}", entry.path, name)))
+        })
+        .collect::<Vec<_>>()
+        .join("|")
+}
+
+// The case patterns of the options taking a value of a kind, as the path of
+// their command and the option, like 'list:--calendar'
+fn value_patterns(entries: &[Entry], value: Value) -> String {
+    entries
+        .iter()
+        .flat_map(|entry| {
+            entry
+                .options
+                .iter()
+                .filter(|option| option.value == value)
+                .flat_map(|option| option.flags.iter())
+                .map(|flag| single_quote(&format!("{}:{}", entry.path, flag)))
+        })
+        .collect::<Vec<_>>()
+        .join("|")
+}
+
+// The flags giving the calendar of the events, whatever their command
+fn calendar_flags(entries: &[Entry]) -> Vec<&str> {
+    let mut flags: Vec<&str> = entries
+        .iter()
+        .flat_map(|entry| entry.options.iter())
+        .filter(|option| option.value == Value::Calendar)
+        .flat_map(|option| option.flags.iter().map(String::as_str))
+        .filter(|flag| matches!(*flag, "-c" | "--calendar"))
+        .collect();
+    flags.sort();
+    flags.dedup();
+    flags
+}
+
+fn bash(entries: &[Entry]) -> String {
+    let mut cases = String::new();
+    for entry in entries {
+        let options: Vec<&str> = entry
+            .options
+            .iter()
+            .flat_map(|option| option.flags.iter().map(String::as_str))
+            .collect();
+        let commands: Vec<&str> = entry
+            .commands
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        cases.push_str(&format!(
+            "        {}) options={}; commands={}; events={} ;;\n",
+            single_quote(&entry.path),
+            single_quote(&options.join(" ")),
+            single_quote(&commands.join(" ")),
+            if entry.events { "1" } else { "''" }
+        ));
+    }
+
+    format!(
+        r#"# bash completion of calendar, from calendar completion bash
+_calendar() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}"
+    local prev="${{COMP_WORDS[COMP_CWORD-1]}}"
+    local cmdpath="" calendar="personal" i
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        case "$cmdpath:${{COMP_WORDS[i]}}" in
+            {commands}) cmdpath="${{cmdpath:+$cmdpath }}${{COMP_WORDS[i]}}" ;;
+        esac
+        case "${{COMP_WORDS[i]}}" in
+            {calendar_flags}) calendar="${{COMP_WORDS[i+1]}}" ;;
+        esac
+    done
+
+    case "$cmdpath:$prev" in
+        {calendars})
+            COMPREPLY=($(compgen -W "$(calendar __complete calendars 2>/dev/null)" -- "$cur"))
+            return ;;
+        {files})
+            COMPREPLY=($(compgen -f -- "$cur"))
+            return ;;
+    esac
+
+    local options commands events
+    case "$cmdpath" in
+{cases}    esac
+
+    if [[ "$cur" == -* ]]; then
+        COMPREPLY=($(compgen -W "$options" -- "$cur"))
+    elif [[ -n "$commands" ]]; then
+        COMPREPLY=($(compgen -W "$commands" -- "$cur"))
+    elif [[ -n "$events" ]]; then
+        COMPREPLY=($(compgen -W "$(calendar __complete events "$calendar" 2>/dev/null | cut -f1)" -- "$cur"))
+    else
+        COMPREPLY=($(compgen -f -- "$cur"))
+    fi
+}}
+complete -F _calendar calendar
+"#,
+        commands = command_patterns(entries),
+        calendar_flags = calendar_flags(entries).join("|"),
+        calendars = value_patterns(entries, Value::Calendar),
+        files = value_patterns(entries, Value::File),
+        cases = cases,
+    )
+}
+
+fn zsh(entries: &[Entry]) -> String {
+    // Colons separate the candidates of _describe from their description
+    let describe =
+        |name: &str, help: &str| single_quote(&format!("{}:{}", name.replace(':', "\\:"), help));
+
+    let mut cases = String::new();
+    for entry in entries {
+        let options: Vec<String> = entry
+            .options
+            .iter()
+            .flat_map(|option| option.flags.iter().map(|flag| describe(flag, &option.help)))
+            .collect();
+        let commands: Vec<String> = entry
+            .commands
+            .iter()
+            .map(|(name, about)| describe(name, about))
+            .collect();
+        cases.push_str(&format!(
+            "        {})\n            options=({})\n            commands=({})\n            events={} ;;\n",
+            single_quote(&entry.path),
+            options.join(" "),
+            commands.join(" "),
+            if entry.events { "1" } else { "''" }
+        ));
+    }
+
+    format!(
+        r#"#compdef calendar
+# zsh completion of calendar, from calendar completion zsh
+_calendar() {{
+    local cur="${{words[CURRENT]}}" prev="${{words[CURRENT-1]}}"
+    local cmdpath="" calendar="personal" i
+    for ((i = 2; i < CURRENT; i++)); do
+        case "$cmdpath:${{words[i]}}" in
+            {commands}) cmdpath="${{cmdpath:+$cmdpath }}${{words[i]}}" ;;
+        esac
+        case "${{words[i]}}" in
+            {calendar_flags}) calendar="${{words[i+1]}}" ;;
+        esac
+    done
+
+    case "$cmdpath:$prev" in
+        {calendars})
+            compadd -- ${{(f)"$(calendar __complete calendars 2>/dev/null)"}}
+            return ;;
+        {files})
+            _files
+            return ;;
+    esac
+
+    local -a options commands ids
+    local events id name
+    case "$cmdpath" in
+{cases}    esac
+
+    if [[ "$cur" == -* ]]; then
+        _describe option options
+    elif (( ${{#commands}} )); then
+        _describe command commands
+    elif [[ -n "$events" ]]; then
+        calendar __complete events "$calendar" 2>/dev/null | while IFS=$'\t' read -r id name; do
+            ids+=("${{id//:/\\:}}:$name")
+        done
+        _describe event ids
+    else
+        _files
+    fi
+}}
+
+if [ "$funcstack[1]" = "_calendar" ]; then
+    _calendar "$@"
+else
+    compdef _calendar calendar
+fi
+"#,
+        commands = command_patterns(entries),
+        calendar_flags = calendar_flags(entries).join("|"),
+        calendars = value_patterns(entries, Value::Calendar),
+        files = value_patterns(entries, Value::File),
+        cases = cases,
+    )
+}
+
+fn fish(entries: &[Entry]) -> String {
+    let mut lines = Vec::new();
+    for entry in entries {
+        let condition = fish_quote(&format!("__calendar_is \"{}\"", entry.path));
+        for (name, about) in &entry.commands {
+            lines.push(format!(
+                "complete -c calendar -f -n {} -a {} -d {}",
+                condition,
+                fish_quote(name),
+                fish_quote(about)
+            ));
+        }
+        for option in &entry.options {
+            let mut line = format!("complete -c calendar -n {}", condition);
+            for flag in &option.flags {
+                match flag.strip_prefix("--") {
+                    Some(long) => line.push_str(&format!(" -l {}", long)),
+                    None => line.push_str(&format!(" -s {}", &flag[1..])),
+                }
+            }
+            match option.value {
+                Value::None => {}
+                Value::Calendar => {
+                    line.push_str(" -r -f -a '(calendar __complete calendars 2>/dev/null)'")
+                }
+                Value::File => line.push_str(" -r"),
+            }
+            line.push_str(&format!(" -d {}", fish_quote(&option.help)));
+            lines.push(line);
+        }
+        if entry.events {
+            lines.push(format!(
+                "complete -c calendar -f -n {} -a '(calendar __complete events (__calendar_calendar) 2>/dev/null)'",
+                condition
+            ));
+        }
+    }
+
+    let calendar_flags: Vec<String> = calendar_flags(entries)
+        .iter()
+        .map(|flag| flag.to_string())
+        .collect();
+    format!(
+        r#"# fish completion of calendar, from calendar completion fish
+function __calendar_path
+    set -l words (commandline -opc)
+    set -e words[1]
+    set -l cmdpath ""
+    for word in $words
+        switch "$cmdpath:$word"
+            case {commands}
+                set cmdpath (string trim -- "$cmdpath $word")
+        end
+    end
+    echo $cmdpath
+end
+
+function __calendar_is
+    set -l cmdpath (__calendar_path)
+    test "$cmdpath" = "$argv[1]"
+end
+
+function __calendar_calendar
+    set -l words (commandline -opc)
+    set -l calendar personal
+    for i in (seq 2 (count $words))
+        if contains -- $words[(math $i - 1)] {calendar_flags}
+            set calendar $words[$i]
+        end
+    end
+    echo $calendar
+end
+
+{lines}
+"#,
+        commands = command_patterns(entries).replace('|', " "),
+        calendar_flags = calendar_flags.join(" "),
+        lines = lines.join("\n"),
+    )
+}
+
+fn single_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+fn fish_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\\', r"\\").replace('\'', r"\'"))
+}
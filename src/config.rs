@@ -0,0 +1,214 @@
+use crate::paths;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub formats: HashMap<String, String>,
+    pub backend: Option<String>,
+    pub highlight: HighlightConfig,
+    pub digest: DigestConfig,
+    pub taskwarrior: TaskwarriorConfig,
+    pub index: IndexConfig,
+    // Read-only calendars downloaded by sync, by name
+    pub subscriptions: HashMap<String, String>,
+    pub graph: GraphConfig,
+    pub work: WorkConfig,
+    pub open: OpenConfig,
+    pub invite: InviteConfig,
+    // Other directories of calendars, read along the calendar directory
+    pub roots: Vec<String>,
+    // Named ranges of days usable with --range, as "2024-09-02..2025-01-17"
+    pub ranges: HashMap<String, String>,
+    pub locale: LocaleConfig,
+    // Defaults of the events added with a tag, by tag
+    #[serde(rename = "tag")]
+    pub tags: HashMap<String, TagConfig>,
+    // Duration of the events added without an end, like "30m" (default: 1h)
+    pub default_duration: Option<String>,
+    // Age after which the events are purged, like "2y", by purge and sync
+    pub retention: Option<String>,
+    // Settings of the calendars, by name
+    #[serde(rename = "calendar")]
+    pub calendars: HashMap<String, CalendarConfig>,
+}
+
+// Styles of the month grid days, as "bold", "red" or "black on white"
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct HighlightConfig {
+    pub today: String,
+    pub focus: String,
+    pub events: String,
+}
+
+impl Default for HighlightConfig {
+    fn default() -> Self {
+        HighlightConfig {
+            today: "black on white".to_string(),
+            focus: "black on yellow".to_string(),
+            events: "bold".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct DigestConfig {
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct TaskwarriorConfig {
+    pub enabled: bool,
+    pub command: String,
+    pub filter: String,
+}
+
+impl Default for TaskwarriorConfig {
+    fn default() -> Self {
+        TaskwarriorConfig {
+            enabled: false,
+            command: "task".to_string(),
+            filter: "status:pending".to_string(),
+        }
+    }
+}
+
+// The SQLite index, used when calendar-rs is built with the sqlite feature
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct IndexConfig {
+    pub enabled: bool,
+}
+
+// Microsoft 365 calendar synced by sync --backend graph, with the client id of
+// an app registered in Azure
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct GraphConfig {
+    pub client_id: Option<String>,
+    pub tenant: String,
+    pub calendar: String,
+}
+
+impl Default for GraphConfig {
+    fn default() -> Self {
+        GraphConfig {
+            client_id: None,
+            tenant: "common".to_string(),
+            calendar: "outlook".to_string(),
+        }
+    }
+}
+
+// The hours and days searched by free
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct WorkConfig {
+    pub hours: String,
+    pub days: String,
+    // When evenings start, for free-evenings
+    pub evening: String,
+}
+
+impl Default for WorkConfig {
+    fn default() -> Self {
+        WorkConfig {
+            hours: "09:00-18:00".to_string(),
+            days: "weekdays".to_string(),
+            evening: "19:00".to_string(),
+        }
+    }
+}
+
+// How open launches links, and where it looks up locations, {} being
+// replaced by the location. With map_links, show and the agendas print the
+// map link of the locations too.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct OpenConfig {
+    pub command: String,
+    pub maps: String,
+    pub map_links: bool,
+}
+
+impl Default for OpenConfig {
+    fn default() -> Self {
+        OpenConfig {
+            command: if cfg!(target_os = "macos") {
+                "open".to_string()
+            } else {
+                "xdg-open".to_string()
+            },
+            maps: "https://www.openstreetmap.org/search?query={}".to_string(),
+            map_links: false,
+        }
+    }
+}
+
+// The organizer of the meetings sent from here, as "Name <address>", and the
+// sendmail compatible command sending the invitations
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct InviteConfig {
+    pub from: Option<String>,
+    pub sendmail: String,
+}
+
+impl Default for InviteConfig {
+    fn default() -> Self {
+        InviteConfig {
+            from: None,
+            sendmail: "sendmail".to_string(),
+        }
+    }
+}
+
+// The language of the month and weekday names, like "fr" (default: the one of
+// LC_ALL, LC_TIME or LANG), or tables of names replacing them, January and
+// Monday first
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct LocaleConfig {
+    pub language: Option<String>,
+    pub months: Vec<String>,
+    pub short_months: Vec<String>,
+    pub weekdays: Vec<String>,
+    pub short_weekdays: Vec<String>,
+}
+
+// The length and the calendar of the events added with a tag, when --to and
+// --calendar are not given
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TagConfig {
+    pub duration: Option<String>,
+    pub calendar: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct CalendarConfig {
+    // Replaces the global default_duration for the events of the calendar
+    pub default_duration: Option<String>,
+    // Replaces the global retention for the events of the calendar
+    pub retention: Option<String>,
+}
+
+pub fn load() -> Result<Config> {
+    let path = paths::config_file()?;
+    if !path.is_file() {
+        return Ok(Config::default());
+    }
+
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read config file '{}'", path.display()))?;
+    toml::from_str(&content)
+        .with_context(|| format!("Failed to parse config file '{}'", path.display()))
+}
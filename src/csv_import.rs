@@ -0,0 +1,259 @@
+use crate::calendar::Event;
+use crate::cli::CsvField;
+use crate::date::CalendarDate;
+use anyhow::{anyhow, Context, Result};
+use chrono::{Duration, NaiveDate, NaiveTime};
+use uuid::Uuid;
+
+// Column names of the Outlook and Google Calendar CSV exports
+const COLUMNS: &[(CsvField, &[&str])] = &[
+    (CsvField::Name, &["subject", "title", "summary", "name"]),
+    (CsvField::StartDate, &["start date", "start"]),
+    (CsvField::StartTime, &["start time"]),
+    (CsvField::EndDate, &["end date", "end"]),
+    (CsvField::EndTime, &["end time"]),
+    (CsvField::AllDay, &["all day event", "all day", "all-day"]),
+    (CsvField::Location, &["location", "place"]),
+    (CsvField::Description, &["description", "notes", "body"]),
+];
+
+// Slashed dates are read month first, as in the Outlook and Google exports
+const DATE_FORMATS: &[&str] = &["%Y-%m-%d", "%m/%d/%Y", "%Y/%m/%d", "%d.%m.%Y"];
+const TIME_FORMATS: &[&str] = &["%H:%M", "%H:%M:%S", "%I:%M %p", "%I:%M:%S %p"];
+
+struct Columns {
+    indices: Vec<(CsvField, usize)>,
+}
+
+impl Columns {
+    fn detect(headers: &csv::StringRecord, map: &[(CsvField, String)]) -> Result<Self> {
+        let mut indices = Vec::new();
+
+        for (field, names) in COLUMNS {
+            let mapped = map.iter().find(|(mapped, _)| mapped == field);
+            let index = match mapped {
+                Some((_, column)) => Some(
+                    headers
+                        .iter()
+                        .position(|header| header.trim() == column)
+                        .ok_or_else(|| anyhow!("Column '{}' not found in the CSV file", column))?,
+                ),
+                None => headers
+                    .iter()
+                    .position(|header| names.contains(&header.trim().to_lowercase().as_str())),
+            };
+            if let Some(index) = index {
+                indices.push((*field, index));
+            }
+        }
+
+        let columns = Columns { indices };
+        for (field, name) in [
+            (CsvField::Name, "name"),
+            (CsvField::StartDate, "start-date"),
+        ] {
+            if columns.index(field).is_none() {
+                return Err(anyhow!(
+                    "Could not detect the {} column, use --map {}=<column>",
+                    name,
+                    name
+                ));
+            }
+        }
+
+        Ok(columns)
+    }
+
+    fn index(&self, field: CsvField) -> Option<usize> {
+        self.indices
+            .iter()
+            .find(|(f, _)| *f == field)
+            .map(|(_, index)| *index)
+    }
+
+    fn get<'a>(&self, record: &'a csv::StringRecord, field: CsvField) -> Option<&'a str> {
+        self.index(field)
+            .and_then(|index| record.get(index))
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    }
+}
+
+// Parses the rows of a CSV export into events, ids are derived from the row
+// content so re-imports are detected
+pub fn parse_events(content: &str, map: &[(CsvField, String)]) -> Result<Vec<Event>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(content.as_bytes());
+    let columns = Columns::detect(reader.headers()?, map)?;
+
+    let mut events = Vec::new();
+    for (row, record) in reader.records().enumerate() {
+        let record = record?;
+        // The header is the first line of the file
+        let line = row + 2;
+        let event = parse_event(&columns, &record)
+            .with_context(|| format!("Failed to import line {}", line))?;
+        events.push(event);
+    }
+
+    Ok(events)
+}
+
+fn parse_event(columns: &Columns, record: &csv::StringRecord) -> Result<Event> {
+    let name = columns
+        .get(record, CsvField::Name)
+        .ok_or_else(|| anyhow!("Name cannot be empty"))?;
+    let start_date = columns
+        .get(record, CsvField::StartDate)
+        .ok_or_else(|| anyhow!("Start date cannot be empty"))?;
+    let start_time = columns.get(record, CsvField::StartTime);
+    let end_date = columns.get(record, CsvField::EndDate);
+    let end_time = columns.get(record, CsvField::EndTime);
+
+    let (start_date, mut start_time) = parse_date_time(start_date, start_time)?;
+    let (end_date, end_time) = match end_date {
+        Some(end_date) => parse_date_time(end_date, end_time)?,
+        None => (start_date, end_time.map(parse_time).transpose()?),
+    };
+
+    let all_day = match columns.get(record, CsvField::AllDay) {
+        Some(value) => matches!(value.to_lowercase().as_str(), "true" | "yes" | "1"),
+        None => start_time.is_none(),
+    };
+    if all_day {
+        start_time = None;
+    }
+
+    let start = start_date.and_time(start_time.unwrap_or(NaiveTime::MIN));
+    let end = if all_day {
+        // Exports give the last day of all-day events, we store the day after
+        end_date
+            .succ_opt()
+            .ok_or_else(|| anyhow!("Date is out of the supported range"))?
+            .and_time(NaiveTime::MIN)
+    } else {
+        match end_time {
+            Some(end_time) => end_date.and_time(end_time),
+            None => start + Duration::hours(1),
+        }
+    };
+
+    let row: Vec<&str> = record.iter().collect();
+    Event::builder(name, start, end)
+        .location(
+            columns
+                .get(record, CsvField::Location)
+                .map(ToString::to_string),
+        )
+        .description(
+            columns
+                .get(record, CsvField::Description)
+                .map(ToString::to_string),
+        )
+        .all_day(all_day)
+        .id(Uuid::new_v5(&Uuid::NAMESPACE_OID, row.join("\u{1f}").as_bytes()).to_string())
+        .build()
+}
+
+// Dates may come with their time in the same column
+fn parse_date_time(date: &str, time: Option<&str>) -> Result<(NaiveDate, Option<NaiveTime>)> {
+    if let Some((date_part, time_part)) = date.split_once([' ', 'T']) {
+        if let (Ok(date), Ok(time)) = (parse_date(date_part), parse_time(time_part.trim())) {
+            return Ok((date, Some(time)));
+        }
+    }
+
+    Ok((parse_date(date)?, time.map(parse_time).transpose()?))
+}
+
+fn parse_date(date: &str) -> Result<NaiveDate> {
+    if let Some(date) = DATE_FORMATS
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(date, format).ok())
+    {
+        return Ok(date);
+    }
+
+    CalendarDate::parse(date)
+        .map(|date| date.inner())
+        .with_context(|| format!("Invalid date '{}'", date))
+}
+
+fn parse_time(time: &str) -> Result<NaiveTime> {
+    TIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveTime::parse_from_str(&time.to_uppercase(), format).ok())
+        .ok_or_else(|| anyhow!("Invalid time '{}'", time))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(day: u32, hour: u32, minute: u32) -> chrono::NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 10, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn outlook_and_google_columns_are_detected() {
+        let outlook =
+            "Subject,Start Date,Start Time,End Date,End Time,All day event,Location,Description\n\
+                       Standup,10/16/2026,9:30:00 AM,10/16/2026,9:45:00 AM,False,Room 1,Daily\n\
+                       Holiday,10/19/2026,,10/20/2026,,True,,\n";
+        let events = parse_events(outlook, &[]).unwrap();
+        assert_eq!(events[0].name, "Standup");
+        assert_eq!(
+            (events[0].start, events[0].end),
+            (at(16, 9, 30), at(16, 9, 45))
+        );
+        assert_eq!(events[0].location.as_deref(), Some("Room 1"));
+        assert_eq!(events[0].description.as_deref(), Some("Daily"));
+        // The last day of all-day events is included
+        assert!(events[1].all_day);
+        assert_eq!(
+            (events[1].start, events[1].end),
+            (at(19, 0, 0), at(21, 0, 0))
+        );
+
+        // Case and spaces aside, dates with their time in the same column
+        let google = " title ,START,end,place\nLunch,2026-10-16 12:00,2026-10-16 13:30,Cafe\n";
+        let events = parse_events(google, &[]).unwrap();
+        assert_eq!(
+            (events[0].start, events[0].end),
+            (at(16, 12, 0), at(16, 13, 30))
+        );
+        assert_eq!(events[0].location.as_deref(), Some("Cafe"));
+        assert!(!events[0].all_day);
+    }
+
+    #[test]
+    fn mapped_columns_are_used_instead() {
+        let content = "What,Day,Subject\nReview,2026-10-16,Ignored\n";
+        let map = [
+            (CsvField::Name, "What".to_string()),
+            (CsvField::StartDate, "Day".to_string()),
+        ];
+        let events = parse_events(content, &map).unwrap();
+        assert_eq!(events[0].name, "Review");
+        // Without a time, the event takes the whole day
+        assert!(events[0].all_day);
+
+        let map = [(CsvField::Name, "Missing".to_string())];
+        assert!(parse_events(content, &map).is_err());
+        // Neither detected nor mapped
+        assert!(parse_events("What,Day\nReview,2026-10-16\n", &[]).is_err());
+    }
+
+    #[test]
+    fn rows_give_the_same_ids_when_imported_again() {
+        let content = "Subject,Start Date\nA,2026-10-16\nB,2026-10-16\n";
+        let first = parse_events(content, &[]).unwrap();
+        let again = parse_events(content, &[]).unwrap();
+        assert_eq!(first[0].id, again[0].id);
+        assert_ne!(first[0].id, first[1].id);
+    }
+}
@@ -0,0 +1,289 @@
+use crate::calendar::Event;
+use crate::cli::{self, CalendarQuery};
+use crate::config;
+use crate::error::{self, CalendarError};
+use crate::event::{self, NEXT_HORIZON_DAYS};
+use crate::paths;
+use crate::request;
+use crate::storage;
+use crate::watch::{self, Snapshot};
+use anyhow::{anyhow, Context, Result};
+use chrono::{Duration, Local, NaiveDate, NaiveTime};
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+
+// A line of JSON sent by a client: the words of its query, and whether its
+// terminal shows colors
+#[derive(Serialize, Deserialize)]
+struct Request {
+    query: Vec<String>,
+    color: bool,
+}
+
+// A line of JSON sent back: the output to print, or the error, with the exit
+// code the command would have
+#[derive(Serialize, Deserialize)]
+struct Response {
+    output: String,
+    error: Option<String>,
+    code: i32,
+}
+
+// The events of every calendar that may be going on from today to the horizon
+// of next, as the queries ask for, read again when the files change
+struct State {
+    events: Vec<Event>,
+    calendars: Vec<String>,
+    day: NaiveDate,
+}
+
+impl State {
+    fn load() -> Result<Self> {
+        let day = Local::now().naive_local().date();
+        let from = day.and_time(NaiveTime::MIN);
+        let to = (day + Duration::days(NEXT_HORIZON_DAYS + 1)).and_time(NaiveTime::MIN);
+        let events = event::load_events(None, from, to, false)?;
+
+        let mut calendars = storage::list_calendars()?;
+        calendars.extend(config::load()?.subscriptions.into_keys());
+        Ok(State {
+            events,
+            calendars,
+            day,
+        })
+    }
+
+    fn events_of(&self, calendar_name: Option<&str>) -> Result<Vec<Event>> {
+        match calendar_name {
+            None => Ok(self.events.clone()),
+            Some(name) if !self.calendars.iter().any(|calendar| calendar == name) => {
+                Err(error::calendar_not_found(name))
+            }
+            Some(name) => Ok(self
+                .events
+                .iter()
+                .filter(|event| event.calendar == name)
+                .cloned()
+                .collect()),
+        }
+    }
+}
+
+// Serves the queries of the clients on a Unix socket, from the events kept in
+// memory, so that a status bar asking every few seconds does not read the
+// files each time. Runs until Ctrl-C.
+pub fn run(_cmd: cli::CalendarDaemonArgs) -> Result<()> {
+    let path = paths::daemon_socket()?;
+    if path.exists() {
+        if UnixStream::connect(&path).is_ok() {
+            return Err(anyhow!(
+                "A daemon is already running on '{}'",
+                path.display()
+            ));
+        }
+        // Left over by a daemon that was stopped
+        fs::remove_file(&path).with_context(|| format!("Failed to remove '{}'", path.display()))?;
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let listener = UnixListener::bind(&path)
+        .with_context(|| format!("Failed to listen on '{}'", path.display()))?;
+    // The events are nobody else's business
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+
+    let seen = watch::snapshot()?;
+    let state = Arc::new(Mutex::new(State::load()?));
+    storage::report_skipped();
+    let watched = Arc::clone(&state);
+    thread::spawn(move || keep_up_to_date(&watched, seen));
+
+    eprintln!("Serving the calendars on '{}'", path.display());
+    for stream in listener.incoming() {
+        let answered = stream
+            .context("Failed to accept a client")
+            .and_then(|stream| answer(stream, &state));
+        if let Err(err) = answered {
+            eprintln!("Warning: {:#}", err);
+        }
+    }
+    Ok(())
+}
+
+// Reads the events again when the files of the calendars change, and when
+// the day changes for the window to follow
+fn keep_up_to_date(state: &Mutex<State>, mut seen: Snapshot) {
+    loop {
+        thread::sleep(watch::POLL);
+        let snapshot = match watch::snapshot() {
+            Ok(snapshot) => snapshot,
+            Err(err) => {
+                eprintln!("Warning: failed to look at the calendars: {:#}", err);
+                continue;
+            }
+        };
+        let today = Local::now().naive_local().date();
+        if snapshot == seen && lock(state).day == today {
+            continue;
+        }
+
+        // Loaded aside, so that the clients are answered meanwhile
+        match State::load() {
+            Ok(loaded) => {
+                *lock(state) = loaded;
+                seen = snapshot;
+            }
+            Err(err) => eprintln!("Warning: failed to read the calendars: {:#}", err),
+        }
+        storage::report_skipped();
+    }
+}
+
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    state
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn answer(stream: UnixStream, state: &Mutex<State>) -> Result<()> {
+    let Some(request) = read_request(&mut request::reader(&stream)?)? else {
+        return Ok(());
+    };
+
+    // The colors are the ones of the terminal of the client
+    colored::control::set_override(request.color);
+    let response = match query(&request.query, &lock(state)) {
+        Ok((output, code)) => Response {
+            output,
+            error: None,
+            code,
+        },
+        Err(err) => Response {
+            output: String::new(),
+            error: Some(format!("{:?}", err)),
+            code: error::exit_code(&err),
+        },
+    };
+    colored::control::unset_override();
+
+    writeln!(&stream, "{}", serde_json::to_string(&response)?).context("Failed to answer")
+}
+
+// The line of JSON of a client, None for the connections closed right away,
+// like the check of a daemon starting
+fn read_request<R: Read>(reader: &mut request::Reader<R>) -> Result<Option<Request>> {
+    match reader.read_line()? {
+        Some(line) => Ok(Some(
+            serde_json::from_str(&line).context("Invalid request")?,
+        )),
+        None => Ok(None),
+    }
+}
+
+// The output of a query, with its exit code
+fn query(words: &[String], state: &State) -> Result<(String, i32)> {
+    let query = cli::ClientQuery::try_parse_from(words)?.validate()?;
+    let now = Local::now().naive_local();
+    match query {
+        CalendarQuery::Next(args) => {
+            if args
+                .within
+                .is_some_and(|within| within > Duration::days(NEXT_HORIZON_DAYS))
+            {
+                return Err(anyhow!(
+                    "The daemon looks for events within {} days",
+                    NEXT_HORIZON_DAYS
+                ));
+            }
+            let events = state.events_of(args.calendar.as_deref())?;
+            Ok(match event::next_line(&args, events, now)? {
+                Some(line) => (format!("{}\n", line), 0),
+                // Like next, for shell conditions
+                None => (String::new(), CalendarError::NoEvent.exit_code()),
+            })
+        }
+        CalendarQuery::Agenda(args) => {
+            let events = state.events_of(args.calendar.as_deref())?;
+            let lines = event::agenda_lines(args.days, events, now.date())?;
+            Ok((format!("{}\n", lines.join("\n")), 0))
+        }
+        CalendarQuery::Status(args) => {
+            let events = state.events_of(args.calendar.as_deref())?;
+            let line = event::status_line(args.format, events, now);
+            Ok((format!("{}\n", line), 0))
+        }
+    }
+}
+
+// Sends a query to the daemon and prints its answer, exiting like the command
+// would have
+pub fn client(cmd: cli::CalendarClientArgs) -> Result<()> {
+    let path = paths::daemon_socket()?;
+    let stream = UnixStream::connect(&path).with_context(|| {
+        format!(
+            "Failed to connect to the daemon on '{}', start it with calendar daemon",
+            path.display()
+        )
+    })?;
+    stream.set_read_timeout(Some(request::TIMEOUT))?;
+
+    let request = Request {
+        query: cmd.query,
+        color: colored::control::SHOULD_COLORIZE.should_colorize(),
+    };
+    writeln!(&stream, "{}", serde_json::to_string(&request)?)
+        .context("Failed to send the query to the daemon")?;
+    let mut line = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut line)
+        .context("The daemon did not answer")?;
+    let response: Response =
+        serde_json::from_str(&line).context("Invalid answer from the daemon")?;
+
+    print!("{}", response.output);
+    if let Some(error) = response.error {
+        eprintln!("Error: {}", error);
+    }
+    if response.code != 0 {
+        std::process::exit(response.code);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(request: &[u8]) -> Result<Option<Request>> {
+        read_request(&mut request::Reader::new(request))
+    }
+
+    #[test]
+    fn requests_are_lines_of_json() {
+        let request = read(b"{\"query\":[\"status\"],\"color\":true}\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!(request.query, ["status"]);
+        assert!(request.color);
+
+        // Closed right away, like the check of a daemon starting
+        assert!(read(b"").unwrap().is_none());
+        assert!(read(b"status\n").is_err());
+        assert!(read(b"{\"query\":\"status\"}\n").is_err());
+
+        let long = format!(
+            "{{\"query\":[\"{}\"],\"color\":false}}\n",
+            "a".repeat(70_000)
+        );
+        assert!(read(long.as_bytes())
+            .err()
+            .unwrap()
+            .is::<request::TooLong>());
+    }
+}
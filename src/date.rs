@@ -1,5 +1,5 @@
 use anyhow::{anyhow, Result};
-use chrono::{Datelike, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+use chrono::{Datelike, Duration, Local, Months, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::collections::HashMap;
@@ -13,13 +13,22 @@ lazy_static! {
     static ref DATE_REGEX_YMD: Regex = Regex::new(r"^(\d{4})[-/](\d{1,2})[-/](\d{1,2})$").unwrap();
     static ref DATE_REGEX_DMY: Regex = Regex::new(r"^(\d{1,2})[-/](\d{1,2})[-/](\d{4})$").unwrap();
     static ref SHORT_DATE_REGEX: Regex = Regex::new(r"^(\d{1,2})[-/](\d{1,2})$").unwrap();
+    static ref DAY_REGEX: Regex = Regex::new(r"^(\d{1,2})$").unwrap();
     static ref TIME_REGEX: Regex = Regex::new(r"^(\d{1,2}):(\d{2})(?::(\d{2}))?$").unwrap();
+    static ref DURATION_REGEX: Regex = Regex::new(r"^(?:(\d+)d)?(?:(\d+)h)?(?:(\d+)m)?$").unwrap();
     static ref RELATIVE_DATE_REGEX: Regex =
         Regex::new(r"^(yesterday|yes|today|tomorrow|tom|(\d+)([dwmy]))$").unwrap();
     static ref WEEKDAY_REGEX: Regex = Regex::new(
         r"^(monday|mon|tuesday|tue|wednesday|wed|thursday|thu|friday|fri|saturday|sat|sunday|sun)$"
     )
     .unwrap();
+    static ref AGE_REGEX: Regex = Regex::new(r"^(\d+)([dwmy])$").unwrap();
+    static ref IN_REGEX: Regex = Regex::new(r"^in (\d+) (day|week|month|year)s?$").unwrap();
+    static ref AGO_REGEX: Regex = Regex::new(r"^(\d+) (day|week|month|year)s? ago$").unwrap();
+    static ref NEXT_REGEX: Regex = Regex::new(r"^(next|last|this) ([a-z]+)$").unwrap();
+    static ref BOUND_REGEX: Regex =
+        Regex::new(r"^(start|beginning|end) of (?:the )?(?:(next|last|this) )?(week|month|year)$")
+            .unwrap();
     static ref MONTH_MAP: HashMap<&'static str, u32> = {
         let mut m = HashMap::new();
         m.insert("jan", 1);
@@ -84,6 +93,11 @@ impl FromStr for CalendarDate {
                 .ok_or_else(|| anyhow!("Invalid date"));
         }
 
+        if let Some(caps) = DAY_REGEX.captures(date_str) {
+            let day: u32 = caps[1].parse()?;
+            return get_next_day_of_month(today, day);
+        }
+
         if let Some(caps) = RELATIVE_DATE_REGEX.captures(date_str) {
             return match &caps[1] {
                 "yesterday" => Ok(CalendarDate(today - Duration::days(1))),
@@ -105,29 +119,17 @@ impl FromStr for CalendarDate {
         }
 
         if let Some(caps) = WEEKDAY_REGEX.captures(date_str) {
-            let target_weekday = match &caps[1] {
-                "mon" => Weekday::Mon,
-                "monday" => Weekday::Mon,
-                "tue" => Weekday::Tue,
-                "tuesday" => Weekday::Tue,
-                "wed" => Weekday::Wed,
-                "wednesday" => Weekday::Wed,
-                "thu" => Weekday::Thu,
-                "thurday" => Weekday::Thu,
-                "fri" => Weekday::Fri,
-                "friday" => Weekday::Fri,
-                "sat" => Weekday::Sat,
-                "saturday" => Weekday::Sat,
-                "sun" => Weekday::Sun,
-                "sunday" => Weekday::Sun,
-                _ => return Err(anyhow!("Invalid weekday")),
-            };
+            let target_weekday = parse_weekday(&caps[1])?;
             let days_ahead = (7 + target_weekday.num_days_from_monday()
                 - today.weekday().num_days_from_monday())
                 % 7;
             return Ok(CalendarDate(today + Duration::days(days_ahead as i64)));
         }
 
+        if let Some(date) = parse_phrase(date_str, today) {
+            return date.map(CalendarDate);
+        }
+
         if let Some(month) = MONTH_MAP.get(date_str.to_lowercase().as_str()) {
             let mut year = today.year();
             if today.month() > *month {
@@ -196,6 +198,130 @@ fn get_next_occurrence(
     }
 }
 
+// Finds the next date falling on this day of the month, skipping the months
+// too short to have it
+fn get_next_day_of_month(today: NaiveDate, day: u32) -> Result<CalendarDate, anyhow::Error> {
+    if !(1..=31).contains(&day) {
+        return Err(anyhow!("Invalid day of the month"));
+    }
+
+    let mut month = today.with_day(1).ok_or_else(|| anyhow!("Invalid date"))?;
+    for _ in 0..12 {
+        if let Some(date) = month.with_day(day) {
+            if date >= today {
+                return Ok(CalendarDate(date));
+            }
+        }
+        month = month
+            .checked_add_months(Months::new(1))
+            .ok_or_else(|| anyhow!("Date is out of the supported range"))?;
+    }
+
+    Err(anyhow!("Invalid day of the month"))
+}
+
+fn parse_weekday(weekday_str: &str) -> Result<Weekday> {
+    match weekday_str {
+        "mon" | "monday" => Ok(Weekday::Mon),
+        "tue" | "tuesday" => Ok(Weekday::Tue),
+        "wed" | "wednesday" => Ok(Weekday::Wed),
+        "thu" | "thursday" => Ok(Weekday::Thu),
+        "fri" | "friday" => Ok(Weekday::Fri),
+        "sat" | "saturday" => Ok(Weekday::Sat),
+        "sun" | "sunday" => Ok(Weekday::Sun),
+        _ => Err(anyhow!("Invalid weekday")),
+    }
+}
+
+// Dates written in words, like "next monday", "in 3 weeks", "2 days ago",
+// "last month" or "end of month", None for anything else. Weeks start on
+// Monday, and "next friday" is the first one after today, a week away on a
+// Friday.
+fn parse_phrase(phrase: &str, today: NaiveDate) -> Option<Result<NaiveDate>> {
+    let phrase = phrase
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase();
+
+    if let Some(caps) = IN_REGEX.captures(&phrase) {
+        return Some(shift(today, &caps[2], caps[1].parse().ok()?));
+    }
+    if let Some(caps) = AGO_REGEX.captures(&phrase) {
+        let amount: i64 = caps[1].parse().ok()?;
+        return Some(shift(today, &caps[2], -amount));
+    }
+    if let Some(caps) = NEXT_REGEX.captures(&phrase) {
+        let amount = match &caps[1] {
+            "next" => 1,
+            "last" => -1,
+            _ => 0,
+        };
+        if let Ok(weekday) = parse_weekday(&caps[2]) {
+            let ahead = (7 + weekday.num_days_from_monday() as i64
+                - today.weekday().num_days_from_monday() as i64)
+                % 7;
+            let days = match amount {
+                1 if ahead == 0 => 7,
+                -1 => ahead - 7,
+                _ => ahead,
+            };
+            return Some(Ok(today + Duration::days(days)));
+        }
+        let unit = caps[2].trim_end_matches('s');
+        return Some(shift(today, unit, amount).and_then(|date| bound(date, unit, false)));
+    }
+    if let Some(caps) = BOUND_REGEX.captures(&phrase) {
+        let amount = match caps.get(2).map(|m| m.as_str()) {
+            Some("next") => 1,
+            Some("last") => -1,
+            _ => 0,
+        };
+        return Some(
+            shift(today, &caps[3], amount)
+                .and_then(|date| bound(date, &caps[3], &caps[1] == "end")),
+        );
+    }
+    None
+}
+
+// The date some days, weeks, months or years away, months and years keeping
+// the day when it exists in the month
+fn shift(date: NaiveDate, unit: &str, amount: i64) -> Result<NaiveDate> {
+    let months = |months: i64| {
+        let count = Months::new(u32::try_from(months.unsigned_abs()).ok()?);
+        match months >= 0 {
+            true => date.checked_add_months(count),
+            false => date.checked_sub_months(count),
+        }
+    };
+    let shifted = match unit {
+        "day" => Duration::try_days(amount).and_then(|days| date.checked_add_signed(days)),
+        "week" => Duration::try_weeks(amount).and_then(|weeks| date.checked_add_signed(weeks)),
+        "month" => months(amount),
+        "year" => amount.checked_mul(12).and_then(months),
+        _ => return Err(anyhow!("Unrecognized date format")),
+    };
+    shifted.ok_or_else(|| anyhow!("Date is out of the supported range"))
+}
+
+// The first or last day of the week, month or year of a date
+fn bound(date: NaiveDate, unit: &str, end: bool) -> Result<NaiveDate> {
+    let first = match unit {
+        "week" => date - Duration::days(date.weekday().num_days_from_monday() as i64),
+        "month" => date.with_day(1).ok_or_else(|| anyhow!("Invalid date"))?,
+        "year" => date
+            .with_ordinal(1)
+            .ok_or_else(|| anyhow!("Invalid date"))?,
+        _ => return Err(anyhow!("Unrecognized date format")),
+    };
+    if !end {
+        return Ok(first);
+    }
+    let next = shift(first, unit, 1)?;
+    Ok(next - Duration::days(1))
+}
+
 fn parse_month(month_str: &str) -> Result<u32, anyhow::Error> {
     MONTH_MAP
         .get(month_str.to_lowercase().as_str())
@@ -208,7 +334,7 @@ impl FromStr for CalendarDateTime {
 
     fn from_str(datetime_str: &str) -> Result<Self, Self::Err> {
         let parts: Vec<&str> = datetime_str.split('@').collect();
-        let date_str = parts.get(0).ok_or_else(|| anyhow!("Missing date"))?;
+        let date_str = parts.first().ok_or_else(|| anyhow!("Missing date"))?;
         let time_str = parts.get(1).ok_or_else(|| anyhow!("Missing time"))?;
 
         let CalendarDate(date) = date_str.parse()?;
@@ -246,11 +372,115 @@ impl CalendarDate {
         s.parse()
     }
 
+    // Like parse, but weekdays are the last ones rather than the next ones,
+    // for dates looking back like the start of a range of past days
+    pub fn parse_past(s: &str) -> Result<Self> {
+        let CalendarDate(date) = s.parse()?;
+        let today = Local::now().naive_local().date();
+        if WEEKDAY_REGEX.is_match(s) && date > today {
+            return Ok(CalendarDate(date - Duration::weeks(1)));
+        }
+        Ok(CalendarDate(date))
+    }
+
     pub fn inner(&self) -> NaiveDate {
         self.0
     }
 }
 
+impl CalendarTime {
+    pub fn parse(s: &str) -> Result<Self> {
+        s.parse()
+    }
+
+    pub fn inner(&self) -> NaiveTime {
+        self.0
+    }
+}
+
+// A range of hours of a day, like 12:30-13:30
+pub fn parse_hours(s: &str) -> Result<(NaiveTime, NaiveTime)> {
+    let (start, end) = s
+        .split_once('-')
+        .ok_or_else(|| anyhow!("Hours must be a range (eg. 12:30-13:30)"))?;
+    let start = CalendarTime::parse(start.trim())?.inner();
+    let end = CalendarTime::parse(end.trim())?.inner();
+    if end <= start {
+        return Err(anyhow!("End time must be after start time"));
+    }
+    Ok((start, end))
+}
+
+// Days of the week: daily, weekdays, weekends or a list like mon,wed,fri
+pub fn parse_days(s: &str) -> Result<Vec<Weekday>> {
+    let mut days = match s {
+        "daily" => vec![
+            Weekday::Mon,
+            Weekday::Tue,
+            Weekday::Wed,
+            Weekday::Thu,
+            Weekday::Fri,
+            Weekday::Sat,
+            Weekday::Sun,
+        ],
+        "weekdays" => vec![
+            Weekday::Mon,
+            Weekday::Tue,
+            Weekday::Wed,
+            Weekday::Thu,
+            Weekday::Fri,
+        ],
+        "weekends" => vec![Weekday::Sat, Weekday::Sun],
+        days => days
+            .split(',')
+            .map(|day| Weekday::from_str(day.trim()).map_err(|_| anyhow!("Invalid day '{}'", day)))
+            .collect::<Result<Vec<_>>>()?,
+    };
+    days.sort_by_key(|day| day.num_days_from_monday());
+    days.dedup();
+    Ok(days)
+}
+
+// The day an age in days, weeks, months or years, like 90d or 2y, before a
+// day
+pub fn parse_age(age: &str, today: NaiveDate) -> Result<NaiveDate> {
+    let caps = AGE_REGEX
+        .captures(age.trim())
+        .ok_or_else(|| anyhow!("Invalid age '{}' (eg. 90d, 6w, 3m, 2y)", age))?;
+    let unit = match &caps[2] {
+        "d" => "day",
+        "w" => "week",
+        "m" => "month",
+        _ => "year",
+    };
+    shift(today, unit, -caps[1].parse::<i64>()?)
+}
+
+// A length of time in hours and minutes, like 1h, 30m or 1h30m
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    // The minutes after the hours may go without their unit, as in 1h30
+    let full = match s.contains('h') && s.ends_with(|c: char| c.is_ascii_digit()) {
+        true => format!("{}m", s),
+        false => s.to_string(),
+    };
+    let caps = DURATION_REGEX
+        .captures(&full)
+        .filter(|_| !s.is_empty())
+        .ok_or_else(|| anyhow!("Invalid duration '{}' (eg. 1h, 30m, 1h30m, 2d)", s))?;
+    let days: i64 = caps.get(1).map_or(Ok(0), |m| m.as_str().parse())?;
+    let hours: i64 = caps.get(2).map_or(Ok(0), |m| m.as_str().parse())?;
+    let minutes: i64 = caps.get(3).map_or(Ok(0), |m| m.as_str().parse())?;
+    let duration = Duration::try_days(days)
+        .zip(Duration::try_hours(hours))
+        .zip(Duration::try_minutes(minutes))
+        .and_then(|((days, hours), minutes)| days.checked_add(&hours)?.checked_add(&minutes))
+        .ok_or_else(|| anyhow!("Duration '{}' is too long", s))?;
+    if duration <= Duration::zero() {
+        return Err(anyhow!("Duration must be positive"));
+    }
+    Ok(duration)
+}
+
 impl CalendarDateTime {
     pub fn parse(s: &str) -> Result<Self> {
         s.parse()
@@ -260,3 +490,48 @@ impl CalendarDateTime {
         self.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phrases_are_relative_to_today() {
+        // A Friday
+        let today = NaiveDate::from_ymd_opt(2026, 10, 16).unwrap();
+        let date = |phrase: &str| {
+            parse_phrase(phrase, today)
+                .unwrap()
+                .unwrap()
+                .format("%Y-%m-%d")
+                .to_string()
+        };
+        assert_eq!(date("next monday"), "2026-10-19");
+        assert_eq!(date("next friday"), "2026-10-23");
+        assert_eq!(date("last  Friday"), "2026-10-09");
+        assert_eq!(date("last tue"), "2026-10-13");
+        assert_eq!(date("in 3 weeks"), "2026-11-06");
+        assert_eq!(date("in 1 month"), "2026-11-16");
+        assert_eq!(date("2 days ago"), "2026-10-14");
+        assert_eq!(date("next week"), "2026-10-19");
+        assert_eq!(date("last month"), "2026-09-01");
+        assert_eq!(date("end of month"), "2026-10-31");
+        assert_eq!(date("end of next month"), "2026-11-30");
+        assert_eq!(date("start of the week"), "2026-10-12");
+        assert_eq!(date("end of year"), "2026-12-31");
+        assert!(parse_phrase("next foo", today).unwrap().is_err());
+        assert!(parse_phrase("someday", today).is_none());
+        assert_eq!(parse_age("2y", today).unwrap().to_string(), "2024-10-16");
+        assert_eq!(parse_age("6w", today).unwrap().to_string(), "2026-09-04");
+        assert!(parse_age("2 years", today).is_err());
+    }
+
+    #[test]
+    fn durations_are_hours_and_minutes() {
+        assert_eq!(parse_duration("1h30").unwrap(), Duration::minutes(90));
+        assert_eq!(parse_duration("2d").unwrap(), Duration::days(2));
+        assert!(parse_duration("0m").is_err());
+        // Longer than chrono can count, refused rather than overflowing
+        assert!(parse_duration("3000000000000000d").is_err());
+    }
+}
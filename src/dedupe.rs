@@ -0,0 +1,117 @@
+use crate::calendar::{self, Event};
+use crate::cli;
+use crate::error;
+use crate::event;
+use crate::storage;
+use anyhow::Result;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+pub fn run(cmd: cli::CalendarDedupeArgs) -> Result<()> {
+    event::create_personal()?;
+
+    let names = match &cmd.calendar {
+        Some(name) if !storage::calendar_exists(name)? => {
+            return Err(error::calendar_not_found(name));
+        }
+        Some(name) => vec![name.clone()],
+        None => storage::list_calendars()?,
+    };
+
+    let mut batch = storage::Batch::default();
+    let mut removed = 0;
+    for name in &names {
+        let calendar = calendar::load(name)?;
+        for copies in duplicates(&calendar.events) {
+            let (kept, others) = copies.split_first().expect("duplicates come in groups");
+            println!(
+                "Duplicates of {} in '{}':",
+                event::describe_event(kept),
+                calendar.name
+            );
+            println!("  keeping  {}", kept.id);
+            for other in others {
+                println!("  removing {}", other.id);
+                batch.trash_event(&calendar.path, other)?;
+                removed += 1;
+            }
+        }
+    }
+
+    if removed == 0 {
+        println!("No duplicates found");
+        return Ok(());
+    }
+    if !cmd.force && !event::confirm(&format!("Remove the {} duplicates?", removed))? {
+        return Ok(());
+    }
+
+    batch.commit()?;
+    println!(
+        "Removed {} duplicates, kept in the trash until it is emptied",
+        removed
+    );
+    Ok(())
+}
+
+// The events with the same name, start and end, like the ones imported twice
+// under other UIDs, the copy to keep first: the last modified one, as changes
+// went to it
+fn duplicates(events: &[Event]) -> Vec<Vec<&Event>> {
+    let mut groups: HashMap<_, Vec<&Event>> = HashMap::new();
+    // The exceptions of a series go with it
+    for event in events.iter().filter(|event| event.recurrence_id.is_none()) {
+        groups
+            .entry((event.name.as_str(), event.start, event.end))
+            .or_default()
+            .push(event);
+    }
+
+    let mut duplicates: Vec<Vec<&Event>> = groups
+        .into_values()
+        .filter(|copies| copies.len() > 1)
+        .map(|mut copies| {
+            copies.sort_by_key(|event| {
+                (
+                    Reverse(event.last_modified.or(event.created)),
+                    event.id.as_str(),
+                )
+            });
+            copies
+        })
+        .collect();
+    duplicates.sort_by_key(|copies| (copies[0].start, copies[0].name.as_str()));
+    duplicates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, NaiveDate};
+
+    #[test]
+    fn the_last_modified_copy_is_kept() {
+        let start = NaiveDate::from_ymd_opt(2026, 1, 1)
+            .unwrap()
+            .and_hms_opt(10, 0, 0)
+            .unwrap();
+        let event = |id: &str, name: &str, modified: i64| {
+            let mut event = Event::builder(name, start, start + Duration::hours(1))
+                .id(id)
+                .build()
+                .unwrap();
+            event.last_modified = Some(start - Duration::days(modified));
+            event
+        };
+        let events = [
+            event("a", "Standup", 3),
+            event("b", "Standup", 1),
+            event("c", "Standup", 2),
+            event("d", "Lunch", 1),
+        ];
+        let groups = duplicates(&events);
+        assert_eq!(groups.len(), 1);
+        let ids: Vec<&str> = groups[0].iter().map(|event| event.id.as_str()).collect();
+        assert_eq!(ids, ["b", "c", "a"]);
+    }
+}
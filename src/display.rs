@@ -0,0 +1,780 @@
+use crate::calendar::Event;
+use crate::cli::OutputFormat;
+use crate::config;
+use crate::freebusy;
+use crate::locale::{self, LocalFormat};
+use crate::open;
+use crate::output;
+use crate::template::{self, Template};
+use anyhow::{anyhow, Result};
+use chrono::{Datelike, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use colored::{Color, ColoredString, Colorize};
+use std::collections::HashSet;
+use std::sync::OnceLock;
+use terminal_size::{terminal_size, Width};
+
+// Backend used when none is configured
+pub const DEFAULT_BACKEND: &str = "text";
+
+// Hours considered as the afternoon by the week summary
+const AFTERNOON_START: NaiveTime = NaiveTime::from_hms_opt(12, 0, 0).unwrap();
+const AFTERNOON_END: NaiveTime = NaiveTime::from_hms_opt(18, 0, 0).unwrap();
+
+// Hours always shown by the week grid and the timeline, they grow to fit
+// earlier and later events
+const GRID_FIRST_HOUR: u32 = 8;
+const GRID_LAST_HOUR: u32 = 18;
+
+// Rows of the week grid for each hour
+const GRID_ROWS_PER_HOUR: u32 = 2;
+
+// Set by --plain: no styles, and the events of the month view listed under the
+// grid in full rather than shortened beside it, for scripts and dumb terminals
+static PLAIN: OnceLock<bool> = OnceLock::new();
+
+// Shortest prefix of the ids shown by list -i, the first group of a UUID
+const SHORT_ID_LENGTH: usize = 8;
+
+// Styles of the days of the year heatmap with 1, 2 and 3 or more events
+const HEATMAP_STYLES: [&str; 3] = ["black on green", "black on yellow", "black on red"];
+
+// Months side by side in the year view, like cal -y
+const YEAR_COLUMNS: usize = 3;
+
+pub struct ViewOptions {
+    // Date the view was asked for, views start on its day, week or month
+    pub date: NaiveDate,
+    // Number of days, weeks or months to show
+    pub number: u32,
+    // Date highlighted in the month grid, on top of today
+    pub focus: Option<NaiveDate>,
+    // Week view drawn as a grid of hours and days instead of day lists
+    pub grid: bool,
+    // Day view drawn as a column of hours instead of a list
+    pub timeline: bool,
+    // Days of the year view colored by their number of events
+    pub heatmap: bool,
+}
+
+// A timed event on a day, its start and end cut at midnight
+type Block<'a> = (NaiveDateTime, NaiveDateTime, &'a Event);
+
+// Renders events on the terminal. The events are sorted by start and already
+// expanded over the range of the view, so a backend only has to lay them out.
+pub trait DisplayBackend {
+    fn name(&self) -> &'static str;
+
+    fn render_day(&self, events: &[Event], options: &ViewOptions) -> Result<()>;
+
+    fn render_week(&self, events: &[Event], options: &ViewOptions) -> Result<()>;
+
+    fn render_month(&self, events: &[Event], options: &ViewOptions) -> Result<()>;
+
+    fn render_year(&self, events: &[Event], options: &ViewOptions) -> Result<()>;
+
+    fn render_list(&self, events: &[Event], template: &Template) -> Result<()>;
+}
+
+pub fn set_plain() {
+    colored::control::set_override(false);
+    let _ = PLAIN.set(true);
+}
+
+fn plain() -> bool {
+    PLAIN.get().copied().unwrap_or(false)
+}
+
+// Every backend selectable by name, custom renderers are registered here
+pub fn backends() -> Vec<Box<dyn DisplayBackend>> {
+    vec![Box::new(TextBackend)]
+}
+
+// Finds a backend by name, falling back on the one from the config file
+pub fn backend(name: Option<String>) -> Result<Box<dyn DisplayBackend>> {
+    let name = match name {
+        Some(name) => name,
+        None => config::load()?
+            .backend
+            .unwrap_or_else(|| DEFAULT_BACKEND.to_string()),
+    };
+    backends()
+        .into_iter()
+        .find(|backend| backend.name() == name)
+        .ok_or_else(|| anyhow!("Unknown display backend '{}'", name))
+}
+
+// The default backend, plain text with colors
+pub struct TextBackend;
+
+impl DisplayBackend for TextBackend {
+    fn name(&self) -> &'static str {
+        "text"
+    }
+
+    fn render_day(&self, events: &[Event], options: &ViewOptions) -> Result<()> {
+        if options.timeline {
+            return render_timeline(events, options);
+        }
+
+        let maps = open::agenda_maps()?;
+        let day_template = template::load(None, "day", template::DAY_FORMAT)?;
+        for i in 0..options.number {
+            let target_date = options
+                .date
+                .checked_add_signed(chrono::Duration::days(i.into()))
+                .ok_or_else(|| anyhow!("Date is out of the supported range"))?;
+            let events_for_day: Vec<_> = events
+                .iter()
+                .filter(|event| event.start.date() == target_date)
+                .collect();
+
+            println!("{}", target_date.format_local("%A, %d %B %Y").bold());
+
+            print_day(&events_for_day, &day_template, maps.as_deref());
+        }
+
+        Ok(())
+    }
+
+    fn render_week(&self, events: &[Event], options: &ViewOptions) -> Result<()> {
+        if options.grid {
+            return render_week_grid(events, options);
+        }
+
+        let maps = open::agenda_maps()?;
+        let day_template = template::load(None, "day", template::DAY_FORMAT)?;
+        for week in 0..options.number {
+            let start_of_week = options
+                .date
+                .checked_add_signed(chrono::Duration::weeks(week.into()))
+                .ok_or_else(|| anyhow!("Date is out of the supported range"))?
+                .week(chrono::Weekday::Mon)
+                .first_day();
+
+            let events_for_week: Vec<_> = events
+                .iter()
+                .filter(|event| {
+                    let date = event.start.date();
+                    date >= start_of_week && date < start_of_week + chrono::Duration::days(7)
+                })
+                .collect();
+            println!("{}", week_summary(start_of_week, &events_for_week));
+
+            for day in 0..7 {
+                let current_date = start_of_week + chrono::Duration::days(day);
+
+                println!("{}", current_date.format_local("%A, %d %B").bold());
+
+                let events_for_day: Vec<_> = events
+                    .iter()
+                    .filter(|event| event.start.date() == current_date)
+                    .collect();
+
+                print_day(&events_for_day, &day_template, maps.as_deref());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn render_month(&self, events: &[Event], options: &ViewOptions) -> Result<()> {
+        let highlight = config::load()?.highlight;
+        // Check the styles before anything is printed
+        for spec in [&highlight.today, &highlight.focus, &highlight.events] {
+            style("", spec)?;
+        }
+        let today = chrono::Local::now().date_naive();
+        let sidebar = template::load(None, "sidebar", template::SIDEBAR_FORMAT)?;
+
+        // Get terminal width
+        let term_width = terminal_size().map(|(Width(w), _)| w).unwrap_or(80);
+
+        // Calculate the total number of rows for all months
+        let mut total_rows = 0;
+        let mut all_month_dates = Vec::new();
+
+        for month in 0..options.number {
+            let (first_of_month, last_of_month, month_rows) = month_layout(options.date, month)?;
+            total_rows += month_rows + 2; // +2 for month header and weekday header
+            all_month_dates.push((first_of_month, last_of_month, month_rows));
+        }
+
+        // Filter upcoming events for all displayed months
+        let last_displayed_date = all_month_dates
+            .last()
+            .map(|(_, last_of_month, _)| *last_of_month)
+            .ok_or_else(|| anyhow!("No month to display"))?;
+
+        let mut line_count = 0;
+
+        let upcoming_events: Vec<_> = events
+            .iter()
+            .filter(|e| e.start.date() >= options.date && e.start.date() <= last_displayed_date)
+            .collect();
+        // With --plain the events are listed under the grid instead
+        let sidebar_events = if plain() {
+            &[][..]
+        } else {
+            &upcoming_events[..]
+        };
+        let mut upcoming_iter = sidebar_events.iter().take(total_rows).peekable();
+
+        // Display each month
+        for (month_index, (first_of_month, _, row_count)) in all_month_dates.into_iter().enumerate()
+        {
+            // Center the month and year
+            let month_year = first_of_month.format_local("%B %Y").bold();
+            print!("{:^20} ", month_year);
+            if line_count == 0 {
+                println!();
+                line_count = 1;
+            }
+
+            // Print upcoming event for the month header line
+            if line_count >= 2 {
+                if let Some(event) = upcoming_iter.next() {
+                    print_event(event, &sidebar, term_width);
+                } else {
+                    println!();
+                }
+            }
+
+            // Print weekday header and "Coming up:" for the first month only
+            if month_index == 0 && !plain() {
+                print!("{}    Coming up:", locale::weekday_header());
+            } else {
+                print!("{} ", locale::weekday_header());
+            }
+
+            // Print upcoming event for the weekday header line
+            if line_count >= 2 {
+                if let Some(event) = upcoming_iter.next() {
+                    print_event(event, &sidebar, term_width);
+                } else {
+                    println!();
+                }
+            }
+
+            if line_count == 1 {
+                println!();
+                line_count = 2;
+            }
+
+            let mut current_date = first_of_month
+                - chrono::Days::new(first_of_month.weekday().num_days_from_monday() as u64);
+
+            for _week in 0..row_count {
+                for _weekday in 0..7 {
+                    let day_str = format!("{:2}", current_date.day());
+                    if current_date.month() != first_of_month.month() {
+                        print!("   ");
+                    } else if Some(current_date) == options.focus {
+                        print!("{} ", style(&day_str, &highlight.focus)?);
+                    } else if current_date == today {
+                        print!("{} ", style(&day_str, &highlight.today)?);
+                    } else if events.iter().any(|e| e.start.date() == current_date) {
+                        print!("{} ", style(&day_str, &highlight.events)?);
+                    } else {
+                        print!("{} ", day_str);
+                    }
+                    current_date = current_date + chrono::Days::new(1);
+                }
+
+                // Print upcoming event for this line
+                if let Some(event) = upcoming_iter.next() {
+                    print_event(event, &sidebar, term_width);
+                } else {
+                    println!();
+                }
+            }
+        }
+
+        if plain() && !upcoming_events.is_empty() {
+            println!("\nComing up:");
+            for event in upcoming_events {
+                println!("{}", sidebar.render(event));
+            }
+        }
+
+        Ok(())
+    }
+
+    // The twelve months of each year side by side, like cal -y
+    fn render_year(&self, events: &[Event], options: &ViewOptions) -> Result<()> {
+        let highlight = config::load()?.highlight;
+        for spec in [&highlight.today, &highlight.events] {
+            style("", spec)?;
+        }
+        let today = chrono::Local::now().date_naive();
+
+        // Fewer months side by side on narrow terminals
+        let term_width = terminal_size().map(|(Width(w), _)| w).unwrap_or(80);
+        let columns = ((term_width as usize + 2) / 22).clamp(1, YEAR_COLUMNS);
+
+        let mut counts = std::collections::HashMap::new();
+        for event in events {
+            *counts.entry(event.start.date()).or_insert(0) += 1;
+        }
+        let day_style = |date: NaiveDate| -> Option<&str> {
+            let count = counts.get(&date).copied().unwrap_or(0);
+            if date == today {
+                Some(&highlight.today)
+            } else if count == 0 {
+                None
+            } else if options.heatmap {
+                Some(HEATMAP_STYLES[count.min(HEATMAP_STYLES.len()) - 1])
+            } else {
+                Some(&highlight.events)
+            }
+        };
+
+        for year in 0..options.number {
+            let year = options.date.year() + year as i32;
+            if year != options.date.year() {
+                println!();
+            }
+            let width = columns * 22 - 2;
+            println!(
+                "{}",
+                format!("{:^width$}", year, width = width).trim_end().bold()
+            );
+
+            let months: Vec<_> = (1..=12)
+                .map(|month| {
+                    NaiveDate::from_ymd_opt(year, month, 1)
+                        .ok_or_else(|| anyhow!("Date is out of the supported range"))
+                })
+                .collect::<Result<_>>()?;
+            for row in months.chunks(columns) {
+                println!();
+                let headers: Vec<_> = row
+                    .iter()
+                    .map(|month| format!("{:^20}", month.format_local("%B")))
+                    .collect();
+                println!("{}", headers.join("  ").trim_end());
+                println!("{}", vec![locale::weekday_header(); row.len()].join("  "));
+
+                // Each month takes up to six weeks
+                let starts: Vec<_> = row
+                    .iter()
+                    .map(|month| {
+                        *month - chrono::Days::new(month.weekday().num_days_from_monday() as u64)
+                    })
+                    .collect();
+                for week in 0..6 {
+                    let mut line = String::new();
+                    for (i, (month, start)) in row.iter().zip(&starts).enumerate() {
+                        if i > 0 {
+                            line.push_str("  ");
+                        }
+                        for weekday in 0..7 {
+                            if weekday > 0 {
+                                line.push(' ');
+                            }
+                            let date = *start + chrono::Days::new(week * 7 + weekday);
+                            let day = format!("{:2}", date.day());
+                            match day_style(date) {
+                                _ if date.month() != month.month() => line.push_str("  "),
+                                Some(spec) => line.push_str(&style(&day, spec)?.to_string()),
+                                None => line.push_str(&day),
+                            }
+                        }
+                    }
+                    if !line.trim().is_empty() {
+                        println!("{}", line.trim_end());
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn render_list(&self, events: &[Event], template: &Template) -> Result<()> {
+        output::print_events(events, OutputFormat::Text, template)
+    }
+}
+
+// The length of the ids shown before the events or todos listed: like git,
+// the shortest prefix of at least SHORT_ID_LENGTH characters telling them apart
+pub fn short_id_length<'a>(ids: impl Iterator<Item = &'a str>) -> usize {
+    let mut ids: Vec<&str> = ids.collect();
+    ids.sort();
+    ids.dedup();
+    let longest = ids.iter().map(|id| id.chars().count()).max().unwrap_or(0);
+    (SHORT_ID_LENGTH..longest)
+        .find(|&length| {
+            let prefixes: HashSet<String> = ids
+                .iter()
+                .map(|id| id.chars().take(length).collect())
+                .collect();
+            prefixes.len() == ids.len()
+        })
+        .unwrap_or(longest)
+}
+
+// The prefix of an id, dimmed and padded to the width of the column so that
+// the lines after it stay aligned
+pub fn id_column(id: &str, length: usize) -> String {
+    let short: String = id.chars().take(length).collect();
+    format!(
+        "{}  ",
+        format!("{:<length$}", short, length = length).dimmed()
+    )
+}
+
+// The weeks as a grid with the hours down and a column for each day, the
+// timed events drawn as blocks as long as they last. The columns share the
+// width of the terminal.
+fn render_week_grid(events: &[Event], options: &ViewOptions) -> Result<()> {
+    let highlight = config::load()?.highlight;
+    style("", &highlight.today)?;
+    let today = chrono::Local::now().date_naive();
+
+    // The hours take 6 columns, each day is followed by a space
+    let term_width = terminal_size().map(|(Width(w), _)| w).unwrap_or(80);
+    let width = ((term_width as usize).saturating_sub(6) / 7)
+        .saturating_sub(1)
+        .max(3);
+    let cell = |text: &str| format!("{:<width$}", template::truncate(text, width), width = width);
+    let row = chrono::Duration::minutes(i64::from(60 / GRID_ROWS_PER_HOUR));
+
+    for week in 0..options.number {
+        let start_of_week = options
+            .date
+            .checked_add_signed(chrono::Duration::weeks(week.into()))
+            .ok_or_else(|| anyhow!("Date is out of the supported range"))?
+            .week(chrono::Weekday::Mon)
+            .first_day();
+        let days: Vec<_> = start_of_week.iter_days().take(7).collect();
+
+        // Tasks take a row
+        let blocks: Vec<Vec<_>> = days
+            .iter()
+            .map(|day| day_blocks(events, *day, row))
+            .collect();
+        let (first_hour, last_hour) = hour_range(blocks.iter().flatten());
+
+        if week > 0 {
+            println!();
+        }
+        print!("      ");
+        for day in &days {
+            let header = cell(&day.format_local("%a %d"));
+            if *day == today {
+                print!("{} ", style(&header, &highlight.today)?);
+            } else {
+                print!("{} ", header.bold());
+            }
+        }
+        println!();
+
+        // All-day events go above the hours
+        let all_day: Vec<Vec<_>> = days
+            .iter()
+            .map(|day| {
+                events
+                    .iter()
+                    .filter(|event| {
+                        event.all_day
+                            && event.start.date() <= *day
+                            && (event.end.date() > *day || event.start.date() == *day)
+                    })
+                    .map(|event| event.name.as_str())
+                    .collect()
+            })
+            .collect();
+        if all_day.iter().any(|names| !names.is_empty()) {
+            print!("      ");
+            for names in &all_day {
+                print!("{} ", cell(&names.join(", ")).reversed());
+            }
+            println!();
+        }
+
+        for slot in first_hour * GRID_ROWS_PER_HOUR..last_hour * GRID_ROWS_PER_HOUR {
+            if slot % GRID_ROWS_PER_HOUR == 0 {
+                print!("{:02}:00 ", slot / GRID_ROWS_PER_HOUR);
+            } else {
+                print!("      ");
+            }
+
+            for (day, blocks) in days.iter().zip(&blocks) {
+                let slot_start = day.and_time(NaiveTime::MIN) + row * slot as i32;
+                let slot_end = slot_start + row;
+                let covering: Vec<_> = blocks
+                    .iter()
+                    .filter(|(start, end, _)| *start < slot_end && *end > slot_start)
+                    .collect();
+                // An event is named on its first row, the others show a bar
+                let starting = covering.iter().find(|(start, _, _)| {
+                    *start >= slot_start || slot == first_hour * GRID_ROWS_PER_HOUR
+                });
+                let text = match starting {
+                    Some((_, _, event)) if covering.len() > 1 => {
+                        format!("+{} {}", covering.len() - 1, event.name)
+                    }
+                    Some((_, _, event)) => event.name.clone(),
+                    None if covering.is_empty() => String::new(),
+                    None => "│".to_string(),
+                };
+                if covering.is_empty() {
+                    print!("{} ", cell(&text));
+                } else {
+                    print!("{} ", cell(&text).reversed());
+                }
+            }
+            println!();
+        }
+    }
+
+    Ok(())
+}
+
+// The days as a column of hours, the events next to the hour they start in
+// and a bar while they go on, the free hours left empty
+fn render_timeline(events: &[Event], options: &ViewOptions) -> Result<()> {
+    let day_template = template::load(None, "day", template::DAY_FORMAT)?;
+
+    for i in 0..options.number {
+        let day = options
+            .date
+            .checked_add_signed(chrono::Duration::days(i.into()))
+            .ok_or_else(|| anyhow!("Date is out of the supported range"))?;
+
+        if i > 0 {
+            println!();
+        }
+        println!("{}", day.format_local("%A, %d %B %Y").bold());
+        for event in events
+            .iter()
+            .filter(|event| event.all_day && event.start.date() == day)
+        {
+            println!("      {}", day_template.render(event));
+        }
+
+        // Tasks take a minute, to be within the hours
+        let blocks = day_blocks(events, day, chrono::Duration::minutes(1));
+        let (first_hour, last_hour) = hour_range(blocks.iter());
+        for hour in first_hour..last_hour {
+            let from = day.and_time(NaiveTime::MIN) + chrono::Duration::hours(hour.into());
+            let to = from + chrono::Duration::hours(1);
+            // Events going on since the day before start on the first hour
+            let starting: Vec<_> = blocks
+                .iter()
+                .filter(|(start, _, _)| *start < to && (*start >= from || hour == first_hour))
+                .collect();
+            let going_on = blocks
+                .iter()
+                .any(|(start, end, _)| *start < from && *end > from);
+
+            let label = format!("{:02}:00", hour);
+            if starting.is_empty() {
+                if going_on {
+                    println!("{} {}", label, "│".dimmed());
+                } else {
+                    println!("{}", label.dimmed());
+                }
+            }
+            for (j, (_, _, event)) in starting.into_iter().enumerate() {
+                let label = if j == 0 { label.clone() } else { " ".repeat(5) };
+                println!("{} {}", label, day_template.render(event));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// The timed events going on during a day, cut at midnight, lasting at least
+// the given time
+fn day_blocks(events: &[Event], day: NaiveDate, min: chrono::Duration) -> Vec<Block<'_>> {
+    let start = day.and_time(NaiveTime::MIN);
+    let end = start + chrono::Duration::days(1);
+    events
+        .iter()
+        .filter(|event| !event.all_day)
+        .map(|event| (event.start, event.end.max(event.start + min), event))
+        .filter(|(event_start, event_end, _)| *event_start < end && *event_end > start)
+        .map(|(event_start, event_end, event)| (event_start.max(start), event_end.min(end), event))
+        .collect()
+}
+
+// The first hour and the hour after the last one to show for blocks. Events
+// going on over midnight do not stretch the hours.
+fn hour_range<'a>(blocks: impl Iterator<Item = &'a Block<'a>> + Clone) -> (u32, u32) {
+    let first_hour = blocks
+        .clone()
+        .filter(|(start, _, event)| *start == event.start)
+        .map(|(start, _, _)| start.hour())
+        .fold(GRID_FIRST_HOUR, u32::min);
+    let last_hour = blocks
+        .filter(|(_, end, event)| *end >= event.end)
+        .map(|(_, end, _)| match end.time() {
+            NaiveTime::MIN => 24,
+            time if time.minute() > 0 => time.hour() + 1,
+            time => time.hour(),
+        })
+        .fold(GRID_LAST_HOUR, u32::max);
+    (first_hour, last_hour)
+}
+
+// One line overview of a week: number of events, hours spent in timed
+// events, the day with the most events and the first afternoon left free.
+// Transparent events do not take any time.
+fn week_summary(start_of_week: NaiveDate, events: &[&Event]) -> String {
+    let timed: Vec<_> = events
+        .iter()
+        .filter(|event| !event.all_day && !event.task && !event.transparent)
+        .collect();
+
+    // Overlapping events only count once
+    let busy = freebusy::busy_blocks(events, NaiveDateTime::MIN, NaiveDateTime::MAX)
+        .into_iter()
+        .fold(chrono::Duration::zero(), |busy, (start, end)| {
+            busy + (end - start)
+        });
+
+    let days: Vec<_> = (0..7)
+        .map(|day| start_of_week + chrono::Duration::days(day))
+        .collect();
+    let count = |date: NaiveDate| events.iter().filter(|e| e.start.date() == date).count();
+
+    let busiest = days
+        .iter()
+        .map(|date| (count(*date), *date))
+        .filter(|(count, _)| *count > 0)
+        .min_by_key(|(count, date)| (std::cmp::Reverse(*count), *date))
+        .map_or("none".to_string(), |(count, date)| {
+            format!("{} ({})", date.format_local("%A"), count)
+        });
+
+    // Past afternoons are of no use
+    let today = chrono::Local::now().date_naive();
+    let free_afternoon = days
+        .iter()
+        .filter(|date| **date >= today)
+        .find(|date| {
+            let afternoon_start = date.and_time(AFTERNOON_START);
+            let afternoon_end = date.and_time(AFTERNOON_END);
+            !timed
+                .iter()
+                .any(|event| event.start < afternoon_end && event.end > afternoon_start)
+        })
+        .map_or("none".to_string(), |date| date.format_local("%A"));
+
+    format!(
+        "Week {}: {} events, {}h{:02} busy, busiest day: {}, first free afternoon: {}",
+        start_of_week.iso_week().week(),
+        events.len(),
+        busy.num_hours(),
+        busy.num_minutes() % 60,
+        busiest,
+        free_afternoon
+    )
+}
+
+// Applies a style such as "bold", "red" or "black on bright white", made of
+// attributes, a foreground color and a background color after "on"
+fn style(text: &str, style: &str) -> Result<ColoredString> {
+    let mut styled = ColoredString::from(text);
+    let mut words = style.split_whitespace();
+    let mut background = false;
+
+    while let Some(word) = words.next() {
+        let word = word.to_lowercase();
+        styled = match word.as_str() {
+            "on" => {
+                background = true;
+                continue;
+            }
+            "none" | "normal" => styled,
+            "bold" => styled.bold(),
+            "dimmed" => styled.dimmed(),
+            "italic" => styled.italic(),
+            "underline" => styled.underline(),
+            "reversed" => styled.reversed(),
+            "blink" => styled.blink(),
+            "strikethrough" => styled.strikethrough(),
+            _ => {
+                let name = match word.as_str() {
+                    "bright" => format!("bright {}", words.next().unwrap_or_default()),
+                    _ => word,
+                };
+                let color: Color = name
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid highlight style '{}'", style))?;
+                if std::mem::take(&mut background) {
+                    styled.on_color(color)
+                } else {
+                    styled.color(color)
+                }
+            }
+        };
+    }
+
+    if background {
+        return Err(anyhow!("Invalid highlight style '{}'", style));
+    }
+
+    Ok(styled)
+}
+
+// Prints the events of a day, all-day events first
+fn print_day(events: &[&Event], template: &Template, maps: Option<&str>) {
+    for line in format_day(events, template, maps) {
+        println!("{}", line);
+    }
+}
+
+// The lines of the events of a day with the day template, all-day events first
+pub fn format_day(events: &[&Event], template: &Template, maps: Option<&str>) -> Vec<String> {
+    let (all_day, timed): (Vec<&&Event>, Vec<_>) = events.iter().partition(|event| event.all_day);
+
+    let mut lines = Vec::new();
+    for event in all_day.into_iter().chain(timed) {
+        lines.push(template.render(event));
+
+        // The map link goes on its own line, to be copied whole
+        let map = maps.zip(event.location.as_deref());
+        if let Some(link) = map.and_then(|(maps, location)| open::map_link(location, maps)) {
+            lines.push(format!("    {}", link));
+        }
+    }
+    lines
+}
+
+// The first and last days of the month some months after the one of a date,
+// and the number of weeks of its grid, starting on Mondays
+pub fn month_layout(date: NaiveDate, months_after: u32) -> Result<(NaiveDate, NaiveDate, usize)> {
+    let target_date = date
+        .checked_add_months(chrono::Months::new(months_after))
+        .ok_or_else(|| anyhow!("Date is out of the supported range"))?;
+    let first_of_month = target_date
+        .with_day(1)
+        .ok_or_else(|| anyhow!("Failed to compute the first day of the month"))?;
+    let last_of_month = first_of_month
+        .checked_add_months(chrono::Months::new(1))
+        .and_then(|date| date.pred_opt())
+        .ok_or_else(|| anyhow!("Date is out of the supported range"))?;
+
+    let mut current_date = first_of_month
+        .checked_sub_days(chrono::Days::new(
+            first_of_month.weekday().num_days_from_monday() as u64,
+        ))
+        .ok_or_else(|| anyhow!("Date is out of the supported range"))?;
+
+    let mut month_rows = 0;
+    while current_date <= last_of_month {
+        month_rows += 1;
+        current_date = current_date + chrono::Days::new(7);
+    }
+    Ok((first_of_month, last_of_month, month_rows))
+}
+
+// Prints an event next to the month grid, shortened to the terminal width
+fn print_event(event: &Event, template: &Template, term_width: u16) {
+    // The grid and the indent take 24 columns
+    let available_width = (term_width as usize).saturating_sub(24).max(3);
+    println!("   {}", template.render_width(event, available_width));
+}
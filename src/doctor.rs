@@ -0,0 +1,233 @@
+use crate::cli;
+use crate::error;
+use crate::paths;
+use crate::storage::{self, RruleWarning};
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+type Repair = Box<dyn Fn(&str) -> Result<String>>;
+
+// A problem of a file, with how to repair it when it is safe to
+struct Problem {
+    message: String,
+    repair: Option<Repair>,
+}
+
+impl Problem {
+    fn new(message: impl Into<String>) -> Self {
+        Problem {
+            message: message.into(),
+            repair: None,
+        }
+    }
+
+    fn repairable(
+        message: impl Into<String>,
+        repair: impl Fn(&str) -> Result<String> + 'static,
+    ) -> Self {
+        Problem {
+            message: message.into(),
+            repair: Some(Box::new(repair)),
+        }
+    }
+}
+
+pub fn run(cmd: cli::CalendarDoctorArgs) -> Result<()> {
+    let dirs = match &cmd.calendar {
+        Some(name) if !storage::calendar_exists(name)? => {
+            return Err(error::calendar_not_found(name));
+        }
+        Some(name) => vec![paths::calendar_path(name)?],
+        None => storage::calendar_dirs()?,
+    };
+
+    let mut batch = storage::Batch::default();
+    let mut files = 0;
+    let mut found = 0;
+    let mut repaired = 0;
+    let mut repairable = 0;
+    for dir in dirs {
+        // Files are shown from the root of their calendar, like work/<collection>/<file>
+        let root = dir.parent().unwrap_or(&dir).to_path_buf();
+        let shown = |file: &Path| {
+            file.strip_prefix(&root)
+                .unwrap_or(file)
+                .display()
+                .to_string()
+        };
+        // The first file of each UID of the calendar
+        let mut uids: HashMap<String, String> = HashMap::new();
+
+        for file in ics_files(&dir)? {
+            files += 1;
+            let content = match fs::read_to_string(&file) {
+                Ok(content) => content,
+                Err(err) => {
+                    found += 1;
+                    println!("{}: cannot be read: {}", shown(&file), err);
+                    continue;
+                }
+            };
+
+            let mut repairs = content.clone();
+            for problem in diagnose(&content, &file, &shown(&file), &mut uids) {
+                found += 1;
+                let status = match (&problem.repair, cmd.fix) {
+                    (Some(repair), true) => {
+                        repairs = repair(&repairs)
+                            .with_context(|| format!("Failed to repair '{}'", file.display()))?;
+                        repaired += 1;
+                        " (fixed)"
+                    }
+                    (Some(_), false) => {
+                        repairable += 1;
+                        " (fixable)"
+                    }
+                    (None, _) => "",
+                };
+                println!("{}: {}{}", shown(&file), problem.message, status);
+            }
+            if repairs != content {
+                batch.replace_file(&file, repairs);
+            }
+        }
+    }
+    batch.commit()?;
+
+    if found == 0 {
+        println!("No problems found in {} files", files);
+        return Ok(());
+    }
+    let mut summary = format!("{} problems found in {} files", found, files);
+    if repaired > 0 {
+        summary.push_str(&format!(", {} fixed", repaired));
+    }
+    if repairable > 0 {
+        summary.push_str(&format!(", {} can be fixed with --fix", repairable));
+    }
+    println!("{}", summary);
+    // Scripts can tell a store with problems left
+    if found > repaired {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+// The ICS files of the collections of a calendar, the hidden ones being
+// written or kept aside skipped
+fn ics_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for collection in fs::read_dir(dir).context("Failed to read directory")? {
+        let collection = collection.context("Failed to read subdirectory entry")?;
+        if !collection.file_type()?.is_dir() {
+            continue;
+        }
+        for entry in fs::read_dir(collection.path()).context("Failed to read subdirectory")? {
+            let path = entry.context("Failed to read directory entry")?.path();
+            let hidden = path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            if path.is_file() && !hidden && path.extension().is_some_and(|ext| ext == "ics") {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// The problems of a file, shown as name, its UID being recorded in uids
+fn diagnose(
+    content: &str,
+    file: &Path,
+    name: &str,
+    uids: &mut HashMap<String, String>,
+) -> Vec<Problem> {
+    let id = match storage::component_kind(content) {
+        Some("VEVENT") => return diagnose_event(content, file, name, uids),
+        Some("VTODO") => storage::read_todo(content).map(|todo| todo.id),
+        Some("VJOURNAL") => storage::read_note(content).map(|note| note.id),
+        Some(_) => return Vec::new(),
+        None => return vec![Problem::new("is not an event, a todo or a note")],
+    };
+    match id {
+        Ok(id) if id.is_empty() => vec![Problem::new("has no UID")],
+        Ok(_) => Vec::new(),
+        Err(err) => vec![Problem::new(format!("cannot be read: {:#}", err))],
+    }
+}
+
+fn diagnose_event(
+    content: &str,
+    file: &Path,
+    name: &str,
+    uids: &mut HashMap<String, String>,
+) -> Vec<Problem> {
+    let (event, warnings) = match storage::parse_event(content) {
+        Ok(parsed) => parsed,
+        Err(err) => return vec![Problem::new(format!("cannot be read: {:#}", err))],
+    };
+    let mut problems = Vec::new();
+
+    if event.id.is_empty() {
+        // Named after its file, the UID matches it
+        let uid = file
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .filter(|stem| storage::event_file(Path::new(""), stem).is_ok())
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+        problems.push(Problem::repairable("has no UID", move |content| {
+            Ok(storage::add_uid(content, &uid))
+        }));
+    }
+
+    if !storage::has_property(content, "VEVENT", "DTSTART") {
+        problems.push(Problem::new("has no DTSTART"));
+    } else if storage::has_property(content, "VEVENT", "DTEND") && event.end < event.start {
+        problems.push(Problem::repairable(
+            "ends before it starts",
+            storage::end_at_start,
+        ));
+    }
+
+    for warning in warnings {
+        problems.push(match warning {
+            RruleWarning::ZeroInterval => Problem::repairable(warning.to_string(), |content| {
+                Ok(storage::repair_interval(content))
+            }),
+            warning => Problem::new(warning.to_string()),
+        });
+    }
+    if let Some(recurrence) = &event.recurrence {
+        if recurrence.until.is_some_and(|until| until < event.start) {
+            problems.push(Problem::new("repeats until a time before its start"));
+        }
+        if recurrence.count == Some(0) {
+            problems.push(Problem::new("RRULE with COUNT=0, it has no occurrence"));
+        }
+    }
+
+    // Exceptions share the UID of their series, and are named otherwise
+    if !event.id.is_empty() && event.recurrence_id.is_none() {
+        let dir = file.parent().unwrap_or(Path::new(""));
+        if let Ok(expected) = storage::event_file(dir, &event.id) {
+            if expected.file_name() != file.file_name() {
+                problems.push(Problem::new(format!(
+                    "is not named after its UID, like '{}'",
+                    expected.file_name().unwrap_or_default().to_string_lossy()
+                )));
+            }
+        }
+        match uids.get(&event.id) {
+            Some(first) => problems.push(Problem::new(format!("has the UID of '{}'", first))),
+            None => {
+                uids.insert(event.id.clone(), name.to_string());
+            }
+        }
+    }
+
+    problems
+}
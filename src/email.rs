@@ -0,0 +1,176 @@
+use anyhow::{anyhow, Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+// The calendars attached to an email, like the text/calendar part of an
+// invitation, decoded. Forwarded messages are looked into too.
+pub fn calendars(message: &str) -> Result<Vec<String>> {
+    let mut calendars = Vec::new();
+    collect(&message.replace("\r\n", "\n"), &mut calendars)?;
+    Ok(calendars)
+}
+
+fn collect(entity: &str, calendars: &mut Vec<String>) -> Result<()> {
+    let (headers, body) = entity.split_once("\n\n").unwrap_or((entity, ""));
+    let headers = unfold_headers(headers);
+    let content_type = header(&headers, "Content-Type").unwrap_or("text/plain");
+    let mime_type = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase();
+    let encoding = header(&headers, "Content-Transfer-Encoding").unwrap_or("7bit");
+
+    // Some clients attach invitations as files of a generic type
+    let file_name = parameter(content_type, "name").or_else(|| {
+        header(&headers, "Content-Disposition").and_then(|value| parameter(value, "filename"))
+    });
+    let is_ics = file_name.is_some_and(|name| name.to_lowercase().ends_with(".ics"));
+
+    if mime_type.starts_with("multipart/") {
+        let boundary = parameter(content_type, "boundary")
+            .ok_or_else(|| anyhow!("A multipart part of the email has no boundary"))?;
+        for part in parts(body, &boundary) {
+            collect(&part, calendars)?;
+        }
+    } else if mime_type == "message/rfc822" {
+        collect(&decode(body, encoding)?, calendars)?;
+    } else if matches!(mime_type.as_str(), "text/calendar" | "application/ics") || is_ics {
+        calendars.push(decode(body, encoding)?);
+    }
+    Ok(())
+}
+
+// The header lines, their continuation lines joined
+fn unfold_headers(headers: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in headers.lines() {
+        match (line.starts_with([' ', '\t']), lines.last_mut()) {
+            (true, Some(last)) => {
+                last.push(' ');
+                last.push_str(line.trim());
+            }
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+fn header<'a>(headers: &'a [String], name: &str) -> Option<&'a str> {
+    headers.iter().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.trim()
+            .eq_ignore_ascii_case(name)
+            .then_some(value.trim())
+    })
+}
+
+// A parameter of a header value, like the boundary of a Content-Type
+fn parameter(value: &str, name: &str) -> Option<String> {
+    value.split(';').skip(1).find_map(|parameter| {
+        let (key, value) = parameter.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case(name)
+            .then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+// The parts of a multipart body, between its boundary lines
+fn parts(body: &str, boundary: &str) -> Vec<String> {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = Vec::new();
+    let mut part: Option<Vec<&str>> = None;
+    for line in body.lines() {
+        let marker = line.trim_end();
+        if marker == delimiter || marker == format!("{}--", delimiter) {
+            parts.extend(part.take().map(|lines| lines.join("\n")));
+            if marker != delimiter {
+                break;
+            }
+            part = Some(Vec::new());
+        } else if let Some(lines) = &mut part {
+            lines.push(line);
+        }
+    }
+    parts
+}
+
+fn decode(body: &str, encoding: &str) -> Result<String> {
+    match encoding.to_lowercase().as_str() {
+        "base64" => {
+            let data: String = body.split_whitespace().collect();
+            let bytes = STANDARD
+                .decode(data)
+                .context("Failed to decode a base64 part of the email")?;
+            Ok(String::from_utf8_lossy(&bytes).to_string())
+        }
+        "quoted-printable" => Ok(decode_quoted_printable(body)),
+        _ => Ok(body.to_string()),
+    }
+}
+
+// Lines ending with = go on with the next one, =XX is a byte in hexadecimal
+fn decode_quoted_printable(body: &str) -> String {
+    let mut bytes = Vec::with_capacity(body.len());
+    for line in body.lines() {
+        let line = line.trim_end();
+        let (line, soft_break) = match line.strip_suffix('=') {
+            Some(line) => (line, true),
+            None => (line, false),
+        };
+        let mut rest = line.as_bytes();
+        while let Some((&byte, tail)) = rest.split_first() {
+            let escaped = (byte == b'=')
+                .then(|| tail.get(..2))
+                .flatten()
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            match escaped {
+                Some(decoded) => {
+                    bytes.push(decoded);
+                    rest = &tail[2..];
+                }
+                None => {
+                    bytes.push(byte);
+                    rest = tail;
+                }
+            }
+        }
+        if !soft_break {
+            bytes.push(b'\n');
+        }
+    }
+    String::from_utf8_lossy(&bytes).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calendar_parts_are_decoded() {
+        let message = "Content-Type: multipart/mixed; boundary=\"b\"\r\n\
+                       \r\n\
+                       --b\r\n\
+                       Content-Type: text/plain\r\n\
+                       \r\n\
+                       Hello\r\n\
+                       --b\r\n\
+                       Content-Type: text/calendar; method=REQUEST\r\n\
+                       Content-Transfer-Encoding: quoted-printable\r\n\
+                       \r\n\
+                       SUMMARY:Caf=C3=A9 cr=\r\n\
+                       =C3=A8me\r\n\
+                       --b\r\n\
+                       Content-Type: application/ics\r\n\
+                       Content-Transfer-Encoding: base64\r\n\
+                       \r\n\
+                       U1VNTUFSWTpUZWE=\r\n\
+                       --b--\r\n";
+        assert_eq!(
+            calendars(message).unwrap(),
+            vec!["SUMMARY:Café crème\n", "SUMMARY:Tea"]
+        );
+    }
+}
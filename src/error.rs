@@ -0,0 +1,54 @@
+use thiserror::Error;
+
+// Exit code of the errors of the arguments, like the usage errors of clap
+pub const USAGE_EXIT_CODE: i32 = 2;
+
+// The errors that scripts can tell apart, by the exit code of the command.
+// The others are anyhow errors with their context, and exit with 1.
+#[derive(Debug, Error)]
+pub enum CalendarError {
+    // A calendar, event or todo that does not exist
+    #[error("{0}")]
+    NotFound(String),
+    // An id or a name matching several events or todos
+    #[error("{0}")]
+    Ambiguous(String),
+    // A calendar being written by another process
+    #[error("{0}")]
+    Locked(String),
+    // A file of the calendars that cannot be read, with --strict
+    #[error("{0}")]
+    Unreadable(String),
+    // No event for the commands that answer with their exit code, next and
+    // list --exists, which print nothing then
+    #[error("No event found")]
+    NoEvent,
+}
+
+impl CalendarError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CalendarError::NotFound(_) => 3,
+            CalendarError::Ambiguous(_) => 4,
+            CalendarError::Locked(_) => 5,
+            CalendarError::Unreadable(_) => 6,
+            CalendarError::NoEvent => 8,
+        }
+    }
+}
+
+pub fn calendar_not_found(name: &str) -> anyhow::Error {
+    CalendarError::NotFound(format!("Calendar '{}' not found", name)).into()
+}
+
+// The exit code of an error: the one of the CalendarError it comes from or
+// was given as context, 7 for input and output errors, else 1
+pub fn exit_code(err: &anyhow::Error) -> i32 {
+    if let Some(err) = err.downcast_ref::<CalendarError>() {
+        return err.exit_code();
+    }
+    if err.downcast_ref::<std::io::Error>().is_some() {
+        return 7;
+    }
+    1
+}
@@ -1,46 +1,79 @@
 use crate::calendar;
 use crate::cli;
+use crate::config;
+use crate::csv_import;
+use crate::date;
+use crate::display;
+use crate::email;
+use crate::error::{self, CalendarError};
+use crate::external;
+use crate::freebusy;
+use crate::fuzzy;
+use crate::html;
+#[cfg(feature = "sqlite")]
+use crate::index;
+use crate::invite;
+use crate::locale::LocalFormat;
+use crate::open;
+use crate::output;
+use crate::paths;
+use crate::picker;
 use crate::storage;
-use anyhow::{anyhow, Result};
-use chrono::{Datelike, Duration, Local, NaiveDate, Timelike};
+use crate::subscription;
+use crate::taskwarrior;
+use crate::template;
+use crate::watch;
+use anyhow::{anyhow, Context, Result};
+use chrono::{Datelike, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Weekday};
 use colored::Colorize;
-use std::io::Write;
-use std::process::Command;
-use terminal_size::{terminal_size, Width};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
 
-pub fn list(cmd: cli::CalendarListArgs) -> Result<()> {
-    let mut events = if let Some(calendar_name) = cmd.calendar {
-        if calendar_name == "personal" {
-            create_personal()?;
-        }
+// Overlapping events listed when adding an event, the others are counted
+const MAX_OVERLAPS_SHOWN: usize = 5;
 
-        // Load events from the specified calendar
-        let calendar = calendar::load(&calendar_name)?;
-        calendar.events
-    } else {
-        // Load events from all calendars
-        let calendars = calendar::load_all()?;
-        calendars
-            .into_iter()
-            .flat_map(|calendar| calendar.events)
-            .collect()
-    };
+// How far ahead motd looks for the next event
+const MOTD_HORIZON_DAYS: i64 = 365;
 
-    // Sort events by start date
-    events.sort_by(|a, b| a.start.cmp(&b.start));
+// How far ahead next looks for an event, unless given
+pub const NEXT_HORIZON_DAYS: i64 = 366;
+
+// Longest event name shown by status, so that the segment stays short
+const STATUS_NAME_WIDTH: usize = 24;
 
+// Events starting this soon are shown in yellow by status --format tmux, and
+// of the imminent class by --format waybar
+const STATUS_SOON_MINUTES: i64 = 15;
+
+// How far ahead when looks for the next occurrence of a recurring event
+const WHEN_HORIZON_DAYS: i64 = 10 * 365;
+
+pub fn list(cmd: cli::CalendarListArgs) -> Result<()> {
     let current_time = Local::now().time();
 
-    // Filter events by date range
-    events.retain(|event| {
-        let from_datetime = cmd.from.and_time(current_time);
-        let to_datetime = cmd.to.and_time(current_time);
-        event.start >= from_datetime && event.start <= to_datetime
-    });
+    // Expand recurring events and filter by date range, all-day events last
+    // the whole day so the ones of the first day are kept
+    let from_datetime = cmd.from.and_time(current_time);
+    let to_datetime = cmd.to.and_time(current_time);
+    let day_start = cmd.from.and_time(NaiveTime::MIN);
+    let events = load_events(cmd.calendar, day_start, to_datetime, true)?;
+    let mut events = calendar::expand(events, day_start, to_datetime);
+    events.retain(|event| event.all_day || event.start >= from_datetime);
 
-    // Implement basic fuzzy search if query is provided
-    if let Some(query) = cmd.query {
-        events.retain(|event| fuzzy_match(&event.name, &query));
+    // Sort events by start date
+    events.sort_by_key(|event| event.start);
+
+    // Keep the events with a field matching the query, fuzzily or by regex
+    match &cmd.query {
+        Some(cli::ListQuery::Fuzzy(query)) => events.retain(|event| {
+            search_fields(event, &cmd.fields).any(|text| fuzzy::matches(text, query))
+        }),
+        Some(cli::ListQuery::Regex(regex)) => events
+            .retain(|event| search_fields(event, &cmd.fields).any(|text| regex.is_match(text))),
+        None => {}
     }
 
     // Limit the number of events if specified
@@ -48,39 +81,46 @@ pub fn list(cmd: cli::CalendarListArgs) -> Result<()> {
         events.truncate(limit);
     }
 
-    // Print events
-    for event in &events {
-        let day_of_week = event.start.format("%a");
-        let date = event.start.format("%d %b");
-        let start_time = event.start.format("%H:%M");
-        let end_time = event.end.format("%H:%M");
+    // Only the exit code answers, for shell conditions
+    if cmd.exists {
+        if events.is_empty() {
+            return Err(CalendarError::NoEvent.into());
+        }
+        return Ok(());
+    }
+    if cmd.count {
+        println!("{}", events.len());
+        return Ok(());
+    }
 
-        let location_part = event
-            .location
-            .as_ref()
-            .map_or(String::new(), |loc| format!(" in {}", loc));
+    let template = template::load(cmd.format, "default", template::DEFAULT_FORMAT)?;
+    let template = if cmd.id { template.with_id() } else { template };
 
-        if cmd.id {
-            println!(
-                "{}: {} {} {}-{} - {}{}",
-                event.id, day_of_week, date, start_time, end_time, event.name, location_part
-            );
-        } else {
-            println!(
-                "{} {} {}-{} - {}{}",
-                day_of_week, date, start_time, end_time, event.name, location_part
-            );
-        }
+    if cmd.output == cli::OutputFormat::Text {
+        return display::backend(cmd.backend)?.render_list(&events, &template);
     }
 
-    Ok(())
+    output::print_events(&events, cmd.output, &template)
+}
+
+// The fields of an event searched by list, the empty ones left out
+fn search_fields<'a>(
+    event: &'a calendar::Event,
+    fields: &'a [cli::SearchField],
+) -> impl Iterator<Item = &'a str> {
+    fields.iter().filter_map(|field| match field {
+        cli::SearchField::Name => Some(event.name.as_str()),
+        cli::SearchField::Location => event.location.as_deref(),
+        cli::SearchField::Description => event.description.as_deref(),
+    })
 }
 
 pub fn add(cmd: cli::CalendarAddArgs) -> Result<()> {
-    create_personal()?;
+    ensure_calendar(&cmd.calendar, cmd.create)?;
 
     let mut calendar = calendar::load(&cmd.calendar)?;
 
+    let mut recurrence = None;
     if let Some(repeat) = cmd.repeat {
         let every = cmd
             .every
@@ -93,469 +133,2256 @@ pub fn add(cmd: cli::CalendarAddArgs) -> Result<()> {
             cli::RepeatFrequency::Yearly => cmd.start.date() + Duration::days(3652),
         });
 
-        let mut i = 0;
-        loop {
-            if i % every == 0 {
-                match repeat {
-                    cli::RepeatFrequency::Daily => {
-                        let start = cmd.start + Duration::days(i.into());
-
-                        if start >= until.into() {
-                            break;
-                        }
-
-                        let duration = cmd.end - cmd.start;
-                        let end = start + duration;
-
-                        calendar.add_event(
-                            cmd.name.clone(),
-                            start,
-                            end,
-                            cmd.loc.clone(),
-                            cmd.desc.clone(),
-                        )?;
-                    }
-                    cli::RepeatFrequency::Weekly => {
-                        let start = cmd.start + Duration::days((i * 7).into());
-
-                        if start >= until.into() {
-                            break;
-                        }
-
-                        let duration = cmd.end - cmd.start;
-                        let end = start + duration;
-
-                        calendar.add_event(
-                            cmd.name.clone(),
-                            start.into(),
-                            end.into(),
-                            cmd.loc.clone(),
-                            cmd.desc.clone(),
-                        )?;
-                    }
-                    cli::RepeatFrequency::Monthly => {
-                        let start_date = cmd.start;
-                        let mut year = start_date.year();
-                        let mut month = start_date.month();
-                        let day = start_date.day();
-                        let hour = start_date.hour();
-                        let min = start_date.minute();
-                        let sec = start_date.second();
-
-                        month += i;
-                        while month > 12 {
-                            month -= 12;
-                            year += 1;
-                        }
-
-                        let start = get_real_date(year, month, day)?
-                            .and_hms_opt(hour, min, sec)
-                            .ok_or_else(|| anyhow!("Failed to create NaiveDateTime"))?;
-
-                        if start >= until.into() {
-                            break;
-                        }
-
-                        let duration = cmd.end - cmd.start;
-                        let end = start + duration;
-
-                        calendar.add_event(
-                            cmd.name.clone(),
-                            start,
-                            end,
-                            cmd.loc.clone(),
-                            cmd.desc.clone(),
-                        )?;
-                    }
-                    cli::RepeatFrequency::Yearly => {
-                        let start_date = cmd.start;
-                        let mut year = start_date.year();
-                        let month = start_date.month();
-                        let day = start_date.day();
-                        let hour = start_date.hour();
-                        let min = start_date.minute();
-                        let sec = start_date.second();
-
-                        year += i as i32;
-
-                        let start = get_real_date(year, month, day)?
-                            .and_hms_opt(hour, min, sec)
-                            .ok_or_else(|| anyhow!("Failed to create NaiveDateTime"))?;
-
-                        if start >= until.into() {
-                            break;
-                        }
-
-                        let duration = cmd.end - cmd.start;
-                        let end = start + duration;
-
-                        calendar.add_event(
-                            cmd.name.clone(),
-                            start,
-                            end,
-                            cmd.loc.clone(),
-                            cmd.desc.clone(),
-                        )?;
-                    }
-                };
-            }
-            i += 1;
+        recurrence = Some(calendar::Recurrence {
+            frequency: repeat,
+            interval: every,
+            count: None,
+            until: Some(until.and_time(NaiveTime::MIN)),
+            by_day: Vec::new(),
+            // On the last day of the months without the day of the start,
+            // like the files written for each occurrence used to be
+            month_end: true,
+            excluded: Vec::new(),
+        });
+    }
+
+    let mut event = calendar::Event::builder(cmd.name, cmd.start, cmd.end)
+        .location(cmd.loc)
+        .description(cmd.desc)
+        .all_day(cmd.all_day)
+        .tags(cmd.tags)
+        .recurrence(recurrence)
+        .build()?;
+
+    if !cmd.force && !event.all_day {
+        let overlaps = overlapping_events(&event)?;
+        for other in overlaps.iter().take(MAX_OVERLAPS_SHOWN) {
+            println!(
+                "Warning: overlaps with '{}' on {}, {}–{}",
+                other.name,
+                other.start.format_local("%a %d %b"),
+                other.start.format("%H:%M"),
+                other.end.format("%H:%M")
+            );
+        }
+        if overlaps.len() > MAX_OVERLAPS_SHOWN {
+            println!(
+                "Warning: and {} more overlaps",
+                overlaps.len() - MAX_OVERLAPS_SHOWN
+            );
+        }
+        if !overlaps.is_empty() && !confirm("Add the event anyway?")? {
+            return Ok(());
         }
-    } else {
-        calendar.add_event(cmd.name, cmd.start, cmd.end, cmd.loc, cmd.desc)?;
     }
 
+    let invite_config = config::load()?.invite;
+    invite::organize(&mut event, cmd.attendees, cmd.send, &invite_config)?;
+    calendar.add_event(event.clone())?;
+    invite::deliver(&event, cmd.invite.as_deref(), cmd.send, &invite_config)?;
+
+    Ok(())
+}
+
+// The busy occurrences of other events overlapping an occurrence of a new
+// event, over the first year of a recurring one
+fn overlapping_events(event: &calendar::Event) -> Result<Vec<calendar::Event>> {
+    let from = event.start;
+    let to = event
+        .recurrence
+        .as_ref()
+        .and_then(|recurrence| recurrence.until)
+        .unwrap_or(event.end)
+        .min(from + Duration::days(365));
+    let occurrences = event.occurrences(from, to);
+
+    let window_end = occurrences.last().map_or(event.end, |last| last.end);
+    let others = load_events_overlapping(None, from, window_end, false)?;
+
+    let mut overlaps: Vec<_> = others
+        .into_iter()
+        .filter(|other| !other.all_day && !other.task && !other.transparent)
+        .filter(|other| {
+            occurrences
+                .iter()
+                .any(|occurrence| other.start < occurrence.end && occurrence.start < other.end)
+        })
+        .collect();
+    overlaps.sort_by_key(|other| other.start);
+
+    Ok(overlaps)
+}
+
+// A block repeating on some days of the week, kept out of the busy time
+pub fn protect(cmd: cli::CalendarProtectArgs) -> Result<()> {
+    ensure_calendar(&cmd.calendar, cmd.create)?;
+
+    let mut calendar = calendar::load(&cmd.calendar)?;
+
+    let first_day = cmd
+        .from
+        .iter_days()
+        .take(7)
+        .find(|day| cmd.days.contains(&day.weekday()))
+        .ok_or_else(|| anyhow!("Date is out of the supported range"))?;
+
+    let event = calendar::Event::builder(
+        cmd.name,
+        first_day.and_time(cmd.start),
+        first_day.and_time(cmd.end),
+    )
+    .transparent(true)
+    .recurrence(Some(calendar::Recurrence {
+        frequency: cli::RepeatFrequency::Weekly,
+        interval: 1,
+        count: None,
+        until: cmd.until.map(|until| until.and_time(cmd.start)),
+        by_day: cmd.days,
+        month_end: false,
+        excluded: Vec::new(),
+    }))
+    .build()?;
+
+    calendar.add_event(event)?;
+
     Ok(())
 }
 
 pub fn edit(cmd: cli::CalendarEditArgs) -> Result<()> {
     create_personal()?;
+    check_writable(&cmd.calendar)?;
 
     let mut calendar = calendar::load(&cmd.calendar)?;
-    calendar.edit_event(
-        cmd.event_id,
-        cmd.name,
-        cmd.start,
-        cmd.end,
-        cmd.loc,
-        cmd.desc,
+    let event = match &cmd.selection {
+        cli::EventSelection::Id(id) => select_event(&calendar.events, id)?,
+        cli::EventSelection::Matching { query, from, to } => {
+            let mut changes = Vec::new();
+            for event in select_matching(&calendar.events, query, *from, *to) {
+                let updated = apply_edit(event, &cmd)?;
+                if changed(event, &updated) {
+                    changes.push((describe_event(event), updated));
+                }
+            }
+            return edit_all(&mut calendar, changes, cmd.yes);
+        }
+    };
+    let loaded = event.file.as_deref().and_then(modified);
+
+    let mut updated = apply_edit(event, &cmd)?;
+    if !print_diff(event, &updated) {
+        println!("Nothing to change");
+        return Ok(());
+    }
+
+    if !cmd.yes && !confirm("Save these changes?")? {
+        return Ok(());
+    }
+
+    // A sync may have changed the file while the changes were confirmed
+    if let Some(file) = event.file.clone() {
+        if modified(&file) != loaded {
+            updated = merge_edit(event, updated, &file, cmd.yes)?;
+        }
+    }
+
+    calendar.update_event(updated)?;
+
+    Ok(())
+}
+
+// The event with the changes of an edit
+fn apply_edit(event: &calendar::Event, cmd: &cli::CalendarEditArgs) -> Result<calendar::Event> {
+    let mut updated = event.clone();
+    if let Some(name) = &cmd.name {
+        updated.name = name.clone();
+    }
+    if let Some(start) = cmd.start {
+        updated.start = start;
+    }
+    if let Some(end) = cmd.end {
+        updated.end = end;
+    }
+    if let Some(shift) = cmd.shift {
+        if updated.all_day {
+            cli::whole_days(shift.abs())?;
+        }
+        updated.start += shift;
+        updated.end += shift;
+    }
+    if let Some(duration) = cmd.duration {
+        if updated.all_day {
+            cli::whole_days(duration)?;
+        }
+        updated.end = updated.start + duration;
+    }
+    if let Some(location) = &cmd.loc {
+        updated.location = Some(location.clone());
+    }
+    if let Some(description) = &cmd.desc {
+        updated.description = Some(description.clone());
+    }
+    Ok(updated)
+}
+
+fn changed(event: &calendar::Event, updated: &calendar::Event) -> bool {
+    event.name != updated.name
+        || event.start != updated.start
+        || event.end != updated.end
+        || event.location != updated.location
+        || event.description != updated.description
+}
+
+// Saves the edits of many events at once, after listing them as they were
+// and as they will be
+fn edit_all(
+    calendar: &mut calendar::Calendar,
+    changes: Vec<(String, calendar::Event)>,
+    yes: bool,
+) -> Result<()> {
+    if changes.is_empty() {
+        println!("Nothing to change");
+        return Ok(());
+    }
+
+    println!("Changing {} events:", changes.len());
+    for (before, updated) in &changes {
+        println!("  {}", before);
+        println!("  {}", format!("-> {}", describe_event(updated)).green());
+    }
+    if !yes && !confirm("Save these changes?")? {
+        return Ok(());
+    }
+
+    let count = changes.len();
+    let mut batch = storage::Batch::default();
+    for (_, updated) in changes {
+        calendar.stage_update(&mut batch, updated)?;
+    }
+    batch.commit()?;
+    println!("Changed {} events", count);
+    Ok(())
+}
+
+// The events of a calendar whose name contains the query, case aside, with an
+// occurrence in a range of days, for the commands changing many events at once.
+// Recurring events are selected as a whole.
+fn select_matching<'a>(
+    events: &'a [calendar::Event],
+    query: &str,
+    from: NaiveDate,
+    to: Option<NaiveDate>,
+) -> Vec<&'a calendar::Event> {
+    let query = query.to_lowercase();
+    let from = from.and_time(NaiveTime::MIN);
+    let to = to.map_or(NaiveDateTime::MAX, |to| {
+        to.and_time(NaiveTime::MIN) + Duration::days(1) - Duration::seconds(1)
+    });
+    let mut matches: Vec<_> = events
+        .iter()
+        .filter(|event| event.recurrence_id.is_none())
+        .filter(|event| event.name.to_lowercase().contains(&query))
+        .filter(|event| !event.occurrences(from, to).is_empty())
+        .collect();
+    matches.sort_by_key(|event| event.start);
+    matches
+}
+
+// An event on a line, to tell it apart from others
+pub fn describe_event(event: &calendar::Event) -> String {
+    let repeats = match event.recurrence {
+        Some(_) => " (every occurrence)",
+        None => "",
+    };
+    format!(
+        "{} {} - {}{}",
+        event.start.format_local("%a %d %b %Y"),
+        event.time_range(),
+        event.name,
+        repeats
+    )
+}
+
+fn modified(file: &Path) -> Option<std::time::SystemTime> {
+    fs::metadata(file)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+// Applies the changes of an edit to the event as it is now in its file. When
+// a field was changed both ways, asks which change to keep.
+fn merge_edit(
+    base: &calendar::Event,
+    mine: calendar::Event,
+    file: &Path,
+    yes: bool,
+) -> Result<calendar::Event> {
+    let content = fs::read_to_string(file)
+        .with_context(|| format!("'{}' was deleted while it was edited", base.name))?;
+    let mut merged = storage::read_event(&content, file)
+        .with_context(|| format!("Failed to read '{}'", file.display()))?;
+    merged.calendar = mine.calendar.clone();
+    merged.file = mine.file.clone();
+
+    println!("'{}' was changed while it was edited, merging", base.name);
+    let datetime = |date: &NaiveDateTime| date.format_local("%a %d %b %Y %H:%M");
+    let optional = |value: &Option<String>| value.clone().unwrap_or_default();
+    merge_field(
+        "Name",
+        &base.name,
+        &mine.name,
+        &mut merged.name,
+        |name| name.clone(),
+        yes,
+    )?;
+    merge_field(
+        "Start",
+        &base.start,
+        &mine.start,
+        &mut merged.start,
+        datetime,
+        yes,
+    )?;
+    merge_field("End", &base.end, &mine.end, &mut merged.end, datetime, yes)?;
+    merge_field(
+        "Location",
+        &base.location,
+        &mine.location,
+        &mut merged.location,
+        optional,
+        yes,
+    )?;
+    merge_field(
+        "Description",
+        &base.description,
+        &mine.description,
+        &mut merged.description,
+        optional,
+        yes,
     )?;
 
+    if merged.end < merged.start {
+        return Err(anyhow!("End time must be after start time"));
+    }
+    Ok(merged)
+}
+
+// Keeps the change of one side, mine when both sides made it differently
+// and the user agrees
+fn merge_field<T: Clone + PartialEq>(
+    label: &str,
+    base: &T,
+    mine: &T,
+    theirs: &mut T,
+    show: impl Fn(&T) -> String,
+    yes: bool,
+) -> Result<()> {
+    if mine == base || mine == theirs {
+        return Ok(());
+    }
+    if theirs == base {
+        *theirs = mine.clone();
+        return Ok(());
+    }
+
+    let label = format!("{}:", label);
+    println!("{:<13}  {} (before)", label.bold(), show(base));
+    println!("{:<13}{}", "", format!("+ {} (yours)", show(mine)).green());
+    println!(
+        "{:<13}{}",
+        "",
+        format!("+ {} (file)", show(theirs)).yellow()
+    );
+    if yes {
+        return Err(anyhow!(
+            "Both the edit and the file changed the {}, edit the event again",
+            label.trim_end_matches(':').to_lowercase()
+        ));
+    }
+    if confirm("Keep yours?")? {
+        *theirs = mine.clone();
+    }
     Ok(())
 }
 
 pub fn delete(cmd: cli::CalendarDeleteArgs) -> Result<()> {
     create_personal()?;
+    check_writable(&cmd.calendar)?;
 
     let mut calendar = calendar::load(&cmd.calendar)?;
+    let event = match &cmd.selection {
+        cli::EventSelection::Id(id) => select_event(&calendar.events, id)?,
+        cli::EventSelection::Matching { query, from, to } => {
+            let events = select_matching(&calendar.events, query, *from, *to);
+            return delete_all(&calendar, &events, cmd.force);
+        }
+    };
 
     if !cmd.force {
-        let event = calendar
-            .get_event(cmd.event_id.clone())
-            .ok_or_else(|| anyhow!("Could not find event with this uuid"))?;
-        print!(
-            "You are about to delete '{}', are you sure? (y/N) ",
-            event.name
-        );
-        let mut input = String::new();
-        std::io::stdout().flush()?;
-        std::io::stdin().read_line(&mut input)?;
-        if input.trim().to_lowercase() != "y" {
+        let prompt = format!("You are about to delete '{}', are you sure?", event.name);
+        if !confirm(&prompt)? {
             return Ok(());
         }
     }
 
-    calendar.remove_event(cmd.event_id)?;
+    calendar.remove_event(event.id.clone())?;
 
     Ok(())
 }
 
-pub fn show(cmd: cli::CalendarShowArgs) -> Result<()> {
-    if cmd.calendar == "personal" {
-        create_personal()?;
+// Deletes many events at once, after listing them
+fn delete_all(
+    calendar: &calendar::Calendar,
+    events: &[&calendar::Event],
+    force: bool,
+) -> Result<()> {
+    if events.is_empty() {
+        println!("No events match");
+        return Ok(());
     }
 
-    let calendar = calendar::load(&cmd.calendar)?;
+    println!("Deleting {} events:", events.len());
+    for event in events {
+        println!("  {}", describe_event(event));
+    }
+    if !force && !confirm("Delete these events?")? {
+        return Ok(());
+    }
+
+    let mut batch = storage::Batch::default();
+    for event in events {
+        batch.trash_event(&calendar.path, event)?;
+    }
+    batch.commit()?;
+    println!("Deleted {} events", events.len());
+    Ok(())
+}
+
+pub fn show(cmd: cli::CalendarShowArgs) -> Result<()> {
+    let event = find_event(&cmd.calendar, &cmd.event_id)?;
 
-    let event = calendar
-        .get_event(cmd.event_id)
-        .ok_or_else(|| anyhow!("Could not find event with this uuid"))?;
+    if cmd.output == cli::OutputFormat::Json {
+        return output::print_event_json(&event);
+    }
 
-    let date = event.start.format("%A, %d %B");
-    let start_time = event.start.format("%H:%M");
-    let end_time = event.end.format("%H:%M");
+    let date = event.start.format_local("%A, %d %B");
 
     println!("Name: {}", event.name);
     println!("Date: {}", date);
-    println!("Time: {}-{}", start_time, end_time);
+    println!("Time: {}", event.time_range());
 
     if let Some(location) = &event.location {
         println!("Location: {}", location);
+        if let Some(maps) = open::agenda_maps()? {
+            if let Some(link) = open::map_link(location, &maps) {
+                println!("Map: {}", link);
+            }
+        }
     }
 
     if let Some(description) = &event.description {
         println!("Description: {}", description);
     }
 
+    if let Some(url) = &event.url {
+        println!("URL: {}", url);
+    }
+
+    if let Some(organizer) = &event.organizer {
+        println!("Organizer: {}", organizer);
+    }
+
+    if !event.attendees.is_empty() {
+        println!("Attendees: {}", event.attendees.join(", "));
+    }
+
+    if !event.attendance.is_empty() {
+        let attended = event.attendance.iter().filter(|a| a.attended).count();
+        println!(
+            "Attendance: {} attended, {} missed",
+            attended,
+            event.attendance.len() - attended
+        );
+    }
+
+    if let Some(created) = event.created {
+        println!("Created: {}", created.format_local("%A, %d %B %Y %H:%M"));
+    }
+
+    if let Some(last_modified) = event.last_modified {
+        println!(
+            "Modified: {}",
+            last_modified.format_local("%A, %d %B %Y %H:%M")
+        );
+    }
+
     println!("Id: {}", event.id);
 
     Ok(())
 }
 
-pub fn view(cmd: cli::CalendarViewArgs) -> Result<()> {
-    let mut events = if let Some(calendar_name) = cmd.calendar {
-        if calendar_name == "personal" {
-            create_personal()?;
+// Moves the file of an event to another calendar as it is, keeping its UID,
+// with the files of its exceptions written by other clients
+pub fn move_event(cmd: cli::CalendarMoveArgs) -> Result<()> {
+    check_writable(&cmd.calendar)?;
+    ensure_calendar(&cmd.target, cmd.create)?;
+
+    let calendar = calendar::load(&cmd.calendar)?;
+    let target = calendar::load(&cmd.target)?;
+    let event = select_event(&calendar.events, &cmd.event_id)?;
+    if target.get_event(event.id.clone()).is_some() {
+        return Err(anyhow!(
+            "'{}' already has an event with the id of '{}'",
+            cmd.target,
+            event.name
+        ));
+    }
+
+    let mut files: Vec<PathBuf> = Vec::new();
+    for same in calendar.events.iter().filter(|other| other.id == event.id) {
+        let file = storage::file_of(&calendar.path, same)?;
+        if !files.contains(&file) {
+            files.push(file);
         }
+    }
+    let mut batch = storage::Batch::default();
+    for file in files {
+        let content = fs::read_to_string(&file)
+            .with_context(|| format!("Failed to read '{}'", file.display()))?;
+        batch.move_file(&file, &target.path, storage::bump_sequence(&content))?;
+    }
+    batch.commit()?;
 
-        let calendar = calendar::load(&calendar_name)?;
-        calendar.events
-    } else {
-        let calendars = calendar::load_all()?;
-        calendars
-            .into_iter()
-            .flat_map(|calendar| calendar.events)
-            .collect()
-    };
+    println!(
+        "Moved '{}' from '{}' to '{}'",
+        event.name, cmd.calendar, cmd.target
+    );
+    Ok(())
+}
 
-    events.sort_by(|a, b| a.start.cmp(&b.start));
+// Records whether an occurrence of an event was attended: the one of the
+// given day, or else the last one that started
+pub fn attend(cmd: cli::CalendarAttendArgs) -> Result<()> {
+    create_personal()?;
+    check_writable(&cmd.calendar)?;
 
-    match cmd.mode {
-        cli::ViewMode::Day => {
-            for i in 0..cmd.number {
-                let target_date = cmd.date + chrono::Duration::days(i.into());
-                let events_for_day: Vec<_> = events
-                    .iter()
-                    .filter(|event| event.start.date() == target_date)
-                    .collect();
-
-                println!("{}", target_date.format("%A, %d %B %Y").to_string().bold());
-
-                for event in events_for_day {
-                    let start_time = event.start.format("%H:%M");
-                    let end_time = event.end.format("%H:%M");
-                    let location_part = event
-                        .location
-                        .as_ref()
-                        .map_or(String::new(), |loc| format!(" in {}", loc));
-
-                    println!(
-                        "{}-{} - {}{}",
-                        start_time, end_time, event.name, location_part
-                    );
-                }
-            }
+    let mut calendar = calendar::load(&cmd.calendar)?;
+    let event = select_event(&calendar.events, &cmd.event_id)?;
+
+    let now = Local::now().naive_local();
+    let occurrence = match cmd.date {
+        Some(date) => {
+            let (from, to) = day_range(date, date);
+            event
+                .occurrences(from, to - Duration::seconds(1))
+                .into_iter()
+                .next()
+                .ok_or_else(|| {
+                    anyhow!(
+                        "'{}' does not take place on {}",
+                        event.name,
+                        date.format_local("%a %d %b %Y")
+                    )
+                })?
         }
-        cli::ViewMode::Week => {
-            for week in 0..cmd.number {
-                let start_of_week = (cmd.date + chrono::Duration::weeks(week.into()))
-                    .week(chrono::Weekday::Mon)
-                    .first_day();
+        None => event
+            .occurrences(now - Duration::days(366), now)
+            .pop()
+            .ok_or_else(|| {
+                anyhow!(
+                    "'{}' did not take place in the last year, give its day with --on",
+                    event.name
+                )
+            })?,
+    };
+    if occurrence.start > now {
+        return Err(anyhow!(
+            "'{}' on {} has not started yet",
+            event.name,
+            occurrence.start.format_local("%a %d %b %Y")
+        ));
+    }
 
-                for day in 0..7 {
-                    let current_date = start_of_week + chrono::Duration::days(day);
+    let mut updated = event.clone();
+    updated.record_attendance(occurrence.start, !cmd.absent);
+    calendar.update_event(updated)?;
 
-                    println!("{}", current_date.format("%A, %d %B").to_string().bold());
+    println!(
+        "Recorded '{}' on {}, {} as {}",
+        occurrence.name,
+        occurrence.start.format_local("%a %d %b %Y"),
+        occurrence.time_range(),
+        if cmd.absent { "missed" } else { "attended" }
+    );
 
-                    let events_for_day: Vec<_> = events
-                        .iter()
-                        .filter(|event| event.start.date() == current_date)
-                        .collect();
+    Ok(())
+}
 
-                    for event in events_for_day {
-                        let start_time = event.start.format("%H:%M");
-                        let end_time = event.end.format("%H:%M");
-                        let location_part = event
-                            .location
-                            .as_ref()
-                            .map_or(String::new(), |loc| format!(" in {}", loc));
-
-                        println!(
-                            "{}-{} - {}{}",
-                            start_time, end_time, event.name, location_part
-                        );
-                    }
-                }
-            }
+pub fn view(mut cmd: cli::CalendarViewArgs) -> Result<()> {
+    if !cmd.watch {
+        return draw_view(&cmd);
+    }
+
+    // A view of today follows the day past midnight
+    let today = Local::now().naive_local().date();
+    let follow = cmd.date == today;
+    watch::run(|| {
+        if follow {
+            cmd.date = Local::now().naive_local().date();
         }
-        cli::ViewMode::Month => {
-            // Get terminal width
-            let term_width = terminal_size().map(|(Width(w), _)| w).unwrap_or(80);
+        draw_view(&cmd)
+    })
+}
 
-            // Calculate the total number of rows for all months
-            let mut total_rows = 0;
-            let mut all_month_dates = Vec::new();
+fn draw_view(cmd: &cli::CalendarViewArgs) -> Result<()> {
+    let (from, to) = view_range(cmd)?;
 
-            for month in 0..cmd.number {
-                let target_date = cmd.date + chrono::Months::new(month);
-                let (year, month, _) = (target_date.year(), target_date.month(), target_date.day());
-                let first_of_month = chrono::NaiveDate::from_ymd_opt(year, month, 1).unwrap();
-                let last_of_month = first_of_month + chrono::Months::new(1) - chrono::Days::new(1);
+    let (from_datetime, to_datetime) = (from.and_time(NaiveTime::MIN), to.and_time(NaiveTime::MIN));
+    let events = load_events(cmd.calendar.clone(), from_datetime, to_datetime, true)?;
 
-                let mut current_date = first_of_month
-                    - chrono::Days::new(first_of_month.weekday().num_days_from_monday() as u64);
+    let mut events = calendar::expand(events, from_datetime, to_datetime);
+    events.sort_by_key(|event| event.start);
 
-                let mut month_rows = 0;
-                while current_date <= last_of_month {
-                    month_rows += 1;
-                    current_date = current_date + chrono::Days::new(7);
-                }
+    if cmd.output == cli::OutputFormat::Json {
+        let last_day = to.pred_opt().unwrap_or(to);
+        events.retain(|event| event.start.date() >= from && event.start.date() <= last_day);
+        return output::print_view_json(from, last_day, &events);
+    }
 
-                total_rows += month_rows + 2; // +2 for month header and weekday header
-                all_month_dates.push((first_of_month, last_of_month, month_rows));
-            }
+    let backend = display::backend(cmd.backend.clone())?;
+    let options = display::ViewOptions {
+        date: cmd.date,
+        number: cmd.number,
+        focus: cmd.focus,
+        grid: cmd.grid,
+        timeline: cmd.timeline,
+        heatmap: cmd.heatmap,
+    };
 
-            // Filter upcoming events for all displayed months
-            let last_displayed_date = all_month_dates.last().unwrap().1;
+    match cmd.mode {
+        cli::ViewMode::Day => backend.render_day(&events, &options),
+        cli::ViewMode::Week => backend.render_week(&events, &options),
+        cli::ViewMode::Month => backend.render_month(&events, &options),
+        cli::ViewMode::Year => backend.render_year(&events, &options),
+    }
+}
 
-            let mut line_count = 0;
+// Returns the [from, to) date range covered by a view
+fn view_range(cmd: &cli::CalendarViewArgs) -> Result<(NaiveDate, NaiveDate)> {
+    let range = match cmd.mode {
+        cli::ViewMode::Day => cmd
+            .date
+            .checked_add_days(chrono::Days::new(cmd.number.into()))
+            .map(|to| (cmd.date, to)),
+        cli::ViewMode::Week => {
+            let start_of_week = cmd.date.week(chrono::Weekday::Mon).first_day();
+            start_of_week
+                .checked_add_days(chrono::Days::new(7 * u64::from(cmd.number)))
+                .map(|to| (start_of_week, to))
+        }
+        cli::ViewMode::Month => cmd.date.with_day(1).and_then(|first_of_month| {
+            first_of_month
+                .checked_add_months(chrono::Months::new(cmd.number))
+                .map(|to| (first_of_month, to))
+        }),
+        cli::ViewMode::Year => cmd.date.with_ordinal(1).and_then(|first_of_year| {
+            first_of_year
+                .checked_add_months(chrono::Months::new(12 * cmd.number))
+                .map(|to| (first_of_year, to))
+        }),
+    };
 
-            let upcoming_events: Vec<_> = events
-                .iter()
-                .filter(|e| e.start.date() >= cmd.date && e.start.date() <= last_displayed_date)
-                .take(total_rows)
-                .collect();
-            let mut upcoming_iter = upcoming_events.iter().peekable();
-
-            // Display each month
-            for (month_index, (first_of_month, _, row_count)) in
-                all_month_dates.into_iter().enumerate()
-            {
-                // Center the month and year
-                let month_year = first_of_month.format("%B %Y").to_string().bold();
-                print!("{:^20} ", month_year);
-                if line_count == 0 {
-                    println!();
-                    line_count = 1;
-                }
+    range.ok_or_else(|| anyhow!("Date is out of the supported range"))
+}
 
-                // Print upcoming event for the month header line
-                if line_count >= 2 {
-                    if let Some(event) = upcoming_iter.next() {
-                        print_event(event, term_width);
-                    } else {
-                        println!();
-                    }
-                }
+pub fn migrate(_cmd: cli::CalendarMigrateArgs) -> Result<()> {
+    let legacy_dir = paths::legacy_calendar_dir()?;
+    let data_dir = storage::migrate_calendars()?;
 
-                // Print weekday header and "Coming up:" for the first month only
-                if month_index == 0 {
-                    print!("Mo Tu We Th Fr Sa Su    Coming up:");
-                } else {
-                    print!("Mo Tu We Th Fr Sa Su ");
-                }
+    println!(
+        "Moved calendars from '{}' to '{}'",
+        legacy_dir.display(),
+        data_dir.display()
+    );
+    println!("Remember to update the storage paths in your vdirsyncer config");
 
-                // Print upcoming event for the weekday header line
-                if line_count >= 2 {
-                    if let Some(event) = upcoming_iter.next() {
-                        print_event(event, term_width);
-                    } else {
-                        println!();
-                    }
-                }
+    Ok(())
+}
 
-                if line_count == 1 {
-                    println!();
-                    line_count = 2;
-                }
+pub fn import(cmd: cli::CalendarImportArgs) -> Result<()> {
+    create_personal()?;
+    if let Some(calendar_name) = &cmd.calendar {
+        check_writable(calendar_name)?;
+    }
 
-                let mut current_date = first_of_month
-                    - chrono::Days::new(first_of_month.weekday().num_days_from_monday() as u64);
-
-                for _week in 0..row_count {
-                    for _weekday in 0..7 {
-                        let day_str = format!("{:2}", current_date.day());
-                        if current_date.month() != first_of_month.month() {
-                            print!("   ");
-                        } else if current_date == chrono::Local::now().date_naive() {
-                            print!("{} ", day_str.on_white().black());
-                        } else if events.iter().any(|e| e.start.date() == current_date) {
-                            print!("{} ", day_str.bold());
-                        } else {
-                            print!("{} ", day_str);
-                        }
-                        current_date = current_date + chrono::Days::new(1);
-                    }
+    let file = cmd.file.as_deref().map(Path::new);
+    if cmd.takeout {
+        let archive = file.ok_or_else(|| anyhow!("A file to import must be specified"))?;
+        return import_takeout(archive, cmd.calendar, cmd.on_conflict);
+    }
 
-                    // Print upcoming event for this line
-                    if let Some(event) = upcoming_iter.next() {
-                        print_event(event, term_width);
-                    } else {
-                        println!();
+    let (raw_events, events) = match (cmd.from, cmd.format) {
+        (Some(cli::ImportTool::Khal), _) => (external::khal_events(file)?, Vec::new()),
+        (Some(cli::ImportTool::Calcurse), _) => (Vec::new(), external::calcurse_events(file)?),
+        (None, format) => {
+            let file = cmd.file.unwrap_or_default();
+            let content = if file == "-" {
+                let mut content = Vec::new();
+                std::io::stdin().read_to_end(&mut content)?;
+                content
+            } else {
+                fs::read(&file).with_context(|| format!("Failed to read '{}'", file))?
+            };
+            // Emails may mix charsets, the calendars are decoded from their parts
+            let content = match format {
+                cli::ImportFormat::Email => String::from_utf8_lossy(&content).into_owned(),
+                _ => String::from_utf8(content)
+                    .with_context(|| format!("Failed to read '{}', it is not UTF-8", file))?,
+            };
+
+            match format {
+                cli::ImportFormat::Ics => (storage::split_events(&content), Vec::new()),
+                cli::ImportFormat::Csv => {
+                    (Vec::new(), csv_import::parse_events(&content, &cmd.map)?)
+                }
+                cli::ImportFormat::Email => {
+                    let calendars = email::calendars(&content)?;
+                    if calendars.is_empty() {
+                        return Err(anyhow!("No calendar found in the email '{}'", file));
                     }
+                    let events = calendars
+                        .iter()
+                        .flat_map(|calendar| storage::split_events(calendar))
+                        .collect();
+                    (events, Vec::new())
                 }
             }
         }
-    }
+    };
 
-    Ok(())
-}
+    let calendar_name = cmd.calendar.unwrap_or_else(|| "personal".to_string());
+    let calendar = calendar::load(&calendar_name)?;
+    let mut batch = storage::Batch::default();
+    let report = import_events(&mut batch, &calendar, raw_events, events, cmd.on_conflict)?;
+    batch.commit()?;
 
-pub fn sync(cmd: cli::CalendarSyncArgs) -> Result<()> {
-    let mut vdirsyncer_command = Command::new("vdirsyncer");
-    vdirsyncer_command.arg("sync");
-    vdirsyncer_command.arg("--force-delete");
+    println!("Imported into '{}': {}", calendar_name, report);
 
-    if let Some(calendar) = cmd.calendar {
-        vdirsyncer_command.arg(&calendar);
-        println!("Syncing calendar '{}' with vdirsyncer", calendar);
-    } else {
-        println!("Syncing calendars with vdirsyncer");
-    }
-    let output = vdirsyncer_command.output()?;
+    Ok(())
+}
 
-    if !output.status.success() {
-        return Err(anyhow::anyhow!("vdirsyncer sync failed"));
+// Imports each calendar of an archive, into the given calendar or the one
+// the user picks for it
+fn import_takeout(
+    archive: &Path,
+    calendar_name: Option<String>,
+    on_conflict: cli::ConflictStrategy,
+) -> Result<()> {
+    let calendars = external::takeout_calendars(archive)?;
+    let total = calendars.len();
+
+    // Nothing is written until every calendar of the archive is read
+    let mut batch = storage::Batch::default();
+
+    for (i, (name, raw_events)) in calendars.into_iter().enumerate() {
+        let target = match &calendar_name {
+            Some(calendar_name) => calendar_name.clone(),
+            None => match ask_calendar(&name, raw_events.len())? {
+                Some(target) => target,
+                None => {
+                    println!("[{}/{}] Skipped '{}'", i + 1, total, name);
+                    continue;
+                }
+            },
+        };
+        ensure_calendar(&target, calendar_name.is_none())?;
+
+        let calendar = calendar::load(&target)?;
+        let report = import_events(&mut batch, &calendar, raw_events, Vec::new(), on_conflict)?;
+        println!(
+            "[{}/{}] Imported '{}' into '{}': {}",
+            i + 1,
+            total,
+            name,
+            target,
+            report
+        );
     }
 
-    Ok(())
+    batch.commit()
 }
 
-fn print_event(event: &calendar::Event, term_width: u16) {
-    print!("   ");
-    let date = event.start.format("%d %b").to_string();
-    let start_time = event.start.format("%H:%M").to_string();
-    let end_time = event.end.format("%H:%M").to_string();
-
-    let location_part = event
-        .location
-        .as_ref()
-        .map_or(String::new(), |loc| format!(" in {}", loc));
+// Asks the calendar to import an archived calendar to, None to skip it
+fn ask_calendar(name: &str, count: usize) -> Result<Option<String>> {
+    let default: String = name
+        .to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join("-")
+        .chars()
+        .filter(|c| c.is_alphanumeric() || "-_.@".contains(*c))
+        .collect();
+    let default = if default.is_empty() {
+        "personal".to_string()
+    } else {
+        default
+    };
 
-    // Format the entire string
-    let formatted_string = format!(
-        "{} {}-{} - {}{}",
-        date, start_time, end_time, event.name, location_part
+    print!(
+        "Calendar for '{}' ({} events, - to skip) [{}]: ",
+        name, count, default
     );
+    let mut input = String::new();
+    std::io::stdout().flush()?;
+    std::io::stdin().read_line(&mut input)?;
+
+    Ok(match input.trim() {
+        "" => Some(default),
+        "-" => None,
+        target => Some(target.to_string()),
+    })
+}
 
-    // Calculate available width
-    let available_width = term_width as usize - 22;
+#[derive(Default)]
+struct ImportReport {
+    imported: usize,
+    overwritten: usize,
+    renamed: usize,
+    skipped: usize,
+}
 
-    // Truncate the formatted string if necessary
-    let truncated_string = if formatted_string.len() > available_width {
-        format!("{}...", &formatted_string[..available_width - 3])
-    } else {
-        formatted_string
-    };
+impl std::fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} new, {} overwritten, {} with a new UID, {} skipped",
+            self.imported, self.overwritten, self.renamed, self.skipped
+        )
+    }
+}
 
-    println!("{}", truncated_string);
+// What to do with an event whose UID is already in the calendar
+enum Resolution {
+    Skip,
+    Overwrite,
+    NewUid,
 }
 
-fn fuzzy_match(text: &str, pattern: &str) -> bool {
-    let text = text.to_lowercase();
-    let pattern = pattern.to_lowercase();
-    let mut text_chars = text.chars();
-    for p in pattern.chars() {
-        if !text_chars.any(|c| c == p) {
-            return false;
+// Writes events to a calendar, the ones whose UID is already there are
+// handled according to the strategy
+fn import_events(
+    batch: &mut storage::Batch,
+    calendar: &calendar::Calendar,
+    raw_events: Vec<(String, String)>,
+    events: Vec<calendar::Event>,
+    mut strategy: cli::ConflictStrategy,
+) -> Result<ImportReport> {
+    // An event overwritten is written in the file it is in, which a server
+    // may have named other than after its UID
+    let files: HashMap<&str, &PathBuf> = calendar
+        .events
+        .iter()
+        .filter(|e| e.recurrence_id.is_none())
+        .filter_map(|e| Some((e.id.as_str(), e.file.as_ref()?)))
+        .collect();
+    let mut known: HashSet<String> = calendar
+        .events
+        .iter()
+        .map(|e| e.id.clone())
+        .chain(calendar.todos.iter().map(|t| t.id.clone()))
+        .chain(calendar.notes.iter().map(|n| n.id.clone()))
+        .collect();
+
+    let mut report = ImportReport::default();
+    let mut resolve = |uid: &str, name: &str, report: &mut ImportReport| -> Result<Resolution> {
+        let resolution = match strategy {
+            cli::ConflictStrategy::Skip => Resolution::Skip,
+            cli::ConflictStrategy::Overwrite => Resolution::Overwrite,
+            cli::ConflictStrategy::NewUid => Resolution::NewUid,
+            cli::ConflictStrategy::Ask => {
+                let (resolution, for_all) = ask_conflict(uid, name)?;
+                if let Some(for_all) = for_all {
+                    strategy = for_all;
+                }
+                resolution
+            }
+        };
+        match resolution {
+            Resolution::Skip => report.skipped += 1,
+            Resolution::Overwrite => report.overwritten += 1,
+            Resolution::NewUid => report.renamed += 1,
         }
-    }
-    true
-}
+        Ok(resolution)
+    };
 
-fn get_real_date(target_year: i32, target_month: u32, target_day: u32) -> Result<NaiveDate> {
-    let mut day = target_day;
-    while day > 0 {
-        if let Some(date) = NaiveDate::from_ymd_opt(target_year, target_month, day) {
-            return Ok(date);
+    for (uid, data) in raw_events {
+        if known.insert(uid.clone()) {
+            batch.write_raw_event(&calendar.path, &uid, &data)?;
+            report.imported += 1;
+            continue;
+        }
+        let name = storage::summary(&data).unwrap_or_default();
+        match resolve(&uid, &name, &mut report)? {
+            Resolution::Skip => {}
+            Resolution::Overwrite => match files.get(uid.as_str()) {
+                Some(file) => batch.replace_file(file, data),
+                None => batch.write_raw_event(&calendar.path, &uid, &data)?,
+            },
+            Resolution::NewUid => {
+                let new_uid = Uuid::new_v4().to_string();
+                let data = storage::replace_uid(&data, &uid, &new_uid);
+                batch.write_raw_event(&calendar.path, &new_uid, &data)?;
+            }
+        }
+    }
+    for mut event in events {
+        if known.insert(event.id.clone()) {
+            calendar.stage_event(batch, event)?;
+            report.imported += 1;
+            continue;
+        }
+        match resolve(&event.id, &event.name, &mut report)? {
+            Resolution::Skip => {}
+            Resolution::Overwrite => {
+                event.file = files.get(event.id.as_str()).map(|file| file.to_path_buf());
+                calendar.stage_event(batch, event)?;
+            }
+            Resolution::NewUid => {
+                event.id = Uuid::new_v4().to_string();
+                calendar.stage_event(batch, event)?;
+            }
         }
-        day -= 1;
     }
 
-    // If we've exhausted all possible days, return an error
-    Err(anyhow!(
-        "Unable to create a valid date for year {}, month {}",
-        target_year,
-        target_month
-    ))
+    Ok(report)
 }
-fn create_personal() -> Result<()> {
-    let calendars = storage::list_calendars()?;
 
-    if !calendars.contains(&"personal".to_string()) {
-        println!("No personal calendar found. Creating it...");
+// Asks what to do with an event whose UID exists, with the strategy to use
+// for the remaining ones when the answer is in uppercase
+fn ask_conflict(uid: &str, name: &str) -> Result<(Resolution, Option<cli::ConflictStrategy>)> {
+    loop {
+        print!(
+            "'{}' ({}) already exists: [s]kip, [o]verwrite, [n]ew UID, uppercase for all? ",
+            name, uid
+        );
+        let mut input = String::new();
+        std::io::stdout().flush()?;
+        if std::io::stdin().read_line(&mut input)? == 0 {
+            return Ok((Resolution::Skip, Some(cli::ConflictStrategy::Skip)));
+        }
+
+        return Ok(match input.trim() {
+            "s" | "" => (Resolution::Skip, None),
+            "o" => (Resolution::Overwrite, None),
+            "n" => (Resolution::NewUid, None),
+            "S" => (Resolution::Skip, Some(cli::ConflictStrategy::Skip)),
+            "O" => (
+                Resolution::Overwrite,
+                Some(cli::ConflictStrategy::Overwrite),
+            ),
+            "N" => (Resolution::NewUid, Some(cli::ConflictStrategy::NewUid)),
+            _ => continue,
+        });
+    }
+}
+
+pub fn export(cmd: cli::CalendarExportArgs) -> Result<()> {
+    if cmd.format == cli::ExportFormat::Html {
+        return export_html(cmd);
+    }
+
+    // Keep the events with at least one occurrence in the range, recurring
+    // events are exported whole rather than expanded
+    let from = cmd
+        .from
+        .map_or(NaiveDateTime::MIN, |from| from.and_time(NaiveTime::MIN));
+    let to = match cmd.to {
+        Some(to) => to
+            .and_hms_opt(23, 59, 59)
+            .ok_or_else(|| anyhow!("Failed to create NaiveDateTime"))?,
+        None => NaiveDateTime::MAX,
+    };
+    let events = load_events(cmd.calendar, from, to, false)?;
+    let mut events: Vec<_> = events
+        .into_iter()
+        .filter(|event| !event.occurrences(from, to).is_empty())
+        .collect();
+    events.sort_by_key(|event| event.start);
+
+    print!("{}", storage::export_events(&events));
+
+    Ok(())
+}
+
+// A standalone page of the month grids or of the agenda, to publish or print
+fn export_html(cmd: cli::CalendarExportArgs) -> Result<()> {
+    let date = cmd
+        .from
+        .unwrap_or_else(|| Local::now().naive_local().date());
+    let (from, to) = match cmd.mode {
+        cli::HtmlMode::Month => {
+            let (first_of_month, _, _) = display::month_layout(date, 0)?;
+            let (_, last_of_month, _) = display::month_layout(date, cmd.number - 1)?;
+            day_range(first_of_month, last_of_month)
+        }
+        cli::HtmlMode::Agenda => agenda_range(date, cmd.number),
+    };
+    let title = cmd.calendar.clone().unwrap_or("Calendar".to_string());
+    let events = load_events(cmd.calendar, from, to, false)?;
+    let mut events = calendar::expand(events, from, to);
+    events.sort_by_key(|event| event.start);
+
+    let page = match cmd.mode {
+        cli::HtmlMode::Month => html::month(&events, date, cmd.number, &title)?,
+        cli::HtmlMode::Agenda => html::agenda(&events, date, cmd.number, &title),
+    };
+    print!("{}", page);
+    Ok(())
+}
+
+pub fn digest(cmd: cli::CalendarDigestArgs) -> Result<()> {
+    let from = cmd.date.and_time(NaiveTime::MIN);
+    let to = cmd
+        .date
+        .and_hms_opt(23, 59, 59)
+        .ok_or_else(|| anyhow!("Failed to create NaiveDateTime"))?;
+    let events = load_events(cmd.calendar, from, to, false)?;
+    let mut events = calendar::expand(events, from, to);
+    events.sort_by_key(|event| event.start);
+
+    let title = cmd.date.format_local("%A, %d %B %Y");
+    let events: Vec<_> = events.iter().collect();
+    let maps = open::agenda_maps()?;
+    let day_template = template::load(None, "day", template::DAY_FORMAT)?;
+    let lines = display::format_day(&events, &day_template, maps.as_deref());
+
+    if cmd.format == cli::DigestFormat::Email {
+        let digest = config::load()?.digest;
+
+        // RFC 5322 headers, the message is meant to be piped to sendmail -t
+        println!("Subject: Agenda for {}", title);
+        println!("Date: {}", Local::now().to_rfc2822());
+        if let Some(from) = digest.from {
+            println!("From: {}", from);
+        }
+        if let Some(to) = digest.to {
+            println!("To: {}", to);
+        }
+        println!("MIME-Version: 1.0");
+        println!("Content-Type: text/plain; charset=utf-8");
+        println!("Content-Transfer-Encoding: 8bit");
+        println!();
+    }
+
+    println!("{}", title);
+    if cmd.format == cli::DigestFormat::Email {
+        println!();
+    }
+
+    if lines.is_empty() {
+        println!("Nothing planned");
+    }
+    for line in lines {
+        println!("{}", line);
+    }
+
+    Ok(())
+}
+
+// The events of the next days under a header for each day, the days with
+// nothing planned marked as such
+pub fn agenda(cmd: cli::CalendarAgendaArgs) -> Result<()> {
+    match cmd.watch {
+        true => watch::run(|| print_agenda(&cmd)),
+        false => print_agenda(&cmd),
+    }
+}
+
+fn print_agenda(cmd: &cli::CalendarAgendaArgs) -> Result<()> {
+    let today = Local::now().naive_local().date();
+    let (from, to) = agenda_range(today, cmd.days);
+    let events = load_events(cmd.calendar.clone(), from, to, false)?;
+    for line in agenda_lines(cmd.days, events, today)? {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+pub fn agenda_range(today: NaiveDate, days: u32) -> freebusy::Interval {
+    day_range(today, today + Duration::days(i64::from(days) - 1))
+}
+
+// The lines of the agenda out of the events loaded for its days
+pub fn agenda_lines(
+    days: u32,
+    events: Vec<calendar::Event>,
+    today: NaiveDate,
+) -> Result<Vec<String>> {
+    let (from, to) = agenda_range(today, days);
+    let mut events = calendar::expand_overlapping(events, from, to);
+    events.sort_by_key(|event| event.start);
+    let maps = open::agenda_maps()?;
+    let day_template = template::load(None, "day", template::DAY_FORMAT)?;
+
+    let mut lines = Vec::new();
+    for (i, day) in today.iter_days().take(days as usize).enumerate() {
+        let (start, end) = day_range(day, day);
+        // Events going on over several days are shown on each of them
+        let events: Vec<_> = events
+            .iter()
+            .filter(|event| event.start < end && (event.end > start || event.start >= start))
+            .collect();
+
+        let header = match i {
+            0 => format!("Today, {}", day.format_local("%A %d %B")),
+            1 => format!("Tomorrow, {}", day.format_local("%A %d %B")),
+            _ => day.format_local("%A %d %B"),
+        };
+        if i > 0 {
+            lines.push(String::new());
+        }
+        lines.push(header.bold().to_string());
+        if events.is_empty() {
+            lines.push(format!("  {}", "Nothing planned".dimmed()));
+        }
+        for line in display::format_day(&events, &day_template, maps.as_deref()) {
+            lines.push(format!("  {}", line));
+        }
+    }
+
+    Ok(lines)
+}
+
+pub fn search(cmd: cli::CalendarSearchArgs) -> Result<()> {
+    let (from, to) = match cmd.range {
+        Some((from, to)) => (
+            from.and_time(NaiveTime::MIN),
+            to.and_hms_opt(23, 59, 59)
+                .ok_or_else(|| anyhow!("Failed to create NaiveDateTime"))?,
+        ),
+        None => (NaiveDateTime::MIN, NaiveDateTime::MAX),
+    };
+
+    // Recurring events are shown once, at their first occurrence in the range
+    let mut events: Vec<_> = search_events(cmd.calendar, &cmd.words, from, to)?
+        .into_iter()
+        .filter_map(|event| match cmd.range {
+            Some(_) => event.occurrences(from, to).into_iter().next(),
+            None => Some(event),
+        })
+        .collect();
+    events.sort_by_key(|event| event.start);
+
+    if let Some(limit) = cmd.limit {
+        events.truncate(limit);
+    }
+
+    if events.is_empty() {
+        println!("No matching events");
+    }
+
+    let id_width = display::short_id_length(events.iter().map(|event| event.id.as_str()));
+    for event in &events {
+        let id_part = if cmd.id {
+            display::id_column(&event.id, id_width)
+        } else {
+            String::new()
+        };
+        let location_part = event
+            .location
+            .as_ref()
+            .map_or(String::new(), |loc| format!(" in {}", loc));
+        let repeat_part = if event.recurrence.is_some() {
+            ", repeats"
+        } else {
+            ""
+        };
+
+        println!(
+            "{}{} {} - {}{} ({}{})",
+            id_part,
+            event.start.format_local("%a %d %b %Y"),
+            event.time_range(),
+            event.name,
+            location_part,
+            event.calendar,
+            repeat_part
+        );
+    }
+
+    Ok(())
+}
+
+// Slots of at least the duration left free by all the calendars, within the
+// work hours of the work days
+pub fn free(cmd: cli::CalendarFreeArgs) -> Result<()> {
+    let busy = load_busy(cmd.calendar, cmd.from, cmd.to)?;
+    let slots = work_slots(cmd.from, cmd.to, cmd.between, cmd.duration, &busy)?;
+    print_slots(&slots);
+    Ok(())
+}
+
+// The days with nothing busy from the start of the evening to midnight, for
+// planning dinners and outings
+pub fn free_evenings(cmd: cli::CalendarFreeEveningsArgs) -> Result<()> {
+    let after = match cmd.after {
+        Some(after) => after,
+        None => date::CalendarTime::parse(&config::load()?.work.evening)
+            .context("Invalid evening in the config")?
+            .inner(),
+    };
+
+    let today = Local::now().naive_local().date();
+    let last_day = today + Duration::days(i64::from(cmd.weeks) * 7 - 1);
+    let busy = load_busy(cmd.calendar, today, last_day)?;
+    let free: Vec<_> = today
+        .iter_days()
+        .take_while(|day| *day <= last_day)
+        .filter(|day| {
+            let (_, end) = day_range(*day, *day);
+            let start = day.and_time(after);
+            !busy
+                .iter()
+                .any(|(busy_start, busy_end)| *busy_start < end && *busy_end > start)
+        })
+        .collect();
+
+    if free.is_empty() {
+        println!("No evening free after {}", after.format("%H:%M"));
+        return Ok(());
+    }
+    println!("Evenings free after {}:", after.format("%H:%M"));
+    for day in free {
+        println!("{}", day.format_local("%a %d %b"));
+    }
+    Ok(())
+}
+
+// Slots free for us and for the attendees whose calendars were given. The
+// event is added at the first one when it is named, and its invitation
+// written out to be sent to them.
+pub fn schedule(cmd: cli::CalendarScheduleArgs) -> Result<()> {
+    let mut busy = load_busy(None, cmd.from, cmd.to)?;
+    let (from, to) = day_range(cmd.from, cmd.to);
+    for file in &cmd.with {
+        busy.extend(freebusy::read_file(Path::new(file), from, to)?);
+    }
+    let busy = freebusy::merge(busy);
+    let slots = work_slots(cmd.from, cmd.to, cmd.between, cmd.duration, &busy)?;
+
+    let Some(name) = cmd.name else {
+        print_slots(&slots);
+        return Ok(());
+    };
+
+    let (start, _) = slots
+        .iter()
+        .flat_map(|(_, slots)| slots.first())
+        .next()
+        .copied()
+        .ok_or_else(|| anyhow!("No common free slot found"))?;
+    ensure_calendar(&cmd.calendar, false)?;
+    let mut calendar = calendar::load(&cmd.calendar)?;
+    let mut event = calendar::Event::builder(name, start, start + cmd.duration).build()?;
+
+    let invite_config = config::load()?.invite;
+    invite::organize(&mut event, cmd.attendees, cmd.send, &invite_config)?;
+    calendar.add_event(event.clone())?;
+    println!(
+        "Added '{}' on {}, {}",
+        event.name,
+        event.start.format_local("%a %d %b"),
+        event.time_range()
+    );
+    invite::deliver(&event, cmd.invite.as_deref(), cmd.send, &invite_config)?;
+
+    Ok(())
+}
+
+// The start of the first day and the end of the last one
+fn day_range(from: NaiveDate, to: NaiveDate) -> freebusy::Interval {
+    (
+        from.and_time(NaiveTime::MIN),
+        (to + Duration::days(1)).and_time(NaiveTime::MIN),
+    )
+}
+
+// The busy blocks of the days, merged across calendars
+pub fn load_busy(
+    calendar_name: Option<String>,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<freebusy::Interval>> {
+    let (from, to) = day_range(from, to);
+    let events = load_events_overlapping(calendar_name, from, to, false)?;
+    let events: Vec<_> = events.iter().collect();
+    Ok(freebusy::busy_blocks(&events, from, to))
+}
+
+// The slots of at least the duration left by the busy blocks within the
+// hours of each work day, by day
+fn work_slots(
+    from: NaiveDate,
+    to: NaiveDate,
+    between: Option<(NaiveTime, NaiveTime)>,
+    duration: Duration,
+    busy: &[freebusy::Interval],
+) -> Result<Vec<(NaiveDate, Vec<freebusy::Interval>)>> {
+    let config = config::load()?;
+    let (start_time, end_time) = match between {
+        Some(between) => between,
+        None => {
+            date::parse_hours(&config.work.hours).context("Invalid work hours in the config")?
+        }
+    };
+    let days = date::parse_days(&config.work.days).context("Invalid work days in the config")?;
+
+    let now = next_quarter(Local::now().naive_local());
+    Ok(slots_by_day(
+        (from, to),
+        (start_time, end_time),
+        &days,
+        duration,
+        busy,
+        now,
+    ))
+}
+
+// Slots start on the next quarter of an hour, not in the past
+fn next_quarter(now: NaiveDateTime) -> NaiveDateTime {
+    let minutes = (now.hour() * 60 + now.minute()) / 15 * 15 + 15;
+    now.date().and_time(NaiveTime::MIN) + Duration::minutes(minutes.into())
+}
+
+// The slots of at least the duration left by the busy blocks within the
+// hours of the days given, from a time on
+fn slots_by_day(
+    (from, to): (NaiveDate, NaiveDate),
+    (start_time, end_time): (NaiveTime, NaiveTime),
+    days: &[Weekday],
+    duration: Duration,
+    busy: &[freebusy::Interval],
+    now: NaiveDateTime,
+) -> Vec<(NaiveDate, Vec<freebusy::Interval>)> {
+    let mut slots = Vec::new();
+    for day in from.iter_days().take_while(|day| *day <= to) {
+        if !days.contains(&day.weekday()) {
+            continue;
+        }
+        let start = day.and_time(start_time).max(now);
+        let end = day.and_time(end_time);
+        if end <= start {
+            continue;
+        }
+
+        let free = freebusy::free_slots(busy, start, end, duration);
+        if !free.is_empty() {
+            slots.push((day, free));
+        }
+    }
+    slots
+}
+
+fn print_slots(slots: &[(NaiveDate, Vec<freebusy::Interval>)]) {
+    if slots.is_empty() {
+        println!("No free slot found");
+    }
+    for (day, slots) in slots {
+        let slots: Vec<_> = slots
+            .iter()
+            .map(|(start, end)| format!("{}-{}", start.format("%H:%M"), end.format("%H:%M")))
+            .collect();
+        println!("{}  {}", day.format_local("%a %d %b"), slots.join(", "));
+    }
+}
+
+// Prints the busy blocks of the days, or a VFREEBUSY of them to send
+pub fn freebusy(cmd: cli::CalendarFreeBusyArgs) -> Result<()> {
+    let blocks = load_busy(cmd.calendar, cmd.from, cmd.to)?;
+    let (from, to) = day_range(cmd.from, cmd.to);
+
+    if cmd.ics {
+        print!("{}", storage::export_freebusy(from, to, &blocks));
+        return Ok(());
+    }
+
+    for day in cmd.from.iter_days().take_while(|day| *day <= cmd.to) {
+        // Blocks going on over midnight are shown on each of their days
+        let (day_start, day_end) = day_range(day, day);
+        let busy: Vec<_> = blocks
+            .iter()
+            .filter(|(start, end)| *start < day_end && *end > day_start)
+            .map(|(start, end)| (*start.max(&day_start), *end.min(&day_end)))
+            .map(|(start, end)| format!("{}-{}", start.format("%H:%M"), end.format("%H:%M")))
+            .collect();
+        let busy = if busy.is_empty() {
+            "free".to_string()
+        } else {
+            busy.join(", ")
+        };
+        println!("{}  {}", day.format_local("%a %d %b"), busy);
+    }
+
+    Ok(())
+}
+
+// The event going on, or else the next one of the coming day, in one short
+// line for status bars, which run it every few seconds: only a day of events
+// is loaded, from the index when it is enabled
+pub fn status(cmd: cli::CalendarStatusArgs) -> Result<()> {
+    let now = Local::now().naive_local();
+    let (from, to) = status_range(now);
+    let events = load_events(cmd.calendar, from, to, false)?;
+    println!("{}", status_line(cmd.format, events, now));
+    Ok(())
+}
+
+pub fn status_range(now: NaiveDateTime) -> freebusy::Interval {
+    (now, now + Duration::days(1))
+}
+
+// The line of status out of the events loaded for the day around now
+pub fn status_line(
+    format: cli::StatusFormat,
+    events: Vec<calendar::Event>,
+    now: NaiveDateTime,
+) -> String {
+    let (from, to) = status_range(now);
+    let mut events = calendar::expand_overlapping(events, from, to);
+    events.retain(|event| !event.all_day && !event.task && event.end > now);
+    events.sort_by_key(|event| event.start);
+
+    let current = events.iter().find(|event| event.is_ongoing(now));
+    let Some(event) = current.or_else(|| events.first()) else {
+        // An empty line clears the segment, an empty text hides the module
+        return match format {
+            cli::StatusFormat::Waybar => serde_json::json!({ "text": "" }).to_string(),
+            _ => String::new(),
+        };
+    };
+
+    let name = template::truncate(&event.name, STATUS_NAME_WIDTH);
+    let text = if event.start <= now {
+        format!(
+            "{} {}, ends in {}",
+            event.start.format("%H:%M"),
+            name,
+            format_countdown(event.end - now)
+        )
+    } else {
+        format!(
+            "{} {} in {}",
+            event.start.format("%H:%M"),
+            name,
+            format_countdown(event.start - now)
+        )
+    };
+
+    let class = if event.start <= now {
+        "ongoing"
+    } else if event.start - now <= Duration::minutes(STATUS_SOON_MINUTES) {
+        "imminent"
+    } else {
+        "upcoming"
+    };
+    // The colors of tmux, and the same in hex for polybar
+    let (color, hex) = match class {
+        "ongoing" => ("red", "#e06c75"),
+        "imminent" => ("yellow", "#e5c07b"),
+        _ => ("green", "#98c379"),
+    };
+
+    match format {
+        cli::StatusFormat::Text => text,
+        // tmux reads # as the start of a format
+        cli::StatusFormat::Tmux => format!("#[fg={}]{}#[default]", color, text.replace('#', "##")),
+        // polybar reads % as the start of a format tag
+        cli::StatusFormat::Polybar => format!("%{{F{}}}{}%{{F-}}", hex, text.replace('%', "%%")),
+        cli::StatusFormat::Waybar => {
+            // The bar shows the time and the name, the tooltip the countdown
+            // and the events of the coming day, styled by the class
+            let coming: Vec<String> = events
+                .iter()
+                .map(|event| match event.start.date() == now.date() {
+                    true => format!("{} {}", event.time_range(), event.name),
+                    false => format!(
+                        "{} {} {}",
+                        relative_day(event.start.date(), now.date()),
+                        event.time_range(),
+                        event.name
+                    ),
+                })
+                .collect();
+            // waybar reads the text as Pango markup
+            let markup = |text: &str| {
+                text.replace('&', "&amp;")
+                    .replace('<', "&lt;")
+                    .replace('>', "&gt;")
+            };
+            serde_json::json!({
+                "text": markup(&format!("{} {}", event.start.format("%H:%M"), name)),
+                "tooltip": markup(&format!("{}\n\n{}", text, coming.join("\n"))),
+                "class": class,
+                "alt": class,
+            })
+            .to_string()
+        }
+    }
+}
+
+// The events going on, with the time left for the timed ones
+pub fn now(cmd: cli::CalendarNowArgs) -> Result<()> {
+    let now = Local::now().naive_local();
+    let mut events = load_events_overlapping(cmd.calendar, now, now, false)?;
+    events.retain(|event| !event.task && event.is_ongoing(now));
+    events.sort_by_key(|event| (!event.all_day, event.start));
+
+    if events.is_empty() {
+        println!("Nothing going on");
+    }
+    for event in events {
+        let location = event
+            .location
+            .as_ref()
+            .map_or(String::new(), |location| format!(" in {}", location));
+        let left = if event.all_day {
+            String::new()
+        } else {
+            format!(", ends in {}", format_countdown(event.end - now))
+        };
+        // The days of events over several days
+        let time = if !event.all_day && event.start.date() != event.end.date() {
+            format!(
+                "{}-{}",
+                event.start.format_local("%a %H:%M"),
+                event.end.format_local("%a %H:%M")
+            )
+        } else {
+            event.time_range()
+        };
+        println!("{} - {}{}{}", time, event.name, location, left);
+    }
+    Ok(())
+}
+
+// The next event to start, within a year or the given time. All-day events
+// are left out like in status. Without one, nothing is printed and the exit
+// code is 1, for shell conditions.
+pub fn next(cmd: cli::CalendarNextArgs) -> Result<()> {
+    let now = Local::now().naive_local();
+    let to = next_horizon(&cmd, now)?;
+    let events = load_events(cmd.calendar.clone(), now, to, false)?;
+
+    match next_line(&cmd, events, now)? {
+        Some(line) => println!("{}", line),
+        None => return Err(CalendarError::NoEvent.into()),
+    }
+    Ok(())
+}
+
+// The end of the time next looks in, refused past the dates supported
+fn next_horizon(cmd: &cli::CalendarNextArgs, now: NaiveDateTime) -> Result<NaiveDateTime> {
+    now.checked_add_signed(cmd.within.unwrap_or(Duration::days(NEXT_HORIZON_DAYS)))
+        .ok_or_else(|| anyhow!("Date is out of the supported range"))
+}
+
+// The next event in the template of next, out of the events loaded for the
+// time it looks in
+pub fn next_line(
+    cmd: &cli::CalendarNextArgs,
+    events: Vec<calendar::Event>,
+    now: NaiveDateTime,
+) -> Result<Option<String>> {
+    let template = template::load(cmd.format.clone(), "default", template::DEFAULT_FORMAT)?;
+
+    let to = next_horizon(cmd, now)?;
+    let events = calendar::expand(events, now, to);
+    let next = events
+        .iter()
+        .filter(|event| !event.all_day && !event.task && event.start > now && event.start <= to)
+        .min_by_key(|event| event.start);
+    Ok(next.map(|event| template.render(event)))
+}
+
+// Minutes left, rounded up so that an event is never said to start in 0m
+fn format_countdown(left: Duration) -> String {
+    let minutes = (left.num_seconds() + 59) / 60;
+    match (minutes / 60, minutes % 60) {
+        (0, minutes) => format!("{}m", minutes),
+        (hours, 0) => format!("{}h", hours),
+        (hours, minutes) => format!("{}h{:02}m", hours, minutes),
+    }
+}
+
+// Today's events and the next one in a box, plain text for /etc/motd
+pub fn motd(cmd: cli::CalendarMotdArgs) -> Result<()> {
+    let now = Local::now().naive_local();
+    let today = now.date();
+    let from = today.and_time(NaiveTime::MIN);
+    let to = from + Duration::days(MOTD_HORIZON_DAYS);
+    let events = load_events(cmd.calendar, from, to, false)?;
+    let mut events = calendar::expand(events, from, to);
+    events.sort_by_key(|event| event.start);
+
+    let today_events: Vec<_> = events
+        .iter()
+        .filter(|event| event.start.date() == today)
+        .collect();
+    let next = events
+        .iter()
+        .find(|event| (!event.all_day && event.start > now) || event.start.date() > today);
+
+    let day_template = template::load(None, "day", template::DAY_FORMAT)?;
+    let list_template = template::load(None, "default", template::DEFAULT_FORMAT)?;
+    let mut lines = display::format_day(&today_events, &day_template, None);
+    // The last line left tells how many events do not fit
+    if lines.len() > cmd.max {
+        let more = lines.len() - (cmd.max - 1);
+        lines.truncate(cmd.max - 1);
+        lines.push(format!("and {} more", more));
+    }
+    if lines.is_empty() {
+        lines.push("Nothing planned today".to_string());
+    }
+
+    let inner = cmd.width - 4;
+    let title: String = format!(" {} ", today.format_local("%A %d %B"))
+        .chars()
+        .take(cmd.width - 3)
+        .collect();
+    let fit = |text: &str| -> String {
+        format!(
+            "│ {:<width$} │",
+            template::truncate(text, inner),
+            width = inner
+        )
+    };
+
+    println!(
+        "╭─{}{}╮",
+        title,
+        "─".repeat((cmd.width - 3).saturating_sub(title.chars().count()))
+    );
+    for line in &lines {
+        println!("{}", fit(line));
+    }
+    if let Some(next) = next {
+        println!("├{}┤", "─".repeat(cmd.width - 2));
+        println!(
+            "{}",
+            fit(&format!(
+                "Next: {} ({})",
+                list_template.render(next),
+                relative_day(next.start.date(), today)
+            ))
+        );
+    }
+    println!("╰{}╯", "─".repeat(cmd.width - 2));
+
+    Ok(())
+}
+
+pub fn when(cmd: cli::CalendarWhenArgs) -> Result<()> {
+    let events = search_events(
+        cmd.calendar,
+        &cmd.words,
+        NaiveDateTime::MIN,
+        NaiveDateTime::MAX,
+    )?;
+
+    // Events named after the query are better answers than the ones that
+    // only mention it
+    let words: Vec<String> = cmd.words.iter().map(|word| word.to_lowercase()).collect();
+    let named: Vec<_> = events
+        .iter()
+        .filter(|event| {
+            let name = event.name.to_lowercase();
+            words.iter().all(|word| name.contains(word.as_str()))
+        })
+        .collect();
+    let candidates = if named.is_empty() {
+        events.iter().collect()
+    } else {
+        named
+    };
+
+    // The next occurrence, or else the last one
+    let now = Local::now().naive_local();
+    let next = candidates
+        .iter()
+        .filter_map(|event| {
+            event
+                .occurrences(now, now + Duration::days(WHEN_HORIZON_DAYS))
+                .into_iter()
+                .next()
+        })
+        .min_by_key(|event| event.start);
+    let answer = match next {
+        Some(event) => event,
+        None => candidates
+            .iter()
+            .filter_map(|event| event.occurrences(event.start, now).pop())
+            .max_by_key(|event| event.start)
+            .ok_or_else(|| anyhow!("No event matches '{}'", cmd.words.join(" ")))?,
+    };
+
+    let today = now.date();
+    let date_format = if answer.start.year() == today.year() {
+        "%A %d %b"
+    } else {
+        "%A %d %b %Y"
+    };
+    let time_part = if answer.all_day {
+        String::new()
+    } else {
+        answer.start.format(", %H:%M").to_string()
+    };
+
+    println!(
+        "{}: {}{} — {}",
+        answer.name,
+        answer.start.format_local(date_format),
+        time_part,
+        relative_day(answer.start.date(), today)
+    );
+
+    Ok(())
+}
+
+// How far a day is from today, in words
+pub fn relative_day(date: NaiveDate, today: NaiveDate) -> String {
+    let days = (date - today).num_days();
+    let amount = |n: i64| match n.abs() {
+        n if n < 60 => format!("{} days", n),
+        n if n < 730 => format!("{} months", n / 30),
+        n => format!("{} years", n / 365),
+    };
+
+    match days {
+        0 => "today".to_string(),
+        1 => "tomorrow".to_string(),
+        -1 => "yesterday".to_string(),
+        days if days > 0 => format!("in {}", amount(days)),
+        days => format!("{} ago", amount(days)),
+    }
+}
+
+// The events with every word in their name, description or location, found
+// with the full-text index when it is enabled
+fn search_events(
+    calendar_name: Option<String>,
+    words: &[String],
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+) -> Result<Vec<calendar::Event>> {
+    let config = config::load()?;
+    let words: Vec<String> = words.iter().map(|word| word.to_lowercase()).collect();
+    let matches = |event: &calendar::Event| {
+        let text = [
+            Some(&event.name),
+            event.description.as_ref(),
+            event.location.as_ref(),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|field| field.to_lowercase())
+        .collect::<Vec<_>>()
+        .join("\n");
+        words.iter().all(|word| text.contains(word.as_str()))
+    };
+
+    let subscribed = |name: &str| config.subscriptions.contains_key(name);
+    if !config.index.enabled || calendar_name.as_deref().is_some_and(subscribed) {
+        let mut events = load_events(calendar_name, from, to, false)?;
+        events.retain(matches);
+        return Ok(events);
+    }
+
+    if let Some(calendar_name) = &calendar_name {
+        if !storage::calendar_exists(calendar_name)? {
+            return Err(error::calendar_not_found(calendar_name));
+        }
+    }
+
+    let mut events = indexed_search(calendar_name.as_deref(), &words, from, to)?;
+    if calendar_name.is_none() {
+        for name in config.subscriptions.keys() {
+            events.extend(subscription::events(name)?.into_iter().filter(matches));
+        }
+    }
+
+    Ok(events)
+}
+
+// Prints the fields that differ between two versions of an event, returns
+// false when nothing changed
+fn print_diff(before: &calendar::Event, after: &calendar::Event) -> bool {
+    let datetime = |date: chrono::NaiveDateTime| date.format_local("%a %d %b %Y %H:%M");
+    let optional = |value: &Option<String>| value.clone().unwrap_or_default();
+
+    let fields = [
+        ("Name", before.name.clone(), after.name.clone()),
+        ("Start", datetime(before.start), datetime(after.start)),
+        ("End", datetime(before.end), datetime(after.end)),
+        (
+            "Location",
+            optional(&before.location),
+            optional(&after.location),
+        ),
+        (
+            "Description",
+            optional(&before.description),
+            optional(&after.description),
+        ),
+    ];
+
+    let mut changed = false;
+    for (label, old, new) in fields.iter().filter(|(_, old, new)| old != new) {
+        let label = format!("{}:", label);
+        println!("{:<13}{}", label.bold(), format!("- {}", old).red());
+        println!("{:<13}{}", "", format!("+ {}", new).green());
+        changed = true;
+    }
+
+    changed
+}
+
+pub fn confirm(prompt: &str) -> Result<bool> {
+    print!("{} (y/N) ", prompt);
+    let mut input = String::new();
+    std::io::stdout().flush()?;
+    std::io::stdin().read_line(&mut input)?;
+    Ok(input.trim().to_lowercase() == "y")
+}
+
+// An event by id, or by name when no id starts with the query: the events
+// named like it, else the ones whose name contains it, else the ones it fuzzily
+// matches. Several matches are chosen from in a menu, or fail when there is no
+// terminal to show it, for scripts to use ids.
+pub fn select_event<'a>(events: &'a [calendar::Event], query: &str) -> Result<&'a calendar::Event> {
+    if events.iter().any(|event| event.id.starts_with(query)) {
+        return calendar::event_by_id(events, query);
+    }
+
+    let lowercase = query.to_lowercase();
+    let tiers: [&dyn Fn(&calendar::Event) -> bool; 3] = [
+        &|event| event.name.to_lowercase() == lowercase,
+        &|event| event.name.to_lowercase().contains(&lowercase),
+        &|event| fuzzy::matches(&event.name, query),
+    ];
+    let mut matches: Vec<&calendar::Event> = tiers
+        .iter()
+        .map(|matches| {
+            events
+                .iter()
+                // The exceptions of a series are chosen with it
+                .filter(|event| event.recurrence_id.is_none() && matches(event))
+                .collect::<Vec<_>>()
+        })
+        .find(|matches| !matches.is_empty())
+        .ok_or_else(|| {
+            CalendarError::NotFound("Could not find event with this uuid or name".to_string())
+        })?;
+    // The best matches first
+    matches.sort_by_key(|event| {
+        (
+            std::cmp::Reverse(fuzzy::score(&event.name, query)),
+            event.start,
+        )
+    });
+
+    if let [event] = matches.as_slice() {
+        return Ok(event);
+    }
+    let describe = |event: &calendar::Event| {
+        format!(
+            "{} {} - {}",
+            event.start.format_local("%a %d %b %Y"),
+            event.time_range(),
+            event.name
+        )
+    };
+    if !std::io::stdin().is_terminal() || !std::io::stderr().is_terminal() {
+        let candidates: Vec<String> = matches
+            .iter()
+            .map(|event| format!("  {}  {}", event.id, describe(event)))
+            .collect();
+        return Err(CalendarError::Ambiguous(format!(
+            "'{}' matches several events, use one of their ids:\n{}",
+            query,
+            candidates.join("\n")
+        ))
+        .into());
+    }
+
+    let items: Vec<String> = matches.iter().map(|event| describe(event)).collect();
+    match picker::pick(&format!("'{}' matches several events:", query), &items)? {
+        Some(index) => Ok(matches[index]),
+        None => Err(anyhow!("No event chosen")),
+    }
+}
+
+// Loads the events of a calendar, or of all calendars, that may be going on
+// between from and to once expanded. Tasks with a due date are added when asked for
+// and enabled in the config.
+pub fn load_events(
+    calendar_name: Option<String>,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    with_tasks: bool,
+) -> Result<Vec<calendar::Event>> {
+    let config = config::load()?;
+    let with_tasks = with_tasks && config.taskwarrior.enabled;
+    let use_index = config.index.enabled;
+    let subscribed = |name: &str| config.subscriptions.contains_key(name);
+
+    let mut events = match calendar_name.as_deref() {
+        Some(taskwarrior::CALENDAR) if with_tasks => Vec::new(),
+        Some(calendar_name) if subscribed(calendar_name) => Vec::new(),
+        Some(calendar_name) if use_index => {
+            if calendar_name == "personal" {
+                create_personal()?;
+            }
+            if !storage::calendar_exists(calendar_name)? {
+                return Err(error::calendar_not_found(calendar_name));
+            }
+
+            indexed_events(Some(calendar_name), from, to)?
+        }
+        Some(calendar_name) => {
+            if calendar_name == "personal" {
+                create_personal()?;
+            }
+
+            calendar::load_between(calendar_name, from, to)?.events
+        }
+        None if use_index => indexed_events(None, from, to)?,
+        None => calendar::load_all_between(from, to)?
+            .into_iter()
+            .flat_map(|calendar| calendar.events)
+            .collect(),
+    };
+
+    for name in config.subscriptions.keys() {
+        if calendar_name
+            .as_deref()
+            .is_none_or(|calendar| calendar == name)
+        {
+            events.extend(subscription::events(name)?);
+        }
+    }
+
+    let tasks_shown = calendar_name
+        .as_deref()
+        .is_none_or(|name| name == taskwarrior::CALENDAR);
+    if with_tasks && tasks_shown {
+        // Tasks are extra information, don't fail the whole view on them
+        match taskwarrior::tasks(&config.taskwarrior) {
+            Ok(tasks) => events.extend(tasks),
+            Err(err) => eprintln!("Warning: could not load taskwarrior tasks: {:#}", err),
+        }
+    }
+
+    Ok(events)
+}
+
+// The occurrences going on between from and to of the events of a calendar,
+// or of all calendars, like load_events then expand, the ones that started
+// before from included whatever their length
+pub fn load_events_overlapping(
+    calendar_name: Option<String>,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    with_tasks: bool,
+) -> Result<Vec<calendar::Event>> {
+    let events = load_events(calendar_name, from, to, with_tasks)?;
+    Ok(calendar::expand_overlapping(events, from, to))
+}
+
+// The events of the SQLite index, brought up to date with the files first
+#[cfg(feature = "sqlite")]
+fn indexed_events(
+    calendar_name: Option<&str>,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+) -> Result<Vec<calendar::Event>> {
+    let (index, _) = index::Index::open()?;
+    index.events(calendar_name, from, to)
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn indexed_events(
+    _calendar_name: Option<&str>,
+    _from: NaiveDateTime,
+    _to: NaiveDateTime,
+) -> Result<Vec<calendar::Event>> {
+    Err(anyhow!(NO_SQLITE))
+}
+
+#[cfg(feature = "sqlite")]
+fn indexed_search(
+    calendar_name: Option<&str>,
+    words: &[String],
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+) -> Result<Vec<calendar::Event>> {
+    let (index, _) = index::Index::open()?;
+    index.search(calendar_name, words, from, to)
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn indexed_search(
+    _calendar_name: Option<&str>,
+    _words: &[String],
+    _from: NaiveDateTime,
+    _to: NaiveDateTime,
+) -> Result<Vec<calendar::Event>> {
+    Err(anyhow!(NO_SQLITE))
+}
+
+#[cfg(not(feature = "sqlite"))]
+const NO_SQLITE: &str =
+    "The index needs calendar-rs to be built with the sqlite feature (cargo install --features sqlite)";
+
+#[cfg(feature = "sqlite")]
+pub fn index(cmd: cli::CalendarIndexArgs) -> Result<()> {
+    let (_, report) = if cmd.rebuild {
+        index::Index::rebuild()?
+    } else {
+        index::Index::open()?
+    };
+    println!("Index up to date: {}", report);
+    Ok(())
+}
+
+// Taskwarrior tasks and subscriptions can only be changed at their source
+fn check_writable(calendar_name: &str) -> Result<()> {
+    if calendar_name == taskwarrior::CALENDAR {
+        return Err(anyhow!(
+            "Taskwarrior tasks are read only, change them with task"
+        ));
+    }
+    if config::load()?.subscriptions.contains_key(calendar_name) {
+        return Err(anyhow!(
+            "'{}' is a read-only subscription, change it at its source",
+            calendar_name
+        ));
+    }
+    Ok(())
+}
+
+// Checks that a calendar exists before adding to it, creating it when asked
+pub fn ensure_calendar(calendar_name: &str, create: bool) -> Result<()> {
+    create_personal()?;
+    check_writable(calendar_name)?;
+
+    if !storage::list_calendars()?
+        .iter()
+        .any(|name| name == calendar_name)
+    {
+        if !create {
+            return Err(CalendarError::NotFound(format!(
+                "Calendar '{}' not found, use --create to create it",
+                calendar_name
+            ))
+            .into());
+        }
+        storage::create_calendar(calendar_name)?;
+        println!("Created calendar '{}'", calendar_name);
+    }
+
+    Ok(())
+}
+
+// An event of a calendar or of a subscription, by id
+pub fn find_event(calendar_name: &str, id: &str) -> Result<calendar::Event> {
+    if calendar_name == "personal" {
+        create_personal()?;
+    }
+
+    let events = if config::load()?.subscriptions.contains_key(calendar_name) {
+        subscription::events(calendar_name)?
+    } else {
+        calendar::load(calendar_name)?.events
+    };
+    select_event(&events, id).cloned()
+}
+
+pub fn create_personal() -> Result<()> {
+    let calendars = storage::list_calendars()?;
+
+    if !calendars.contains(&"personal".to_string()) {
+        println!("No personal calendar found. Creating it...");
         storage::create_personal()?;
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(day: u32, hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 10, day)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap()
+    }
+
+    fn merged(base: &str, mine: &str, theirs: &str, yes: bool) -> Result<String> {
+        let mut theirs = theirs.to_string();
+        merge_field(
+            "Name",
+            &base.to_string(),
+            &mine.to_string(),
+            &mut theirs,
+            |name| name.clone(),
+            yes,
+        )?;
+        Ok(theirs)
+    }
+
+    #[test]
+    fn edits_keep_the_fields_changed_on_one_side() {
+        // Changed by the edit only, by the file only, or the same way
+        assert_eq!(merged("a", "b", "a", true).unwrap(), "b");
+        assert_eq!(merged("a", "a", "c", true).unwrap(), "c");
+        assert_eq!(merged("a", "b", "b", true).unwrap(), "b");
+        // Changed both ways, without anyone to ask
+        assert!(merged("a", "b", "c", true).is_err());
+    }
+
+    #[test]
+    fn edits_are_merged_into_the_file_as_it_is_now() {
+        let dir = std::env::temp_dir().join(format!("calendar-rs-merge-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("event.ics");
+
+        let base = calendar::Event::builder("Review", at(16, 10), at(16, 11))
+            .id("event")
+            .build()
+            .unwrap();
+        // A sync moved the event, while the edit renamed it
+        let mut theirs = base.clone();
+        theirs.start = at(17, 10);
+        theirs.end = at(17, 11);
+        fs::write(&file, storage::export_events(&[theirs])).unwrap();
+        let mut mine = base.clone();
+        mine.name = "Design review".to_string();
+        mine.file = Some(file.clone());
+
+        let event = merge_edit(&base, mine.clone(), &file, true).unwrap();
+        assert_eq!(event.name, "Design review");
+        assert_eq!((event.start, event.end), (at(17, 10), at(17, 11)));
+        assert_eq!(event.file, Some(file.clone()));
+
+        // Moved both ways
+        mine.start = at(18, 10);
+        mine.end = at(18, 11);
+        assert!(merge_edit(&base, mine, &file, true).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn slots_start_on_the_next_quarter_of_an_hour() {
+        let time = |day, hour, minute| {
+            NaiveDate::from_ymd_opt(2026, 10, day)
+                .unwrap()
+                .and_hms_opt(hour, minute, 30)
+                .unwrap()
+        };
+        assert_eq!(
+            next_quarter(time(16, 10, 7)),
+            at(16, 10) + Duration::minutes(15)
+        );
+        assert_eq!(
+            next_quarter(time(16, 10, 0)),
+            at(16, 10) + Duration::minutes(15)
+        );
+        assert_eq!(next_quarter(time(16, 10, 45)), at(16, 11));
+        assert_eq!(next_quarter(time(16, 23, 50)), at(17, 0));
+    }
+
+    #[test]
+    fn work_slots_leave_out_the_busy_times_and_the_past() {
+        let hours = (
+            NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+        );
+        let days = [Weekday::Fri, Weekday::Mon];
+        let day = |day| NaiveDate::from_ymd_opt(2026, 10, day).unwrap();
+        // From the evening before until late morning, then a short gap
+        let busy = [
+            (at(15, 20), at(16, 11)),
+            (at(16, 11) + Duration::minutes(30), at(16, 16)),
+        ];
+
+        let slots = slots_by_day(
+            (day(15), day(19)),
+            hours,
+            &days,
+            Duration::hours(1),
+            &busy,
+            at(16, 8),
+        );
+        assert_eq!(
+            slots,
+            [
+                (day(16), vec![(at(16, 16), at(16, 17))]),
+                (day(19), vec![(at(19, 9), at(19, 17))]),
+            ]
+        );
+
+        // Nothing is left of a day already over
+        let slots = slots_by_day(
+            (day(16), day(16)),
+            hours,
+            &days,
+            Duration::hours(1),
+            &[],
+            at(16, 17),
+        );
+        assert!(slots.is_empty());
+    }
+
+    #[test]
+    fn events_overwritten_keep_their_file() {
+        let dir =
+            std::env::temp_dir().join(format!("calendar-rs-overwrite-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("server-href-123.ics");
+        fs::write(&file, "BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n").unwrap();
+
+        let mut event = calendar::Event::builder("Review", at(16, 10), at(16, 11))
+            .build()
+            .unwrap();
+        event.id = "shared-uid".to_string();
+        event.file = Some(file.clone());
+        let calendar = calendar::Calendar {
+            name: "work".to_string(),
+            path: dir.clone(),
+            events: vec![event],
+            todos: Vec::new(),
+            notes: Vec::new(),
+        };
+
+        let data = "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nUID:shared-uid\r\nSUMMARY:Moved\r\n\
+                    END:VEVENT\r\nEND:VCALENDAR\r\n";
+        let mut batch = storage::Batch::default();
+        let report = import_events(
+            &mut batch,
+            &calendar,
+            vec![("shared-uid".to_string(), data.to_string())],
+            Vec::new(),
+            cli::ConflictStrategy::Overwrite,
+        )
+        .unwrap();
+        batch.commit().unwrap();
+
+        assert_eq!(report.overwritten, 1);
+        assert_eq!(fs::read_to_string(&file).unwrap(), data);
+        assert!(!dir.join("shared-uid.ics").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,418 @@
+use crate::calendar::{Event, Recurrence};
+use crate::cli::RepeatFrequency;
+use crate::paths::{self, expand_home};
+use crate::storage;
+use anyhow::{anyhow, Context, Result};
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+// The (uid, content) pairs of the events of a calendar
+type RawEvents = Vec<(String, String)>;
+
+lazy_static! {
+    // MM/DD/YYYY @ HH:MM -> MM/DD/YYYY @ HH:MM {rec} >note |description
+    static ref CALCURSE_APPOINTMENT_REGEX: Regex = Regex::new(
+        r"^(\d{2}/\d{2}/\d{4}) @ (\d{2}:\d{2}) -> (\d{2}/\d{2}/\d{4}) @ (\d{2}:\d{2})\s*(\{[^}]*\})?\s*(?:>(\S+))?\s*[|!](.*)$"
+    )
+    .unwrap();
+    // MM/DD/YYYY [id] {rec} >note description
+    static ref CALCURSE_EVENT_REGEX: Regex =
+        Regex::new(r"^(\d{2}/\d{2}/\d{4}) \[\d+\]\s*(\{[^}]*\})?\s*(?:>(\S+) )?(.*)$").unwrap();
+    // {N[DWMY] -> MM/DD/YYYY !exception ...}
+    static ref CALCURSE_RECURRENCE_REGEX: Regex =
+        Regex::new(r"^\{(\d+)([DWMY])(?: -> (\d{2}/\d{2}/\d{4}))?(.*)\}$").unwrap();
+}
+
+// Reads the ICS files of the calendars listed in a khal config, as (uid,
+// content) pairs ready to be written in our storage
+pub fn khal_events(config: Option<&Path>) -> Result<RawEvents> {
+    let config = match config {
+        Some(config) => config.to_path_buf(),
+        None => khal_config()?,
+    };
+    let content = fs::read_to_string(&config)
+        .with_context(|| format!("Failed to read khal config '{}'", config.display()))?;
+
+    let mut events = Vec::new();
+    for path in khal_calendar_paths(&content)? {
+        for directory in expand_glob(&path)? {
+            for entry in fs::read_dir(&directory)
+                .with_context(|| format!("Failed to read '{}'", directory.display()))?
+            {
+                let path = entry?.path();
+                if path.extension().is_some_and(|ext| ext == "ics") {
+                    let content = fs::read_to_string(&path)?;
+                    events.extend(storage::split_events(&content));
+                }
+            }
+        }
+    }
+
+    Ok(events)
+}
+
+fn khal_config() -> Result<PathBuf> {
+    let config_dir = paths::xdg_config_home()?;
+    let home_dir = dirs::home_dir().ok_or_else(|| anyhow!("Unable to determine home directory"))?;
+
+    [
+        config_dir.join("khal").join("config"),
+        home_dir.join(".khal").join("khal.conf"),
+    ]
+    .into_iter()
+    .find(|path| path.is_file())
+    .ok_or_else(|| anyhow!("No khal config found, give its path as FILE"))
+}
+
+// The paths of the [[calendar]] subsections of the [calendars] section
+fn khal_calendar_paths(config: &str) -> Result<Vec<PathBuf>> {
+    let mut in_calendars = false;
+    let mut paths = Vec::new();
+
+    for line in config.lines().map(str::trim) {
+        if line.starts_with("[[") {
+            continue;
+        }
+        if line.starts_with('[') {
+            in_calendars = line == "[calendars]";
+            continue;
+        }
+        if !in_calendars {
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            if key.trim() == "path" {
+                paths.push(expand_home(value.trim().trim_matches('"'))?);
+            }
+        }
+    }
+
+    if paths.is_empty() {
+        return Err(anyhow!("No calendar found in the khal config"));
+    }
+
+    Ok(paths)
+}
+
+// khal calendars of type discover use * in their path to match directories
+fn expand_glob(path: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = vec![PathBuf::new()];
+
+    for component in path.components() {
+        let component = component.as_os_str().to_string_lossy();
+        if !component.contains('*') {
+            paths.iter_mut().for_each(|path| path.push(&*component));
+            continue;
+        }
+
+        let mut matches = Vec::new();
+        for path in paths.iter().filter(|path| path.is_dir()) {
+            for entry in fs::read_dir(path)? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().to_string();
+                if entry.file_type()?.is_dir() && wildcard_match(&component, &name) {
+                    matches.push(entry.path());
+                }
+            }
+        }
+        paths = matches;
+    }
+
+    Ok(paths.into_iter().filter(|path| path.is_dir()).collect())
+}
+
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let mut rest = match name.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        if i == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.is_empty()
+}
+
+// Reads the appointments and events of a calcurse data directory
+pub fn calcurse_events(directory: Option<&Path>) -> Result<Vec<Event>> {
+    let directory = match directory {
+        Some(directory) => directory.to_path_buf(),
+        None => calcurse_dir()?,
+    };
+    let apts = directory.join("apts");
+    let content = fs::read_to_string(&apts)
+        .with_context(|| format!("Failed to read calcurse data '{}'", apts.display()))?;
+
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            parse_calcurse_line(line, &directory)
+                .with_context(|| format!("Failed to import line {} of '{}'", i + 1, apts.display()))
+        })
+        .collect()
+}
+
+fn calcurse_dir() -> Result<PathBuf> {
+    let data_dir = paths::xdg_data_home()?;
+    let home_dir = dirs::home_dir().ok_or_else(|| anyhow!("Unable to determine home directory"))?;
+
+    [data_dir.join("calcurse"), home_dir.join(".calcurse")]
+        .into_iter()
+        .find(|path| path.join("apts").is_file())
+        .ok_or_else(|| anyhow!("No calcurse data found, give its directory as FILE"))
+}
+
+fn parse_calcurse_line(line: &str, directory: &Path) -> Result<Event> {
+    let (start, end, all_day, recurrence, note, name) =
+        if let Some(caps) = CALCURSE_APPOINTMENT_REGEX.captures(line) {
+            let start = parse_calcurse_datetime(&caps[1], &caps[2])?;
+            let end = parse_calcurse_datetime(&caps[3], &caps[4])?;
+            (
+                start,
+                end,
+                false,
+                caps.get(5),
+                caps.get(6),
+                caps[7].to_string(),
+            )
+        } else if let Some(caps) = CALCURSE_EVENT_REGEX.captures(line) {
+            let start = parse_calcurse_date(&caps[1])?.and_time(NaiveTime::MIN);
+            (
+                start,
+                start + Duration::days(1),
+                true,
+                caps.get(2),
+                caps.get(3),
+                caps[4].to_string(),
+            )
+        } else {
+            return Err(anyhow!("Unrecognized calcurse entry"));
+        };
+
+    // Notes are stored in files named after their hash
+    let description = match note {
+        Some(note) => Some(
+            fs::read_to_string(directory.join("notes").join(note.as_str()))?
+                .trim_end()
+                .to_string(),
+        ),
+        None => None,
+    };
+
+    Event::builder(name.trim(), start, end)
+        .description(description)
+        .all_day(all_day)
+        .recurrence(
+            recurrence
+                .map(|recurrence| parse_calcurse_recurrence(recurrence.as_str()))
+                .transpose()?,
+        )
+        .id(Uuid::new_v5(&Uuid::NAMESPACE_OID, line.as_bytes()).to_string())
+        .build()
+}
+
+fn parse_calcurse_recurrence(value: &str) -> Result<Recurrence> {
+    let caps = CALCURSE_RECURRENCE_REGEX
+        .captures(value)
+        .ok_or_else(|| anyhow!("Unsupported calcurse recurrence '{}'", value))?;
+
+    if !caps[4].trim().is_empty() {
+        eprintln!(
+            "Warning: ignoring the exceptions of calcurse recurrence '{}'",
+            value
+        );
+    }
+
+    let frequency = match &caps[2] {
+        "D" => RepeatFrequency::Daily,
+        "W" => RepeatFrequency::Weekly,
+        "M" => RepeatFrequency::Monthly,
+        _ => RepeatFrequency::Yearly,
+    };
+    let until = caps
+        .get(3)
+        .map(|until| parse_calcurse_date(until.as_str()))
+        .transpose()?
+        .map(|until| until.and_time(NaiveTime::MIN));
+
+    Ok(Recurrence {
+        frequency,
+        interval: caps[1].parse::<u32>()?.max(1),
+        count: None,
+        until,
+        by_day: Vec::new(),
+        month_end: false,
+        excluded: Vec::new(),
+    })
+}
+
+fn parse_calcurse_date(date: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(date, "%m/%d/%Y").with_context(|| format!("Invalid date '{}'", date))
+}
+
+fn parse_calcurse_datetime(date: &str, time: &str) -> Result<NaiveDateTime> {
+    let time = NaiveTime::parse_from_str(time, "%H:%M")
+        .with_context(|| format!("Invalid time '{}'", time))?;
+    Ok(parse_calcurse_date(date)?.and_time(time))
+}
+
+// Reads the ICS files of a Google Takeout or Outlook export archive, as the
+// name of each calendar (the file name) with its (uid, content) pairs
+pub fn takeout_calendars(archive: &Path) -> Result<Vec<(String, RawEvents)>> {
+    let file =
+        File::open(archive).with_context(|| format!("Failed to open '{}'", archive.display()))?;
+    let mut zip = zip::ZipArchive::new(file)
+        .with_context(|| format!("'{}' is not a zip archive", archive.display()))?;
+
+    let mut calendars = Vec::new();
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i)?;
+        let path = match entry.enclosed_name() {
+            Some(path) => path,
+            None => continue,
+        };
+        let is_ics = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("ics"));
+        if !entry.is_file() || !is_ics {
+            continue;
+        }
+
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let mut content = String::new();
+        entry
+            .read_to_string(&mut content)
+            .with_context(|| format!("Failed to read '{}' in the archive", path.display()))?;
+        calendars.push((name, storage::split_events(&content)));
+    }
+
+    if calendars.is_empty() {
+        return Err(anyhow!("No ICS file found in '{}'", archive.display()));
+    }
+
+    Ok(calendars)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(month: u32, day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, month, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn khal_paths_are_read_from_the_calendars_section() {
+        let config = "[calendars]\n\
+                      [[work]]\n\
+                      path = ~/.calendars/work/\n\
+                      [[shared]]\n\
+                      path = \"/srv/calendars/*\"\n\
+                      type = discover\n\
+                      [locale]\n\
+                      path = /not/a/calendar\n";
+        let paths = khal_calendar_paths(config).unwrap();
+        assert_eq!(paths.len(), 2);
+        assert!(paths[0].ends_with(".calendars/work"));
+        assert_eq!(paths[1], PathBuf::from("/srv/calendars/*"));
+        assert!(khal_calendar_paths("[locale]\npath = /x\n").is_err());
+    }
+
+    #[test]
+    fn khal_globs_match_directories() {
+        assert!(wildcard_match("*", "personal"));
+        assert!(wildcard_match("cal-*", "cal-work"));
+        assert!(wildcard_match("*-work", "cal-work"));
+        assert!(wildcard_match("a*b*c", "axxbyyc"));
+        assert!(!wildcard_match("cal-*", "work"));
+        assert!(!wildcard_match("a*b*c", "axxbyy"));
+
+        let dir = std::env::temp_dir().join(format!("calendar-rs-khal-{}", std::process::id()));
+        for calendar in ["cal-a", "cal-b", "other"] {
+            fs::create_dir_all(dir.join(calendar)).unwrap();
+        }
+        fs::write(dir.join("cal-file"), "").unwrap();
+        let mut paths = expand_glob(&dir.join("cal-*")).unwrap();
+        paths.sort();
+        assert_eq!(paths, [dir.join("cal-a"), dir.join("cal-b")]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn calcurse_appointments_and_events_are_read() {
+        let dir = std::env::temp_dir().join(format!("calendar-rs-calcurse-{}", std::process::id()));
+        fs::create_dir_all(dir.join("notes")).unwrap();
+        fs::write(dir.join("notes").join("abc123"), "Bring slides\n").unwrap();
+
+        let event = parse_calcurse_line(
+            "10/16/2026 @ 09:30 -> 10/16/2026 @ 10:00 >abc123 |Review",
+            &dir,
+        )
+        .unwrap();
+        assert_eq!(event.name, "Review");
+        assert_eq!(
+            (event.start, event.end),
+            (at(10, 16, 9, 30), at(10, 16, 10, 0))
+        );
+        assert_eq!(event.description.as_deref(), Some("Bring slides"));
+        assert!(!event.all_day && event.recurrence.is_none());
+
+        let event = parse_calcurse_line("10/19/2026 [1] {1Y} Birthday", &dir).unwrap();
+        assert_eq!(event.name, "Birthday");
+        assert!(event.all_day);
+        assert_eq!(
+            (event.start, event.end),
+            (at(10, 19, 0, 0), at(10, 20, 0, 0))
+        );
+        assert!(matches!(
+            event.recurrence.unwrap().frequency,
+            RepeatFrequency::Yearly
+        ));
+
+        // The same line gives the same id, to skip it when imported again
+        let line = "10/16/2026 @ 12:00 -> 10/16/2026 @ 13:00 !Lunch";
+        assert_eq!(
+            parse_calcurse_line(line, &dir).unwrap().id,
+            parse_calcurse_line(line, &dir).unwrap().id
+        );
+        assert!(parse_calcurse_line("not an entry", &dir).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn calcurse_recurrences_are_read() {
+        let recurrence = parse_calcurse_recurrence("{2W -> 12/31/2026}").unwrap();
+        assert!(matches!(recurrence.frequency, RepeatFrequency::Weekly));
+        assert_eq!(recurrence.interval, 2);
+        assert_eq!(recurrence.until, Some(at(12, 31, 0, 0)));
+
+        // An interval of 0 repeats every period, exceptions are dropped
+        let recurrence = parse_calcurse_recurrence("{0D !10/20/2026}").unwrap();
+        assert!(matches!(recurrence.frequency, RepeatFrequency::Daily));
+        assert_eq!(recurrence.interval, 1);
+        assert!(parse_calcurse_recurrence("{1X}").is_err());
+    }
+}
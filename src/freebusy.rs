@@ -0,0 +1,157 @@
+use crate::calendar::{self, Event};
+use crate::storage;
+use anyhow::{anyhow, Context, Result};
+use chrono::{Duration, NaiveDateTime};
+use std::fs;
+use std::path::Path;
+
+// A start and an end
+pub type Interval = (NaiveDateTime, NaiveDateTime);
+
+// The times taken by the occurrences of events, overlapping and adjoining
+// ones merged, sorted and clipped to [from, to). All-day and transparent
+// events, and tasks, take no time.
+pub fn busy_blocks(events: &[&Event], from: NaiveDateTime, to: NaiveDateTime) -> Vec<Interval> {
+    let intervals = events
+        .iter()
+        .filter(|event| !event.all_day && !event.task && !event.transparent)
+        .map(|event| (event.start, event.end));
+    clip(merge(intervals.collect()), from, to)
+}
+
+// Sorts intervals and merges the overlapping and adjoining ones
+pub fn merge(mut intervals: Vec<Interval>) -> Vec<Interval> {
+    intervals.sort();
+
+    let mut blocks: Vec<Interval> = Vec::new();
+    for (start, end) in intervals {
+        match blocks.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = (*last_end).max(end),
+            _ => blocks.push((start, end)),
+        }
+    }
+    blocks
+}
+
+fn clip(blocks: Vec<Interval>, from: NaiveDateTime, to: NaiveDateTime) -> Vec<Interval> {
+    blocks
+        .into_iter()
+        .map(|(start, end)| (start.max(from), end.min(to)))
+        .filter(|(start, end)| start < end)
+        .collect()
+}
+
+// The busy blocks of a calendar file sent by someone else, from its events or
+// from a VFREEBUSY like the ones of freebusy --ics
+pub fn read_file(path: &Path, from: NaiveDateTime, to: NaiveDateTime) -> Result<Vec<Interval>> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read '{}'", path.display()))?;
+    if !content.trim_start().starts_with("BEGIN:VCALENDAR") {
+        return Err(anyhow!("'{}' is not an ICS calendar", path.display()));
+    }
+
+    let events = storage::split_events(&content)
+        .into_iter()
+        .filter(|(_, data)| storage::component_kind(data) == Some("VEVENT"))
+        .map(|(_, data)| {
+            storage::read_event(&data, path)
+                .with_context(|| format!("Failed to read an event of '{}'", path.display()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let events = calendar::expand_overlapping(events, from, to);
+    let events: Vec<_> = events.iter().collect();
+
+    let mut blocks = busy_blocks(&events, from, to);
+    blocks.extend(
+        storage::read_freebusy(&content)
+            .with_context(|| format!("Failed to read the busy times of '{}'", path.display()))?,
+    );
+    Ok(clip(merge(blocks), from, to))
+}
+
+// The gaps of at least the duration left in [from, to) by sorted and merged
+// busy blocks, as returned by busy_blocks
+pub fn free_slots(
+    busy: &[Interval],
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    duration: Duration,
+) -> Vec<Interval> {
+    let mut slots = Vec::new();
+    let mut start = from;
+    for &(busy_start, busy_end) in busy {
+        if busy_start >= to {
+            break;
+        }
+        if busy_start - start >= duration {
+            slots.push((start, busy_start));
+        }
+        start = start.max(busy_end);
+    }
+    if to - start >= duration {
+        slots.push((start, to));
+    }
+    slots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(day: u32, hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 10, day)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn overlapping_and_touching_blocks_are_merged() {
+        let blocks = merge(vec![
+            (at(16, 14), at(16, 15)),
+            (at(16, 9), at(16, 11)),
+            (at(16, 10), at(16, 12)),
+            (at(16, 12), at(16, 13)),
+            (at(16, 9), at(16, 10)),
+        ]);
+        assert_eq!(blocks, [(at(16, 9), at(16, 13)), (at(16, 14), at(16, 15))]);
+    }
+
+    #[test]
+    fn blocks_are_clipped_to_the_window() {
+        let trip = Event::builder("Trip", at(15, 20), at(16, 10))
+            .build()
+            .unwrap();
+        let holiday = Event::builder("Holiday", at(16, 0), at(17, 0))
+            .all_day(true)
+            .build()
+            .unwrap();
+        let late = Event::builder("Late", at(16, 23), at(17, 1))
+            .build()
+            .unwrap();
+        let events = [&trip, &holiday, &late];
+        assert_eq!(
+            busy_blocks(&events, at(16, 0), at(17, 0)),
+            [(at(16, 0), at(16, 10)), (at(16, 23), at(17, 0))]
+        );
+    }
+
+    #[test]
+    fn free_slots_are_at_least_the_duration() {
+        let busy = [
+            (at(16, 8), at(16, 10)),
+            (at(16, 11), at(16, 12)),
+            (at(16, 15), at(16, 20)),
+        ];
+        let slots = free_slots(&busy, at(16, 9), at(16, 17), Duration::hours(2));
+        // The hour between 10 and 11 is too short
+        assert_eq!(slots, [(at(16, 12), at(16, 15))]);
+
+        let slots = free_slots(&busy, at(16, 9), at(16, 17), Duration::hours(1));
+        assert_eq!(slots, [(at(16, 10), at(16, 11)), (at(16, 12), at(16, 15))]);
+
+        let slots = free_slots(&[], at(16, 9), at(16, 17), Duration::hours(8));
+        assert_eq!(slots, [(at(16, 9), at(16, 17))]);
+    }
+}
@@ -0,0 +1,111 @@
+// Scored fuzzy matching of names, in the way of fzf: the characters of the
+// pattern must appear in order, and matches are scored by how close together
+// they are and by how many start a word, so that the scattered ones can be
+// told apart from the good ones and dropped
+
+const SCORE_MATCH: i64 = 16;
+const GAP_START: i64 = -3;
+const GAP_EXTENSION: i64 = -1;
+// A character starting a word, after a separator or in camelCase
+const BONUS_BOUNDARY: i64 = 8;
+const BONUS_CONSECUTIVE: i64 = 4;
+// The first character of the pattern weighs more at the start of a word
+const BONUS_FIRST_CHAR_MULTIPLIER: i64 = 2;
+
+// The best score of the pattern in the text, case aside, or None when its
+// characters do not all appear in order
+pub fn score(text: &str, pattern: &str) -> Option<i64> {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    if pattern.is_empty() {
+        return Some(0);
+    }
+    let text: Vec<char> = text.chars().collect();
+    let lowercase: Vec<char> = text.iter().map(|&c| lower(c)).collect();
+    let bonuses: Vec<i64> = (0..text.len()).map(|j| bonus(&text, j)).collect();
+
+    // The best score of the pattern so far with its last character at each
+    // position of the text
+    let mut previous: Vec<Option<i64>> = vec![None; text.len()];
+    for (i, &p) in pattern.iter().enumerate() {
+        let mut current: Vec<Option<i64>> = vec![None; text.len()];
+        // The best score left by a match before the previous position,
+        // with the penalty of the gap since
+        let mut gapped: Option<i64> = None;
+        for j in 0..text.len() {
+            if j >= 2 {
+                let extended = gapped.map(|score| score + GAP_EXTENSION);
+                let started = previous[j - 2].map(|score| score + GAP_START);
+                gapped = extended.max(started);
+            }
+            if lowercase[j] != p {
+                continue;
+            }
+
+            current[j] = if i == 0 {
+                Some(SCORE_MATCH + bonuses[j] * BONUS_FIRST_CHAR_MULTIPLIER)
+            } else {
+                let consecutive = match j {
+                    0 => None,
+                    _ => previous[j - 1].map(|score| score + bonuses[j].max(BONUS_CONSECUTIVE)),
+                };
+                let gapped = gapped.map(|score| score + bonuses[j]);
+                consecutive.max(gapped).map(|score| score + SCORE_MATCH)
+            };
+        }
+        previous = current;
+    }
+    previous.into_iter().flatten().max()
+}
+
+// Whether the pattern matches the text well enough: the score of the
+// pattern found whole at the start of a word is taken as perfect, and two
+// thirds of it are needed
+pub fn matches(text: &str, pattern: &str) -> bool {
+    let length = pattern.chars().count() as i64;
+    let perfect = length * SCORE_MATCH
+        + BONUS_BOUNDARY * BONUS_FIRST_CHAR_MULTIPLIER
+        + (length - 1).max(0) * BONUS_CONSECUTIVE;
+    score(text, pattern).is_some_and(|score| score * 3 >= perfect * 2)
+}
+
+fn lower(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+fn bonus(text: &[char], j: usize) -> i64 {
+    let current = text[j];
+    let boundary = match j {
+        0 => true,
+        _ => {
+            let before = text[j - 1];
+            !before.is_alphanumeric()
+                || (before.is_lowercase() && current.is_uppercase())
+                || (!before.is_ascii_digit() && current.is_ascii_digit())
+        }
+    };
+    if boundary && current.is_alphanumeric() {
+        BONUS_BOUNDARY
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scattered_matches_are_dropped() {
+        assert!(matches("Team meeting", "mtg"));
+        assert!(matches("Team meeting", "tm"));
+        assert!(matches("Daily standup", "stdup"));
+        assert!(matches("Code review", "re"));
+        assert!(!matches("Project standup", "re"));
+        assert!(!matches("Lunch", "lunchs"));
+
+        // Word starts and closer characters rank first
+        let rank = |text| score(text, "rev").unwrap();
+        assert!(rank("Code review") > rank("Reserve venue"));
+        assert!(rank("Reserve venue") > rank("Car service"));
+    }
+}
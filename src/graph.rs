@@ -0,0 +1,361 @@
+use crate::calendar::{self, Event};
+use crate::config::GraphConfig;
+use crate::event;
+use crate::paths;
+use crate::progress::Progress;
+use crate::retry;
+use crate::storage;
+use anyhow::{anyhow, Context, Result};
+use chrono::{Duration, Local, NaiveDateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions, Permissions};
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+use std::thread;
+
+const SCOPE: &str = "offline_access Calendars.Read";
+const GRAPH_URL: &str = "https://graph.microsoft.com/v1.0";
+
+// Range of the calendar view kept in sync, around the first sync
+const PAST_DAYS: i64 = 365;
+const FUTURE_DAYS: i64 = 2 * 365;
+
+// Name of the events that have none, as shown by Outlook
+const NO_SUBJECT: &str = "(No subject)";
+
+#[derive(Serialize, Deserialize)]
+struct Token {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_at: i64,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_in: i64,
+}
+
+#[derive(Deserialize)]
+struct TokenError {
+    error: String,
+    error_description: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DeviceCode {
+    device_code: String,
+    message: String,
+    interval: u64,
+}
+
+#[derive(Deserialize)]
+struct DeltaPage {
+    value: Vec<GraphEvent>,
+    #[serde(rename = "@odata.nextLink")]
+    next_link: Option<String>,
+    #[serde(rename = "@odata.deltaLink")]
+    delta_link: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphEvent {
+    id: String,
+    #[serde(rename = "@removed")]
+    removed: Option<serde_json::Value>,
+    subject: Option<String>,
+    body_preview: Option<String>,
+    location: Option<GraphLocation>,
+    start: Option<GraphDateTime>,
+    end: Option<GraphDateTime>,
+    #[serde(default)]
+    is_all_day: bool,
+    created_date_time: Option<String>,
+    last_modified_date_time: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphLocation {
+    display_name: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphDateTime {
+    date_time: String,
+}
+
+#[derive(Default)]
+pub struct SyncReport {
+    pub updated: usize,
+    pub removed: usize,
+}
+
+impl std::fmt::Display for SyncReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} updated, {} removed", self.updated, self.removed)
+    }
+}
+
+// Downloads the changes of the Outlook calendar since the last sync into the
+// calendar of the config. The first sync signs in with a device code and
+// reads the whole range, the next ones only ask for the delta.
+pub fn sync(config: &GraphConfig) -> Result<SyncReport> {
+    let client_id = config
+        .client_id
+        .as_deref()
+        .ok_or_else(|| anyhow!("Set the client_id of the [graph] section of the config"))?;
+    let token = access_token(config, client_id)?;
+
+    event::ensure_calendar(&config.calendar, true)?;
+    let mut calendar = calendar::load(&config.calendar)?;
+
+    let delta_file = paths::graph_dir()?.join("delta");
+    let mut url = match fs::read_to_string(&delta_file) {
+        Ok(delta_link) => delta_link.trim().to_string(),
+        Err(_) => {
+            let now = Utc::now();
+            format!(
+                "{}/me/calendarView/delta?startDateTime={}&endDateTime={}",
+                GRAPH_URL,
+                (now - Duration::days(PAST_DAYS)).format("%Y-%m-%dT%H:%M:%SZ"),
+                (now + Duration::days(FUTURE_DAYS)).format("%Y-%m-%dT%H:%M:%SZ")
+            )
+        }
+    };
+
+    // The changes are only written once every page is read, with the delta
+    // link of the next sync
+    let mut batch = storage::Batch::default();
+    let mut report = SyncReport::default();
+    let mut progress = Progress::spinner("Reading changes");
+    let delta_link = loop {
+        let response = retry::retry(retry::is_transient, || {
+            ureq::get(&url)
+                .set("Authorization", &format!("Bearer {}", token))
+                .set("Prefer", "outlook.timezone=\"UTC\"")
+                .call()
+                .context("Failed to read the Outlook calendar")
+        })?;
+        let page: DeltaPage = serde_json::from_str(&response.into_string()?)
+            .context("Failed to parse the Outlook calendar")?;
+
+        progress.inc(page.value.len() as u64);
+        for graph_event in page.value {
+            let existing = calendar.get_event(graph_event.id.clone()).cloned();
+            if graph_event.removed.is_some() {
+                if let Some(existing) = existing {
+                    batch.delete_event(&calendar.path, &existing)?;
+                    calendar.events.retain(|e| e.id != existing.id);
+                    report.removed += 1;
+                }
+                continue;
+            }
+
+            let mut event = to_event(graph_event)?;
+            event.calendar = calendar.name.clone();
+            event.file = existing.and_then(|existing| existing.file);
+            batch.write_event(&calendar.path, &event)?;
+            calendar.events.retain(|e| e.id != event.id);
+            calendar.events.push(event);
+            report.updated += 1;
+        }
+
+        match (page.next_link, page.delta_link) {
+            (Some(next_link), _) => url = next_link,
+            (None, Some(delta_link)) => break delta_link,
+            (None, None) => return Err(anyhow!("The Outlook calendar gave no delta link")),
+        }
+    };
+    progress.finish();
+
+    batch.commit()?;
+    write_private(&delta_file, &delta_link)?;
+
+    Ok(report)
+}
+
+fn to_event(graph_event: GraphEvent) -> Result<Event> {
+    // All-day events go from midnight to midnight in any time zone
+    let all_day = graph_event.is_all_day;
+    let date = |value: &str| -> Result<NaiveDateTime> {
+        let datetime = parse_datetime(value)?;
+        Ok(if all_day {
+            datetime
+        } else {
+            Local.from_utc_datetime(&datetime).naive_local()
+        })
+    };
+
+    let start = graph_event
+        .start
+        .map(|start| date(&start.date_time))
+        .transpose()?
+        .ok_or_else(|| anyhow!("Outlook event '{}' has no start", graph_event.id))?;
+    let end = graph_event
+        .end
+        .map(|end| date(&end.date_time))
+        .transpose()?
+        .unwrap_or(start);
+
+    let utc = |value: String| -> Result<NaiveDateTime> {
+        Ok(Local
+            .from_utc_datetime(&parse_datetime(&value)?)
+            .naive_local())
+    };
+
+    // Outlook allows events without a subject
+    let name = graph_event
+        .subject
+        .filter(|subject| !subject.trim().is_empty())
+        .unwrap_or_else(|| NO_SUBJECT.to_string());
+    Event::builder(name, start, end)
+        .location(
+            graph_event
+                .location
+                .and_then(|location| location.display_name)
+                .filter(|name| !name.is_empty()),
+        )
+        .description(graph_event.body_preview.filter(|body| !body.is_empty()))
+        .id(graph_event.id.clone())
+        .all_day(all_day)
+        .created(graph_event.created_date_time.map(utc).transpose()?)
+        .last_modified(graph_event.last_modified_date_time.map(utc).transpose()?)
+        .build()
+        .with_context(|| format!("Invalid Outlook event '{}'", graph_event.id))
+}
+
+// Graph dates have up to 7 decimals, and a Z when they are timestamps
+fn parse_datetime(value: &str) -> Result<NaiveDateTime> {
+    let value = value.trim_end_matches('Z');
+    let value = value.split('.').next().unwrap_or(value);
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S")
+        .with_context(|| format!("Invalid Outlook date '{}'", value))
+}
+
+// A valid access token, refreshed or asked for when needed
+fn access_token(config: &GraphConfig, client_id: &str) -> Result<String> {
+    let token_file = paths::graph_dir()?.join("token.json");
+    let token: Option<Token> = fs::read_to_string(&token_file)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok());
+
+    let now = Utc::now().timestamp();
+    let token = match token {
+        Some(token) if token.expires_at > now + 60 => return Ok(token.access_token),
+        Some(Token {
+            refresh_token: Some(refresh_token),
+            ..
+        }) => request_token(
+            config,
+            &[
+                ("grant_type", "refresh_token"),
+                ("client_id", client_id),
+                ("refresh_token", &refresh_token),
+                ("scope", SCOPE),
+            ],
+        )
+        .or_else(|_| sign_in(config, client_id))?,
+        _ => sign_in(config, client_id)?,
+    };
+
+    if let Some(parent) = token_file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_private(&token_file, &serde_json::to_string(&token)?)?;
+
+    Ok(token.access_token)
+}
+
+// The token and the delta link give access to the calendar, only the user
+// may read them, the files written before included
+fn write_private(path: &Path, content: &str) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+        .with_context(|| format!("Failed to write '{}'", path.display()))?;
+    file.set_permissions(Permissions::from_mode(0o600))?;
+    file.write_all(content.as_bytes())?;
+    Ok(())
+}
+
+// Device code flow: the user signs in from a browser while we poll
+fn sign_in(config: &GraphConfig, client_id: &str) -> Result<Token> {
+    let device_code: DeviceCode = serde_json::from_str(
+        &ureq::post(&format!(
+            "https://login.microsoftonline.com/{}/oauth2/v2.0/devicecode",
+            config.tenant
+        ))
+        .send_form(&[("client_id", client_id), ("scope", SCOPE)])
+        .context("Failed to start the Microsoft sign in")?
+        .into_string()?,
+    )?;
+    println!("{}", device_code.message);
+
+    let mut interval = device_code.interval;
+    loop {
+        thread::sleep(std::time::Duration::from_secs(interval));
+        let result = request_token(
+            config,
+            &[
+                ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+                ("client_id", client_id),
+                ("device_code", &device_code.device_code),
+            ],
+        );
+        match result {
+            Ok(token) => return Ok(token),
+            Err(err) => match err.downcast_ref::<TokenError>().map(|e| e.error.as_str()) {
+                Some("authorization_pending") => {}
+                Some("slow_down") => interval += 5,
+                _ => return Err(err),
+            },
+        }
+    }
+}
+
+fn request_token(config: &GraphConfig, form: &[(&str, &str)]) -> Result<Token> {
+    let url = format!(
+        "https://login.microsoftonline.com/{}/oauth2/v2.0/token",
+        config.tenant
+    );
+    match ureq::post(&url).send_form(form) {
+        Ok(response) => {
+            let token: TokenResponse = serde_json::from_str(&response.into_string()?)?;
+            Ok(Token {
+                access_token: token.access_token,
+                refresh_token: token.refresh_token,
+                expires_at: Utc::now().timestamp() + token.expires_in,
+            })
+        }
+        Err(ureq::Error::Status(_, response)) => {
+            let error: TokenError = serde_json::from_str(&response.into_string()?)?;
+            Err(anyhow::Error::new(error))
+        }
+        Err(err) => Err(err).context("Failed to reach the Microsoft sign in"),
+    }
+}
+
+impl std::fmt::Display for TokenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.error_description {
+            Some(description) => write!(f, "{}", description),
+            None => write!(f, "{}", self.error),
+        }
+    }
+}
+
+impl std::fmt::Debug for TokenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl std::error::Error for TokenError {}
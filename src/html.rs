@@ -0,0 +1,141 @@
+use crate::calendar::Event;
+use crate::display;
+use crate::locale::LocalFormat;
+use anyhow::Result;
+use chrono::{Datelike, Duration, Local, NaiveDate, NaiveTime, Weekday};
+
+// A page that prints on its own, without anything to fetch
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; color: #222; }
+h1 { font-size: 1.4em; margin: 1em 0 .5em; }
+h2 { font-size: 1.1em; margin: 1.2em 0 .3em; border-bottom: 1px solid #ccc; }
+.month { display: grid; grid-template-columns: repeat(7, 1fr); gap: 1px; background: #ccc; border: 1px solid #ccc; }
+.weekday { background: #eee; font-weight: bold; text-align: center; padding: .3em; }
+.day { background: #fff; min-height: 6em; padding: .3em; overflow: hidden; }
+.outside { background: #f6f6f6; }
+.number { font-weight: bold; }
+.today .number { color: #fff; background: #c0392b; border-radius: 1em; padding: 0 .3em; }
+.event { font-size: .8em; white-space: nowrap; overflow: hidden; text-overflow: ellipsis; }
+.all-day { background: #dde8f5; border-radius: .2em; padding: 0 .2em; }
+.time { color: #666; }
+ul { list-style: none; padding: 0; margin: 0; }
+li { margin: .2em 0; }
+.nothing { color: #999; }
+@media print { body { margin: 0; } .month { break-inside: avoid; } }
+";
+
+// The month grids of a number of months from the one of a date, the events
+// written in their days
+pub fn month(events: &[Event], date: NaiveDate, number: u32, title: &str) -> Result<String> {
+    let today = Local::now().date_naive();
+    let mut body = String::new();
+    for month in 0..number {
+        let (first_of_month, last_of_month, weeks) = display::month_layout(date, month)?;
+        let first_of_grid = first_of_month.week(Weekday::Mon).first_day();
+
+        body.push_str(&format!(
+            "<h1>{}</h1>\n<div class=\"month\">\n",
+            escape(&first_of_month.format_local("%B %Y"))
+        ));
+        for day in first_of_grid.iter_days().take(7) {
+            body.push_str(&format!(
+                "<div class=\"weekday\">{}</div>\n",
+                escape(&day.format_local("%a"))
+            ));
+        }
+        for day in first_of_grid.iter_days().take(weeks * 7) {
+            // The days of the months around are left empty, like in the view
+            if day < first_of_month || day > last_of_month {
+                body.push_str("<div class=\"day outside\"></div>\n");
+                continue;
+            }
+            let class = if day == today { "day today" } else { "day" };
+            body.push_str(&format!(
+                "<div class=\"{}\"><div class=\"number\">{}</div>\n",
+                class,
+                day.day()
+            ));
+            for event in events_of_day(events, day) {
+                let label = match event.all_day {
+                    true => format!("<span class=\"all-day\">{}</span>", escape(&event.name)),
+                    false => format!(
+                        "<span class=\"time\">{}</span> {}",
+                        event.start.format("%H:%M"),
+                        escape(&event.name)
+                    ),
+                };
+                body.push_str(&format!(
+                    "<div class=\"event\" title=\"{}\">{}</div>\n",
+                    escape(&describe(event)),
+                    label
+                ));
+            }
+            body.push_str("</div>\n");
+        }
+        body.push_str("</div>\n");
+    }
+    Ok(page(title, &body))
+}
+
+// The events of a number of days from a date, day by day
+pub fn agenda(events: &[Event], date: NaiveDate, days: u32, title: &str) -> String {
+    let mut body = format!("<h1>{}</h1>\n", escape(title));
+    for day in date.iter_days().take(days as usize) {
+        body.push_str(&format!(
+            "<h2>{}</h2>\n<ul>\n",
+            escape(&day.format_local("%A %d %B %Y"))
+        ));
+        let events = events_of_day(events, day);
+        if events.is_empty() {
+            body.push_str("<li class=\"nothing\">Nothing planned</li>\n");
+        }
+        for event in events {
+            body.push_str(&format!(
+                "<li><span class=\"time\">{}</span> {}</li>\n",
+                escape(&event.time_range()),
+                escape(&describe_place(event))
+            ));
+        }
+        body.push_str("</ul>\n");
+    }
+    page(title, &body)
+}
+
+// Events going on over several days are written in each of them, all-day
+// events first
+fn events_of_day(events: &[Event], day: NaiveDate) -> Vec<&Event> {
+    let start = day.and_time(NaiveTime::MIN);
+    let end = start + Duration::days(1);
+    let (all_day, timed): (Vec<&Event>, Vec<&Event>) = events
+        .iter()
+        .filter(|event| event.start < end && (event.end > start || event.start >= start))
+        .partition(|event| event.all_day);
+    all_day.into_iter().chain(timed).collect()
+}
+
+fn describe(event: &Event) -> String {
+    format!("{} {}", event.time_range(), describe_place(event))
+}
+
+fn describe_place(event: &Event) -> String {
+    match &event.location {
+        Some(location) => format!("{} in {}", event.name, location),
+        None => event.name.clone(),
+    }
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+        escape(title),
+        STYLE,
+        body
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,427 @@
+use crate::calendar::{Event, Recurrence};
+use crate::cli::RepeatFrequency;
+use crate::paths;
+use crate::progress::Progress;
+use crate::storage;
+use anyhow::{Context, Result};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, Row};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::UNIX_EPOCH;
+
+// Bumped when the tables or what is stored in them change, older indexes are
+// rebuilt from the files
+const SCHEMA_VERSION: i32 = 10;
+
+// Dates are compared as text, which only sorts right for years of 4 digits,
+// so the bounds of the queries are kept between these
+const FIRST_DATE: NaiveDateTime = NaiveDate::from_ymd_opt(1, 1, 1)
+    .unwrap()
+    .and_time(NaiveTime::MIN);
+const LAST_DATE: NaiveDateTime = NaiveDate::from_ymd_opt(9999, 12, 31)
+    .unwrap()
+    .and_time(NaiveTime::from_hms_opt(23, 59, 59).unwrap());
+
+const SCHEMA: &str = "
+CREATE TABLE files (
+    path TEXT PRIMARY KEY,
+    calendar TEXT NOT NULL,
+    mtime INTEGER NOT NULL
+);
+CREATE TABLE events (
+    id INTEGER PRIMARY KEY,
+    file TEXT NOT NULL,
+    uid TEXT NOT NULL,
+    calendar TEXT NOT NULL,
+    name TEXT NOT NULL,
+    description TEXT,
+    location TEXT,
+    url TEXT,
+    organizer TEXT,
+    attendees TEXT,
+    -- The attendance of the occurrences, as JSON
+    attendance TEXT,
+    start TEXT NOT NULL,
+    end TEXT NOT NULL,
+    all_day INTEGER NOT NULL,
+    transparent INTEGER NOT NULL,
+    tags TEXT,
+    frequency TEXT,
+    interval INTEGER,
+    count INTEGER,
+    until TEXT,
+    by_day TEXT,
+    month_end INTEGER,
+    -- The starts of the occurrences removed from the series, as JSON
+    excluded TEXT,
+    recurrence_id TEXT,
+    created TEXT,
+    last_modified TEXT
+);
+CREATE INDEX events_file ON events(file);
+-- Dates are stored as text, which sorts like the dates themselves
+CREATE INDEX events_start ON events(start);
+CREATE VIRTUAL TABLE events_fts USING fts5(
+    name, description, location, content='events', content_rowid='id'
+);
+CREATE TRIGGER events_insert AFTER INSERT ON events BEGIN
+    INSERT INTO events_fts(rowid, name, description, location)
+    VALUES (new.id, new.name, new.description, new.location);
+END;
+CREATE TRIGGER events_delete AFTER DELETE ON events BEGIN
+    INSERT INTO events_fts(events_fts, rowid, name, description, location)
+    VALUES ('delete', old.id, old.name, old.description, old.location);
+END;
+";
+
+// A cache of the events of the ICS files, which stay the source of truth. It
+// is brought up to date with the files each time it is opened, only the files
+// whose modification time changed are read again.
+pub struct Index {
+    connection: Connection,
+}
+
+#[derive(Default)]
+pub struct RefreshReport {
+    pub files: usize,
+    pub events: usize,
+    pub updated: usize,
+    pub removed: usize,
+}
+
+impl std::fmt::Display for RefreshReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} events in {} files, {} files read, {} removed",
+            self.events, self.files, self.updated, self.removed
+        )
+    }
+}
+
+impl Index {
+    pub fn open() -> Result<(Self, RefreshReport)> {
+        let path = paths::index_file()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let connection = Connection::open(&path)
+            .with_context(|| format!("Failed to open index '{}'", path.display()))?;
+
+        let version: i32 = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version != SCHEMA_VERSION {
+            create_schema(&connection)?;
+        }
+
+        let mut index = Index { connection };
+        let report = index.refresh()?;
+        Ok((index, report))
+    }
+
+    // Drops everything and indexes all the files again
+    pub fn rebuild() -> Result<(Self, RefreshReport)> {
+        let path = paths::index_file()?;
+        if path.is_file() {
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove index '{}'", path.display()))?;
+        }
+        Self::open()
+    }
+
+    fn refresh(&mut self) -> Result<RefreshReport> {
+        let mut known: HashMap<String, i64> = HashMap::new();
+        {
+            let mut statement = self.connection.prepare("SELECT path, mtime FROM files")?;
+            let rows = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            for row in rows {
+                let (path, mtime) = row?;
+                known.insert(path, mtime);
+            }
+        }
+
+        let mut report = RefreshReport::default();
+        let files = calendar_files()?;
+        report.files = files.len();
+        let changed: Vec<_> = files
+            .into_iter()
+            .filter(|(_, path, mtime)| known.remove(&*path.to_string_lossy()) != Some(*mtime))
+            .collect();
+
+        let mut progress = Progress::new("Indexing", changed.len() as u64);
+        let transaction = self.connection.transaction()?;
+
+        for (calendar, path, mtime) in changed {
+            let key = path.to_string_lossy().to_string();
+            transaction.execute("DELETE FROM events WHERE file = ?1", [&key])?;
+            progress.inc(1);
+            // The exceptions of a series are indexed with it when in its file
+            let events = fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|content| match storage::component_kind(&content) {
+                    Some("VEVENT") => storage::read_events(&content, &path),
+                    _ => Ok(Vec::new()),
+                });
+            match events {
+                Ok(events) => {
+                    for event in events {
+                        insert_event(&transaction, &key, &calendar, &event)?;
+                    }
+                }
+                // Left out of the files, it is read again until repaired
+                Err(err) => {
+                    storage::skip_unreadable(&path, err)?;
+                    continue;
+                }
+            }
+            transaction.execute(
+                "INSERT OR REPLACE INTO files (path, calendar, mtime) VALUES (?1, ?2, ?3)",
+                params![key, calendar, mtime],
+            )?;
+            report.updated += 1;
+        }
+        progress.finish();
+
+        // What is left was deleted since the last refresh
+        for path in known.keys() {
+            transaction.execute("DELETE FROM events WHERE file = ?1", [path])?;
+            transaction.execute("DELETE FROM files WHERE path = ?1", [path])?;
+            report.removed += 1;
+        }
+
+        transaction.commit()?;
+
+        report.events = self
+            .connection
+            .query_row("SELECT COUNT(*) FROM events", [], |row| row.get(0))?;
+        Ok(report)
+    }
+
+    // The events of a calendar, or of all calendars, with an occurrence that
+    // may start between from and to. Recurring events are returned whole, to
+    // be expanded like the ones read from the files, with all their
+    // exceptions.
+    pub fn events(
+        &self,
+        calendar: Option<&str>,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Result<Vec<Event>> {
+        self.query(calendar, None, from, to)
+    }
+
+    // Like events, restricted to the events with every word in their name,
+    // description or location, as a word or the start of one
+    pub fn search(
+        &self,
+        calendar: Option<&str>,
+        words: &[String],
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Result<Vec<Event>> {
+        let query = words
+            .iter()
+            .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+            .collect::<Vec<_>>()
+            .join(" ");
+        self.query(calendar, Some(&query), from, to)
+    }
+
+    fn query(
+        &self,
+        calendar: Option<&str>,
+        fts_query: Option<&str>,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Result<Vec<Event>> {
+        let mut statement = self.connection.prepare(
+            "SELECT uid, calendar, name, description, location, start, end, all_day,
+                    frequency, interval, count, until, created, last_modified, file,
+                    transparent, by_day, tags, url, organizer, attendees, attendance,
+                    recurrence_id, month_end, excluded
+             FROM events
+             WHERE (?1 IS NULL OR calendar = ?1)
+               AND (recurrence_id IS NOT NULL OR start <= ?3
+               AND (end > ?2 OR start >= ?2 OR (frequency IS NOT NULL AND (until IS NULL
+                    OR julianday(until) + julianday(end) - julianday(start) > julianday(?2)))))
+               AND (?4 IS NULL OR id IN (SELECT rowid FROM events_fts WHERE events_fts MATCH ?4))
+             ORDER BY start",
+        )?;
+        let from = from.clamp(FIRST_DATE, LAST_DATE);
+        let to = to.clamp(FIRST_DATE, LAST_DATE);
+        let rows = statement.query_map(params![calendar, from, to, fts_query], read_row)?;
+
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+}
+
+fn create_schema(connection: &Connection) -> Result<()> {
+    connection.execute_batch(
+        "DROP TRIGGER IF EXISTS events_insert;
+         DROP TRIGGER IF EXISTS events_delete;
+         DROP TABLE IF EXISTS events_fts;
+         DROP TABLE IF EXISTS events;
+         DROP TABLE IF EXISTS files;",
+    )?;
+    connection.execute_batch(SCHEMA)?;
+    connection.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+    Ok(())
+}
+
+// The ICS files of every collection, with their calendar and modification time
+fn calendar_files() -> Result<Vec<(String, PathBuf, i64)>> {
+    let mut files = Vec::new();
+
+    for calendar in storage::calendar_dirs()? {
+        let name = calendar
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+
+        for collection in fs::read_dir(&calendar)? {
+            let collection = collection?;
+            if !collection.file_type()?.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(collection.path())? {
+                let entry = entry?;
+                let path = entry.path();
+                if !entry.file_type()?.is_file() || path.extension().is_none_or(|ext| ext != "ics")
+                {
+                    continue;
+                }
+                files.push((name.clone(), path, mtime(&entry.metadata()?)?));
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+fn mtime(metadata: &fs::Metadata) -> Result<i64> {
+    let modified = metadata.modified()?.duration_since(UNIX_EPOCH)?;
+    Ok(modified.as_nanos() as i64)
+}
+
+fn insert_event(connection: &Connection, file: &str, calendar: &str, event: &Event) -> Result<()> {
+    let recurrence = event.recurrence.as_ref();
+    connection.execute(
+        "INSERT INTO events (file, uid, calendar, name, description, location, start, end,
+                             all_day, transparent, tags, frequency, interval, count, until,
+                             by_day, created, last_modified, url, organizer, attendees,
+                             attendance, recurrence_id, month_end, excluded)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17,
+                 ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25)",
+        params![
+            file,
+            event.id,
+            calendar,
+            event.name,
+            event.description,
+            event.location,
+            event.start,
+            event.end,
+            event.all_day,
+            event.transparent,
+            Some(&event.tags)
+                .filter(|tags| !tags.is_empty())
+                .map(|tags| storage::format_categories(tags)),
+            recurrence.map(|r| frequency_name(r.frequency)),
+            recurrence.map(|r| r.interval),
+            recurrence.and_then(|r| r.count),
+            recurrence.and_then(|r| r.until),
+            recurrence
+                .filter(|r| !r.by_day.is_empty())
+                .map(|r| storage::format_weekdays(&r.by_day)),
+            event.created,
+            event.last_modified,
+            event.url,
+            event.organizer,
+            Some(event.attendees.join(",")).filter(|attendees| !attendees.is_empty()),
+            Some(&event.attendance)
+                .filter(|attendance| !attendance.is_empty())
+                .map(serde_json::to_string)
+                .transpose()?,
+            event.recurrence_id,
+            recurrence.map(|r| r.month_end),
+            recurrence
+                .filter(|r| !r.excluded.is_empty())
+                .map(|r| serde_json::to_string(&r.excluded))
+                .transpose()?,
+        ],
+    )?;
+    Ok(())
+}
+
+fn read_row(row: &Row) -> rusqlite::Result<Event> {
+    let recurrence = match row.get::<_, Option<String>>(8)? {
+        Some(frequency) => Some(Recurrence {
+            frequency: RepeatFrequency::from_str(&frequency).map_err(|err| {
+                rusqlite::Error::FromSqlConversionFailure(8, Type::Text, err.into())
+            })?,
+            interval: row.get::<_, Option<u32>>(9)?.unwrap_or(1),
+            count: row.get(10)?,
+            until: row.get(11)?,
+            by_day: row
+                .get::<_, Option<String>>(16)?
+                .and_then(|by_day| storage::parse_weekdays(&by_day))
+                .unwrap_or_default(),
+            month_end: row.get::<_, Option<bool>>(23)?.unwrap_or_default(),
+            excluded: match row.get::<_, Option<String>>(24)? {
+                Some(excluded) => serde_json::from_str(&excluded).map_err(|err| {
+                    rusqlite::Error::FromSqlConversionFailure(24, Type::Text, err.into())
+                })?,
+                None => Vec::new(),
+            },
+        }),
+        None => None,
+    };
+    let file: String = row.get(14)?;
+
+    Ok(Event {
+        id: row.get(0)?,
+        calendar: row.get(1)?,
+        name: row.get(2)?,
+        description: row.get(3)?,
+        location: row.get(4)?,
+        url: row.get(18)?,
+        organizer: row.get(19)?,
+        attendees: row
+            .get::<_, Option<String>>(20)?
+            .map(|attendees| attendees.split(',').map(String::from).collect())
+            .unwrap_or_default(),
+        start: row.get(5)?,
+        end: row.get(6)?,
+        all_day: row.get(7)?,
+        task: false,
+        transparent: row.get(15)?,
+        tags: row
+            .get::<_, Option<String>>(17)?
+            .map(|tags| storage::parse_categories(&tags))
+            .unwrap_or_default(),
+        recurrence,
+        recurrence_id: row.get(22)?,
+        attendance: match row.get::<_, Option<String>>(21)? {
+            Some(attendance) => serde_json::from_str(&attendance).map_err(|err| {
+                rusqlite::Error::FromSqlConversionFailure(21, Type::Text, err.into())
+            })?,
+            None => Vec::new(),
+        },
+        created: row.get(12)?,
+        last_modified: row.get(13)?,
+        file: Some(PathBuf::from(file)),
+    })
+}
+
+fn frequency_name(frequency: RepeatFrequency) -> &'static str {
+    match frequency {
+        RepeatFrequency::Daily => "daily",
+        RepeatFrequency::Weekly => "weekly",
+        RepeatFrequency::Monthly => "monthly",
+        RepeatFrequency::Yearly => "yearly",
+    }
+}
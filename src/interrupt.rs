@@ -0,0 +1,90 @@
+use anyhow::{anyhow, Context, Result};
+use std::io::IsTerminal;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+// Exit code after a Ctrl-C, 128 + SIGINT like shells
+pub const EXIT_CODE: i32 = 130;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static CRITICAL: AtomicUsize = AtomicUsize::new(0);
+static NO_ECHO: AtomicBool = AtomicBool::new(false);
+
+// Ctrl-C exits right away, even from a prompt, unless files are being
+// written: then the writes stop or finish first, see Critical
+pub fn install() -> Result<()> {
+    ctrlc::set_handler(|| {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+        if CRITICAL.load(Ordering::SeqCst) == 0 {
+            exit();
+        }
+    })?;
+    Ok(())
+}
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+// While one is alive, Ctrl-C only sets the flag of interrupted, the exit
+// happens when the last one is dropped
+pub struct Critical;
+
+impl Critical {
+    pub fn enter() -> Self {
+        CRITICAL.fetch_add(1, Ordering::SeqCst);
+        Critical
+    }
+}
+
+impl Drop for Critical {
+    fn drop(&mut self) {
+        if CRITICAL.fetch_sub(1, Ordering::SeqCst) == 1 && interrupted() {
+            exit();
+        }
+    }
+}
+
+// While it is alive, what is typed in the terminal is not shown, like a
+// password. It is shown again once it is dropped, or on Ctrl-C.
+pub struct NoEcho;
+
+impl NoEcho {
+    pub fn enter() -> Result<Self> {
+        stty("-echo")?;
+        NO_ECHO.store(true, Ordering::SeqCst);
+        Ok(NoEcho)
+    }
+}
+
+impl Drop for NoEcho {
+    fn drop(&mut self) {
+        NO_ECHO.store(false, Ordering::SeqCst);
+        let _ = stty("echo");
+    }
+}
+
+// Sets the terminal of stdin with stty, there to be set on any Unix
+fn stty(setting: &str) -> Result<()> {
+    let status = Command::new("stty")
+        .arg(setting)
+        .stdin(Stdio::inherit())
+        .status()
+        .context("Failed to run stty")?;
+    if !status.success() {
+        return Err(anyhow!("stty {} failed", setting));
+    }
+    Ok(())
+}
+
+fn exit() -> ! {
+    if NO_ECHO.load(Ordering::SeqCst) {
+        let _ = stty("echo");
+    }
+    // Ends the line of a prompt and shows the cursor a progress bar hid
+    if std::io::stderr().is_terminal() {
+        eprintln!("\x1b[?25h");
+    }
+    eprintln!("Interrupted");
+    std::process::exit(EXIT_CODE);
+}
@@ -0,0 +1,295 @@
+use crate::calendar::{self, Event};
+use crate::cli;
+use crate::config::{self, InviteConfig};
+use crate::event;
+use crate::storage;
+use anyhow::{anyhow, Context, Result};
+use chrono::Local;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use uuid::Uuid;
+
+// Makes us the organizer of a meeting with these attendees
+pub fn organize(
+    event: &mut Event,
+    attendees: Vec<String>,
+    send: bool,
+    config: &InviteConfig,
+) -> Result<()> {
+    if send && config.from.is_none() {
+        return Err(anyhow!(
+            "Set 'from' in the [invite] section of the config to send invitations"
+        ));
+    }
+    if !attendees.is_empty() {
+        event.organizer = config.from.as_deref().map(|from| address(from).to_string());
+    }
+    event.attendees = attendees;
+    Ok(())
+}
+
+// Writes the invitation to a meeting to a file, and sends it to the attendees
+// by email, as asked
+pub fn deliver(event: &Event, path: Option<&str>, send: bool, config: &InviteConfig) -> Result<()> {
+    let request = storage::export_invite(event);
+
+    if let Some(path) = path {
+        fs::write(path, &request).with_context(|| format!("Failed to write '{}'", path))?;
+        println!("Wrote the invitation to '{}'", path);
+    }
+
+    if send {
+        let from = config
+            .from
+            .as_deref()
+            .ok_or_else(|| anyhow!("No 'from' address to send the invitation from"))?;
+        let subject = format!(
+            "Invitation: {} on {}, {}",
+            event.name,
+            event.start.format("%a %d %b %Y"),
+            event.time_range()
+        );
+        let mut text = format!(
+            "{} invites you to {}\n\nWhen: {}, {}\n",
+            from,
+            event.name,
+            event.start.format("%A %d %B %Y"),
+            event.time_range()
+        );
+        if let Some(location) = &event.location {
+            text.push_str(&format!("Where: {}\n", location));
+        }
+        if let Some(description) = &event.description {
+            text.push_str(&format!("\n{}\n", description));
+        }
+        sendmail(
+            config,
+            &event.attendees,
+            &message(from, &event.attendees, &subject, &text, "REQUEST", &request),
+        )?;
+        println!("Sent the invitation to {}", event.attendees.join(", "));
+    }
+
+    Ok(())
+}
+
+// Answers an invitation: its event is added to the calendar with our
+// participation status, or removed from it when declined, and the organizer
+// gets a reply
+pub fn rsvp(cmd: cli::CalendarRsvpArgs) -> Result<()> {
+    let config = config::load()?.invite;
+    let me = match (&cmd.address, &config.from) {
+        (Some(me), _) => me.clone(),
+        (None, Some(from)) => address(from).to_string(),
+        (None, None) => {
+            return Err(anyhow!(
+                "Set 'from' in the [invite] section of the config, or give your address with --as"
+            ))
+        }
+    };
+
+    let content = if cmd.file == "-" {
+        let mut content = String::new();
+        std::io::stdin().read_to_string(&mut content)?;
+        content
+    } else {
+        fs::read_to_string(&cmd.file).with_context(|| format!("Failed to read '{}'", cmd.file))?
+    };
+    let method = content
+        .lines()
+        .find_map(|line| line.trim_end_matches('\r').strip_prefix("METHOD:"));
+    if let Some(method) = method.filter(|method| *method != "REQUEST") {
+        return Err(anyhow!(
+            "'{}' is a {} message, not an invitation",
+            cmd.file,
+            method
+        ));
+    }
+
+    let mut events = storage::split_events(&content)
+        .into_iter()
+        .filter(|(_, data)| storage::component_kind(data) == Some("VEVENT"));
+    let (uid, data) = match (events.next(), events.next()) {
+        (Some(event), None) => event,
+        (None, _) => return Err(anyhow!("'{}' has no event", cmd.file)),
+        (Some(_), Some(_)) => {
+            return Err(anyhow!(
+                "'{}' holds several events, answer them one by one",
+                cmd.file
+            ))
+        }
+    };
+    let invitation = storage::read_event(&data, Path::new(&cmd.file))
+        .with_context(|| format!("Failed to read the event of '{}'", cmd.file))?;
+    let organizer = invitation
+        .organizer
+        .clone()
+        .ok_or_else(|| anyhow!("'{}' has no organizer to answer", invitation.name))?;
+    let data = storage::set_partstat(&data, &me, cmd.answer.partstat())
+        .ok_or_else(|| anyhow!("{} is not invited to '{}'", me, invitation.name))?;
+
+    event::ensure_calendar(&cmd.calendar, false)?;
+    let calendar = calendar::load(&cmd.calendar)?;
+    let mut batch = storage::Batch::default();
+    // A new answer replaces the event of the previous one
+    if let Some(existing) = calendar.get_event(uid.clone()) {
+        batch.delete_event(&calendar.path, existing)?;
+    }
+    if cmd.answer != cli::RsvpAnswer::Decline {
+        batch.write_raw_event(&calendar.path, &uid, &data)?;
+    }
+    batch.commit()?;
+
+    let when = format!(
+        "{}, {}",
+        invitation.start.format("%a %d %b %Y"),
+        invitation.time_range()
+    );
+    let (answered, subject) = match cmd.answer {
+        cli::RsvpAnswer::Accept => ("accepted", "Accepted"),
+        cli::RsvpAnswer::Decline => ("declined", "Declined"),
+        cli::RsvpAnswer::Tentative => ("tentatively accepted", "Tentative"),
+    };
+    match cmd.answer {
+        cli::RsvpAnswer::Decline => println!("Declined '{}' on {}", invitation.name, when),
+        _ => println!(
+            "Added '{}' on {} to '{}', {}",
+            invitation.name, when, cmd.calendar, answered
+        ),
+    }
+
+    let reply = storage::export_reply(&data, &me);
+    if let Some(path) = &cmd.reply {
+        fs::write(path, &reply).with_context(|| format!("Failed to write '{}'", path))?;
+        println!("Wrote the reply to '{}'", path);
+    }
+
+    if cmd.send {
+        // The mailbox of the config keeps its name when it is the one invited
+        let from = config
+            .from
+            .as_deref()
+            .filter(|from| address(from).eq_ignore_ascii_case(&me))
+            .unwrap_or(&me);
+        let text = format!(
+            "{} has {} the invitation to {}\n\nWhen: {}\n",
+            from, answered, invitation.name, when
+        );
+        sendmail(
+            &config,
+            std::slice::from_ref(&organizer),
+            &message(
+                from,
+                std::slice::from_ref(&organizer),
+                &format!("{}: {}", subject, invitation.name),
+                &text,
+                "REPLY",
+                &reply,
+            ),
+        )?;
+        println!("Sent the reply to {}", organizer);
+    }
+
+    Ok(())
+}
+
+// The address of a "Name <address>" mailbox
+pub fn address(mailbox: &str) -> &str {
+    match (mailbox.find('<'), mailbox.rfind('>')) {
+        (Some(start), Some(end)) if start < end => mailbox[start + 1..end].trim(),
+        _ => mailbox.trim(),
+    }
+}
+
+// An iMIP message, a text for people and the calendar for their clients
+fn message(
+    from: &str,
+    to: &[String],
+    subject: &str,
+    text: &str,
+    method: &str,
+    calendar: &str,
+) -> String {
+    let boundary = format!("calendar-rs-{}", Uuid::new_v4().simple());
+    format!(
+        "Subject: {subject}\n\
+         Date: {date}\n\
+         From: {from}\n\
+         To: {to}\n\
+         MIME-Version: 1.0\n\
+         Content-Type: multipart/alternative; boundary=\"{boundary}\"\n\
+         \n\
+         --{boundary}\n\
+         Content-Type: text/plain; charset=utf-8\n\
+         Content-Transfer-Encoding: 8bit\n\
+         \n\
+         {text}\n\
+         --{boundary}\n\
+         Content-Type: text/calendar; charset=utf-8; method={method}\n\
+         Content-Transfer-Encoding: 8bit\n\
+         \n\
+         {calendar}\n\
+         --{boundary}--\n",
+        subject = header(subject),
+        date = Local::now().to_rfc2822(),
+        from = header(from),
+        to = header(&to.join(", ")),
+        boundary = boundary,
+        text = text,
+        method = method,
+        calendar = calendar.replace("\r\n", "\n"),
+    )
+}
+
+// The value of a header on a single line: the names and addresses of
+// invitations come from their senders, and a line break in them would add
+// headers of their choosing
+fn header(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect()
+}
+
+// Sends a message to the addresses of the recipients given, not to the ones
+// its headers name
+fn sendmail(config: &InviteConfig, recipients: &[String], message: &str) -> Result<()> {
+    let mut child = Command::new(&config.sendmail)
+        .arg("--")
+        .args(recipients.iter().map(|recipient| address(recipient)))
+        .stdin(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run '{}'", config.sendmail))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(message.as_bytes())?;
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(anyhow!("'{}' failed to send the message", config.sendmail));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headers_stay_on_their_line() {
+        let message = message(
+            "Organizer <organizer@example.com>",
+            &["me@example.com".to_string()],
+            "Accepted: Review\r\nBcc: someone@example.com",
+            "Text",
+            "REPLY",
+            "BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n",
+        );
+        let (headers, _) = message.split_once("\n\n").unwrap();
+        assert!(headers
+            .lines()
+            .any(|line| line == "Subject: Accepted: Review  Bcc: someone@example.com"));
+        assert!(!headers.lines().any(|line| line.starts_with("Bcc:")));
+    }
+}
@@ -0,0 +1,400 @@
+use crate::config::{self, LocaleConfig};
+use chrono::{Datelike, NaiveDate, NaiveDateTime};
+use std::env;
+use std::sync::OnceLock;
+
+// The names of the months and of the weekdays, Monday first, of a language
+struct Language {
+    code: &'static str,
+    months: [&'static str; 12],
+    short_months: [&'static str; 12],
+    weekdays: [&'static str; 7],
+    short_weekdays: [&'static str; 7],
+}
+
+const LANGUAGES: &[Language] = &[
+    Language {
+        code: "en",
+        months: [
+            "January",
+            "February",
+            "March",
+            "April",
+            "May",
+            "June",
+            "July",
+            "August",
+            "September",
+            "October",
+            "November",
+            "December",
+        ],
+        short_months: [
+            "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+        ],
+        weekdays: [
+            "Monday",
+            "Tuesday",
+            "Wednesday",
+            "Thursday",
+            "Friday",
+            "Saturday",
+            "Sunday",
+        ],
+        short_weekdays: ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"],
+    },
+    Language {
+        code: "fr",
+        months: [
+            "janvier",
+            "février",
+            "mars",
+            "avril",
+            "mai",
+            "juin",
+            "juillet",
+            "août",
+            "septembre",
+            "octobre",
+            "novembre",
+            "décembre",
+        ],
+        short_months: [
+            "jan", "fév", "mar", "avr", "mai", "jun", "jul", "aoû", "sep", "oct", "nov", "déc",
+        ],
+        weekdays: [
+            "lundi", "mardi", "mercredi", "jeudi", "vendredi", "samedi", "dimanche",
+        ],
+        short_weekdays: ["lun", "mar", "mer", "jeu", "ven", "sam", "dim"],
+    },
+    Language {
+        code: "de",
+        months: [
+            "Januar",
+            "Februar",
+            "März",
+            "April",
+            "Mai",
+            "Juni",
+            "Juli",
+            "August",
+            "September",
+            "Oktober",
+            "November",
+            "Dezember",
+        ],
+        short_months: [
+            "Jan", "Feb", "Mär", "Apr", "Mai", "Jun", "Jul", "Aug", "Sep", "Okt", "Nov", "Dez",
+        ],
+        weekdays: [
+            "Montag",
+            "Dienstag",
+            "Mittwoch",
+            "Donnerstag",
+            "Freitag",
+            "Samstag",
+            "Sonntag",
+        ],
+        short_weekdays: ["Mo", "Di", "Mi", "Do", "Fr", "Sa", "So"],
+    },
+    Language {
+        code: "es",
+        months: [
+            "enero",
+            "febrero",
+            "marzo",
+            "abril",
+            "mayo",
+            "junio",
+            "julio",
+            "agosto",
+            "septiembre",
+            "octubre",
+            "noviembre",
+            "diciembre",
+        ],
+        short_months: [
+            "ene", "feb", "mar", "abr", "may", "jun", "jul", "ago", "sep", "oct", "nov", "dic",
+        ],
+        weekdays: [
+            "lunes",
+            "martes",
+            "miércoles",
+            "jueves",
+            "viernes",
+            "sábado",
+            "domingo",
+        ],
+        short_weekdays: ["lun", "mar", "mié", "jue", "vie", "sáb", "dom"],
+    },
+    Language {
+        code: "it",
+        months: [
+            "gennaio",
+            "febbraio",
+            "marzo",
+            "aprile",
+            "maggio",
+            "giugno",
+            "luglio",
+            "agosto",
+            "settembre",
+            "ottobre",
+            "novembre",
+            "dicembre",
+        ],
+        short_months: [
+            "gen", "feb", "mar", "apr", "mag", "giu", "lug", "ago", "set", "ott", "nov", "dic",
+        ],
+        weekdays: [
+            "lunedì",
+            "martedì",
+            "mercoledì",
+            "giovedì",
+            "venerdì",
+            "sabato",
+            "domenica",
+        ],
+        short_weekdays: ["lun", "mar", "mer", "gio", "ven", "sab", "dom"],
+    },
+    Language {
+        code: "pt",
+        months: [
+            "janeiro",
+            "fevereiro",
+            "março",
+            "abril",
+            "maio",
+            "junho",
+            "julho",
+            "agosto",
+            "setembro",
+            "outubro",
+            "novembro",
+            "dezembro",
+        ],
+        short_months: [
+            "jan", "fev", "mar", "abr", "mai", "jun", "jul", "ago", "set", "out", "nov", "dez",
+        ],
+        weekdays: [
+            "segunda-feira",
+            "terça-feira",
+            "quarta-feira",
+            "quinta-feira",
+            "sexta-feira",
+            "sábado",
+            "domingo",
+        ],
+        short_weekdays: ["seg", "ter", "qua", "qui", "sex", "sáb", "dom"],
+    },
+    Language {
+        code: "nl",
+        months: [
+            "januari",
+            "februari",
+            "maart",
+            "april",
+            "mei",
+            "juni",
+            "juli",
+            "augustus",
+            "september",
+            "oktober",
+            "november",
+            "december",
+        ],
+        short_months: [
+            "jan", "feb", "mrt", "apr", "mei", "jun", "jul", "aug", "sep", "okt", "nov", "dec",
+        ],
+        weekdays: [
+            "maandag",
+            "dinsdag",
+            "woensdag",
+            "donderdag",
+            "vrijdag",
+            "zaterdag",
+            "zondag",
+        ],
+        short_weekdays: ["ma", "di", "wo", "do", "vr", "za", "zo"],
+    },
+];
+
+// The names used in the dates shown, from the language of the config or of
+// the environment, with the tables of the config replacing them
+struct Names {
+    months: Vec<String>,
+    short_months: Vec<String>,
+    weekdays: Vec<String>,
+    short_weekdays: Vec<String>,
+}
+
+static NAMES: OnceLock<Names> = OnceLock::new();
+
+fn names() -> &'static Names {
+    NAMES.get_or_init(|| {
+        // A broken config is reported by the commands reading it
+        let locale = config::load().map(|c| c.locale).unwrap_or_default();
+        load(locale)
+    })
+}
+
+fn load(locale: LocaleConfig) -> Names {
+    let code = locale.language.clone().or_else(environment_language);
+    let language = code
+        .as_deref()
+        .and_then(|code| {
+            let found = LANGUAGES.iter().find(|l| l.code == code);
+            if found.is_none() && locale.language.is_some() {
+                eprintln!("Warning: unknown language '{}', using English", code);
+            }
+            found
+        })
+        .unwrap_or(&LANGUAGES[0]);
+
+    let names = |custom: Vec<String>, builtin: &[&str], what: &str| {
+        if custom.is_empty() {
+            return builtin.iter().map(|name| name.to_string()).collect();
+        }
+        if custom.len() != builtin.len() {
+            eprintln!(
+                "Warning: [locale] {} needs {} names, ignoring it",
+                what,
+                builtin.len()
+            );
+            return builtin.iter().map(|name| name.to_string()).collect();
+        }
+        custom
+    };
+    // Custom names are shortened to their first three letters by default
+    let short = |names: &[String]| -> Vec<String> {
+        names.iter().map(|n| n.chars().take(3).collect()).collect()
+    };
+    let derive_short_months = locale.short_months.is_empty() && !locale.months.is_empty();
+    let derive_short_weekdays = locale.short_weekdays.is_empty() && !locale.weekdays.is_empty();
+    let months = names(locale.months, &language.months, "months");
+    let weekdays = names(locale.weekdays, &language.weekdays, "weekdays");
+    let short_months = match derive_short_months {
+        true => short(&months),
+        false => names(locale.short_months, &language.short_months, "short_months"),
+    };
+    let short_weekdays = match derive_short_weekdays {
+        true => short(&weekdays),
+        false => names(
+            locale.short_weekdays,
+            &language.short_weekdays,
+            "short_weekdays",
+        ),
+    };
+
+    Names {
+        months,
+        short_months,
+        weekdays,
+        short_weekdays,
+    }
+}
+
+// The language of dates set in the environment, like "fr" for
+// LANG=fr_FR.UTF-8, with the usual precedence of the locale variables
+fn environment_language() -> Option<String> {
+    ["LC_ALL", "LC_TIME", "LANG"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .map(|value| {
+            let code = value.split(['_', '.', '@']).next().unwrap_or_default();
+            match code {
+                "C" | "POSIX" => "en".to_string(),
+                code => code.to_lowercase(),
+            }
+        })
+}
+
+impl Names {
+    // Replaces the month and weekday names of a strftime format (%B, %b, %h,
+    // %A and %a) by the names of the language
+    fn localize(&self, format: &str, month0: u32, weekday: usize) -> String {
+        let mut localized = String::with_capacity(format.len());
+        let mut chars = format.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                localized.push(c);
+                continue;
+            }
+            let name = match chars.next() {
+                Some('B') => &self.months[month0 as usize],
+                Some('b' | 'h') => &self.short_months[month0 as usize],
+                Some('A') => &self.weekdays[weekday],
+                Some('a') => &self.short_weekdays[weekday],
+                Some(spec) => {
+                    localized.push('%');
+                    localized.push(spec);
+                    continue;
+                }
+                None => {
+                    localized.push('%');
+                    continue;
+                }
+            };
+            localized.push_str(&name.replace('%', "%%"));
+        }
+        localized
+    }
+
+    fn weekday_header(&self) -> String {
+        self.short_weekdays
+            .iter()
+            .map(|name| format!("{:<2}", name.chars().take(2).collect::<String>()))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+// Formats dates like chrono does, with the month and weekday names of the
+// language of the user
+pub trait LocalFormat {
+    fn format_local(&self, format: &str) -> String;
+}
+
+impl LocalFormat for NaiveDate {
+    fn format_local(&self, format: &str) -> String {
+        let format = names().localize(
+            format,
+            self.month0(),
+            self.weekday().num_days_from_monday() as usize,
+        );
+        self.format(&format).to_string()
+    }
+}
+
+impl LocalFormat for NaiveDateTime {
+    fn format_local(&self, format: &str) -> String {
+        let format = names().localize(
+            format,
+            self.month0(),
+            self.weekday().num_days_from_monday() as usize,
+        );
+        self.format(&format).to_string()
+    }
+}
+
+// The header of the columns of the month grids, like "Mo Tu We Th Fr Sa Su"
+pub fn weekday_header() -> String {
+    names().weekday_header()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_the_names_of_the_language() {
+        let french = load(LocaleConfig {
+            language: Some("fr".to_string()),
+            ..LocaleConfig::default()
+        });
+        assert_eq!(french.localize("%A, %d %B", 7, 0), "lundi, %d août");
+        assert_eq!(french.localize("%a %d %b %%A", 7, 0), "lun %d aoû %%A");
+        assert_eq!(french.weekday_header(), "lu ma me je ve sa di");
+    }
+}
@@ -0,0 +1,114 @@
+use crate::error::CalendarError;
+use anyhow::{Context, Result};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// The file of the lock of a directory
+const LOCK_FILE: &str = ".lock";
+
+// How long a writer waits for another one before giving up
+const WAIT: Duration = Duration::from_secs(10);
+const POLL: Duration = Duration::from_millis(100);
+
+// An advisory lock of a calendar, so that processes writing to it at the
+// same time, like a sync and an add, take turns. It is a lock of the system
+// on a file kept in the directory, released when dropped or when its process
+// ends, however it ends. The file holds the pid of the last writer, to tell
+// who holds it.
+pub struct Lock {
+    _file: File,
+}
+
+// Takes the lock of a directory, waiting for the process holding it
+pub fn acquire(dir: &Path) -> Result<Lock> {
+    acquire_within(dir, WAIT)
+}
+
+fn acquire_within(dir: &Path, wait: Duration) -> Result<Lock> {
+    let path = dir.join(LOCK_FILE);
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .with_context(|| format!("Failed to open '{}'", path.display()))?;
+    let started = Instant::now();
+    loop {
+        match file.try_lock() {
+            Ok(()) => break,
+            Err(TryLockError::WouldBlock) => {}
+            Err(TryLockError::Error(err)) => {
+                return Err(err).with_context(|| format!("Failed to lock '{}'", path.display()))
+            }
+        }
+        if started.elapsed() >= wait {
+            let holder = fs::read_to_string(&path)
+                .ok()
+                .and_then(|pid| pid.trim().parse::<u32>().ok())
+                .map_or("another process".to_string(), |pid| {
+                    format!("process {}", pid)
+                });
+            return Err(CalendarError::Locked(format!(
+                "'{}' is being written by {}",
+                dir.display(),
+                holder
+            ))
+            .into());
+        }
+        thread::sleep(POLL);
+    }
+
+    file.set_len(0)
+        .and_then(|()| writeln!(file, "{}", std::process::id()))
+        .with_context(|| format!("Failed to write '{}'", path.display()))?;
+    Ok(Lock { _file: file })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writers_take_turns() {
+        let dir = std::env::temp_dir().join(format!("calendar-rs-lock-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let lock = acquire(&dir).unwrap();
+        let err = acquire_within(&dir, Duration::ZERO).err().unwrap();
+        assert!(matches!(
+            err.downcast_ref::<CalendarError>(),
+            Some(CalendarError::Locked(message)) if message.contains(&std::process::id().to_string())
+        ));
+
+        // The next writer gets it once it is released
+        let waiting = thread::spawn({
+            let dir = dir.clone();
+            move || acquire(&dir).is_ok()
+        });
+        thread::sleep(POLL * 2);
+        drop(lock);
+        assert!(waiting.join().unwrap());
+        assert!(dir.join(LOCK_FILE).is_file());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn locks_of_processes_that_died_are_taken_over() {
+        let dir =
+            std::env::temp_dir().join(format!("calendar-rs-lock-stale-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // Left by a writer that was killed: the file stays, not its lock
+        fs::write(dir.join(LOCK_FILE), "4194304\n").unwrap();
+
+        let lock = acquire_within(&dir, Duration::ZERO).unwrap();
+        let pid = fs::read_to_string(dir.join(LOCK_FILE)).unwrap();
+        assert_eq!(pid.trim(), std::process::id().to_string());
+        drop(lock);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
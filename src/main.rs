@@ -1,19 +1,87 @@
+mod cache;
 mod calendar;
 mod cli;
+mod completion;
+mod config;
+mod csv_import;
+mod daemon;
 mod date;
+mod dedupe;
+mod display;
+mod doctor;
+mod email;
+mod error;
 mod event;
+mod external;
+mod freebusy;
+mod fuzzy;
+#[cfg(feature = "graph")]
+mod graph;
+mod html;
+#[cfg(feature = "sqlite")]
+mod index;
+mod interrupt;
+mod invite;
+mod locale;
+mod lock;
+mod menu;
+mod note;
+mod open;
+mod output;
+mod paths;
+mod picker;
+mod progress;
+mod purge;
+mod request;
+mod retry;
+mod rooms;
+mod serve;
+mod setup;
+mod stats;
 mod storage;
+mod subscription;
+mod sync;
+mod taskwarrior;
+mod template;
+mod todo;
+mod trash;
+mod watch;
 use std::fs;
 
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let command = cli::parse_cli()?;
+fn main() {
+    let command = match cli::parse_cli() {
+        Ok(command) => command,
+        Err(err) => {
+            eprintln!("Error: {:?}", err);
+            let code = match error::exit_code(&err) {
+                1 => error::USAGE_EXIT_CODE,
+                code => code,
+            };
+            std::process::exit(code);
+        }
+    };
 
-    let home_dir = dirs::home_dir().ok_or_else(|| anyhow!("Unable to determine home directory"))?;
-    let calendar_dir = home_dir.join(".calendars");
+    let result = start().and_then(|()| run(command));
+    storage::report_skipped();
+    if let Err(err) = result {
+        if !matches!(err.downcast_ref(), Some(error::CalendarError::NoEvent)) {
+            eprintln!("Error: {:?}", err);
+        }
+        std::process::exit(error::exit_code(&err));
+    }
+}
+
+fn start() -> Result<()> {
+    interrupt::install()?;
+
+    let calendar_dir = paths::calendar_dir()?;
     fs::create_dir_all(&calendar_dir)?;
+    Ok(())
+}
 
+fn run(command: cli::CalendarCommand) -> Result<()> {
     match command {
         cli::CalendarCommand::List(args) => {
             event::list(args)?;
@@ -21,20 +89,144 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         cli::CalendarCommand::Add(args) => {
             event::add(args)?;
         }
+        cli::CalendarCommand::Protect(args) => {
+            event::protect(args)?;
+        }
         cli::CalendarCommand::Edit(args) => {
             event::edit(args)?;
         }
         cli::CalendarCommand::Delete(args) => {
             event::delete(args)?;
         }
+        cli::CalendarCommand::Move(args) => {
+            event::move_event(args)?;
+        }
         cli::CalendarCommand::Show(args) => {
             event::show(args)?;
         }
+        cli::CalendarCommand::Open(args) => {
+            open::run(args)?;
+        }
+        cli::CalendarCommand::Attend(args) => {
+            event::attend(args)?;
+        }
+        cli::CalendarCommand::Stats(args) => {
+            stats::run(args)?;
+        }
         cli::CalendarCommand::View(args) => {
             event::view(args)?;
         }
         cli::CalendarCommand::Sync(args) => {
-            event::sync(args)?;
+            sync::run(args)?;
+        }
+        cli::CalendarCommand::Migrate(args) => {
+            event::migrate(args)?;
+        }
+        cli::CalendarCommand::Setup(args) => {
+            setup::run(args)?;
+        }
+        cli::CalendarCommand::Import(args) => {
+            event::import(args)?;
+        }
+        cli::CalendarCommand::Export(args) => {
+            event::export(args)?;
+        }
+        cli::CalendarCommand::Digest(args) => {
+            event::digest(args)?;
+        }
+        cli::CalendarCommand::Agenda(args) => {
+            event::agenda(args)?;
+        }
+        cli::CalendarCommand::Free(args) => {
+            event::free(args)?;
+        }
+        cli::CalendarCommand::FreeEvenings(args) => {
+            event::free_evenings(args)?;
+        }
+        cli::CalendarCommand::Schedule(args) => {
+            event::schedule(args)?;
+        }
+        cli::CalendarCommand::Rsvp(args) => {
+            invite::rsvp(args)?;
+        }
+        cli::CalendarCommand::FreeBusy(args) => {
+            event::freebusy(args)?;
+        }
+        cli::CalendarCommand::Status(args) => {
+            event::status(args)?;
+        }
+        cli::CalendarCommand::Next(args) => {
+            event::next(args)?;
+        }
+        cli::CalendarCommand::Now(args) => {
+            event::now(args)?;
+        }
+        cli::CalendarCommand::Motd(args) => {
+            event::motd(args)?;
+        }
+        cli::CalendarCommand::Menu(args) => {
+            menu::run(args)?;
+        }
+        cli::CalendarCommand::Daemon(args) => {
+            daemon::run(args)?;
+        }
+        cli::CalendarCommand::Client(args) => {
+            daemon::client(args)?;
+        }
+        cli::CalendarCommand::Serve(args) => {
+            serve::run(args)?;
+        }
+        cli::CalendarCommand::RoomsFree(args) => {
+            rooms::free(args)?;
+        }
+        cli::CalendarCommand::TodoAdd(args) => {
+            todo::add(args)?;
+        }
+        cli::CalendarCommand::TodoList(args) => {
+            todo::list(args)?;
+        }
+        cli::CalendarCommand::TodoDone(args) => {
+            todo::done(args)?;
+        }
+        cli::CalendarCommand::Note(args) => {
+            note::add(args)?;
+        }
+        cli::CalendarCommand::Notes(args) => {
+            note::list(args)?;
+        }
+        cli::CalendarCommand::Search(args) => {
+            event::search(args)?;
+        }
+        cli::CalendarCommand::When(args) => {
+            event::when(args)?;
+        }
+        #[cfg(feature = "sqlite")]
+        cli::CalendarCommand::Index(args) => {
+            event::index(args)?;
+        }
+        cli::CalendarCommand::TrashList(args) => {
+            trash::list(args)?;
+        }
+        cli::CalendarCommand::TrashRestore(args) => {
+            trash::restore(args)?;
+        }
+        cli::CalendarCommand::TrashEmpty(args) => {
+            trash::empty(args)?;
+        }
+        cli::CalendarCommand::Purge(args) => {
+            purge::run(args)?;
+        }
+        cli::CalendarCommand::Dedupe(args) => {
+            dedupe::run(args)?;
+        }
+        cli::CalendarCommand::Doctor(args) => {
+            doctor::run(args)?;
+        }
+        cli::CalendarCommand::Completion(args) => {
+            completion::script(args)?;
+        }
+        cli::CalendarCommand::Complete(args) => {
+            completion::complete(args)?;
         }
     }
 
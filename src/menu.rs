@@ -0,0 +1,114 @@
+use crate::cli::{self, MenuAction};
+use crate::display;
+use crate::event;
+use crate::locale::LocalFormat;
+use anyhow::{anyhow, Context, Result};
+use chrono::{Duration, Local, NaiveTime};
+use clap::Parser;
+use std::io::{self, BufRead};
+
+// The lines of the actions on an event, before the line of the event, as
+// rofi shows them once the event is chosen
+const SHOW: &str = "Show  ";
+const DELETE: &str = "Delete  ";
+const CONFIRM_DELETE: &str = "Yes, delete  ";
+
+// Lists the coming events, one per line, for rofi or dmenu to choose from,
+// then acts on the line chosen: with --pick, or as a rofi script, which is
+// run again with the chosen line until it prints nothing
+pub fn run(cmd: cli::CalendarMenuArgs) -> Result<()> {
+    // The lines are read by menus, not by terminals
+    display::set_plain();
+
+    let selection = match cmd.selection.as_deref() {
+        Some("-") => {
+            let mut line = String::new();
+            io::stdin()
+                .lock()
+                .read_line(&mut line)
+                .context("Failed to read the selection")?;
+            Some(line.trim_end_matches(['\r', '\n']).to_string())
+        }
+        selection => selection.map(str::to_string),
+    };
+
+    let Some(line) = selection else {
+        return print_events(&cmd);
+    };
+    if let Some(action) = cmd.action {
+        return act(action, &line, cmd.changes);
+    }
+
+    if let Some(line) = line.strip_prefix(SHOW) {
+        act(MenuAction::Show, line, Vec::new())
+    } else if let Some(line) = line.strip_prefix(DELETE) {
+        println!("{}{}", CONFIRM_DELETE, line);
+        Ok(())
+    } else if let Some(line) = line.strip_prefix(CONFIRM_DELETE) {
+        act(MenuAction::Delete, line, Vec::new())
+    } else if parse_line(&line).is_some() {
+        println!("{}{}", SHOW, line);
+        println!("{}{}", DELETE, line);
+        Ok(())
+    } else {
+        // Another line, like one of the event shown, closes the menu
+        Ok(())
+    }
+}
+
+fn print_events(cmd: &cli::CalendarMenuArgs) -> Result<()> {
+    let now = Local::now().naive_local();
+    let to = (now.date() + Duration::days(i64::from(cmd.days))).and_time(NaiveTime::MIN);
+    let mut events = event::load_events_overlapping(cmd.calendar.clone(), now, to, false)?;
+    events.retain(|event| !event.task && event.end > now);
+    events.sort_by_key(|event| event.start);
+
+    for event in &events {
+        println!(
+            "{} {}  {}  ({} {})",
+            event.start.format_local("%a %d %b"),
+            event.time_range(),
+            event.name.replace('\n', " "),
+            event.calendar,
+            event.id
+        );
+    }
+    Ok(())
+}
+
+// The calendar and the id of the event of a line, at its end
+fn parse_line(line: &str) -> Option<(&str, &str)> {
+    let (_, end) = line.strip_suffix(')')?.rsplit_once('(')?;
+    let (calendar, id) = end.rsplit_once(' ')?;
+    match calendar.is_empty() || id.is_empty() {
+        true => None,
+        false => Some((calendar, id)),
+    }
+}
+
+// Acts on the event of a line through the commands, like from the command
+// line: choosing it is taken as the confirmation
+fn act(action: MenuAction, line: &str, changes: Vec<String>) -> Result<()> {
+    let (calendar, id) =
+        parse_line(line).ok_or_else(|| anyhow!("'{}' is not a line of the menu", line))?;
+    match action {
+        MenuAction::Show => event::show(cli::CalendarShowArgs {
+            event_id: id.to_string(),
+            calendar: calendar.to_string(),
+            output: cli::OutputFormat::Text,
+        }),
+        MenuAction::Delete => event::delete(cli::CalendarDeleteArgs {
+            selection: cli::EventSelection::Id(id.to_string()),
+            calendar: calendar.to_string(),
+            force: true,
+        }),
+        MenuAction::Edit => {
+            let args = ["edit", id, "--calendar", calendar, "--yes"]
+                .into_iter()
+                .map(str::to_string)
+                .chain(changes);
+            let edit = cli::EditArgs::try_parse_from(args)?;
+            event::edit(edit.validate()?)
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use crate::calendar::{self, Note};
+use crate::cli;
+use crate::event;
+use crate::locale::LocalFormat;
+use anyhow::Result;
+use colored::Colorize;
+
+pub fn add(cmd: cli::CalendarNoteArgs) -> Result<()> {
+    event::ensure_calendar(&cmd.calendar, cmd.create)?;
+
+    let mut calendar = calendar::load(&cmd.calendar)?;
+    calendar.add_note(Note::new(cmd.date, cmd.text))?;
+
+    Ok(())
+}
+
+pub fn list(cmd: cli::CalendarNotesArgs) -> Result<()> {
+    let mut notes: Vec<Note> = match cmd.calendar {
+        Some(calendar_name) => {
+            if calendar_name == "personal" {
+                event::create_personal()?;
+            }
+            calendar::load(&calendar_name)?.notes
+        }
+        None => calendar::load_all()?
+            .into_iter()
+            .flat_map(|calendar| calendar.notes)
+            .collect(),
+    };
+
+    notes.retain(|note| note.date >= cmd.from && note.date <= cmd.to);
+    notes.sort_by_key(|note| (note.date, note.created));
+
+    if notes.is_empty() {
+        println!("No notes");
+    }
+
+    // Notes are grouped under their day
+    let mut day = None;
+    for note in &notes {
+        if day != Some(note.date) {
+            if day.is_some() {
+                println!();
+            }
+            println!("{}", note.date.format_local("%A, %d %B %Y").bold());
+            day = Some(note.date);
+        }
+        for (i, line) in note.text.lines().enumerate() {
+            let bullet = if i == 0 { "-" } else { " " };
+            println!("{} {}", bullet, line);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,169 @@
+use crate::calendar::Event;
+use crate::cli;
+use crate::config;
+use crate::event;
+use anyhow::{anyhow, Context, Result};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::process::Command;
+
+lazy_static! {
+    static ref URL_REGEX: Regex = Regex::new(r#"https?://[^\s<>"]+"#).unwrap();
+}
+
+// Hosts of video calls, whose links are looked for in the location and the
+// description of events without a URL
+const MEETING_HOSTS: &[&str] = &[
+    "zoom.us",
+    "meet.google.com",
+    "teams.microsoft.com",
+    "teams.live.com",
+    "webex.com",
+    "meet.jit.si",
+    "whereby.com",
+];
+
+pub fn run(cmd: cli::CalendarOpenArgs) -> Result<()> {
+    let event = event::find_event(&cmd.calendar, &cmd.event_id)?;
+    let config = config::load()?.open;
+
+    let (url, source) = link(&event, &config.maps)
+        .ok_or_else(|| anyhow!("'{}' has no URL, meeting link or location", event.name))?;
+
+    if cmd.dry_run {
+        println!("Would open {} ({})", url, source);
+        return Ok(());
+    }
+
+    println!("Opening {}", url);
+    let status = Command::new(&config.command)
+        .arg(&url)
+        .status()
+        .with_context(|| format!("Failed to run '{}'", config.command))?;
+    if !status.success() {
+        return Err(anyhow!("'{}' failed to open {}", config.command, url));
+    }
+
+    Ok(())
+}
+
+// What to open for an event, in order: its URL, a meeting link found in its
+// location or description, or its location, itself a link or else looked up
+// with the maps template. The URL comes from whoever wrote the event, only
+// web and mail links are opened, not files or commands of other schemes.
+fn link(event: &Event, maps: &str) -> Option<(String, &'static str)> {
+    if let Some(url) = event
+        .url
+        .as_deref()
+        .map(str::trim)
+        .filter(|url| is_link(url))
+    {
+        return Some((url.to_string(), "URL"));
+    }
+
+    let texts = [event.location.as_deref(), event.description.as_deref()];
+    let meeting = texts
+        .iter()
+        .flatten()
+        .flat_map(|text| URL_REGEX.find_iter(text))
+        .map(|url| url.as_str().trim_end_matches(['.', ',', ';', ')', '>']))
+        .find(|url| is_meeting(url));
+    if let Some(url) = meeting {
+        return Some((url.to_string(), "meeting link"));
+    }
+
+    let location = event.location.as_deref().map(str::trim)?;
+    if is_url(location) {
+        return Some((location.to_string(), "location"));
+    }
+    map_link(location, maps).map(|url| (url, "location"))
+}
+
+// The link to a place on a map, from the maps template, for locations that
+// are not links themselves
+pub fn map_link(location: &str, maps: &str) -> Option<String> {
+    let location = location.trim();
+    if location.is_empty() || is_url(location) {
+        return None;
+    }
+    Some(maps.replace("{}", &encode(location)))
+}
+
+// The maps template when map links are shown in the agendas
+pub fn agenda_maps() -> Result<Option<String>> {
+    let config = config::load()?.open;
+    Ok(config.map_links.then_some(config.maps))
+}
+
+fn is_url(text: &str) -> bool {
+    URL_REGEX.find(text).is_some_and(|url| url.as_str() == text)
+}
+
+// A web link, or the mailto link of an address
+fn is_link(text: &str) -> bool {
+    is_url(text)
+        || text
+            .strip_prefix("mailto:")
+            .is_some_and(|address| !address.is_empty() && !address.contains(char::is_whitespace))
+}
+
+fn is_meeting(url: &str) -> bool {
+    let host = url
+        .split("://")
+        .nth(1)
+        .and_then(|rest| rest.split(['/', '?', '#', ':']).next())
+        .unwrap_or_default()
+        .to_lowercase();
+    MEETING_HOSTS
+        .iter()
+        .any(|meeting| host == *meeting || host.ends_with(&format!(".{}", meeting)))
+}
+
+// Percent-encodes a query parameter
+fn encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn only_web_and_mail_links_are_opened() {
+        let start = NaiveDate::from_ymd_opt(2026, 10, 16)
+            .unwrap()
+            .and_hms_opt(10, 0, 0)
+            .unwrap();
+        let mut event = Event::builder("Review", start, start).build().unwrap();
+        event.location = Some("Room 4".to_string());
+
+        let opened = |url: &str| {
+            let mut event = event.clone();
+            event.url = Some(url.to_string());
+            link(&event, "https://maps.example/?q={}").map(|(url, _)| url)
+        };
+        assert_eq!(
+            opened("https://example.com/review").as_deref(),
+            Some("https://example.com/review")
+        );
+        assert_eq!(
+            opened("mailto:me@example.com").as_deref(),
+            Some("mailto:me@example.com")
+        );
+        // The others are left for the location
+        for url in ["file:///etc/passwd", "--help", "javascript:alert(1)"] {
+            assert_eq!(
+                opened(url).as_deref(),
+                Some("https://maps.example/?q=Room%204")
+            );
+        }
+    }
+}
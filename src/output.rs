@@ -0,0 +1,139 @@
+use crate::calendar::{local_time, Event};
+use crate::cli::OutputFormat;
+use crate::display;
+use crate::template::Template;
+use anyhow::Result;
+use chrono::{Datelike, NaiveDate};
+use serde::Serialize;
+
+// An event of the JSON output: the event itself, with the date, ISO week and
+// weekday of its start as shown in the views
+#[derive(Serialize)]
+struct JsonEvent<'a> {
+    #[serde(flatten)]
+    event: &'a Event,
+    date: NaiveDate,
+    iso_week: String,
+    weekday: String,
+}
+
+#[derive(Serialize)]
+struct JsonView<'a> {
+    from: NaiveDate,
+    to: NaiveDate,
+    events: Vec<JsonEvent<'a>>,
+}
+
+impl<'a> From<&'a Event> for JsonEvent<'a> {
+    fn from(event: &'a Event) -> Self {
+        JsonEvent {
+            event,
+            date: event.start.date(),
+            iso_week: iso_week(event.start.date()),
+            weekday: event.start.format("%A").to_string(),
+        }
+    }
+}
+
+pub fn print_events(events: &[Event], format: OutputFormat, template: &Template) -> Result<()> {
+    match format {
+        OutputFormat::Text => {
+            let width = display::short_id_length(events.iter().map(|event| event.id.as_str()));
+            for event in events {
+                match template.shows_id() {
+                    true => println!(
+                        "{}{}",
+                        display::id_column(&event.id, width),
+                        template.render(event)
+                    ),
+                    false => println!("{}", template.render(event)),
+                }
+            }
+            Ok(())
+        }
+        OutputFormat::Json => print_events_json(events),
+        OutputFormat::Csv => print_events_delimited(events, b','),
+        OutputFormat::Tsv => print_events_delimited(events, b'\t'),
+    }
+}
+
+fn print_events_json(events: &[Event]) -> Result<()> {
+    println!("{}", events_json(events)?);
+    Ok(())
+}
+
+pub fn events_json(events: &[Event]) -> Result<String> {
+    let events: Vec<JsonEvent> = events.iter().map(JsonEvent::from).collect();
+    Ok(serde_json::to_string_pretty(&events)?)
+}
+
+fn print_events_delimited(events: &[Event], delimiter: u8) -> Result<()> {
+    // TSV has no quoting, tabs and line breaks cannot appear in fields
+    let tsv = delimiter == b'\t';
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .quote_style(match tsv {
+            true => csv::QuoteStyle::Never,
+            false => csv::QuoteStyle::Necessary,
+        })
+        .from_writer(std::io::stdout().lock());
+    let field = |field: &str| match tsv {
+        true => field.replace(['\t', '\r', '\n'], " "),
+        false => field.to_string(),
+    };
+
+    writer.write_record([
+        "id",
+        "calendar",
+        "start",
+        "end",
+        "name",
+        "location",
+        "description",
+        "iso_week",
+        "weekday",
+        "tags",
+    ])?;
+    for event in events {
+        writer.write_record([
+            field(&event.id),
+            field(&event.calendar),
+            local_time::rfc3339(event.start),
+            local_time::rfc3339(event.end),
+            field(&event.name),
+            field(event.location.as_deref().unwrap_or_default()),
+            field(event.description.as_deref().unwrap_or_default()),
+            iso_week(event.start.date()),
+            event.start.format("%A").to_string(),
+            field(&event.tags.join(",")),
+        ])?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+pub fn print_event_json(event: &Event) -> Result<()> {
+    println!("{}", event_json(event)?);
+    Ok(())
+}
+
+pub fn event_json(event: &Event) -> Result<String> {
+    Ok(serde_json::to_string_pretty(&JsonEvent::from(event))?)
+}
+
+pub fn print_view_json(from: NaiveDate, to: NaiveDate, events: &[Event]) -> Result<()> {
+    let view = JsonView {
+        from,
+        to,
+        events: events.iter().map(JsonEvent::from).collect(),
+    };
+    println!("{}", serde_json::to_string_pretty(&view)?);
+    Ok(())
+}
+
+// ISO 8601 week, whose year can differ from the one of the date
+fn iso_week(date: NaiveDate) -> String {
+    let week = date.iso_week();
+    format!("{}-W{:02}", week.year(), week.week())
+}
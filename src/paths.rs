@@ -0,0 +1,203 @@
+use crate::config;
+use anyhow::{anyhow, Result};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use uuid::Uuid;
+
+// The calendar directory given with --root, someone else's, for this
+// invocation only
+static ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+// The roots of calendars, read from the config once
+static ROOTS: OnceLock<Vec<PathBuf>> = OnceLock::new();
+
+pub fn set_root(root: &Path) -> Result<()> {
+    let root = root
+        .canonicalize()
+        .ok()
+        .filter(|root| root.is_dir())
+        .ok_or_else(|| anyhow!("'{}' is not a calendar directory", root.display()))?;
+    ROOT.set(root)
+        .map_err(|_| anyhow!("The calendar directory is already set"))
+}
+
+pub fn calendar_dir() -> Result<PathBuf> {
+    if let Some(root) = ROOT.get() {
+        return Ok(root.clone());
+    }
+
+    let data_dir = data_calendar_dir()?;
+    if data_dir.is_dir() {
+        return Ok(data_dir);
+    }
+
+    // Keep using the legacy location until the user runs `calendar migrate`
+    let legacy_dir = legacy_calendar_dir()?;
+    if legacy_dir.is_dir() {
+        return Ok(legacy_dir);
+    }
+
+    Ok(data_dir)
+}
+
+// The calendar directory, then the other roots of the config, like a shared
+// drive of team calendars. They are not read with --root.
+pub fn calendar_roots() -> Result<Vec<PathBuf>> {
+    if let Some(roots) = ROOTS.get() {
+        return Ok(roots.clone());
+    }
+
+    let mut roots = vec![calendar_dir()?];
+    if ROOT.get().is_none() {
+        for root in config::load()?.roots {
+            let root = expand_home(&root)?;
+            if !root.is_dir() {
+                eprintln!("Warning: calendar root '{}' not found", root.display());
+                continue;
+            }
+            if !roots.contains(&root) {
+                roots.push(root);
+            }
+        }
+    }
+    Ok(ROOTS.get_or_init(|| roots).clone())
+}
+
+// The directory of a calendar, in the first root that has it. New calendars
+// go to the calendar directory.
+pub fn calendar_path(calendar_name: &str) -> Result<PathBuf> {
+    let roots = calendar_roots()?;
+    Ok(roots
+        .iter()
+        .map(|root| root.join(calendar_name))
+        .find(|path| path.is_dir())
+        .unwrap_or_else(|| roots[0].join(calendar_name)))
+}
+
+// Deleted events, by calendar, until the trash is emptied. Hidden, it is not
+// read as a calendar.
+pub fn trash_dir() -> Result<PathBuf> {
+    Ok(calendar_dir()?.join(".trash"))
+}
+
+pub fn expand_home(path: &str) -> Result<PathBuf> {
+    match path.strip_prefix("~/") {
+        Some(rest) => {
+            let home_dir =
+                dirs::home_dir().ok_or_else(|| anyhow!("Unable to determine home directory"))?;
+            Ok(home_dir.join(rest))
+        }
+        None => Ok(PathBuf::from(path)),
+    }
+}
+
+pub fn data_calendar_dir() -> Result<PathBuf> {
+    Ok(xdg_data_home()?.join("calendar-rs"))
+}
+
+// $XDG_DATA_HOME, else ~/.local/share, on every platform: dirs::data_dir()
+// would be ~/Library/Application Support on macOS
+pub fn xdg_data_home() -> Result<PathBuf> {
+    xdg_home("XDG_DATA_HOME", &[".local", "share"])
+}
+
+// $XDG_CONFIG_HOME, else ~/.config, like the data
+pub fn xdg_config_home() -> Result<PathBuf> {
+    xdg_home("XDG_CONFIG_HOME", &[".config"])
+}
+
+// $XDG_CACHE_HOME, else ~/.cache, like the data
+fn xdg_cache_home() -> Result<PathBuf> {
+    xdg_home("XDG_CACHE_HOME", &[".cache"])
+}
+
+// $XDG_STATE_HOME, else ~/.local/state, like the data
+#[cfg(feature = "graph")]
+fn xdg_state_home() -> Result<PathBuf> {
+    xdg_home("XDG_STATE_HOME", &[".local", "state"])
+}
+
+// A base directory of the XDG spec, else its default in the home directory.
+// A relative path is ignored, as the spec asks.
+fn xdg_home(variable: &str, default: &[&str]) -> Result<PathBuf> {
+    match std::env::var_os(variable).map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => Ok(dir),
+        _ => {
+            let home_dir =
+                dirs::home_dir().ok_or_else(|| anyhow!("Unable to determine home directory"))?;
+            Ok(default.iter().fold(home_dir, |dir, part| dir.join(part)))
+        }
+    }
+}
+
+pub fn legacy_calendar_dir() -> Result<PathBuf> {
+    let home_dir = dirs::home_dir().ok_or_else(|| anyhow!("Unable to determine home directory"))?;
+    Ok(home_dir.join(".calendars"))
+}
+
+pub fn config_file() -> Result<PathBuf> {
+    Ok(xdg_config_home()?.join("calendar-rs").join("config.toml"))
+}
+
+// The cached state of a calendar directory, each --root having its own
+fn state_dir() -> Result<PathBuf> {
+    let cache_dir = xdg_cache_home()?.join("calendar-rs");
+    Ok(match ROOT.get() {
+        Some(root) => {
+            let name = Uuid::new_v5(&Uuid::NAMESPACE_URL, root.to_string_lossy().as_bytes());
+            cache_dir.join("roots").join(name.to_string())
+        }
+        None => cache_dir,
+    })
+}
+
+#[cfg(feature = "sqlite")]
+pub fn index_file() -> Result<PathBuf> {
+    Ok(state_dir()?.join("index.sqlite"))
+}
+
+// The events, todos and notes parsed from the files, see cache
+pub fn cache_file() -> Result<PathBuf> {
+    Ok(state_dir()?.join("cache.json"))
+}
+
+// The Unix socket of the daemon serving the calendar directory
+pub fn daemon_socket() -> Result<PathBuf> {
+    Ok(state_dir()?.join("daemon.sock"))
+}
+
+// Downloaded ICS feeds of the subscriptions
+pub fn subscriptions_dir() -> Result<PathBuf> {
+    Ok(xdg_cache_home()?.join("calendar-rs").join("subscriptions"))
+}
+
+// Sign in token and delta link of the Microsoft Graph backend, kept with the
+// state rather than the cache, which may be cleaned or shared
+#[cfg(feature = "graph")]
+pub fn graph_dir() -> Result<PathBuf> {
+    Ok(xdg_state_home()?.join("calendar-rs").join("graph"))
+}
+
+// Where vdirsyncer reads its config, in the order it looks for it
+pub fn vdirsyncer_config() -> Result<PathBuf> {
+    if let Some(path) = std::env::var_os("VDIRSYNCER_CONFIG") {
+        return Ok(PathBuf::from(path));
+    }
+
+    let home_dir = dirs::home_dir().ok_or_else(|| anyhow!("Unable to determine home directory"))?;
+    let legacy_file = home_dir.join(".vdirsyncer").join("config");
+    if legacy_file.is_file() {
+        return Ok(legacy_file);
+    }
+
+    Ok(xdg_config_home()?.join("vdirsyncer").join("config"))
+}
+
+pub fn vdirsyncer_status_dir() -> Result<PathBuf> {
+    Ok(xdg_data_home()?.join("vdirsyncer").join("status"))
+}
+
+// Time of the last successful sync of each calendar
+pub fn sync_state_file() -> Result<PathBuf> {
+    Ok(state_dir()?.join("sync.json"))
+}
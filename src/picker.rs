@@ -0,0 +1,74 @@
+use anyhow::Result;
+use colored::Colorize;
+use console::{Key, Term};
+
+// Lets the user choose one of the items, with the arrow keys or j and k and
+// Enter, or by typing its number. The menu is drawn on stderr and replaced by
+// the choice once made. None when it is cancelled with Escape or q.
+pub fn pick(prompt: &str, items: &[String]) -> Result<Option<usize>> {
+    let term = Term::stderr();
+    term.hide_cursor()?;
+    let choice = run(&term, prompt, items);
+    term.show_cursor()?;
+    choice
+}
+
+fn run(term: &Term, prompt: &str, items: &[String]) -> Result<Option<usize>> {
+    // Long lists scroll, the prompt and a line of help aside
+    let rows = (term.size().0 as usize).saturating_sub(2).max(1);
+    let mut selected: usize = 0;
+    let mut top = 0;
+    let mut number = String::new();
+    let mut drawn = 0;
+
+    loop {
+        top = top.min(selected).max((selected + 1).saturating_sub(rows));
+        term.clear_last_lines(drawn)?;
+        term.write_line(&prompt.bold().to_string())?;
+        for (index, item) in items.iter().enumerate().skip(top).take(rows) {
+            let line = format!("{:>3}. {}", index + 1, item);
+            match index == selected {
+                true => term.write_line(&format!("> {}", line).bold().to_string())?,
+                false => term.write_line(&format!("  {}", line))?,
+            }
+        }
+        term.write_line(
+            &"  ↑/↓ to move, Enter to choose, Esc to cancel"
+                .dimmed()
+                .to_string(),
+        )?;
+        drawn = items.len().min(rows) + 2;
+
+        match term.read_key()? {
+            Key::ArrowUp | Key::Char('k') => selected = selected.saturating_sub(1),
+            Key::ArrowDown | Key::Char('j') => selected = (selected + 1).min(items.len() - 1),
+            Key::Home => selected = 0,
+            Key::End => selected = items.len() - 1,
+            Key::Char(digit) if digit.is_ascii_digit() => {
+                // Digits add up to numbers above 9 while they stay in the list
+                number.push(digit);
+                match number.parse::<usize>() {
+                    Ok(n) if (1..=items.len()).contains(&n) => selected = n - 1,
+                    _ => {
+                        number = digit.to_string();
+                        if let Some(n) = digit.to_digit(10).filter(|&n| n >= 1) {
+                            selected = (n as usize - 1).min(items.len() - 1);
+                        }
+                    }
+                }
+                continue;
+            }
+            Key::Enter => {
+                term.clear_last_lines(drawn)?;
+                term.write_line(&format!("{} {}", prompt.bold(), items[selected]))?;
+                return Ok(Some(selected));
+            }
+            Key::Escape | Key::Char('q') => {
+                term.clear_last_lines(drawn)?;
+                return Ok(None);
+            }
+            _ => {}
+        }
+        number.clear();
+    }
+}
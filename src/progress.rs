@@ -0,0 +1,78 @@
+use indicatif::{ProgressBar, ProgressStyle};
+use std::io::IsTerminal;
+use std::time::{Duration, Instant};
+
+// Shorter operations finish before a bar is worth drawing
+const MIN_STEPS: u64 = 50;
+
+// How often the progress is logged when stderr is not a terminal
+const LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+// Progress of a long operation, on stderr: a bar with the counts and the
+// time left on a terminal, a line every few seconds otherwise, for logs
+pub struct Progress {
+    bar: Option<ProgressBar>,
+    label: String,
+    total: Option<u64>,
+    done: u64,
+    logged: Instant,
+}
+
+impl Progress {
+    pub fn new(label: &str, total: u64) -> Self {
+        let bar = (total >= MIN_STEPS).then(|| {
+            ProgressBar::new(total).with_style(
+                ProgressStyle::with_template("{msg} [{bar:30}] {pos}/{len} ({eta} left)")
+                    .unwrap_or_else(|_| ProgressStyle::default_bar())
+                    .progress_chars("=> "),
+            )
+        });
+        Self::start(label, Some(total), bar)
+    }
+
+    // For operations whose length is not known in advance
+    #[cfg(feature = "graph")]
+    pub fn spinner(label: &str) -> Self {
+        let bar = ProgressBar::new_spinner().with_style(
+            ProgressStyle::with_template("{spinner} {msg} {pos} ({elapsed})")
+                .unwrap_or_else(|_| ProgressStyle::default_spinner()),
+        );
+        bar.enable_steady_tick(Duration::from_millis(100));
+        Self::start(label, None, Some(bar))
+    }
+
+    fn start(label: &str, total: Option<u64>, bar: Option<ProgressBar>) -> Self {
+        let bar = bar.filter(|_| std::io::stderr().is_terminal());
+        if let Some(bar) = &bar {
+            bar.set_message(label.to_string());
+        }
+        Progress {
+            bar,
+            label: label.to_string(),
+            total,
+            done: 0,
+            logged: Instant::now(),
+        }
+    }
+
+    pub fn inc(&mut self, steps: u64) {
+        self.done += steps;
+        match &self.bar {
+            Some(bar) => bar.inc(steps),
+            None if self.logged.elapsed() >= LOG_INTERVAL => {
+                match self.total {
+                    Some(total) => eprintln!("{}: {}/{}", self.label, self.done, total),
+                    None => eprintln!("{}: {}", self.label, self.done),
+                }
+                self.logged = Instant::now();
+            }
+            None => {}
+        }
+    }
+
+    pub fn finish(self) {
+        if let Some(bar) = self.bar {
+            bar.finish_and_clear();
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use crate::calendar::{self, Event};
+use crate::cli;
+use crate::config::{self, Config};
+use crate::date;
+use crate::error;
+use crate::event;
+use crate::storage;
+use anyhow::{anyhow, Context, Result};
+use chrono::{Local, NaiveDate, NaiveTime};
+use std::path::PathBuf;
+
+pub fn run(cmd: cli::CalendarPurgeArgs) -> Result<()> {
+    event::create_personal()?;
+
+    let names = match &cmd.calendar {
+        Some(name) if !storage::calendar_exists(name)? => {
+            return Err(error::calendar_not_found(name));
+        }
+        Some(name) => vec![name.clone()],
+        None => storage::list_calendars()?,
+    };
+    let config = config::load()?;
+    if cmd.before.is_none() && names.iter().all(|name| retention(&config, name).is_none()) {
+        return Err(anyhow!(
+            "Give the age of the events to purge with --older-than, or a retention in the config"
+        ));
+    }
+
+    let events = ancient(&names, cmd.before, &config)?;
+    if events.is_empty() {
+        println!("No events to purge");
+        return Ok(());
+    }
+
+    match cmd.dry_run {
+        true => println!("Would purge {} events:", events.len()),
+        false => println!("Purging {} events:", events.len()),
+    }
+    for (_, event) in &events {
+        println!("  {}: {}", event.calendar, event::describe_event(event));
+    }
+    if cmd.dry_run || (!cmd.force && !event::confirm("Move these events to the trash?")?) {
+        return Ok(());
+    }
+
+    trash(&events)?;
+    println!(
+        "Purged {} events, kept in the trash until it is emptied",
+        events.len()
+    );
+    Ok(())
+}
+
+// Purges the calendars by the retention of the config, without asking, like
+// sync does before syncing them
+pub fn apply_retention(names: &[String], dry_run: bool) -> Result<()> {
+    let events = ancient(names, None, &config::load()?)?;
+    if events.is_empty() {
+        return Ok(());
+    }
+
+    if dry_run {
+        println!(
+            "Would purge {} events older than their retention",
+            events.len()
+        );
+        return Ok(());
+    }
+    trash(&events)?;
+    println!("Purged {} events older than their retention", events.len());
+    Ok(())
+}
+
+// The retention of a calendar, else the global one
+fn retention<'a>(config: &'a Config, name: &str) -> Option<&'a str> {
+    config
+        .calendars
+        .get(name)
+        .and_then(|calendar| calendar.retention.as_deref())
+        .or(config.retention.as_deref())
+}
+
+// The events of the calendars over before a day, or else before the retention
+// of their calendar, with the path of the calendar. A series is purged as a
+// whole, once its last occurrence is over.
+fn ancient(
+    names: &[String],
+    before: Option<NaiveDate>,
+    config: &Config,
+) -> Result<Vec<(PathBuf, Event)>> {
+    let today = Local::now().naive_local().date();
+    let mut events = Vec::new();
+    for name in names {
+        let before = match (before, retention(config, name)) {
+            (Some(before), _) => before,
+            (None, Some(age)) => date::parse_age(age, today)
+                .with_context(|| format!("Invalid retention of '{}' in the config", name))?,
+            (None, None) => continue,
+        };
+
+        let calendar = calendar::load(name)?;
+        let mut old: Vec<Event> = calendar
+            .events
+            .into_iter()
+            .filter(|event| {
+                event.recurrence_id.is_none() && event.ended_before(before.and_time(NaiveTime::MIN))
+            })
+            .collect();
+        old.sort_by_key(|event| event.start);
+        events.extend(old.into_iter().map(|event| (calendar.path.clone(), event)));
+    }
+    Ok(events)
+}
+
+fn trash(events: &[(PathBuf, Event)]) -> Result<()> {
+    let mut batch = storage::Batch::default();
+    for (calendar_path, event) in events {
+        batch.trash_event(calendar_path, event)?;
+    }
+    batch.commit()
+}
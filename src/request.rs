@@ -0,0 +1,105 @@
+use anyhow::{Context, Result};
+use std::io::{self, BufRead, BufReader, Read, Take};
+use std::net::TcpStream;
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+
+// How long a client has to send its request, and the client of the daemon
+// waits for its answer
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+// Longest request read from a client, the headers of HTTP included
+const MAX_REQUEST_SIZE: u64 = 64 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+#[error("Request longer than {} bytes", MAX_REQUEST_SIZE)]
+pub struct TooLong;
+
+// The streams of the clients of daemon and serve
+pub trait Client: Read {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl Client for &UnixStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_read_timeout(self, timeout)
+    }
+}
+
+impl Client for &TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+// The request of a client, read line by line up to MAX_REQUEST_SIZE
+pub struct Reader<R> {
+    reader: BufReader<Take<R>>,
+}
+
+impl<R: Read> Reader<R> {
+    pub fn new(stream: R) -> Self {
+        Reader {
+            reader: BufReader::new(stream.take(MAX_REQUEST_SIZE)),
+        }
+    }
+
+    // The next line, without its end, or None once the client sent nothing
+    // more
+    pub fn read_line(&mut self) -> Result<Option<String>> {
+        let mut line = String::new();
+        self.reader
+            .read_line(&mut line)
+            .context("Failed to read a request")?;
+        if !line.ends_with('\n') && self.reader.get_ref().limit() == 0 {
+            return Err(TooLong.into());
+        }
+        if line.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+    }
+}
+
+// Starts reading the request of a client. A client that sends nothing does
+// not hold up the others for long.
+pub fn reader<C: Client>(client: C) -> Result<Reader<C>> {
+    client.set_read_timeout(Some(TIMEOUT))?;
+    Ok(Reader::new(client))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_are_read_line_by_line() {
+        let mut reader = Reader::new(&b"first\r\nsecond\nlast"[..]);
+        assert_eq!(reader.read_line().unwrap().as_deref(), Some("first"));
+        assert_eq!(reader.read_line().unwrap().as_deref(), Some("second"));
+        assert_eq!(reader.read_line().unwrap().as_deref(), Some("last"));
+        assert_eq!(reader.read_line().unwrap(), None);
+
+        assert_eq!(Reader::new(&b""[..]).read_line().unwrap(), None);
+    }
+
+    #[test]
+    fn requests_too_long_are_refused() {
+        let long = "a".repeat(MAX_REQUEST_SIZE as usize + 1);
+        let err = Reader::new(long.as_bytes()).read_line().unwrap_err();
+        assert!(err.is::<TooLong>());
+
+        // Up to the limit, the end of the line included
+        let fitting = format!("{}\n", "a".repeat(MAX_REQUEST_SIZE as usize - 1));
+        let line = Reader::new(fitting.as_bytes()).read_line().unwrap();
+        assert_eq!(
+            line.map(|line| line.len()),
+            Some(MAX_REQUEST_SIZE as usize - 1)
+        );
+    }
+
+    #[test]
+    fn requests_that_are_not_text_are_refused() {
+        assert!(Reader::new(&b"\xff\xfe\n"[..]).read_line().is_err());
+    }
+}
@@ -0,0 +1,69 @@
+use anyhow::Result;
+use std::thread;
+use std::time::Duration;
+
+// Attempts of an operation before giving up
+const ATTEMPTS: u32 = 4;
+
+// Wait before the second attempt, doubled after each failure
+const FIRST_DELAY: Duration = Duration::from_secs(2);
+
+// Longest wait, even when a server asks for more
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+// Runs an operation again when it fails with a transient error, up to
+// ATTEMPTS times, waiting twice as long each time or as long as the server
+// asks with Retry-After
+pub fn retry<T>(
+    transient: impl Fn(&anyhow::Error) -> bool,
+    mut operation: impl FnMut() -> Result<T>,
+) -> Result<T> {
+    let mut delay = FIRST_DELAY;
+    let mut attempt = 1;
+    loop {
+        match operation() {
+            Ok(value) => return Ok(value),
+            Err(err) if attempt < ATTEMPTS && transient(&err) => {
+                let wait = retry_after(&err).unwrap_or(delay).min(MAX_DELAY);
+                eprintln!(
+                    "Warning: {:#}, retrying in {}s ({}/{})",
+                    err,
+                    wait.as_secs(),
+                    attempt + 1,
+                    ATTEMPTS
+                );
+                thread::sleep(wait);
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+// Network errors, rate limits and server errors of HTTP requests
+pub fn is_transient(err: &anyhow::Error) -> bool {
+    match http_error(err) {
+        Some(ureq::Error::Transport(_)) => true,
+        Some(ureq::Error::Status(code, _)) => *code == 429 || *code >= 500,
+        None => false,
+    }
+}
+
+fn http_error(err: &anyhow::Error) -> Option<&ureq::Error> {
+    err.chain()
+        .find_map(|cause| cause.downcast_ref::<ureq::Error>())
+}
+
+// The wait asked for by a rate limited server, in seconds
+fn retry_after(err: &anyhow::Error) -> Option<Duration> {
+    match http_error(err)? {
+        ureq::Error::Status(_, response) => response
+            .header("Retry-After")?
+            .trim()
+            .parse()
+            .ok()
+            .map(Duration::from_secs),
+        ureq::Error::Transport(_) => None,
+    }
+}
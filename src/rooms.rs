@@ -0,0 +1,79 @@
+use crate::calendar::{self, Event};
+use crate::cli;
+use crate::error;
+use crate::freebusy;
+use crate::locale::LocalFormat;
+use crate::storage;
+use anyhow::{anyhow, Result};
+
+// Lists the rooms of a shared calendar without events during the slot. The
+// rooms are the collections of the calendar when it has several, like the
+// resources of a CalDAV server, or else the CATEGORIES of its events.
+pub fn free(cmd: cli::CalendarRoomsFreeArgs) -> Result<()> {
+    if !storage::calendar_exists(&cmd.calendar)? {
+        return Err(error::calendar_not_found(&cmd.calendar));
+    }
+
+    let events = calendar::load(&cmd.calendar)?.events;
+    // Every room is known, the ones without bookings in the slot too
+    let mut tags: Vec<_> = events.iter().flat_map(|event| event.tags.clone()).collect();
+    tags.sort();
+    tags.dedup();
+    let events = calendar::expand_overlapping(events, cmd.start, cmd.end);
+
+    let collections = storage::collections(&cmd.calendar)?;
+    let rooms: Vec<(String, Vec<&Event>)> = if collections.len() > 1 {
+        collections
+            .into_iter()
+            .map(|(path, name)| {
+                let events = events
+                    .iter()
+                    .filter(|event| {
+                        event
+                            .file
+                            .as_ref()
+                            .is_some_and(|file| file.parent() == Some(path.as_path()))
+                    })
+                    .collect();
+                (name, events)
+            })
+            .collect()
+    } else {
+        tags.into_iter()
+            .map(|tag| {
+                let events = events
+                    .iter()
+                    .filter(|event| event.tags.contains(&tag))
+                    .collect();
+                (tag, events)
+            })
+            .collect()
+    };
+
+    if rooms.is_empty() {
+        return Err(anyhow!(
+            "Calendar '{}' has no rooms, as collections or as categories of its events",
+            cmd.calendar
+        ));
+    }
+
+    let free: Vec<_> = rooms
+        .iter()
+        .filter(|(_, events)| freebusy::busy_blocks(events, cmd.start, cmd.end).is_empty())
+        .map(|(name, _)| name)
+        .collect();
+
+    if free.is_empty() {
+        println!(
+            "No room is free on {}, {}-{}",
+            cmd.start.format_local("%a %d %b"),
+            cmd.start.format("%H:%M"),
+            cmd.end.format("%H:%M")
+        );
+    }
+    for name in free {
+        println!("{}", name);
+    }
+
+    Ok(())
+}
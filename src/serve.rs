@@ -0,0 +1,354 @@
+use crate::calendar::{self, Event};
+use crate::cli;
+use crate::config;
+use crate::date::CalendarDate;
+use crate::error::{self, CalendarError};
+use crate::event;
+use crate::output;
+use crate::request;
+use crate::storage;
+use crate::subscription;
+use crate::taskwarrior;
+use anyhow::{Context, Result};
+use chrono::{Duration, Local, NaiveDate, NaiveDateTime, NaiveTime};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+// The days of events given when the request does not ask for a range, like
+// for list
+const DEFAULT_DAYS: i64 = 30;
+
+const JSON: &str = "application/json";
+const ICS: &str = "text/calendar; charset=utf-8";
+
+// A request that cannot be answered as asked, like a date that cannot be read
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+struct BadRequest(String);
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn error(status: u16, message: &str) -> Self {
+        Response {
+            status,
+            content_type: JSON,
+            body: serde_json::json!({ "error": message }).to_string(),
+        }
+    }
+}
+
+// Serves the calendars and their events as JSON to the GET requests of
+// clients, like a web page, reading the files at each request so that they
+// are always up to date. Nothing is ever written. Runs until Ctrl-C.
+pub fn run(cmd: cli::CalendarServeArgs) -> Result<()> {
+    let listener = TcpListener::bind(cmd.address)
+        .with_context(|| format!("Failed to listen on '{}'", cmd.address))?;
+    eprintln!("Serving the calendars on http://{}", cmd.address);
+
+    for stream in listener.incoming() {
+        let answered = stream
+            .context("Failed to accept a client")
+            .and_then(|stream| answer(stream, cmd.allow_origin.as_deref()));
+        if let Err(err) = answered {
+            eprintln!("Warning: {:#}", err);
+        }
+        storage::report_skipped();
+    }
+    Ok(())
+}
+
+fn answer(stream: TcpStream, allow_origin: Option<&str>) -> Result<()> {
+    let response = match read_request(&mut request::reader(&stream)?) {
+        Ok(Some(request_line)) => match target(&request_line) {
+            Ok(target) => route(target),
+            Err(response) => response,
+        },
+        // Connections closed without a request
+        Ok(None) => return Ok(()),
+        Err(err) if err.is::<request::TooLong>() => Response::error(400, &err.to_string()),
+        Err(err) => return Err(err),
+    };
+    respond(&stream, &response, allow_origin)
+}
+
+// The request line of a request. The headers are not needed, but are read
+// for the client to get the answer rather than a reset connection.
+fn read_request<R: Read>(reader: &mut request::Reader<R>) -> Result<Option<String>> {
+    let request_line = reader.read_line()?;
+    while let Some(header) = reader.read_line()? {
+        if header.trim().is_empty() {
+            break;
+        }
+    }
+    Ok(request_line)
+}
+
+// The target of the GET request of a request line, or else the error to
+// answer
+fn target(request_line: &str) -> Result<&str, Response> {
+    match request_line.split_whitespace().collect::<Vec<_>>()[..] {
+        ["GET", target, _] => Ok(target),
+        [_, _, _] => Err(Response::error(405, "Only GET requests are served")),
+        _ => Err(Response::error(400, "Invalid request")),
+    }
+}
+
+fn respond(mut stream: &TcpStream, response: &Response, allow_origin: Option<&str>) -> Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        reason(response.status),
+        response.content_type,
+        response.body.len()
+    );
+    if response.status == 405 {
+        head.push_str("Allow: GET\r\n");
+    }
+    if let Some(origin) = allow_origin {
+        head.push_str(&format!("Access-Control-Allow-Origin: {}\r\n", origin));
+    }
+    head.push_str("\r\n");
+
+    stream
+        .write_all(head.as_bytes())
+        .and_then(|()| stream.write_all(response.body.as_bytes()))
+        .context("Failed to answer")
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    }
+}
+
+fn route(target: &str) -> Response {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let segments: Vec<String> = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| decode(segment, false))
+        .collect();
+    let params: Vec<(String, String)> = query
+        .split('&')
+        .filter(|param| !param.is_empty())
+        .map(|param| {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            (decode(key, true), decode(value, true))
+        })
+        .collect();
+    // Like the options of the commands, some can be repeated
+    let values = |key: &str| -> Vec<&str> {
+        params
+            .iter()
+            .filter(|(name, _)| name == key)
+            .map(|(_, value)| value.as_str())
+            .collect()
+    };
+    let param = |key: &str| values(key).first().copied();
+
+    let (body, content_type) = match segments.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["calendars"] => (
+            calendars().and_then(|calendars| Ok(serde_json::to_string_pretty(&calendars)?)),
+            JSON,
+        ),
+        ["events"] => (events(param("calendar"), param("from"), param("to")), JSON),
+        ["events", id] => (event_of_id(param("calendar"), id), JSON),
+        ["feed.ics"] => (
+            feed(&values("calendar"), &values("tag"), param("from")),
+            ICS,
+        ),
+        _ => return Response::error(404, "Not found"),
+    };
+    match body {
+        Ok(body) => Response {
+            status: 200,
+            content_type,
+            body,
+        },
+        Err(err) => {
+            let status = match err.downcast_ref::<CalendarError>() {
+                Some(CalendarError::NotFound(_)) => 404,
+                Some(CalendarError::Ambiguous(_)) => 400,
+                _ if err.is::<BadRequest>() => 400,
+                _ => 500,
+            };
+            Response::error(status, &format!("{:#}", err))
+        }
+    }
+}
+
+// The calendars and the subscriptions, and taskwarrior when its tasks are
+// shown
+fn calendars() -> Result<Vec<String>> {
+    let config = config::load()?;
+    let mut calendars = storage::list_calendars()?;
+    calendars.extend(config.subscriptions.into_keys());
+    if config.taskwarrior.enabled {
+        calendars.push(taskwarrior::CALENDAR.to_string());
+    }
+    Ok(calendars)
+}
+
+// Checked first, for an unknown calendar not to be created like personal
+fn check_calendar(calendar_name: Option<&str>) -> Result<()> {
+    match calendar_name {
+        Some(name) if !calendars()?.iter().any(|calendar| calendar == name) => {
+            Err(error::calendar_not_found(name))
+        }
+        _ => Ok(()),
+    }
+}
+
+// The events from the day from to the day to, both included, like list
+fn events(calendar_name: Option<&str>, from: Option<&str>, to: Option<&str>) -> Result<String> {
+    check_calendar(calendar_name)?;
+    let today = Local::now().naive_local().date();
+    let from = from.map(parse_date).transpose()?.unwrap_or(today);
+    let to = to
+        .map(parse_date)
+        .transpose()?
+        .unwrap_or(from + Duration::days(DEFAULT_DAYS));
+    if to < from {
+        return Err(BadRequest("'to' date must be after 'from' date".to_string()).into());
+    }
+
+    let from = from.and_time(NaiveTime::MIN);
+    let to = (to + Duration::days(1)).and_time(NaiveTime::MIN);
+    let events = event::load_events(calendar_name.map(str::to_string), from, to, true)?;
+    let mut events = calendar::expand(events, from, to);
+    events.sort_by_key(|event| event.start);
+    output::events_json(&events)
+}
+
+fn parse_date(date: &str) -> Result<NaiveDate> {
+    CalendarDate::parse(date)
+        .map(|date| date.inner())
+        .map_err(|err| BadRequest(format!("Invalid date '{}': {}", date, err)).into())
+}
+
+// An event of a calendar, or of any of them, by its id or a prefix of it
+fn event_of_id(calendar_name: Option<&str>, id: &str) -> Result<String> {
+    check_calendar(calendar_name)?;
+    let config = config::load()?;
+    let mut events: Vec<Event> = Vec::new();
+    match calendar_name {
+        Some(name) if config.subscriptions.contains_key(name) => {
+            events = subscription::events(name)?;
+        }
+        Some(name) => events = calendar::load(name)?.events,
+        None => {
+            for calendar in calendar::load_all()? {
+                events.extend(calendar.events);
+            }
+            for name in config.subscriptions.keys() {
+                events.extend(subscription::events(name)?);
+            }
+        }
+    }
+    output::event_json(calendar::event_by_id(&events, id)?)
+}
+
+// The events of the calendars, all of them by default, merged into one
+// calendar for phones to subscribe to. With tags, only the events with one
+// of them are given. Recurring events are given whole, like by export.
+fn feed(calendar_names: &[&str], tags: &[&str], from: Option<&str>) -> Result<String> {
+    let mut calendar_names = calendar_names.to_vec();
+    calendar_names.sort_unstable();
+    calendar_names.dedup();
+    for name in &calendar_names {
+        check_calendar(Some(name))?;
+    }
+    let from = from
+        .map(parse_date)
+        .transpose()?
+        .map_or(NaiveDateTime::MIN, |from| from.and_time(NaiveTime::MIN));
+    let to = NaiveDateTime::MAX;
+
+    let mut events = Vec::new();
+    if calendar_names.is_empty() {
+        events = event::load_events(None, from, to, false)?;
+    }
+    for name in calendar_names {
+        events.extend(event::load_events(Some(name.to_string()), from, to, false)?);
+    }
+    events.retain(|event| {
+        (tags.is_empty() || event.tags.iter().any(|tag| tags.contains(&tag.as_str())))
+            && !event.occurrences(from, to).is_empty()
+    });
+    events.sort_by_key(|event| event.start);
+    Ok(storage::export_events(&events))
+}
+
+// Percent-decodes a part of the target, + standing for a space in the query
+fn decode(text: &str, in_query: bool) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (b'+', _) if in_query => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(request: &[u8]) -> Result<Option<String>> {
+        read_request(&mut request::Reader::new(request))
+    }
+
+    #[test]
+    fn request_lines_are_read_past_their_headers() {
+        let request = read(b"GET /calendars HTTP/1.1\r\nHost: localhost\r\nAccept: */*\r\n\r\n");
+        assert_eq!(request.unwrap().as_deref(), Some("GET /calendars HTTP/1.1"));
+        // Connections closed without a request, and requests without headers
+        assert_eq!(read(b"").unwrap(), None);
+        assert_eq!(
+            read(b"GET / HTTP/1.0\r\n").unwrap().as_deref(),
+            Some("GET / HTTP/1.0")
+        );
+
+        let long = format!("GET / HTTP/1.1\r\nCookie: {}\r\n\r\n", "a".repeat(70_000));
+        assert!(read(long.as_bytes()).unwrap_err().is::<request::TooLong>());
+    }
+
+    #[test]
+    fn only_get_requests_are_answered() {
+        let status = |request_line| target(request_line).err().map(|response| response.status);
+        assert_eq!(
+            target("GET /events?from=tod HTTP/1.1").ok(),
+            Some("/events?from=tod")
+        );
+        assert_eq!(status("POST /events HTTP/1.1"), Some(405));
+        assert_eq!(status("GET /events"), Some(400));
+        assert_eq!(status("GET /events HTTP/1.1 junk"), Some(400));
+        assert_eq!(status(""), Some(400));
+    }
+}
@@ -0,0 +1,163 @@
+use crate::cli;
+use crate::interrupt;
+use crate::paths;
+use anyhow::{anyhow, Context, Result};
+use std::fs::{self, OpenOptions, Permissions};
+use std::io::{IsTerminal, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::process::Command;
+
+// Asks for a CalDAV account, adds a vdirsyncer pair for it named after the
+// calendar, so that `calendar sync <calendar>` syncs it, and discovers its
+// collections
+pub fn run(_cmd: cli::CalendarSetupArgs) -> Result<()> {
+    let config_file = paths::vdirsyncer_config()?;
+    let existing = if config_file.is_file() {
+        fs::read_to_string(&config_file)
+            .with_context(|| format!("Failed to read '{}'", config_file.display()))?
+    } else {
+        String::new()
+    };
+
+    let calendar = ask("Calendar name", Some("personal"))?;
+    if calendar.starts_with('.') || calendar.contains(std::path::is_separator) {
+        return Err(anyhow!("Invalid calendar name '{}'", calendar));
+    }
+    if existing
+        .lines()
+        .any(|line| line.trim() == format!("[pair {}]", calendar))
+    {
+        return Err(anyhow!(
+            "'{}' already has a pair '{}'",
+            config_file.display(),
+            calendar
+        ));
+    }
+
+    let url = ask("CalDAV server URL", None)?;
+    if !url.starts_with("https://") && !url.starts_with("http://") {
+        return Err(anyhow!("Invalid server URL '{}'", url));
+    }
+    let username = ask("Username", None)?;
+    let password = match ask("Command printing the password (empty to type it)", Some(""))? {
+        command if command.is_empty() => format!(
+            "password = {}",
+            quote(&ask_password("Password (stored in the vdirsyncer config)")?)
+        ),
+        command => format!(
+            "password.fetch = [\"command\", \"sh\", \"-c\", {}]",
+            quote(&command)
+        ),
+    };
+
+    // Collections are named by the server and created under the calendar
+    let calendar_dir = paths::calendar_dir()?.join(&calendar);
+    fs::create_dir_all(&calendar_dir)?;
+    let status_dir = paths::vdirsyncer_status_dir()?;
+    fs::create_dir_all(&status_dir)?;
+
+    let mut config = existing.clone();
+    if !config.is_empty() && !config.ends_with('\n') {
+        config.push('\n');
+    }
+    if !existing.lines().any(|line| line.trim() == "[general]") {
+        config.push_str(&format!(
+            "[general]\nstatus_path = {}\n",
+            quote(&format!("{}/", status_dir.display()))
+        ));
+    }
+    config.push_str(&format!(
+        "
+[pair {calendar}]
+a = \"{calendar}_local\"
+b = \"{calendar}_remote\"
+collections = [\"from b\"]
+metadata = [\"color\", \"displayname\"]
+
+[storage {calendar}_local]
+type = \"filesystem\"
+path = {path}
+fileext = \".ics\"
+
+[storage {calendar}_remote]
+type = \"caldav\"
+url = {url}
+username = {username}
+{password}
+",
+        calendar = calendar,
+        path = quote(&format!("{}/", calendar_dir.display())),
+        url = quote(&url),
+        username = quote(&username),
+        password = password,
+    ));
+
+    if let Some(parent) = config_file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // The config may hold the password, only the user may read it
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&config_file)
+        .and_then(|mut file| {
+            file.set_permissions(Permissions::from_mode(0o600))?;
+            file.write_all(config.as_bytes())
+        })
+        .with_context(|| format!("Failed to write '{}'", config_file.display()))?;
+    println!("Added pair '{}' to '{}'", calendar, config_file.display());
+
+    // Discover asks itself before creating anything, so it keeps the terminal
+    let status = Command::new("vdirsyncer")
+        .arg("discover")
+        .arg(&calendar)
+        .status()
+        .context("Failed to run vdirsyncer, is it installed?")?;
+    if !status.success() {
+        return Err(anyhow!("vdirsyncer discover failed"));
+    }
+
+    println!("Run `calendar sync {}` to download the events", calendar);
+
+    Ok(())
+}
+
+fn ask(prompt: &str, default: Option<&str>) -> Result<String> {
+    loop {
+        match default {
+            Some(default) if !default.is_empty() => print!("{} [{}]: ", prompt, default),
+            _ => print!("{}: ", prompt),
+        }
+        let mut input = String::new();
+        std::io::stdout().flush()?;
+        if std::io::stdin().read_line(&mut input)? == 0 {
+            return Err(anyhow!("Setup cancelled"));
+        }
+
+        match (input.trim(), default) {
+            ("", Some(default)) => return Ok(default.to_string()),
+            ("", None) => continue,
+            (answer, _) => return Ok(answer.to_string()),
+        }
+    }
+}
+
+// Like ask, without showing what is typed in a terminal
+fn ask_password(prompt: &str) -> Result<String> {
+    if !std::io::stdin().is_terminal() {
+        return ask(prompt, None);
+    }
+    let no_echo = interrupt::NoEcho::enter()?;
+    let password = ask(prompt, None);
+    drop(no_echo);
+    // The Enter typed was not shown either
+    println!();
+    password
+}
+
+// vdirsyncer reads the values of its config as JSON
+fn quote(value: &str) -> String {
+    serde_json::Value::String(value.to_string()).to_string()
+}
@@ -0,0 +1,162 @@
+use crate::cli;
+use crate::config;
+use crate::date;
+use crate::event;
+use crate::locale::LocalFormat;
+use anyhow::{anyhow, Context, Result};
+use chrono::{Datelike, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+use colored::Colorize;
+
+// The weeks shown by --utilization before and after the current one
+const UTILIZATION_WEEKS: i64 = 4;
+// The share of the work hours from which a week is shown as overloaded
+const OVERLOADED: i64 = 90;
+
+// The attendance of the recurring events with some recorded: how many of
+// their past occurrences were attended, missed or not recorded
+pub fn run(cmd: cli::CalendarStatsArgs) -> Result<()> {
+    if cmd.utilization {
+        return utilization(cmd);
+    }
+
+    let now = Local::now().naive_local();
+    let from = cmd
+        .from
+        .map_or(NaiveDateTime::MIN, |from| from.and_time(NaiveTime::MIN));
+    let to = match cmd.to {
+        Some(to) => to
+            .and_hms_opt(23, 59, 59)
+            .ok_or_else(|| anyhow!("Failed to create NaiveDateTime"))?
+            .min(now),
+        None => now,
+    };
+
+    let mut rows = Vec::new();
+    for event in event::load_events(cmd.calendar, from, to, false)? {
+        if event.recurrence.is_none() {
+            continue;
+        }
+        // Without a first day, the series is followed from its first record
+        let first_record = event.attendance.iter().map(|a| a.occurrence).min();
+        let start = match (cmd.from, first_record) {
+            (Some(_), _) => from,
+            (None, Some(first_record)) => first_record,
+            (None, None) => continue,
+        };
+
+        let (mut attended, mut missed, mut unrecorded) = (0, 0, 0);
+        for occurrence in event.occurrences(start, to) {
+            match event.attendance_of(occurrence.start) {
+                Some(attendance) if attendance.attended => attended += 1,
+                Some(_) => missed += 1,
+                None => unrecorded += 1,
+            }
+        }
+        if attended + missed > 0 {
+            rows.push((event.name, attended, missed, unrecorded));
+        }
+    }
+
+    if rows.is_empty() {
+        println!("No attendance recorded, record it with calendar attend");
+        return Ok(());
+    }
+
+    rows.sort_by(|a, b| a.0.cmp(&b.0));
+    let width = rows
+        .iter()
+        .map(|(name, ..)| name.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("Event".len());
+    println!(
+        "{:<width$}  Attended  Missed  Not recorded  Rate",
+        "Event",
+        width = width
+    );
+    for (name, attended, missed, unrecorded) in rows {
+        println!(
+            "{:<width$}  {:>8}  {:>6}  {:>12}  {:>3}%",
+            name,
+            attended,
+            missed,
+            unrecorded,
+            attended * 100 / (attended + missed),
+            width = width
+        );
+    }
+
+    Ok(())
+}
+
+// The share of the work hours of the config taken by events, week by week.
+// The weeks cut by the first or the last day only count their days within.
+fn utilization(cmd: cli::CalendarStatsArgs) -> Result<()> {
+    let config = config::load()?;
+    let (start_time, end_time) =
+        date::parse_hours(&config.work.hours).context("Invalid work hours in the config")?;
+    let days = date::parse_days(&config.work.days).context("Invalid work days in the config")?;
+
+    let this_week = Local::now().date_naive().week(Weekday::Mon).first_day();
+    let from = cmd
+        .from
+        .unwrap_or(this_week - Duration::weeks(UTILIZATION_WEEKS));
+    let to = cmd
+        .to
+        .unwrap_or(this_week + Duration::weeks(UTILIZATION_WEEKS + 1) - Duration::days(1));
+    let busy = event::load_busy(cmd.calendar, from, to)?;
+
+    // The first day of each week, with its booked and work hours
+    let mut weeks: Vec<(NaiveDate, Duration, Duration)> = Vec::new();
+    for day in from.iter_days().take_while(|day| *day <= to) {
+        let start = day.and_time(start_time);
+        let end = day.and_time(end_time);
+        if !days.contains(&day.weekday()) || end <= start {
+            continue;
+        }
+
+        let booked = busy
+            .iter()
+            .map(|&(busy_start, busy_end)| busy_end.min(end) - busy_start.max(start))
+            .filter(|booked| *booked > Duration::zero())
+            .fold(Duration::zero(), |total, booked| total + booked);
+        let week = day.week(Weekday::Mon).first_day();
+        match weeks.last_mut() {
+            Some((first_day, week_booked, week_hours)) if *first_day == week => {
+                *week_booked += booked;
+                *week_hours += end - start;
+            }
+            _ => weeks.push((week, booked, end - start)),
+        }
+    }
+
+    if weeks.is_empty() {
+        println!("No work days in these days");
+        return Ok(());
+    }
+    for (week, booked, hours) in weeks {
+        let rate = booked.num_minutes() * 100 / hours.num_minutes();
+        let line = format!(
+            "Week of {}  {:>6} of {:>6}  {:>3}%",
+            week.format_local("%a %d %b"),
+            format_hours(booked),
+            format_hours(hours),
+            rate
+        );
+        if rate >= OVERLOADED {
+            println!("{}", line.red());
+        } else {
+            println!("{}", line);
+        }
+    }
+
+    Ok(())
+}
+
+fn format_hours(duration: Duration) -> String {
+    format!(
+        "{}h{:02}",
+        duration.num_hours(),
+        duration.num_minutes() % 60
+    )
+}
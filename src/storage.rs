@@ -1,30 +1,32 @@
-use crate::calendar::{Calendar, Event};
+use crate::cache::{self, Parsed};
+use crate::calendar::{self, Attendance, Calendar, Event, Note, Recurrence, Todo};
+use crate::cli::RepeatFrequency;
+use crate::error::{self, CalendarError};
+use crate::interrupt;
+use crate::lock::{self, Lock};
+use crate::paths;
+use crate::progress::Progress;
 use anyhow::{anyhow, Context, Result};
-use chrono::{NaiveDate, NaiveDateTime, Utc};
-use std::fs::{self, File};
-use std::io::Write;
-use std::io::{BufRead, BufReader};
-use std::path::Path;
+use chrono::{Datelike, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Utc, Weekday};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
 use uuid::Uuid;
 
 pub fn list_calendars() -> Result<Vec<String>> {
-    let home_dir = dirs::home_dir().ok_or_else(|| anyhow!("Unable to determine home directory"))?;
-    let calendar_dir = home_dir.join(".calendars");
     let mut valid_calendars = Vec::new();
 
-    for entry in fs::read_dir(calendar_dir)? {
-        let entry = entry?;
-        if entry.file_type()?.is_dir() {
-            let calendar_path = entry.path();
-            // Check if this directory contains at least one subdirectory
-            if fs::read_dir(&calendar_path)?
-                .filter_map(Result::ok)
-                .any(|e| e.file_type().map(|ft| ft.is_dir()).unwrap_or(false))
-            {
-                if let Some(name) = calendar_path.file_name() {
-                    if let Some(name_str) = name.to_str() {
-                        valid_calendars.push(name_str.to_string());
-                    }
+    for calendar_path in calendar_dirs()? {
+        // Check if this directory contains at least one subdirectory
+        if fs::read_dir(&calendar_path)?
+            .filter_map(Result::ok)
+            .any(|e| e.file_type().map(|ft| ft.is_dir()).unwrap_or(false))
+        {
+            if let Some(name) = calendar_path.file_name() {
+                if let Some(name_str) = name.to_str() {
+                    valid_calendars.push(name_str.to_string());
                 }
             }
         }
@@ -33,51 +35,101 @@ pub fn list_calendars() -> Result<Vec<String>> {
     Ok(valid_calendars)
 }
 
+// The directories of the calendars of every root. A calendar of a root hides
+// the ones of the same name in the roots after it.
+pub fn calendar_dirs() -> Result<Vec<PathBuf>> {
+    let mut dirs: Vec<PathBuf> = Vec::new();
+
+    for root in paths::calendar_roots()? {
+        for entry in fs::read_dir(root)? {
+            let entry = entry?;
+            // Hidden directories, like the trash, are not calendars
+            if entry.file_type()?.is_dir()
+                && !entry.file_name().to_string_lossy().starts_with('.')
+                && !dirs
+                    .iter()
+                    .any(|dir| dir.file_name() == Some(entry.file_name().as_os_str()))
+            {
+                dirs.push(entry.path());
+            }
+        }
+    }
+
+    Ok(dirs)
+}
+
 pub fn create_personal() -> Result<()> {
-    let home_dir = dirs::home_dir().ok_or_else(|| anyhow!("Unable to determine home directory"))?;
-    let personal_dir = home_dir.join(".calendars").join("personal");
-    fs::create_dir_all(&personal_dir)?;
+    create_calendar("personal")
+}
+
+// Creates a calendar with a collection subdirectory, as vdirsyncer expects
+pub fn create_calendar(calendar_name: &str) -> Result<()> {
+    if calendar_name.is_empty()
+        || calendar_name.starts_with('.')
+        || calendar_name.contains(std::path::is_separator)
+    {
+        return Err(anyhow!("Invalid calendar name '{}'", calendar_name));
+    }
+
+    let calendar_dir = paths::calendar_dir()?.join(calendar_name);
+    fs::create_dir_all(&calendar_dir)?;
 
     let uuid = Uuid::new_v4();
-    let uuid_dir = personal_dir.join(uuid.to_string());
+    let uuid_dir = calendar_dir.join(uuid.to_string());
     fs::create_dir_all(&uuid_dir)?;
 
     Ok(())
 }
 
+// A start and an end, for the calendars read with only the events that may
+// occur in between
+pub type Window = (NaiveDateTime, NaiveDateTime);
+
 pub fn load_calendars() -> Result<Vec<Calendar>> {
-    let home_dir = dirs::home_dir().ok_or_else(|| anyhow!("Unable to determine home directory"))?;
-    let calendar_dir = home_dir.join(".calendars");
+    load_calendars_in(None)
+}
 
+pub fn load_calendars_in(window: Option<Window>) -> Result<Vec<Calendar>> {
     let mut calendars = Vec::new();
 
-    for entry in fs::read_dir(calendar_dir)? {
-        let entry = entry?;
-        if entry.file_type()?.is_dir() {
-            let calendar_path = entry.path();
-
-            calendars.push(read_calendar(&calendar_path)?);
-        }
+    for calendar_path in calendar_dirs()? {
+        calendars.push(read_calendar(&calendar_path, window)?);
     }
 
     Ok(calendars)
 }
 
 pub fn load_calendar(calendar_name: &str) -> Result<Calendar> {
-    let home_dir = dirs::home_dir().ok_or_else(|| anyhow!("Unable to determine home directory"))?;
-    let calendar_path = home_dir.join(".calendars").join(calendar_name);
+    load_calendar_in(calendar_name, None)
+}
 
-    if !calendar_path.is_dir() {
-        return Err(anyhow!("Calendar '{}' not found", calendar_name));
+pub fn load_calendar_in(calendar_name: &str, window: Option<Window>) -> Result<Calendar> {
+    if !calendar_exists(calendar_name)? {
+        return Err(error::calendar_not_found(calendar_name));
     }
 
-    read_calendar(&calendar_path)
+    read_calendar(&paths::calendar_path(calendar_name)?, window)
 }
 
-pub fn read_calendar(path: &Path) -> Result<Calendar> {
+pub fn calendar_exists(calendar_name: &str) -> Result<bool> {
+    Ok(paths::calendar_path(calendar_name)?.is_dir())
+}
+
+// A calendar, with only the events that may occur in the window when there is
+// one. The files that did not change since they were last parsed are taken
+// from the cache, without being read.
+pub fn read_calendar(path: &Path, window: Option<Window>) -> Result<Calendar> {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default()
+        .to_string();
     let mut calendar = Calendar {
+        name,
         path: path.to_path_buf(),
         events: Vec::new(),
+        todos: Vec::new(),
+        notes: Vec::new(),
     };
     for subcalendar in fs::read_dir(path).context("Failed to read directory")? {
         let subcalendar = subcalendar.context("Failed to read subdirectory entry")?;
@@ -89,69 +141,567 @@ pub fn read_calendar(path: &Path) -> Result<Calendar> {
             calendar.path = subcalendar.path();
             for entry in fs::read_dir(subcalendar.path()).context("Failed to read subdirectory")? {
                 let entry = entry.context("Failed to read directory entry")?;
-                if entry
+                if !entry
                     .file_type()
                     .context("Failed to get file type")?
                     .is_file()
-                    && entry.path().extension().map_or(false, |ext| ext == "ics")
+                    || entry.path().extension().is_none_or(|ext| ext != "ics")
                 {
-                    let event = read_event(&entry.path()).context("Failed to read event")?;
-                    calendar.events.push(event);
+                    continue;
+                }
+
+                let path = entry.path();
+                let metadata = match entry.metadata() {
+                    Ok(metadata) => metadata,
+                    Err(err) => {
+                        skip_unreadable(&path, err.into())?;
+                        continue;
+                    }
+                };
+                let parsed = match cache::get(&path, &metadata) {
+                    Some(parsed) => parsed,
+                    None => {
+                        let content = match fs::read_to_string(&path) {
+                            Ok(content) => content,
+                            Err(err) => {
+                                skip_unreadable(&path, err.into())?;
+                                continue;
+                            }
+                        };
+                        match parse_file(&content) {
+                            Ok(parsed) => {
+                                cache::insert(&path, &metadata, &parsed);
+                                parsed
+                            }
+                            Err(err) => {
+                                skip_unreadable(&path, err)?;
+                                continue;
+                            }
+                        }
+                    }
+                };
+                match parsed {
+                    Parsed::Events(events, warnings) => {
+                        // Exceptions are kept whatever their time, they remove
+                        // the occurrence of their series they replace
+                        let kept: Vec<Event> = events
+                            .into_iter()
+                            .filter(|event| {
+                                let outside = |(from, to)| !may_occur_between(event, from, to);
+                                event.recurrence_id.is_some() || !window.is_some_and(outside)
+                            })
+                            .collect();
+                        if kept.is_empty() {
+                            continue;
+                        }
+                        for warning in warnings {
+                            eprintln!("Warning: '{}': {}", path.display(), warning);
+                        }
+                        for mut event in kept {
+                            event.calendar = calendar.name.clone();
+                            event.file = Some(path.clone());
+                            calendar.events.push(event);
+                        }
+                    }
+                    Parsed::Todo(mut todo) => {
+                        todo.calendar = calendar.name.clone();
+                        calendar.todos.push(todo);
+                    }
+                    Parsed::Note(mut note) => {
+                        note.calendar = calendar.name.clone();
+                        calendar.notes.push(note);
+                    }
+                    Parsed::Other => {}
                 }
             }
         }
     }
+    if let Err(err) = cache::save() {
+        eprintln!("Warning: {:#}", err);
+    }
     Ok(calendar)
 }
 
-fn read_event(path: &Path) -> Result<Event> {
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
+// The collections of a calendar, with the name vdirsyncer got from the
+// server in their displayname file, or else the name of their directory
+pub fn collections(calendar_name: &str) -> Result<Vec<(PathBuf, String)>> {
+    let mut collections = Vec::new();
+    for entry in fs::read_dir(paths::calendar_path(calendar_name)?)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let path = entry.path();
+        let name = fs::read_to_string(path.join("displayname"))
+            .ok()
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| entry.file_name().to_string_lossy().to_string());
+        collections.push((path, name));
+    }
+    collections.sort_by(|(_, a), (_, b)| a.cmp(b));
+    Ok(collections)
+}
+
+// Set by --strict: a file that cannot be read fails the command, instead of
+// being skipped with a warning
+static STRICT: OnceLock<bool> = OnceLock::new();
+
+// The files skipped while reading the calendars, with why
+static SKIPPED: Mutex<Vec<(PathBuf, String)>> = Mutex::new(Vec::new());
+
+pub fn set_strict() {
+    let _ = STRICT.set(true);
+}
+
+// A file that cannot be read, like junk synced from a server: with --strict
+// it fails the command, else it is left out and reported at the end
+pub fn skip_unreadable(path: &Path, err: anyhow::Error) -> Result<()> {
+    if STRICT.get().copied().unwrap_or(false) {
+        let unreadable = format!("Failed to read '{}'", path.display());
+        return Err(err.context(CalendarError::Unreadable(unreadable)));
+    }
+    if let Ok(mut skipped) = SKIPPED.lock() {
+        skipped.push((path.to_path_buf(), format!("{:#}", err)));
+    }
+    Ok(())
+}
+
+// Warns of the files skipped, after the output of the command so that it is
+// not cut by them. A file read several times is reported once, and files are
+// reported again only when skipped again, like in the next draw of a watch.
+pub fn report_skipped() {
+    let Ok(mut skipped) = SKIPPED
+        .lock()
+        .map(|mut skipped| std::mem::take(&mut *skipped))
+    else {
+        return;
+    };
+    skipped.sort();
+    skipped.dedup();
+    for (path, err) in skipped.iter() {
+        eprintln!("Warning: skipped '{}': {}", path.display(), err);
+    }
+    if !skipped.is_empty() {
+        eprintln!("Check them with calendar doctor, or fail on them with --strict");
+    }
+}
+
+// Whether an event may have an occurrence going on in [from, to]: a series
+// starting before the end whose UNTIL, its length added, is not before the
+// start, or an event starting before the end and ending after the start
+fn may_occur_between(event: &Event, from: NaiveDateTime, to: NaiveDateTime) -> bool {
+    let length = event.end - event.start;
+    let ends_after = |start: NaiveDateTime| start + length > from || start >= from;
+    event.start <= to
+        && match &event.recurrence {
+            Some(recurrence) => recurrence.until.is_none_or(ends_after),
+            None => ends_after(event.start),
+        }
+}
+
+// The kind of the first component of a calendar object, timezones aside
+pub fn component_kind(content: &str) -> Option<&str> {
+    content
+        .lines()
+        .filter_map(|line| line.trim_end_matches('\r').strip_prefix("BEGIN:"))
+        .find(|kind| !matches!(*kind, "VCALENDAR" | "VTIMEZONE" | "STANDARD" | "DAYLIGHT"))
+}
+
+// The content lines of the first component of a kind, without the ones of
+// the components nested in it like VALARM, as (name with parameters, value)
+fn component_properties(content: &str, kind: &str) -> Vec<(String, String)> {
+    let mut properties = Vec::new();
+    let mut in_component = false;
+    let mut nested = 0;
+
+    for line in unfold_lines(content) {
+        match line.strip_prefix("BEGIN:").or(line.strip_prefix("END:")) {
+            Some(name) if name == kind => {
+                if in_component {
+                    break;
+                }
+                in_component = true;
+            }
+            _ if !in_component => continue,
+            Some(_) if line.starts_with("BEGIN:") => nested += 1,
+            Some(_) => nested -= 1,
+            None if nested > 0 => continue,
+            None => {
+                if let Some((key, value)) = line.split_once(':') {
+                    properties.push((key.to_string(), value.to_string()));
+                }
+            }
+        }
+    }
+
+    properties
+}
+
+// A file as kept in the cache, the warnings of its events being given each
+// time it is read
+fn parse_file(content: &str) -> Result<Parsed> {
+    Ok(match component_kind(content) {
+        Some("VEVENT") => {
+            let (events, warnings) = parse_events(content)?;
+            let warnings = warnings.iter().map(ToString::to_string).collect();
+            Parsed::Events(events, warnings)
+        }
+        Some("VTODO") => Parsed::Todo(read_todo(content)?),
+        Some("VJOURNAL") => Parsed::Note(read_note(content)?),
+        _ => Parsed::Other,
+    })
+}
+
+pub fn read_event(content: &str, path: &Path) -> Result<Event> {
+    let (event, warnings) = parse_event(content)?;
+    for warning in warnings {
+        eprintln!("Warning: '{}': {}", path.display(), warning);
+    }
+    Ok(event)
+}
+
+// Every VEVENT of a file, read_event reading the first one only: the events
+// of other UIDs some clients bundle, and the exceptions of a series they
+// keep in its file
+#[cfg(feature = "sqlite")]
+pub fn read_events(content: &str, path: &Path) -> Result<Vec<Event>> {
+    let (events, warnings) = parse_events(content)?;
+    for warning in warnings {
+        eprintln!("Warning: '{}': {}", path.display(), warning);
+    }
+    Ok(events)
+}
+
+// Like read_events, with the warnings about their RRULE rather than printing
+// them
+fn parse_events(content: &str) -> Result<(Vec<Event>, Vec<RruleWarning>)> {
+    let mut events = Vec::new();
+    let mut warnings = Vec::new();
+    for (kind, component) in components(content) {
+        if kind == "VEVENT" {
+            let (event, event_warnings) = parse_event(&component)?;
+            events.push(event);
+            warnings.extend(event_warnings);
+        }
+    }
+    Ok((events, warnings))
+}
+
+// What is read otherwise than written in the RRULE of an event
+pub enum RruleWarning {
+    Unsupported(String),
+    // A part that would change the dates of the occurrences, like BYMONTHDAY
+    // or the BYDAY of a monthly rule
+    UnsupportedPart(String),
+    Invalid(String),
+    ZeroInterval,
+    Count(u32),
+}
+
+impl fmt::Display for RruleWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RruleWarning::Unsupported(rule) => {
+                write!(
+                    f,
+                    "unsupported RRULE '{}', showing a single occurrence",
+                    rule
+                )
+            }
+            RruleWarning::UnsupportedPart(part) => write!(
+                f,
+                "unsupported RRULE part '{}', showing a single occurrence",
+                part
+            ),
+            RruleWarning::Invalid(part) => write!(
+                f,
+                "invalid RRULE part '{}', showing a single occurrence",
+                part
+            ),
+            RruleWarning::ZeroInterval => {
+                write!(f, "RRULE with INTERVAL=0, using an interval of 1")
+            }
+            RruleWarning::Count(count) => write!(
+                f,
+                "RRULE with COUNT={}, limiting it to {} occurrences",
+                count,
+                calendar::MAX_OCCURRENCES
+            ),
+        }
+    }
+}
+
+// Like read_event, with the warnings about its RRULE rather than printing them
+pub fn parse_event(content: &str) -> Result<(Event, Vec<RruleWarning>)> {
+    let mut warnings = Vec::new();
     let mut event = Event {
         id: String::new(),
+        calendar: String::new(),
         name: String::new(),
         start: Utc::now().naive_utc(),
         end: Utc::now().naive_utc(),
         location: None,
         description: None,
+        url: None,
+        organizer: None,
+        attendees: Vec::new(),
+        attendance: Vec::new(),
+        all_day: false,
+        task: false,
+        transparent: false,
+        tags: Vec::new(),
+        recurrence: None,
+        recurrence_id: None,
+        created: None,
+        last_modified: None,
+        file: None,
     };
-    let mut in_event = false;
     let mut timezone: Option<String> = None;
+    let mut rrule = None;
+    let mut excluded = Vec::new();
 
-    for line in reader.lines() {
-        let line = line?;
-        match line.as_str() {
-            "BEGIN:VEVENT" => in_event = true,
-            "END:VEVENT" => break,
-            _ if !in_event => continue,
-            _ => {
-                let parts: Vec<&str> = line.splitn(2, ':').collect();
-                if parts.len() == 2 {
-                    let (key, value) = (parts[0], parts[1]);
-                    let key_parts: Vec<&str> = key.split(';').collect();
-                    let main_key = key_parts[0];
-
-                    match main_key {
-                        "UID" => event.id = value.to_string(),
-                        "SUMMARY" => event.name = value.to_string(),
-                        "LOCATION" => event.location = Some(value.to_string()),
-                        "DESCRIPTION" => event.description = Some(value.to_string()),
-                        "DTSTART" | "DTEND" => {
-                            let (datetime, tz) = parse_datetime(key, value, &timezone)?;
-                            timezone = tz;
-                            if main_key == "DTSTART" {
-                                event.start = datetime;
-                            } else {
-                                event.end = datetime;
-                            }
-                        }
-                        _ => {}
-                    }
+    for (key, value) in component_properties(content, "VEVENT") {
+        let (key, value) = (key.as_str(), value.as_str());
+        let main_key = key.split(';').next().unwrap_or_default();
+
+        match main_key {
+            "UID" => event.id = value.to_string(),
+            "SUMMARY" => event.name = unescape_text(value),
+            "LOCATION" => event.location = Some(unescape_text(value)),
+            "DESCRIPTION" => event.description = Some(unescape_text(value)),
+            "URL" => event.url = Some(value.to_string()),
+            "ORGANIZER" => event.organizer = Some(parse_address(value)),
+            "ATTENDEE" => event.attendees.push(parse_address(value)),
+            // Read once the start is known
+            "RRULE" => rrule = Some(value.to_string()),
+            "EXDATE" => {
+                for date in value.split(',') {
+                    excluded.push(parse_datetime(key, date, &timezone)?.0);
+                }
+            }
+            "X-CALENDAR-RS-ATTENDED" | "X-CALENDAR-RS-ABSENT" => {
+                let occurrence = key
+                    .split(';')
+                    .find_map(|parameter| parameter.strip_prefix("X-OCCURRENCE="))
+                    .ok_or_else(|| anyhow!("{} has no X-OCCURRENCE", main_key))?;
+                event.attendance.push(Attendance {
+                    occurrence: parse_datetime("", occurrence, &None)?.0,
+                    attended: main_key == "X-CALENDAR-RS-ATTENDED",
+                    recorded: parse_datetime("", value, &None)?.0,
+                });
+            }
+            "TRANSP" => event.transparent = value == "TRANSPARENT",
+            "CATEGORIES" => event.tags.extend(parse_categories(value)),
+            "CREATED" => event.created = Some(parse_datetime(key, value, &None)?.0),
+            "LAST-MODIFIED" => event.last_modified = Some(parse_datetime(key, value, &None)?.0),
+            "RECURRENCE-ID" => event.recurrence_id = Some(parse_datetime(key, value, &timezone)?.0),
+            "DTSTART" | "DTEND" => {
+                let (datetime, tz) = parse_datetime(key, value, &timezone)?;
+                timezone = tz;
+                if main_key == "DTSTART" {
+                    // DATE values have no time part
+                    event.all_day = !value.contains('T');
+                    event.start = datetime;
+                } else {
+                    event.end = datetime;
                 }
             }
+            _ => {}
         }
     }
-    Ok(event)
+    event.recurrence = rrule.and_then(|rrule| parse_rrule(&rrule, event.start, &mut warnings));
+    if let Some(recurrence) = &mut event.recurrence {
+        recurrence.excluded = excluded;
+    }
+    Ok((event, warnings))
+}
+
+// The busy periods of the VFREEBUSY of a calendar, the free ones aside
+pub fn read_freebusy(content: &str) -> Result<Vec<(NaiveDateTime, NaiveDateTime)>> {
+    let mut periods = Vec::new();
+    for (key, value) in component_properties(content, "VFREEBUSY") {
+        let mut parts = key.split(';');
+        if parts.next() != Some("FREEBUSY") || parts.any(|param| param == "FBTYPE=FREE") {
+            continue;
+        }
+        for period in value.split(',') {
+            let (start, end) = period
+                .split_once('/')
+                .ok_or_else(|| anyhow!("Invalid period '{}'", period))?;
+            let (start, _) = parse_datetime("FREEBUSY", start, &None)?;
+            // Periods end at a time or after a duration
+            let end = match parse_duration(end) {
+                Some(duration) => start + duration,
+                None => parse_datetime("FREEBUSY", end, &None)?.0,
+            };
+            periods.push((start, end));
+        }
+    }
+    Ok(periods)
+}
+
+// A DURATION value, like PT1H30M or P1D
+fn parse_duration(value: &str) -> Option<Duration> {
+    let mut duration = Duration::zero();
+    let mut number = String::new();
+    let mut time = false;
+    for c in value.strip_prefix('P')?.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'T' => time = true,
+            unit => {
+                let n: i64 = number.parse().ok()?;
+                number.clear();
+                duration += match (unit, time) {
+                    ('W', false) => Duration::weeks(n),
+                    ('D', false) => Duration::days(n),
+                    ('H', true) => Duration::hours(n),
+                    ('M', true) => Duration::minutes(n),
+                    ('S', true) => Duration::seconds(n),
+                    _ => return None,
+                };
+            }
+        }
+    }
+    number.is_empty().then_some(duration)
+}
+
+pub fn read_todo(content: &str) -> Result<Todo> {
+    let mut todo = Todo::new(String::new(), None, false, None);
+
+    for (key, value) in component_properties(content, "VTODO") {
+        let (key, value) = (key.as_str(), value.as_str());
+        match key.split(';').next().unwrap_or_default() {
+            "UID" => todo.id = value.to_string(),
+            "SUMMARY" => todo.name = unescape_text(value),
+            "DESCRIPTION" => todo.description = Some(unescape_text(value)),
+            "STATUS" => todo.done = value == "COMPLETED",
+            "COMPLETED" => todo.completed = Some(parse_datetime(key, value, &None)?.0),
+            "CREATED" => todo.created = Some(parse_datetime(key, value, &None)?.0),
+            "LAST-MODIFIED" => todo.last_modified = Some(parse_datetime(key, value, &None)?.0),
+            "DUE" => {
+                todo.all_day = !value.contains('T');
+                todo.due = Some(parse_datetime(key, value, &None)?.0);
+            }
+            _ => {}
+        }
+    }
+
+    // Some clients only set the completion date
+    todo.done |= todo.completed.is_some();
+
+    Ok(todo)
+}
+
+pub fn read_note(content: &str) -> Result<Note> {
+    let mut note = Note::new(Local::now().date_naive(), String::new());
+    let mut summary = String::new();
+
+    for (key, value) in component_properties(content, "VJOURNAL") {
+        let (key, value) = (key.as_str(), value.as_str());
+        match key.split(';').next().unwrap_or_default() {
+            "UID" => note.id = value.to_string(),
+            "SUMMARY" => summary = unescape_text(value),
+            "DESCRIPTION" => note.text = unescape_text(value),
+            "DTSTART" => note.date = parse_datetime(key, value, &None)?.0.date(),
+            "CREATED" => note.created = Some(parse_datetime(key, value, &None)?.0),
+            "LAST-MODIFIED" => note.last_modified = Some(parse_datetime(key, value, &None)?.0),
+            _ => {}
+        }
+    }
+
+    // Some clients only give a summary
+    if note.text.is_empty() {
+        note.text = summary;
+    }
+
+    Ok(note)
+}
+
+// Joins the continuation lines of folded content lines (RFC 5545 3.1)
+fn unfold_lines(content: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in content.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+// Splits a content line longer than 75 octets into continuation lines
+fn fold_line(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len());
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        width += c.len_utf8();
+        folded.push(c);
+    }
+    folded
+}
+
+fn unescape_text(value: &str) -> String {
+    let mut text = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => text.push('\n'),
+            Some(escaped) => text.push(escaped),
+            None => text.push('\\'),
+        }
+    }
+    text
+}
+
+// The categories of a CATEGORIES value, separated by the unescaped commas
+pub fn parse_categories(value: &str) -> Vec<String> {
+    let mut categories = Vec::new();
+    let mut current = String::new();
+    let mut escaped = false;
+    for c in value.chars() {
+        match c {
+            ',' if !escaped => categories.push(unescape_text(&std::mem::take(&mut current))),
+            _ => current.push(c),
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    categories.push(unescape_text(&current));
+
+    categories.retain(|category| !category.is_empty());
+    categories
+}
+
+pub fn format_categories(categories: &[String]) -> String {
+    categories
+        .iter()
+        .map(|category| escape_text(category))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// The email address of a CAL-ADDRESS, a mailto: URI
+fn parse_address(value: &str) -> String {
+    match value.get(..7) {
+        Some(scheme) if scheme.eq_ignore_ascii_case("mailto:") => value[7..].to_string(),
+        _ => value.to_string(),
+    }
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
 }
 
 fn parse_datetime(
@@ -166,7 +716,11 @@ fn parse_datetime(
         None
     };
 
-    let datetime = if value.contains('T') {
+    let datetime = if let Some(utc) = value.strip_suffix('Z') {
+        // Events are handled in local time
+        let utc = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S")?;
+        Local.from_utc_datetime(&utc).naive_local()
+    } else if value.contains('T') {
         NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")?
     } else {
         NaiveDate::parse_from_str(value, "%Y%m%d")?
@@ -177,54 +731,1890 @@ fn parse_datetime(
     Ok((datetime, tz.or_else(|| timezone.clone())))
 }
 
-pub fn write_event(calendar_path: &Path, event: &Event) -> Result<()> {
-    let filename = format!("{}.ics", event.id);
-    let file_path = calendar_path.join(filename);
-    let mut file = File::create(file_path)?;
+// Rules with parts that are not understood, or that cannot be read, are
+// shown as a single occurrence rather than at dates they do not give
+fn parse_rrule(
+    value: &str,
+    start: NaiveDateTime,
+    warnings: &mut Vec<RruleWarning>,
+) -> Option<Recurrence> {
+    let mut frequency = None;
+    let mut interval = 1;
+    let mut count = None;
+    let mut until = None;
+    let mut by_day = None;
+    let mut week_start = None;
+    let mut by_month = None;
+    let mut by_month_day = None;
+    let mut by_set_pos = None;
+
+    for part in value.split(';') {
+        let (key, value) = match part.split_once('=') {
+            Some(pair) => pair,
+            None => continue,
+        };
+        let parsed = match key {
+            "FREQ" => {
+                frequency = match value {
+                    "DAILY" => Some(RepeatFrequency::Daily),
+                    "WEEKLY" => Some(RepeatFrequency::Weekly),
+                    "MONTHLY" => Some(RepeatFrequency::Monthly),
+                    "YEARLY" => Some(RepeatFrequency::Yearly),
+                    _ => None,
+                };
+                true
+            }
+            "INTERVAL" => value.parse().map(|value| interval = value).is_ok(),
+            "COUNT" => value.parse().map(|value| count = Some(value)).is_ok(),
+            "UNTIL" => parse_datetime("UNTIL", value, &None)
+                .map(|(value, _)| until = Some(value))
+                .is_ok(),
+            "BYDAY" => {
+                by_day = Some(value);
+                true
+            }
+            "WKST" => {
+                week_start = Some(value);
+                true
+            }
+            "BYMONTH" => {
+                by_month = Some(value);
+                true
+            }
+            "BYMONTHDAY" => {
+                by_month_day = Some(value);
+                true
+            }
+            "BYSETPOS" => {
+                by_set_pos = Some(value);
+                true
+            }
+            _ => {
+                warnings.push(RruleWarning::UnsupportedPart(part.to_string()));
+                return None;
+            }
+        };
+        if !parsed {
+            warnings.push(RruleWarning::Invalid(part.to_string()));
+            return None;
+        }
+    }
 
-    let ics_content = format!(
-        "BEGIN:VCALENDAR\r\n\
-         VERSION:2.0\r\n\
-         PRODID:-//paulchambaz//calendar-rs 1.0.2//EN\r\n\
-         BEGIN:VEVENT\r\n\
-         UID:{}\r\n\
-         DTSTART:{}\r\n\
-         DTEND:{}\r\n\
-         SUMMARY:{}\r\n\
-         {}\
-         {}\
-         BEGIN:VALARM\r\n\
-         ACTION:DISPLAY\r\n\
-         TRIGGER:-PT10M\r\n\
-         END:VALARM\r\n\
-         END:VEVENT\r\n\
-         END:VCALENDAR\r\n",
-        event.id,
-        event.start.format("%Y%m%dT%H%M%S").to_string(),
-        event.end.format("%Y%m%dT%H%M%S").to_string(),
-        event.name,
-        event
-            .location
-            .as_ref()
-            .map_or(String::new(), |loc| format!("LOCATION:{}\r\n", loc)),
-        event
-            .description
-            .as_ref()
-            .map_or(String::new(), |desc| format!("DESCRIPTION:{}\r\n", desc)),
-    );
+    let frequency = match frequency {
+        Some(frequency) => frequency,
+        None => {
+            warnings.push(RruleWarning::Unsupported(value.to_string()));
+            return None;
+        }
+    };
 
-    file.write_all(ics_content.as_bytes())?;
-    Ok(())
+    // Only plain days are understood, of daily and weekly rules
+    let by_day = match (by_day, frequency) {
+        (None, _) => Vec::new(),
+        (Some(value), RepeatFrequency::Daily | RepeatFrequency::Weekly) => {
+            match parse_weekdays(value) {
+                Some(days) => days,
+                None => {
+                    warnings.push(RruleWarning::UnsupportedPart(format!("BYDAY={}", value)));
+                    return None;
+                }
+            }
+        }
+        (Some(value), _) => {
+            warnings.push(RruleWarning::UnsupportedPart(format!("BYDAY={}", value)));
+            return None;
+        }
+    };
+
+    let month_end = match month_end(frequency, start, by_month, by_month_day, by_set_pos) {
+        Some(month_end) => month_end,
+        None => {
+            let parts = [
+                ("BYMONTH", by_month),
+                ("BYMONTHDAY", by_month_day),
+                ("BYSETPOS", by_set_pos),
+            ];
+            let parts: Vec<String> = parts
+                .iter()
+                .filter_map(|(key, value)| value.map(|value| format!("{}={}", key, value)))
+                .collect();
+            warnings.push(RruleWarning::UnsupportedPart(parts.join(";")));
+            return None;
+        }
+    };
+
+    if interval == 0 {
+        warnings.push(RruleWarning::ZeroInterval);
+        interval = 1;
+    }
+
+    // Weeks start on monday, the start of the week only matters to weekly
+    // rules on several days that skip weeks
+    if let Some(value) = week_start.filter(|value| *value != "MO") {
+        if matches!(frequency, RepeatFrequency::Weekly) && !by_day.is_empty() && interval > 1 {
+            warnings.push(RruleWarning::UnsupportedPart(format!("WKST={}", value)));
+            return None;
+        }
+    }
+
+    if let Some(c) = count.filter(|c| *c > calendar::MAX_OCCURRENCES) {
+        warnings.push(RruleWarning::Count(c));
+        count = Some(calendar::MAX_OCCURRENCES);
+    }
+
+    Some(Recurrence {
+        frequency,
+        interval,
+        count,
+        until,
+        by_day,
+        month_end,
+        excluded: Vec::new(),
+    })
+}
+
+// Whether the BYMONTH, BYMONTHDAY and BYSETPOS of a rule give the date of its
+// start only, true when they fall on the last day of the months without it,
+// like the rules written by format_rrule. None for rules giving other dates.
+fn month_end(
+    frequency: RepeatFrequency,
+    start: NaiveDateTime,
+    by_month: Option<&str>,
+    by_month_day: Option<&str>,
+    by_set_pos: Option<&str>,
+) -> Option<bool> {
+    let month = start.month().to_string();
+    let on_start_month = match frequency {
+        RepeatFrequency::Daily | RepeatFrequency::Weekly | RepeatFrequency::Monthly => {
+            by_month.is_none()
+        }
+        // Without BYMONTH, BYMONTHDAY gives days of each month of the year
+        RepeatFrequency::Yearly => match by_month {
+            Some(by_month) => by_month == month,
+            None => by_month_day.is_none() && by_set_pos.is_none(),
+        },
+    };
+    if !on_start_month {
+        return None;
+    }
+    match (frequency, by_month_day, by_set_pos) {
+        (_, None, None) => Some(false),
+        (RepeatFrequency::Daily | RepeatFrequency::Weekly, _, _) => None,
+        (_, Some(days), None) if days == start.day().to_string() => Some(false),
+        (RepeatFrequency::Monthly, Some("-1"), None) if start.day() == 31 => Some(true),
+        (_, Some(days), Some("-1")) if days == days_to_month_end(start) => Some(true),
+        _ => None,
+    }
 }
 
-pub fn delete_event(calendar_path: &Path, event_id: String) -> Result<()> {
-    let filename = format!("{}.ics", event_id);
-    let file_path = calendar_path.join(filename);
+// The days from the 28th to the one of the start, the last of them that a
+// month has being the one of the occurrence
+fn days_to_month_end(start: NaiveDateTime) -> String {
+    (28..=start.day())
+        .map(|day| day.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
 
-    if file_path.exists() {
-        fs::remove_file(&file_path)?;
-        Ok(())
+// The days of a BYDAY list, None when one of them is not a plain day
+pub fn parse_weekdays(value: &str) -> Option<Vec<Weekday>> {
+    value
+        .split(',')
+        .map(|day| match day {
+            "MO" => Some(Weekday::Mon),
+            "TU" => Some(Weekday::Tue),
+            "WE" => Some(Weekday::Wed),
+            "TH" => Some(Weekday::Thu),
+            "FR" => Some(Weekday::Fri),
+            "SA" => Some(Weekday::Sat),
+            "SU" => Some(Weekday::Sun),
+            _ => None,
+        })
+        .collect()
+}
+
+pub fn format_weekdays(days: &[Weekday]) -> String {
+    days.iter()
+        .map(|day| match day {
+            Weekday::Mon => "MO",
+            Weekday::Tue => "TU",
+            Weekday::Wed => "WE",
+            Weekday::Thu => "TH",
+            Weekday::Fri => "FR",
+            Weekday::Sat => "SA",
+            Weekday::Sun => "SU",
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn format_utc(datetime: NaiveDateTime) -> String {
+    let utc = Local
+        .from_local_datetime(&datetime)
+        .earliest()
+        .map_or(datetime, |local| local.naive_utc());
+    format!("{}Z", utc.format("%Y%m%dT%H%M%S"))
+}
+
+// All-day events use DATE values, other events floating DATE-TIME values
+fn format_date(datetime: NaiveDateTime, all_day: bool) -> String {
+    if all_day {
+        datetime.format("%Y%m%d").to_string()
     } else {
-        Err(anyhow!("Event file not found in the calendar directory"))
+        datetime.format("%Y%m%dT%H%M%S").to_string()
+    }
+}
+
+fn format_rrule(recurrence: &Recurrence, start: NaiveDateTime, all_day: bool) -> String {
+    let frequency = match recurrence.frequency {
+        RepeatFrequency::Daily => "DAILY",
+        RepeatFrequency::Weekly => "WEEKLY",
+        RepeatFrequency::Monthly => "MONTHLY",
+        RepeatFrequency::Yearly => "YEARLY",
+    };
+    let mut rrule = format!("RRULE:FREQ={};INTERVAL={}", frequency, recurrence.interval);
+    if let Some(count) = recurrence.count {
+        rrule.push_str(&format!(";COUNT={}", count));
+    }
+    if let Some(until) = recurrence.until {
+        // UNTIL must have the same value type as DTSTART
+        rrule.push_str(&format!(";UNTIL={}", format_date(until, all_day)));
+    }
+    if !recurrence.by_day.is_empty() {
+        rrule.push_str(&format!(";BYDAY={}", format_weekdays(&recurrence.by_day)));
+    }
+    if recurrence.month_end && recurrence.has_short_months(start) {
+        if matches!(recurrence.frequency, RepeatFrequency::Yearly) {
+            rrule.push_str(&format!(";BYMONTH={}", start.month()));
+        }
+        rrule.push_str(&format!(
+            ";BYMONTHDAY={};BYSETPOS=-1",
+            days_to_month_end(start)
+        ));
+    }
+    rrule.push_str("\r\n");
+    rrule
+}
+
+// The EXDATE of the occurrences removed from a series, with the value type of
+// its start
+fn format_exdates(
+    recurrence: &Recurrence,
+    value_type: &str,
+    format: impl Fn(NaiveDateTime) -> String,
+) -> String {
+    recurrence
+        .excluded
+        .iter()
+        .map(|date| format!("EXDATE{}:{}\r\n", value_type, format(*date)))
+        .collect()
+}
+
+// The file a new event is written to in a collection, named after its UID.
+// UIDs are free form: the characters not portable in file names, separators
+// included, become _ so that the file stays in the collection, and a UID
+// starting with a dot, like .., is refused as its file would be hidden.
+// Distinct UIDs can then have the same name, like a/b and a:b: when the file
+// holds another UID, a short hash of the UID is added to the name.
+pub fn event_file(calendar_path: &Path, uid: &str) -> Result<PathBuf> {
+    let stem = file_stem(uid)?;
+    let path = calendar_path.join(format!("{}.ics", stem));
+    if file_uid(&path).is_some_and(|other| other != uid) {
+        let hash = Uuid::new_v5(&Uuid::NAMESPACE_OID, uid.as_bytes())
+            .simple()
+            .to_string();
+        return Ok(calendar_path.join(format!("{}-{}.ics", stem, &hash[..8])));
+    }
+    Ok(path)
+}
+
+// The UID of the event, todo or note of a file
+fn file_uid(path: &Path) -> Option<String> {
+    let content = fs::read_to_string(path).ok()?;
+    ["VEVENT", "VTODO", "VJOURNAL"].iter().find_map(|kind| {
+        component_properties(&content, kind)
+            .into_iter()
+            .find(|(key, _)| key == "UID")
+            .map(|(_, value)| value)
+    })
+}
+
+fn file_stem(uid: &str) -> Result<String> {
+    if uid.is_empty() || uid.starts_with('.') {
+        return Err(anyhow!("'{}' cannot be used as the name of a file", uid));
+    }
+    Ok(uid
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "-_.@".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect())
+}
+
+// The file of an event: the one it was read from, which other clients may
+// have named otherwise, or else the one of its UID
+pub fn file_of(calendar_path: &Path, event: &Event) -> Result<PathBuf> {
+    match &event.file {
+        Some(file) => Ok(file.clone()),
+        None => event_file(calendar_path, &event.id),
+    }
+}
+
+// Written like the events, under a name safe for any id
+pub fn write_todo(calendar_path: &Path, todo: &Todo) -> Result<()> {
+    let path = event_file(calendar_path, &todo.id)?;
+    let _locks = lock_calendars([&path])?;
+    fs::write(path, format_calendar(&format_todo(todo)))?;
+    Ok(())
+}
+
+pub fn write_note(calendar_path: &Path, note: &Note) -> Result<()> {
+    let path = event_file(calendar_path, &note.id)?;
+    let _locks = lock_calendars([&path])?;
+    fs::write(path, format_calendar(&format_note(note)))?;
+    Ok(())
+}
+
+// Locks the calendars of files, the directories above their collections, or
+// the trash for the files moved to it. Taken in order, two processes cannot
+// wait for each other.
+fn lock_calendars<'a>(files: impl IntoIterator<Item = &'a PathBuf>) -> Result<Vec<Lock>> {
+    let dirs: BTreeSet<&Path> = files
+        .into_iter()
+        .filter_map(|file| file.parent()?.parent())
+        .collect();
+    dirs.into_iter().map(lock::acquire).collect()
+}
+
+// Bundles events in a single VCALENDAR. The VEVENTs of their files are given
+// as they are, with the timezones of the files, so that nothing is lost of
+// what other clients wrote, like the exceptions of a series or the parts of
+// its RRULE that are not understood. Events without a file are written from
+// what was read of them.
+pub fn export_events(events: &[Event]) -> String {
+    let mut timezones = String::new();
+    let mut tzids = BTreeSet::new();
+    let mut exported = BTreeSet::new();
+    let mut components = String::new();
+
+    for event in events {
+        let content = event
+            .file
+            .as_ref()
+            .and_then(|file| fs::read_to_string(file).ok());
+        let Some(content) = content else {
+            components.push_str(&format_event(event, false));
+            continue;
+        };
+        // A series and its exceptions share their UID and their file
+        if !exported.insert((event.file.clone(), event.id.clone())) {
+            continue;
+        }
+
+        let mut found = false;
+        for (kind, component) in self::components(&content) {
+            let value = |name: &str| {
+                component_properties(&component, &kind)
+                    .into_iter()
+                    .find(|(key, _)| key.split(';').next() == Some(name))
+                    .map(|(_, value)| value)
+            };
+            match kind.as_str() {
+                "VTIMEZONE" if tzids.insert(value("TZID")) => timezones.push_str(&component),
+                "VEVENT" if value("UID").as_deref() == Some(event.id.as_str()) => {
+                    components.push_str(&component);
+                    found = true;
+                }
+                _ => {}
+            }
+        }
+        if !found {
+            components.push_str(&format_event(event, false));
+        }
+    }
+
+    format_calendar(&format!("{}{}", timezones, components))
+}
+
+// An invitation to an event (iTIP REQUEST), to be sent to its attendees. Its
+// times are in UTC, the attendees may live in other time zones.
+pub fn export_invite(event: &Event) -> String {
+    let organizer = event
+        .organizer
+        .iter()
+        .map(|organizer| format!("ORGANIZER:mailto:{}\r\n", organizer));
+    let attendees = event.attendees.iter().map(|attendee| {
+        format!(
+            "ATTENDEE;ROLE=REQ-PARTICIPANT;PARTSTAT=NEEDS-ACTION;RSVP=TRUE:mailto:{}\r\n",
+            attendee
+        )
+    });
+    let participants: String = organizer.chain(attendees).collect();
+
+    // All-day events keep their dates, UNTIL follows DTSTART in UTC
+    let (value_type, start, end) = if event.all_day {
+        (
+            ";VALUE=DATE",
+            format_date(event.start, true),
+            format_date(event.end, true),
+        )
+    } else {
+        ("", format_utc(event.start), format_utc(event.end))
+    };
+    let rrule = event
+        .recurrence
+        .as_ref()
+        .map_or(String::new(), |recurrence| {
+            let rrule = format_rrule(recurrence, event.start, event.all_day);
+            let rrule = match recurrence.until {
+                Some(until) if !event.all_day => rrule.replace(
+                    &format!("UNTIL={}", format_date(until, false)),
+                    &format!("UNTIL={}", format_utc(until)),
+                ),
+                _ => rrule,
+            };
+            let exdates = format_exdates(recurrence, value_type, |date| {
+                if event.all_day {
+                    format_date(date, true)
+                } else {
+                    format_utc(date)
+                }
+            });
+            format!("{}{}", rrule, exdates)
+        });
+
+    format_calendar(&format!(
+        "METHOD:REQUEST\r\n\
+         BEGIN:VEVENT\r\n\
+         UID:{}\r\n\
+         DTSTAMP:{}\r\n\
+         DTSTART{}:{}\r\n\
+         DTEND{}:{}\r\n\
+         SUMMARY:{}\r\n\
+         {}\
+         {}\
+         {}\
+         SEQUENCE:0\r\n\
+         STATUS:CONFIRMED\r\n\
+         {}\
+         END:VEVENT\r\n",
+        event.id,
+        format_utc(Local::now().naive_local()),
+        value_type,
+        start,
+        value_type,
+        end,
+        escape_text(&event.name),
+        event.location.as_ref().map_or(String::new(), |loc| format!(
+            "LOCATION:{}\r\n",
+            escape_text(loc)
+        )),
+        event.description.as_ref().map_or(String::new(), |desc| {
+            format!("DESCRIPTION:{}\r\n", escape_text(desc))
+        }),
+        rrule,
+        participants
+    ))
+}
+
+// The answer of an attendee to an invitation, from the event with their
+// participation status set by set_partstat
+pub fn export_reply(content: &str, attendee: &str) -> String {
+    let properties: String = component_properties(content, "VEVENT")
+        .into_iter()
+        .filter(
+            |(key, value)| match key.split(';').next().unwrap_or_default() {
+                "UID" | "SEQUENCE" | "RECURRENCE-ID" | "SUMMARY" | "ORGANIZER" => true,
+                "ATTENDEE" => parse_address(value).eq_ignore_ascii_case(attendee),
+                _ => false,
+            },
+        )
+        .map(|(key, value)| format!("{}\r\n", fold_line(&format!("{}:{}", key, value))))
+        .collect();
+
+    format_calendar(&format!(
+        "METHOD:REPLY\r\n\
+         BEGIN:VEVENT\r\n\
+         DTSTAMP:{}\r\n\
+         {}\
+         END:VEVENT\r\n",
+        format_utc(Local::now().naive_local()),
+        properties
+    ))
+}
+
+// A VFREEBUSY of busy blocks, one FREEBUSY line each to keep them short
+pub fn export_freebusy(
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    blocks: &[(NaiveDateTime, NaiveDateTime)],
+) -> String {
+    let busy: String = blocks
+        .iter()
+        .map(|(start, end)| {
+            format!(
+                "FREEBUSY;FBTYPE=BUSY:{}/{}\r\n",
+                format_utc(*start),
+                format_utc(*end)
+            )
+        })
+        .collect();
+
+    format_calendar(&format!(
+        "METHOD:PUBLISH\r\n\
+         BEGIN:VFREEBUSY\r\n\
+         UID:{}\r\n\
+         DTSTAMP:{}\r\n\
+         DTSTART:{}\r\n\
+         DTEND:{}\r\n\
+         {}\
+         END:VFREEBUSY\r\n",
+        Uuid::new_v4(),
+        format_utc(Local::now().naive_local()),
+        format_utc(from),
+        format_utc(to),
+        busy
+    ))
+}
+
+fn format_calendar(components: &str) -> String {
+    format!(
+        "BEGIN:VCALENDAR\r\n\
+         VERSION:2.0\r\n\
+         PRODID:-//paulchambaz//calendar-rs 1.0.2//EN\r\n\
+         {}\
+         END:VCALENDAR\r\n",
+        components
+    )
+}
+
+// Events are written with a reminder 10 minutes before them, the ones of the
+// other clients keep theirs
+const ALARM: &str = "BEGIN:VALARM\r\nACTION:DISPLAY\r\nTRIGGER:-PT10M\r\nEND:VALARM\r\n";
+
+fn format_event(event: &Event, alarm: bool) -> String {
+    let value_type = if event.all_day { ";VALUE=DATE" } else { "" };
+
+    format!(
+        "BEGIN:VEVENT\r\n\
+         UID:{}\r\n\
+         {}\
+         {}\
+         DTSTART{}:{}\r\n\
+         DTEND{}:{}\r\n\
+         {}\
+         SUMMARY:{}\r\n\
+         {}\
+         {}\
+         {}\
+         {}\
+         {}\
+         {}\
+         {}\
+         {}\
+         {}\
+         END:VEVENT\r\n",
+        event.id,
+        event.created.map_or(String::new(), |created| format!(
+            "CREATED:{}\r\n",
+            format_utc(created)
+        )),
+        event.last_modified.map_or(String::new(), |modified| {
+            format!("LAST-MODIFIED:{}\r\n", format_utc(modified))
+        }),
+        value_type,
+        format_date(event.start, event.all_day),
+        value_type,
+        format_date(event.end, event.all_day),
+        event
+            .recurrence_id
+            .map_or(String::new(), |occurrence| format!(
+                "RECURRENCE-ID{}:{}\r\n",
+                value_type,
+                format_date(occurrence, event.all_day)
+            )),
+        escape_text(&event.name),
+        event.location.as_ref().map_or(String::new(), |loc| format!(
+            "LOCATION:{}\r\n",
+            escape_text(loc)
+        )),
+        event.description.as_ref().map_or(String::new(), |desc| {
+            format!("DESCRIPTION:{}\r\n", escape_text(desc))
+        }),
+        event
+            .url
+            .as_ref()
+            .map_or(String::new(), |url| format!("URL:{}\r\n", url)),
+        event
+            .recurrence
+            .as_ref()
+            .map_or(String::new(), |recurrence| format!(
+                "{}{}",
+                format_rrule(recurrence, event.start, event.all_day),
+                format_exdates(recurrence, value_type, |date| format_date(
+                    date,
+                    event.all_day
+                ))
+            )),
+        if event.transparent {
+            "TRANSP:TRANSPARENT\r\n"
+        } else {
+            ""
+        },
+        if event.tags.is_empty() {
+            String::new()
+        } else {
+            format!("CATEGORIES:{}\r\n", format_categories(&event.tags))
+        },
+        format_participants(event),
+        format_attendance(event),
+        if alarm { ALARM } else { "" },
+    )
+}
+
+// The attendance of the occurrences, when they started and when it was
+// recorded, in UTC
+fn format_attendance(event: &Event) -> String {
+    event
+        .attendance
+        .iter()
+        .map(|attendance| {
+            format!(
+                "X-CALENDAR-RS-{};X-OCCURRENCE={}:{}\r\n",
+                if attendance.attended {
+                    "ATTENDED"
+                } else {
+                    "ABSENT"
+                },
+                format_utc(attendance.occurrence),
+                format_utc(attendance.recorded)
+            )
+        })
+        .collect()
+}
+
+// ORGANIZER and ATTENDEE lines, attendees being asked to reply
+fn format_participants(event: &Event) -> String {
+    let organizer = event
+        .organizer
+        .iter()
+        .map(|organizer| format!("ORGANIZER:mailto:{}\r\n", organizer));
+    let attendees = event
+        .attendees
+        .iter()
+        .map(|attendee| format!("ATTENDEE;RSVP=TRUE:mailto:{}\r\n", attendee));
+    organizer.chain(attendees).collect()
+}
+
+fn format_todo(todo: &Todo) -> String {
+    let mut lines = vec![format!("UID:{}", todo.id)];
+    if let Some(created) = todo.created {
+        lines.push(format!("CREATED:{}", format_utc(created)));
+    }
+    if let Some(modified) = todo.last_modified {
+        lines.push(format!("LAST-MODIFIED:{}", format_utc(modified)));
+    }
+    if let Some(due) = todo.due {
+        let value_type = if todo.all_day { ";VALUE=DATE" } else { "" };
+        lines.push(format!(
+            "DUE{}:{}",
+            value_type,
+            format_date(due, todo.all_day)
+        ));
+    }
+    lines.push(format!("SUMMARY:{}", escape_text(&todo.name)));
+    if let Some(description) = &todo.description {
+        lines.push(format!("DESCRIPTION:{}", escape_text(description)));
+    }
+    if todo.done {
+        lines.push("STATUS:COMPLETED".to_string());
+        if let Some(completed) = todo.completed {
+            lines.push(format!("COMPLETED:{}", format_utc(completed)));
+        }
+    } else {
+        lines.push("STATUS:NEEDS-ACTION".to_string());
+    }
+
+    format!("BEGIN:VTODO\r\n{}\r\nEND:VTODO\r\n", lines.join("\r\n"))
+}
+
+// The first line of the note is its summary, the whole text its description
+fn format_note(note: &Note) -> String {
+    let mut lines = vec![format!("UID:{}", note.id)];
+    if let Some(created) = note.created {
+        lines.push(format!("CREATED:{}", format_utc(created)));
+    }
+    if let Some(modified) = note.last_modified {
+        lines.push(format!("LAST-MODIFIED:{}", format_utc(modified)));
+    }
+    lines.push(format!("DTSTART;VALUE=DATE:{}", note.date.format("%Y%m%d")));
+    let summary = note.text.lines().next().unwrap_or_default();
+    lines.push(format!("SUMMARY:{}", escape_text(summary)));
+    lines.push(format!("DESCRIPTION:{}", escape_text(&note.text)));
+
+    format!(
+        "BEGIN:VJOURNAL\r\n{}\r\nEND:VJOURNAL\r\n",
+        lines.join("\r\n")
+    )
+}
+
+// Splits an ICS calendar into one VCALENDAR per UID, keeping the original
+// components (and the timezones they may refer to) untouched
+pub fn split_events(content: &str) -> Vec<(String, String)> {
+    let mut timezones = String::new();
+    let mut events: Vec<(String, String)> = Vec::new();
+    let mut component: Option<(&str, String)> = None;
+
+    for line in content.lines() {
+        let line = line.trim_end_matches('\r');
+        let (kind, data) = match &mut component {
+            Some(component) => component,
+            None => {
+                let kind = match line {
+                    "BEGIN:VEVENT" => "VEVENT",
+                    "BEGIN:VTODO" => "VTODO",
+                    "BEGIN:VJOURNAL" => "VJOURNAL",
+                    "BEGIN:VTIMEZONE" => "VTIMEZONE",
+                    _ => continue,
+                };
+                component.insert((kind, String::new()))
+            }
+        };
+
+        data.push_str(line);
+        data.push_str("\r\n");
+
+        if line == format!("END:{}", kind) {
+            let (kind, data) = component.take().unwrap_or_default();
+            if kind == "VTIMEZONE" {
+                timezones.push_str(&data);
+                continue;
+            }
+
+            let uid = unfold_lines(&data)
+                .iter()
+                .find_map(|line| line.strip_prefix("UID:").map(String::from));
+            let (uid, data) = match uid {
+                Some(uid) => (uid, data),
+                None => {
+                    // Derive the UID from the content so re-imports are detected
+                    let uid = Uuid::new_v5(&Uuid::NAMESPACE_OID, data.as_bytes()).to_string();
+                    let begin = format!("BEGIN:{}\r\n", kind);
+                    let data = data.replacen(&begin, &format!("{}UID:{}\r\n", begin, uid), 1);
+                    (uid, data)
+                }
+            };
+
+            // Recurrence exceptions share the UID of their master event
+            match events.iter_mut().find(|(existing, _)| *existing == uid) {
+                Some((_, existing)) => existing.push_str(&data),
+                None => events.push((uid, data)),
+            }
+        }
+    }
+
+    events
+        .into_iter()
+        .map(|(uid, data)| (uid, format_calendar(&format!("{}{}", timezones, data))))
+        .collect()
+}
+
+// The top-level components of a calendar object as they are, with their kind
+fn components(content: &str) -> Vec<(String, String)> {
+    let mut components = Vec::new();
+    let mut component: Option<(String, String)> = None;
+    let mut depth = 0;
+
+    for line in content.lines() {
+        let line = line.trim_end_matches('\r');
+        let (_, data) = match &mut component {
+            Some(component) => component,
+            None => match line.strip_prefix("BEGIN:") {
+                Some(kind) if kind != "VCALENDAR" => {
+                    component.insert((kind.to_string(), String::new()))
+                }
+                _ => continue,
+            },
+        };
+
+        data.push_str(line);
+        data.push_str("\r\n");
+
+        if line.starts_with("BEGIN:") {
+            depth += 1;
+        } else if line.starts_with("END:") {
+            depth -= 1;
+            if depth == 0 {
+                components.extend(component.take());
+            }
+        }
+    }
+
+    components
+}
+
+// Whether the first component of a kind has a property
+pub fn has_property(content: &str, kind: &str, name: &str) -> bool {
+    component_properties(content, kind)
+        .iter()
+        .any(|(key, _)| key.split(';').next() == Some(name))
+}
+
+// The value of a property of a single component, unescaped
+fn property(component: &str, kind: &str, name: &str) -> Option<String> {
+    component_properties(component, kind)
+        .into_iter()
+        .find(|(key, _)| key.split(';').next() == Some(name))
+        .map(|(_, value)| unescape_text(&value))
+}
+
+// Whether a component overrides an occurrence of the recurring event of a UID
+fn is_exception(component: &str, uid: &str) -> bool {
+    property(component, "VEVENT", "UID").as_deref() == Some(uid)
+        && property(component, "VEVENT", "RECURRENCE-ID").is_some()
+}
+
+// Renames the exceptions of a recurring event, the VEVENTs of its UID with a
+// RECURRENCE-ID, that were named like the series, None when there are none.
+// The ones named otherwise on purpose keep their name.
+fn rename_exceptions(content: &str, uid: &str, old_name: &str, new_name: &str) -> Option<String> {
+    let mut result: String = content
+        .lines()
+        .map(|line| format!("{}\r\n", line.trim_end_matches('\r')))
+        .collect();
+    let mut renamed = false;
+    for (kind, component) in components(content) {
+        if kind != "VEVENT"
+            || !is_exception(&component, uid)
+            || property(&component, "VEVENT", "SUMMARY").as_deref() != Some(old_name)
+        {
+            continue;
+        }
+        let mut replacement = String::with_capacity(component.len());
+        for line in unfold_lines(&component) {
+            let line = match line.split_once(':') {
+                Some((key, _)) if key.split(';').next() == Some("SUMMARY") => {
+                    format!("SUMMARY:{}", escape_text(new_name))
+                }
+                _ => line,
+            };
+            replacement.push_str(&fold_line(&line));
+            replacement.push_str("\r\n");
+        }
+        result = result.replacen(&component, &replacement, 1);
+        renamed = true;
+    }
+    renamed.then_some(result)
+}
+
+// The other exceptions of a recurring event in the file it is written over,
+// and the timezones they may refer to, kept when it is written again and
+// renamed with it. The first VEVENT is the one the event was read from.
+fn exceptions(content: &str, event: &Event) -> String {
+    let old_name = components(content)
+        .iter()
+        .find(|(kind, _)| kind == "VEVENT")
+        .and_then(|(_, component)| property(component, "VEVENT", "SUMMARY"));
+    let content = old_name
+        .filter(|old_name| *old_name != event.name && event.recurrence_id.is_none())
+        .and_then(|old_name| rename_exceptions(content, &event.id, &old_name, &event.name))
+        .unwrap_or_else(|| content.to_string());
+
+    let mut components = components(&content);
+    if let Some(first) = components.iter().position(|(kind, _)| kind == "VEVENT") {
+        components.remove(first);
+    }
+    if !components
+        .iter()
+        .any(|(kind, component)| kind == "VEVENT" && is_exception(component, &event.id))
+    {
+        return String::new();
+    }
+    components
+        .into_iter()
+        .filter(|(kind, component)| {
+            kind == "VTIMEZONE" || (kind == "VEVENT" && is_exception(component, &event.id))
+        })
+        .map(|(_, component)| component)
+        .collect()
+}
+
+// The SUMMARY of the first component of a calendar object
+pub fn summary(content: &str) -> Option<String> {
+    let kind = component_kind(content)?;
+    component_properties(content, kind)
+        .into_iter()
+        .find(|(key, _)| key == "SUMMARY")
+        .map(|(_, value)| unescape_text(&value))
+}
+
+// Gives a new UID to a calendar object, recurrence exceptions included
+pub fn replace_uid(content: &str, uid: &str, new_uid: &str) -> String {
+    content.replace(
+        &format!("\r\nUID:{}\r\n", uid),
+        &format!("\r\nUID:{}\r\n", new_uid),
+    )
+}
+
+// Sets the participation status of an attendee in a calendar object,
+// recurrence exceptions included, None when they are not an attendee
+pub fn set_partstat(content: &str, attendee: &str, partstat: &str) -> Option<String> {
+    let mut found = false;
+    let mut result = String::with_capacity(content.len());
+    for line in unfold_lines(content) {
+        let line = match line.split_once(':') {
+            Some((key, value))
+                if key.split(';').next() == Some("ATTENDEE")
+                    && parse_address(value).eq_ignore_ascii_case(attendee) =>
+            {
+                found = true;
+                // The answer is given, it is no longer asked for
+                let parameters = key.split(';').filter(|parameter| {
+                    let name = parameter.split('=').next().unwrap_or_default();
+                    !name.eq_ignore_ascii_case("PARTSTAT") && !name.eq_ignore_ascii_case("RSVP")
+                });
+                format!(
+                    "{};PARTSTAT={}:{}",
+                    parameters.collect::<Vec<_>>().join(";"),
+                    partstat,
+                    value
+                )
+            }
+            _ => line,
+        };
+        result.push_str(&fold_line(&line));
+        result.push_str("\r\n");
+    }
+    found.then_some(result)
+}
+
+// Increments the SEQUENCE of the events of a calendar object, for the
+// clients and the attendees to know that they changed, adding it to the ones
+// without one
+pub fn bump_sequence(content: &str) -> String {
+    let mut result = String::with_capacity(content.len());
+    let mut in_event = false;
+    let mut nested = 0;
+    let mut sequenced = false;
+    for line in unfold_lines(content) {
+        if line == "BEGIN:VEVENT" {
+            in_event = true;
+            sequenced = false;
+        } else if in_event && line.starts_with("BEGIN:") {
+            nested += 1;
+        } else if in_event && line.starts_with("END:") {
+            if nested > 0 {
+                nested -= 1;
+            } else {
+                if !sequenced {
+                    result.push_str("SEQUENCE:1\r\n");
+                }
+                in_event = false;
+            }
+        }
+
+        let line = match line.split_once(':') {
+            Some(("SEQUENCE", value)) if in_event && nested == 0 => {
+                sequenced = true;
+                format!("SEQUENCE:{}", value.trim().parse::<u32>().unwrap_or(0) + 1)
+            }
+            _ => line,
+        };
+        result.push_str(&fold_line(&line));
+        result.push_str("\r\n");
+    }
+    result
+}
+
+// Rewrites the properties of the first VEVENT of a calendar object, its
+// BEGIN line included, the components nested in it aside
+fn edit_first_event(content: &str, mut edit: impl FnMut(String) -> Vec<String>) -> String {
+    let mut result = String::with_capacity(content.len());
+    let mut in_event = false;
+    let mut seen = false;
+    let mut nested = 0;
+    for line in unfold_lines(content) {
+        let lines = if !seen && line == "BEGIN:VEVENT" {
+            in_event = true;
+            seen = true;
+            edit(line)
+        } else if in_event && line.starts_with("BEGIN:") {
+            nested += 1;
+            vec![line]
+        } else if in_event && line.starts_with("END:") {
+            match nested > 0 {
+                true => nested -= 1,
+                false => in_event = false,
+            }
+            vec![line]
+        } else if in_event && nested == 0 {
+            edit(line)
+        } else {
+            vec![line]
+        };
+        for line in lines {
+            result.push_str(&fold_line(&line));
+            result.push_str("\r\n");
+        }
+    }
+    result
+}
+
+// Gives a UID to an event without one
+pub fn add_uid(content: &str, uid: &str) -> String {
+    edit_first_event(content, |line| match line == "BEGIN:VEVENT" {
+        true => vec![line, format!("UID:{}", uid)],
+        false => vec![line],
+    })
+}
+
+// Ends an event ending before it starts at its start, or the day after for
+// all-day events
+pub fn end_at_start(content: &str) -> Result<String> {
+    let (key, value) = component_properties(content, "VEVENT")
+        .into_iter()
+        .find(|(key, _)| key.split(';').next() == Some("DTSTART"))
+        .ok_or_else(|| anyhow!("The event has no DTSTART"))?;
+    let value = match value.contains('T') {
+        true => value,
+        false => (NaiveDate::parse_from_str(&value, "%Y%m%d")? + Duration::days(1))
+            .format("%Y%m%d")
+            .to_string(),
+    };
+    let end = format!("{}:{}", key.replacen("DTSTART", "DTEND", 1), value);
+    Ok(edit_first_event(content, |line| {
+        match line.split(':').next().and_then(|key| key.split(';').next()) {
+            Some("DTEND") => vec![end.clone()],
+            _ => vec![line],
+        }
+    }))
+}
+
+// Writes the INTERVAL=0 of an RRULE as the interval of 1 it is read as
+pub fn repair_interval(content: &str) -> String {
+    edit_first_event(content, |line| match line.strip_prefix("RRULE:") {
+        Some(rule) => {
+            let parts: Vec<&str> = rule
+                .split(';')
+                .map(|part| match part {
+                    "INTERVAL=0" => "INTERVAL=1",
+                    part => part,
+                })
+                .collect();
+            vec![format!("RRULE:{}", parts.join(";"))]
+        }
+        None => vec![line],
+    })
+}
+
+// File changes applied together, for the commands writing many files. The
+// new contents are first written to temporary files next to their targets,
+// which are then renamed over them, the replaced and removed files being
+// kept aside until the end. An error before the renames leaves the storage
+// untouched, an error during them puts the files replaced so far back.
+#[derive(Default)]
+pub struct Batch {
+    writes: Vec<(PathBuf, String)>,
+    removals: Vec<PathBuf>,
+}
+
+impl Batch {
+    pub fn write_event(&mut self, calendar_path: &Path, event: &Event) -> Result<()> {
+        let path = file_of(calendar_path, event)?;
+        let exceptions = match fs::read_to_string(&path) {
+            Ok(content) => exceptions(&content, event),
+            Err(_) => String::new(),
+        };
+        self.write(
+            path,
+            format_calendar(&format!("{}{}", format_event(event, true), exceptions)),
+        );
+        Ok(())
+    }
+
+    // Renames the exceptions of a series kept in a file of their own, when
+    // they were named like it
+    pub fn rename_exceptions(
+        &mut self,
+        file: &Path,
+        uid: &str,
+        old_name: &str,
+        new_name: &str,
+    ) -> Result<()> {
+        let content = fs::read_to_string(file)
+            .with_context(|| format!("Failed to read '{}'", file.display()))?;
+        if let Some(renamed) = rename_exceptions(&content, uid, old_name, new_name) {
+            self.write(file.to_path_buf(), renamed);
+        }
+        Ok(())
+    }
+
+    // Writes a file again, like the ones repaired by doctor
+    pub fn replace_file(&mut self, file: &Path, content: String) {
+        self.write(file.to_path_buf(), content);
+    }
+
+    pub fn write_raw_event(
+        &mut self,
+        calendar_path: &Path,
+        uid: &str,
+        content: &str,
+    ) -> Result<()> {
+        self.write(event_file(calendar_path, uid)?, content.to_string());
+        Ok(())
+    }
+
+    pub fn delete_event(&mut self, calendar_path: &Path, event: &Event) -> Result<()> {
+        let path = file_of(calendar_path, event)?;
+        let staged = self.writes.len();
+        self.writes.retain(|(target, _)| *target != path);
+        // A file only written by this batch has nothing to remove
+        if self.writes.len() == staged || path.exists() {
+            self.removals.push(path);
+        }
+        Ok(())
+    }
+
+    // Moves the file of an event to the trash of its calendar, under its name
+    // after the time it was deleted
+    pub fn trash_event(&mut self, calendar_path: &Path, event: &Event) -> Result<()> {
+        let file = file_of(calendar_path, event)?;
+        let content = fs::read_to_string(&file)
+            .with_context(|| format!("Failed to read '{}'", file.display()))?;
+        let name = file
+            .file_name()
+            .ok_or_else(|| anyhow!("Invalid event file '{}'", file.display()))?;
+        let trash = paths::trash_dir()?.join(&event.calendar);
+        fs::create_dir_all(&trash)
+            .with_context(|| format!("Failed to create '{}'", trash.display()))?;
+        let deleted = Local::now().format(TRASH_TIME_FORMAT);
+        let target = trash.join(format!("{}-{}", deleted, name.to_string_lossy()));
+        self.move_to(&file, target, content)
+    }
+
+    // Moves the file of an event, under the same name, to another calendar
+    pub fn move_file(&mut self, file: &Path, calendar_path: &Path, content: String) -> Result<()> {
+        let name = file
+            .file_name()
+            .ok_or_else(|| anyhow!("Invalid event file '{}'", file.display()))?;
+        self.move_to(file, calendar_path.join(name), content)
+    }
+
+    // Moves a file elsewhere, written with the content given
+    pub fn move_to(&mut self, file: &Path, target: PathBuf, content: String) -> Result<()> {
+        if target.exists() {
+            return Err(anyhow!("'{}' already exists", target.display()));
+        }
+        self.write(target, content);
+        self.removals.push(file.to_path_buf());
+        Ok(())
+    }
+
+    // A later write of a file replaces the earlier one
+    fn write(&mut self, path: PathBuf, content: String) {
+        self.writes.retain(|(target, _)| *target != path);
+        self.removals.retain(|target| *target != path);
+        self.writes.push((path, content));
+    }
+
+    pub fn commit(self) -> Result<()> {
+        let _locks = lock_calendars(
+            self.writes
+                .iter()
+                .map(|(path, _)| path)
+                .chain(&self.removals),
+        )?;
+        // Ctrl-C drops the temporary files, or waits for the renames
+        let _critical = interrupt::Critical::enter();
+
+        let mut progress = Progress::new("Writing", self.writes.len() as u64);
+        let mut staged = Vec::new();
+        for (path, content) in &self.writes {
+            let temporary = sibling(path, "tmp")?;
+            let written = if interrupt::interrupted() {
+                Err(anyhow!("Interrupted"))
+            } else {
+                fs::write(&temporary, content)
+                    .with_context(|| format!("Failed to write '{}'", path.display()))
+            };
+            if let Err(err) = written {
+                let _ = fs::remove_file(&temporary);
+                for (temporary, _) in &staged {
+                    let _ = fs::remove_file(temporary);
+                }
+                return Err(err);
+            }
+            staged.push((temporary, path));
+            progress.inc(1);
+        }
+        progress.finish();
+
+        let mut replaced = Vec::new();
+        let mut created = Vec::new();
+        let mut apply = || -> Result<()> {
+            for (temporary, path) in &staged {
+                if path.exists() {
+                    let backup = sibling(path, "bak")?;
+                    fs::rename(path, &backup)?;
+                    replaced.push((backup, path.to_path_buf()));
+                }
+                fs::rename(temporary, path)?;
+                created.push(path.to_path_buf());
+            }
+            for path in &self.removals {
+                if !path.exists() {
+                    return Err(anyhow!(
+                        "Event file '{}' not found in the calendar directory",
+                        path.display()
+                    ));
+                }
+                let backup = sibling(path, "bak")?;
+                fs::rename(path, &backup)?;
+                replaced.push((backup, path.clone()));
+            }
+            Ok(())
+        };
+
+        if let Err(err) = apply() {
+            // What cannot be put back is reported, with the backups left for
+            // the files to be restored by hand
+            let mut failures = Vec::new();
+            for path in &created {
+                if let Err(err) = fs::remove_file(path) {
+                    failures.push(format!(
+                        "'{}' could not be removed: {}",
+                        path.display(),
+                        err
+                    ));
+                }
+            }
+            for (backup, path) in replaced.iter().rev() {
+                if let Err(err) = fs::rename(backup, path) {
+                    failures.push(format!(
+                        "'{}' could not be restored from '{}': {}",
+                        path.display(),
+                        backup.display(),
+                        err
+                    ));
+                }
+            }
+            // The ones renamed are gone, the others are hidden files that
+            // calendars do not read
+            for (temporary, _) in &staged {
+                let _ = fs::remove_file(temporary);
+            }
+            if !failures.is_empty() {
+                return Err(err.context(format!(
+                    "Failed to apply the changes, and to put the calendars back as they were:\n{}",
+                    failures.join("\n")
+                )));
+            }
+            return Err(
+                err.context("Failed to apply the changes, the calendars were left as they were")
+            );
+        }
+
+        for (backup, _) in &replaced {
+            let _ = fs::remove_file(backup);
+        }
+        Ok(())
+    }
+}
+
+// A hidden file next to another, which calendars do not read
+fn sibling(path: &Path, extension: &str) -> Result<PathBuf> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("Invalid file '{}'", path.display()))?;
+    Ok(path.with_file_name(format!(
+        ".{}.{}.{}",
+        name.to_string_lossy(),
+        std::process::id(),
+        extension
+    )))
+}
+
+pub fn trash_event(calendar_path: &Path, event: &Event) -> Result<()> {
+    let mut batch = Batch::default();
+    batch.trash_event(calendar_path, event)?;
+    batch.commit()
+}
+
+// The files of the trash start with the time they were deleted, like
+// 20240806T143000-<name>.ics
+pub const TRASH_TIME_FORMAT: &str = "%Y%m%dT%H%M%S";
+
+pub fn migrate_calendars() -> Result<PathBuf> {
+    let legacy_dir = paths::legacy_calendar_dir()?;
+    let data_dir = paths::data_calendar_dir()?;
+
+    if !legacy_dir.is_dir() {
+        return Err(anyhow!(
+            "No calendars found in '{}', nothing to migrate",
+            legacy_dir.display()
+        ));
+    }
+
+    if data_dir.is_dir() {
+        if fs::read_dir(&data_dir)?.next().is_some() {
+            return Err(anyhow!(
+                "'{}' already exists and is not empty",
+                data_dir.display()
+            ));
+        }
+        fs::remove_dir(&data_dir)?;
+    }
+
+    if let Some(parent) = data_dir.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // A plain rename fails across filesystems, fall back to copying the tree
+    if fs::rename(&legacy_dir, &data_dir).is_err() {
+        copy_dir(&legacy_dir, &data_dir).context("Failed to copy calendars")?;
+        fs::remove_dir_all(&legacy_dir).context("Failed to remove legacy calendars")?;
+    }
+
+    Ok(data_dir)
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn start() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 10, 16)
+            .unwrap()
+            .and_hms_opt(10, 0, 0)
+            .unwrap()
+    }
+
+    fn rrule(value: &str) -> Recurrence {
+        parse_rrule(value, start(), &mut Vec::new()).unwrap()
+    }
+
+    #[test]
+    fn rrule_with_zero_interval_repeats_every_period() {
+        assert_eq!(rrule("FREQ=WEEKLY;INTERVAL=0").interval, 1);
+    }
+
+    #[test]
+    fn rrule_count_is_capped() {
+        assert_eq!(rrule("FREQ=DAILY;COUNT=10").count, Some(10));
+        let count = calendar::MAX_OCCURRENCES + 1;
+        assert_eq!(
+            rrule(&format!("FREQ=DAILY;COUNT={}", count)).count,
+            Some(calendar::MAX_OCCURRENCES)
+        );
+    }
+
+    #[test]
+    fn rrule_until_is_read_as_a_date_time() {
+        let until = NaiveDate::from_ymd_opt(2026, 12, 31)
+            .unwrap()
+            .and_hms_opt(23, 59, 0)
+            .unwrap();
+        assert_eq!(
+            rrule("FREQ=MONTHLY;UNTIL=20261231T235900").until,
+            Some(until)
+        );
+        assert_eq!(
+            rrule("FREQ=YEARLY;UNTIL=20261231").until,
+            NaiveDate::from_ymd_opt(2026, 12, 31)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+        );
+    }
+
+    #[test]
+    fn unsupported_rrule_gives_a_single_occurrence() {
+        let mut warnings = Vec::new();
+        assert!(parse_rrule("FREQ=HOURLY", start(), &mut warnings).is_none());
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn rrule_parts_changing_the_dates_are_not_dropped() {
+        assert_eq!(rrule("FREQ=WEEKLY;BYDAY=MO,FR").by_day.len(), 2);
+        assert_eq!(rrule("FREQ=WEEKLY;WKST=SU;BYDAY=MO").by_day.len(), 1);
+        for value in [
+            "FREQ=MONTHLY;BYMONTHDAY=15",
+            "FREQ=MONTHLY;BYDAY=MO;BYSETPOS=1",
+            "FREQ=MONTHLY;BYDAY=2TU",
+            "FREQ=WEEKLY;BYDAY=1MO",
+            "FREQ=YEARLY;BYMONTH=3",
+            "FREQ=WEEKLY;INTERVAL=2;WKST=SU;BYDAY=MO,TH",
+        ] {
+            let mut warnings = Vec::new();
+            assert!(
+                parse_rrule(value, start(), &mut warnings).is_none(),
+                "{}",
+                value
+            );
+            assert!(matches!(warnings[..], [RruleWarning::UnsupportedPart(_)]));
+        }
+    }
+
+    #[test]
+    fn rrules_on_the_end_of_months_read_back() {
+        let at = |month, day| {
+            NaiveDate::from_ymd_opt(2027, month, day)
+                .unwrap()
+                .and_hms_opt(10, 0, 0)
+                .unwrap()
+        };
+        for (frequency, start) in [
+            (RepeatFrequency::Monthly, at(1, 31)),
+            (RepeatFrequency::Monthly, at(1, 30)),
+            (RepeatFrequency::Yearly, at(2, 28)),
+            (RepeatFrequency::Yearly, at(2, 28) + Duration::days(365)),
+        ] {
+            let recurrence = Recurrence {
+                frequency,
+                interval: 1,
+                count: None,
+                until: None,
+                by_day: Vec::new(),
+                month_end: true,
+                excluded: Vec::new(),
+            };
+            let value = format_rrule(&recurrence, start, false);
+            let value = value.trim_end().strip_prefix("RRULE:").unwrap();
+            let read = parse_rrule(value, start, &mut Vec::new()).unwrap();
+            assert_eq!(
+                read.month_end,
+                recurrence.has_short_months(start),
+                "{}",
+                value
+            );
+        }
+        assert_eq!(
+            format_rrule(
+                &Recurrence {
+                    frequency: RepeatFrequency::Monthly,
+                    interval: 1,
+                    count: None,
+                    until: None,
+                    by_day: Vec::new(),
+                    month_end: true,
+                    excluded: Vec::new(),
+                },
+                at(1, 31),
+                false
+            ),
+            "RRULE:FREQ=MONTHLY;INTERVAL=1;BYMONTHDAY=28,29,30,31;BYSETPOS=-1\r\n"
+        );
+
+        // The rules of other clients giving the date of the start only
+        assert!(
+            parse_rrule("FREQ=MONTHLY;BYMONTHDAY=-1", at(1, 31), &mut Vec::new())
+                .unwrap()
+                .month_end
+        );
+        assert!(
+            !parse_rrule("FREQ=MONTHLY;BYMONTHDAY=15", at(1, 15), &mut Vec::new())
+                .unwrap()
+                .month_end
+        );
+        assert!(parse_rrule("FREQ=MONTHLY;BYMONTHDAY=-1", at(1, 30), &mut Vec::new()).is_none());
+        assert!(parse_rrule("FREQ=YEARLY;BYMONTHDAY=15", at(1, 15), &mut Vec::new()).is_none());
+        assert!(
+            !parse_rrule(
+                "FREQ=YEARLY;BYMONTH=1;BYMONTHDAY=15",
+                at(1, 15),
+                &mut Vec::new()
+            )
+            .unwrap()
+            .month_end
+        );
+    }
+
+    #[test]
+    fn invalid_rrule_parts_do_not_fail_the_event() {
+        for value in [
+            "FREQ=DAILY;INTERVAL=x",
+            "FREQ=DAILY;COUNT=-1",
+            "FREQ=DAILY;UNTIL=soon",
+        ] {
+            let mut warnings = Vec::new();
+            assert!(
+                parse_rrule(value, start(), &mut warnings).is_none(),
+                "{}",
+                value
+            );
+            assert!(matches!(warnings[..], [RruleWarning::Invalid(_)]));
+        }
+        let content = "BEGIN:VEVENT\r\nUID:a\r\nDTSTART:20261016T100000\r\n\
+                       RRULE:FREQ=DAILY;COUNT=many\r\nEND:VEVENT\r\n";
+        let (event, warnings) = parse_event(content).unwrap();
+        assert!(event.recurrence.is_none());
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn partstat_is_set_on_the_attendee_only() {
+        let content = "BEGIN:VEVENT\r\n\
+                       ATTENDEE;CN=Me;PARTSTAT=NEEDS-ACTION;RSVP=TRUE:mailto:Me@example.c\r\n om\r\n\
+                       ATTENDEE;PARTSTAT=NEEDS-ACTION:mailto:you@example.com\r\n\
+                       END:VEVENT\r\n";
+        assert_eq!(
+            set_partstat(content, "me@example.com", "ACCEPTED").unwrap(),
+            "BEGIN:VEVENT\r\n\
+             ATTENDEE;CN=Me;PARTSTAT=ACCEPTED:mailto:Me@example.com\r\n\
+             ATTENDEE;PARTSTAT=NEEDS-ACTION:mailto:you@example.com\r\n\
+             END:VEVENT\r\n"
+        );
+        assert_eq!(set_partstat(content, "them@example.com", "ACCEPTED"), None);
+    }
+
+    #[test]
+    fn repairs_edit_the_event_only() {
+        let content = "BEGIN:VCALENDAR\r\n\
+                       BEGIN:VEVENT\r\n\
+                       DTSTART;VALUE=DATE:20261020\r\n\
+                       DTEND;VALUE=DATE:20261019\r\n\
+                       RRULE:FREQ=WEEKLY;INTERVAL=0\r\n\
+                       BEGIN:VALARM\r\n\
+                       TRIGGER:-PT10M\r\n\
+                       END:VALARM\r\n\
+                       END:VEVENT\r\n\
+                       END:VCALENDAR\r\n";
+        let repaired = repair_interval(&end_at_start(&add_uid(content, "abc")).unwrap());
+        assert_eq!(
+            repaired,
+            "BEGIN:VCALENDAR\r\n\
+             BEGIN:VEVENT\r\n\
+             UID:abc\r\n\
+             DTSTART;VALUE=DATE:20261020\r\n\
+             DTEND;VALUE=DATE:20261021\r\n\
+             RRULE:FREQ=WEEKLY;INTERVAL=1\r\n\
+             BEGIN:VALARM\r\n\
+             TRIGGER:-PT10M\r\n\
+             END:VALARM\r\n\
+             END:VEVENT\r\n\
+             END:VCALENDAR\r\n"
+        );
+    }
+
+    #[test]
+    fn sequence_is_bumped_or_added() {
+        let content = "BEGIN:VEVENT\r\n\
+                       SEQUENCE:3\r\n\
+                       BEGIN:VALARM\r\n\
+                       END:VALARM\r\n\
+                       END:VEVENT\r\n\
+                       BEGIN:VEVENT\r\n\
+                       END:VEVENT\r\n";
+        assert_eq!(
+            bump_sequence(content),
+            "BEGIN:VEVENT\r\n\
+             SEQUENCE:4\r\n\
+             BEGIN:VALARM\r\n\
+             END:VALARM\r\n\
+             END:VEVENT\r\n\
+             BEGIN:VEVENT\r\n\
+             SEQUENCE:1\r\n\
+             END:VEVENT\r\n"
+        );
+    }
+
+    #[test]
+    fn exceptions_named_like_the_series_are_renamed() {
+        let content = "BEGIN:VCALENDAR\r\n\
+                       BEGIN:VEVENT\r\n\
+                       UID:sync\r\n\
+                       SUMMARY:Sync\r\n\
+                       END:VEVENT\r\n\
+                       BEGIN:VEVENT\r\n\
+                       UID:sync\r\n\
+                       RECURRENCE-ID:20261026T090000\r\n\
+                       SUMMARY:Sync\r\n\
+                       END:VEVENT\r\n\
+                       BEGIN:VEVENT\r\n\
+                       UID:sync\r\n\
+                       RECURRENCE-ID:20261102T090000\r\n\
+                       SUMMARY:Sync (offsite)\r\n\
+                       END:VEVENT\r\n\
+                       END:VCALENDAR\r\n";
+        let renamed = rename_exceptions(content, "sync", "Sync", "Weekly sync").unwrap();
+        // The series itself is left to write_event
+        let summaries: Vec<_> = renamed
+            .lines()
+            .filter(|l| l.starts_with("SUMMARY"))
+            .collect();
+        assert_eq!(
+            summaries,
+            [
+                "SUMMARY:Sync",
+                "SUMMARY:Weekly sync",
+                "SUMMARY:Sync (offsite)"
+            ]
+        );
+        assert_eq!(rename_exceptions(content, "sync", "Weekly", "Daily"), None);
+
+        let mut event = read_event(content, Path::new("sync.ics")).unwrap();
+        event.name = "Weekly sync".to_string();
+        let kept = exceptions(content, &event);
+        assert!(kept.contains("SUMMARY:Weekly sync\r\n"));
+        assert!(kept.contains("SUMMARY:Sync (offsite)\r\n"));
+        assert_eq!(kept.matches("BEGIN:VEVENT").count(), 2);
+    }
+
+    #[test]
+    fn events_out_of_the_window_are_skipped() {
+        let day = |day| {
+            NaiveDate::from_ymd_opt(2026, 10, day)
+                .unwrap()
+                .and_time(chrono::NaiveTime::MIN)
+        };
+        let event = |properties: &str| {
+            format!(
+                "BEGIN:VCALENDAR\r\n\
+                 BEGIN:VTIMEZONE\r\n\
+                 BEGIN:STANDARD\r\n\
+                 DTSTART:20261012T030000\r\n\
+                 END:STANDARD\r\n\
+                 END:VTIMEZONE\r\n\
+                 BEGIN:VEVENT\r\n\
+                 {}\
+                 END:VEVENT\r\n\
+                 END:VCALENDAR\r\n",
+                properties
+            )
+        };
+        let (from, to) = (day(10), day(20));
+        let may_occur = |properties| {
+            let (event, _) = parse_event(&event(properties)).unwrap();
+            may_occur_between(&event, from, to)
+        };
+
+        assert!(may_occur(
+            "DTSTART:20261012T100000\r\nDTEND:20261012T110000\r\n"
+        ));
+        assert!(!may_occur(
+            "DTSTART:20261002T100000\r\nDTEND:20261002T110000\r\n"
+        ));
+        assert!(!may_occur(
+            "DTSTART:20261022T100000\r\nDTEND:20261022T110000\r\n"
+        ));
+        // Events started before may still be going on, until their end
+        assert!(may_occur(
+            "DTSTART;VALUE=DATE:20261005\r\nDTEND;VALUE=DATE:20261012\r\n"
+        ));
+        assert!(!may_occur(
+            "DTSTART;VALUE=DATE:20261009\r\nDTEND;VALUE=DATE:20261010\r\n"
+        ));
+        // Series started before may still occur, until their UNTIL
+        assert!(may_occur(
+            "DTSTART:20261002T100000\r\nDTEND:20261002T110000\r\nRRULE:FREQ=WEEKLY\r\n"
+        ));
+        assert!(!may_occur(
+            "DTSTART:20261022T100000\r\nDTEND:20261022T110000\r\nRRULE:FREQ=WEEKLY\r\n"
+        ));
+        assert!(!may_occur(
+            "DTSTART:20261002T100000\r\nDTEND:20261002T110000\r\n\
+             RRULE:FREQ=DAILY;UNTIL=20261008T100000\r\n"
+        ));
+        assert!(may_occur(
+            "DTSTART;VALUE=DATE:20261001\r\nDTEND;VALUE=DATE:20261004\r\n\
+             RRULE:FREQ=DAILY;UNTIL=20261008\r\n"
+        ));
+    }
+
+    const SERIES: &str = "BEGIN:VCALENDAR\r\n\
+                         BEGIN:VTIMEZONE\r\n\
+                         TZID:Europe/Paris\r\n\
+                         END:VTIMEZONE\r\n\
+                         BEGIN:VEVENT\r\n\
+                         UID:series\r\n\
+                         DTSTART;TZID=Europe/Paris:20261016T100000\r\n\
+                         RRULE:FREQ=DAILY;COUNT=3\r\n\
+                         EXDATE;TZID=Europe/Paris:20261017T100000\r\n\
+                         END:VEVENT\r\n\
+                         BEGIN:VEVENT\r\n\
+                         UID:series\r\n\
+                         RECURRENCE-ID;TZID=Europe/Paris:20261018T100000\r\n\
+                         DTSTART;TZID=Europe/Paris:20261018T123000\r\n\
+                         END:VEVENT\r\n\
+                         BEGIN:VEVENT\r\n\
+                         UID:other\r\n\
+                         DTSTART:20261016T100000\r\n\
+                         END:VEVENT\r\n\
+                         END:VCALENDAR\r\n";
+
+    #[test]
+    fn occurrences_removed_or_replaced_are_left_out() {
+        let events: Vec<Event> = components(SERIES)
+            .into_iter()
+            .filter(|(kind, _)| kind == "VEVENT")
+            .map(|(_, component)| parse_event(&component).unwrap().0)
+            .filter(|event| event.id == "series")
+            .collect();
+        let (series, exception) = (&events[0], &events[1]);
+        assert_eq!(series.recurrence.as_ref().unwrap().excluded.len(), 1);
+
+        // The 17th is removed, the 18th replaced by the exception at 12:30
+        let starts: Vec<_> = calendar::expand(
+            events.clone(),
+            series.start,
+            series.start + Duration::days(7),
+        )
+        .iter()
+        .map(|event| event.start)
+        .collect();
+        assert_eq!(starts, [series.start, exception.start]);
+
+        // and EXDATE is written back with the series
+        let written = format_event(series, false);
+        assert_eq!(
+            parse_event(&written)
+                .unwrap()
+                .0
+                .recurrence
+                .unwrap()
+                .excluded,
+            series.recurrence.as_ref().unwrap().excluded
+        );
+    }
+
+    #[test]
+    fn export_keeps_the_components_of_the_files() {
+        let dir = std::env::temp_dir().join(format!("calendar-rs-export-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("series.ics");
+        let content = SERIES;
+        fs::write(&file, content).unwrap();
+
+        let start = NaiveDate::from_ymd_opt(2026, 10, 16)
+            .unwrap()
+            .and_hms_opt(10, 0, 0)
+            .unwrap();
+        let event = |id: &str, file: Option<PathBuf>| {
+            let mut event = Event::builder("Series", start, start)
+                .id(id)
+                .build()
+                .unwrap();
+            event.file = file;
+            event
+        };
+        // The series is read with its exception, given once
+        let exported = export_events(&[
+            event("series", Some(file.clone())),
+            event("series", Some(file.clone())),
+            event("new", None),
+        ]);
+        assert_eq!(exported.matches("BEGIN:VTIMEZONE").count(), 1);
+        assert_eq!(exported.matches("UID:series").count(), 2);
+        assert!(exported.contains("RRULE:FREQ=DAILY;COUNT=3\r\n"));
+        assert!(exported.contains("EXDATE;TZID=Europe/Paris:20261017T100000\r\n"));
+        assert!(!exported.contains("UID:other"));
+        assert!(exported.contains("UID:new"));
+        assert!(!exported.contains("VALARM"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn every_vevent_of_a_file_is_read() {
+        let content = "BEGIN:VCALENDAR\r\n\
+                       BEGIN:VEVENT\r\n\
+                       UID:series\r\n\
+                       DTSTART:20261016T100000\r\n\
+                       RRULE:FREQ=DAILY\r\n\
+                       BEGIN:VALARM\r\n\
+                       TRIGGER:-PT10M\r\n\
+                       END:VALARM\r\n\
+                       END:VEVENT\r\n\
+                       BEGIN:VEVENT\r\n\
+                       UID:series\r\n\
+                       RECURRENCE-ID:20261017T100000\r\n\
+                       DTSTART:20261017T120000\r\n\
+                       END:VEVENT\r\n\
+                       END:VCALENDAR\r\n";
+        // By the index and the cache alike
+        let Ok(Parsed::Events(events, _)) = parse_file(content) else {
+            panic!("the events of the file are not read");
+        };
+        assert_eq!(events.len(), 2);
+        assert!(events[0].recurrence.is_some() && events[0].recurrence_id.is_none());
+        assert_eq!(
+            events[1].recurrence_id.map(|occurrence| occurrence.date()),
+            NaiveDate::from_ymd_opt(2026, 10, 17)
+        );
+        assert_eq!(events[1].id, "series");
+    }
+
+    #[test]
+    fn failed_batches_put_the_files_back() {
+        let root = std::env::temp_dir().join(format!("calendar-rs-batch-{}", std::process::id()));
+        let dir = root.join("calendar").join("collection");
+        fs::create_dir_all(&dir).unwrap();
+        let (a, b, c) = (dir.join("a.ics"), dir.join("b.ics"), dir.join("c.ics"));
+        fs::write(&a, "old a").unwrap();
+        fs::write(&c, "old c").unwrap();
+        // The backup of c cannot take the place of this directory
+        let blocked = sibling(&c, "bak").unwrap();
+        fs::create_dir_all(blocked.join("file")).unwrap();
+
+        let mut batch = Batch::default();
+        batch.replace_file(&a, "new a".to_string());
+        batch.replace_file(&b, "new b".to_string());
+        batch.removals.push(c.clone());
+        assert!(batch.commit().is_err());
+
+        assert_eq!(fs::read_to_string(&a).unwrap(), "old a");
+        assert!(!b.exists());
+        assert_eq!(fs::read_to_string(&c).unwrap(), "old c");
+        fs::remove_dir_all(&blocked).unwrap();
+        let mut left: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        left.sort();
+        assert_eq!(left, ["a.ics", "c.ics"]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn uids_with_the_same_name_get_files_of_their_own() {
+        let dir = std::env::temp_dir().join(format!("calendar-rs-names-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let write = |uid: &str| {
+            let path = event_file(&dir, uid).unwrap();
+            fs::write(
+                &path,
+                format!("BEGIN:VEVENT\r\nUID:{}\r\nEND:VEVENT\r\n", uid),
+            )
+            .unwrap();
+            path
+        };
+
+        let first = write("a/b");
+        assert_eq!(first, dir.join("a_b.ics"));
+        let second = write("a:b");
+        assert_ne!(second, first);
+        assert!(second.starts_with(&dir));
+        // The files keep their names when written again
+        assert_eq!(event_file(&dir, "a/b").unwrap(), first);
+        assert_eq!(event_file(&dir, "a:b").unwrap(), second);
+        assert_eq!(file_uid(&first).as_deref(), Some("a/b"));
+        fs::remove_dir_all(&dir).unwrap();
     }
 }
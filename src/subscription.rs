@@ -0,0 +1,80 @@
+use crate::calendar::Event;
+use crate::paths;
+use crate::storage;
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::io::Read;
+use std::path::PathBuf;
+
+// Feeds larger than this are refused rather than filling the cache
+const MAX_FEED_SIZE: u64 = 64 * 1024 * 1024;
+
+// Downloads the ICS feed of a subscription to the cache, returns the number
+// of events in it
+pub fn download(name: &str, url: &str) -> Result<usize> {
+    // webcal:// is a hint for calendar apps, the feed is served over HTTPS
+    let url = match url.strip_prefix("webcal://") {
+        Some(rest) => format!("https://{}", rest),
+        None => url.to_string(),
+    };
+
+    let response = ureq::get(&url)
+        .call()
+        .with_context(|| format!("Failed to download '{}'", url))?;
+    let mut content = String::new();
+    response
+        .into_reader()
+        .take(MAX_FEED_SIZE)
+        .read_to_string(&mut content)
+        .with_context(|| format!("Failed to read '{}'", url))?;
+
+    if !content.trim_start().starts_with("BEGIN:VCALENDAR") {
+        return Err(anyhow!("'{}' is not an ICS calendar", url));
+    }
+    let count = storage::split_events(&content)
+        .iter()
+        .filter(|(_, data)| storage::component_kind(data) == Some("VEVENT"))
+        .count();
+
+    let path = cache_file(name)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, content)?;
+
+    Ok(count)
+}
+
+// The events of a subscription as of its last download, none before the
+// first one
+pub fn events(name: &str) -> Result<Vec<Event>> {
+    let path = cache_file(name)?;
+    if !path.is_file() {
+        return Ok(Vec::new());
+    }
+
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read subscription '{}'", path.display()))?;
+
+    let mut events = Vec::new();
+    for (_, data) in storage::split_events(&content) {
+        if storage::component_kind(&data) != Some("VEVENT") {
+            continue;
+        }
+        match storage::read_event(&data, &path) {
+            Ok(mut event) => {
+                event.calendar = name.to_string();
+                events.push(event);
+            }
+            Err(err) => storage::skip_unreadable(&path, err.context("Failed to read an event"))?,
+        }
+    }
+    Ok(events)
+}
+
+fn cache_file(name: &str) -> Result<PathBuf> {
+    if name.is_empty() || name.starts_with('.') || name.contains(std::path::is_separator) {
+        return Err(anyhow!("Invalid subscription name '{}'", name));
+    }
+    Ok(paths::subscriptions_dir()?.join(format!("{}.ics", name)))
+}
@@ -0,0 +1,230 @@
+use crate::cli;
+use crate::config;
+use crate::event;
+#[cfg(feature = "graph")]
+use crate::graph;
+use crate::locale::LocalFormat;
+use crate::paths;
+use crate::purge;
+use crate::retry;
+use crate::storage;
+use crate::subscription;
+use anyhow::{anyhow, Context, Result};
+use chrono::{Local, NaiveDateTime};
+use std::collections::BTreeMap;
+use std::fs;
+use std::process::Command;
+
+pub fn run(cmd: cli::CalendarSyncArgs) -> Result<()> {
+    if cmd.status {
+        return status();
+    }
+
+    if cmd.backend == cli::SyncBackend::Graph {
+        return sync_graph(cmd.dry_run);
+    }
+
+    let subscriptions = config::load()?.subscriptions;
+
+    // Subscriptions are not known to vdirsyncer, they are downloaded here
+    let subscription = cmd
+        .calendar
+        .as_ref()
+        .and_then(|calendar| subscriptions.get_key_value(calendar));
+    if let Some((calendar, url)) = subscription {
+        if cmd.dry_run {
+            println!("Would download '{}' from {}", calendar, url);
+            return Ok(());
+        }
+        let count = download(calendar, url)?;
+        println!("Downloaded {} events of '{}'", count, calendar);
+        record(&[calendar])?;
+        return Ok(());
+    }
+
+    // A failure does not stop the other syncs, they are all reported at the end
+    let mut results = Vec::new();
+
+    // Pairs are named after their calendar, like the ones of `calendar setup`
+    let pair = cmd.pair.as_ref().or(cmd.calendar.as_ref());
+    let mut vdirsyncer_command = Command::new("vdirsyncer");
+    vdirsyncer_command.arg("sync");
+    vdirsyncer_command.arg("--force-delete");
+    if let Some(pair) = pair {
+        vdirsyncer_command.arg(pair);
+    }
+
+    // Old events are purged first, for their deletion to be synced
+    let purged = match pair {
+        Some(pair) if storage::calendar_exists(pair)? => vec![pair.clone()],
+        Some(_) => Vec::new(),
+        None => storage::list_calendars()?,
+    };
+    if let Err(err) = purge::apply_retention(&purged, cmd.dry_run) {
+        eprintln!("Warning: failed to purge the old events: {:#}", err);
+    }
+
+    if cmd.dry_run {
+        println!(
+            "Would run: vdirsyncer sync --force-delete{}",
+            pair.map_or(String::new(), |pair| format!(" {}", pair))
+        );
+    } else {
+        match pair {
+            Some(pair) => println!("Syncing pair '{}' with vdirsyncer", pair),
+            None => println!("Syncing calendars with vdirsyncer"),
+        }
+
+        // The output of vdirsyncer is shown as it goes, syncs can be long.
+        // Its failures may come from the network, it is run again unless it
+        // could not be started.
+        let result = retry::retry(
+            |err| !err.chain().any(|cause| cause.is::<std::io::Error>()),
+            || {
+                let status = vdirsyncer_command
+                    .status()
+                    .context("Failed to run vdirsyncer, is it installed?")?;
+                if !status.success() {
+                    return Err(anyhow!("vdirsyncer sync failed"));
+                }
+                Ok(())
+            },
+        )
+        .and_then(|()| match pair {
+            Some(pair) => record(&[pair]),
+            None => record(&storage::list_calendars()?),
+        });
+        results.push((
+            pair.cloned().unwrap_or_else(|| "vdirsyncer".to_string()),
+            result,
+        ));
+    }
+
+    if pair.is_none() {
+        let mut names: Vec<_> = subscriptions.keys().collect();
+        names.sort();
+        for name in names {
+            if cmd.dry_run {
+                println!("Would download '{}' from {}", name, subscriptions[name]);
+                continue;
+            }
+            // An unreachable feed keeps its last download
+            let result = download(name, &subscriptions[name]).and_then(|count| {
+                println!("Downloaded {} events of '{}'", count, name);
+                record(&[name])
+            });
+            results.push((name.clone(), result));
+        }
+    }
+
+    report(results)
+}
+
+fn download(name: &str, url: &str) -> Result<usize> {
+    retry::retry(retry::is_transient, || subscription::download(name, url))
+}
+
+// With several syncs, prints which ones failed. The command fails when any did.
+fn report(results: Vec<(String, Result<()>)>) -> Result<()> {
+    if results.len() <= 1 {
+        return results.into_iter().try_for_each(|(_, result)| result);
+    }
+
+    let failed = results.iter().filter(|(_, result)| result.is_err()).count();
+    if failed == 0 {
+        return Ok(());
+    }
+
+    let width = results
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0);
+    eprintln!();
+    for (name, result) in &results {
+        match result {
+            Ok(()) => eprintln!("{:<width$}  synced", name, width = width),
+            Err(err) => eprintln!("{:<width$}  failed: {:#}", name, err, width = width),
+        }
+    }
+    Err(anyhow!("{} of {} syncs failed", failed, results.len()))
+}
+
+// Prints the last successful sync of each calendar and subscription
+fn status() -> Result<()> {
+    let synced = load_state()?;
+
+    let mut names = storage::list_calendars()?;
+    names.extend(config::load()?.subscriptions.into_keys());
+    names.extend(synced.keys().cloned());
+    names.sort();
+    names.dedup();
+
+    let now = Local::now().naive_local();
+    let width = names.iter().map(|name| name.len()).max().unwrap_or(0);
+    for name in &names {
+        match synced.get(name) {
+            Some(time) => println!(
+                "{:<width$}  {} ({})",
+                name,
+                time.format_local("%a %d %b %Y %H:%M"),
+                event::relative_day(time.date(), now.date()),
+                width = width
+            ),
+            None => println!("{:<width$}  never synced", name, width = width),
+        }
+    }
+
+    Ok(())
+}
+
+fn load_state() -> Result<BTreeMap<String, NaiveDateTime>> {
+    let path = paths::sync_state_file()?;
+    if !path.is_file() {
+        return Ok(BTreeMap::new());
+    }
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read '{}'", path.display()))?;
+    serde_json::from_str(&content).with_context(|| format!("Failed to parse '{}'", path.display()))
+}
+
+// Remembers that these calendars were synced now
+fn record<S: AsRef<str>>(names: &[S]) -> Result<()> {
+    let mut synced = load_state()?;
+    let now = Local::now().naive_local();
+    for name in names {
+        synced.insert(name.as_ref().to_string(), now);
+    }
+
+    let path = paths::sync_state_file()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, serde_json::to_string_pretty(&synced)?)?;
+    Ok(())
+}
+
+#[cfg(feature = "graph")]
+fn sync_graph(dry_run: bool) -> Result<()> {
+    let config = config::load()?.graph;
+    if dry_run {
+        println!(
+            "Would sync calendar '{}' with Microsoft 365",
+            config.calendar
+        );
+        return Ok(());
+    }
+
+    println!("Syncing calendar '{}' with Microsoft 365", config.calendar);
+    let report = graph::sync(&config)?;
+    println!("Synced '{}': {}", config.calendar, report);
+    record(&[config.calendar])?;
+    Ok(())
+}
+
+#[cfg(not(feature = "graph"))]
+fn sync_graph(_dry_run: bool) -> Result<()> {
+    Err(anyhow!(
+        "The graph backend needs calendar-rs to be built with the graph feature (cargo install --features graph)"
+    ))
+}
@@ -0,0 +1,56 @@
+use crate::calendar::Event;
+use crate::config::TaskwarriorConfig;
+use anyhow::{anyhow, Context, Result};
+use chrono::{Local, NaiveDateTime, TimeZone};
+use serde::Deserialize;
+use std::process::Command;
+
+// Name of the pseudo calendar holding the tasks
+pub const CALENDAR: &str = "taskwarrior";
+
+#[derive(Deserialize)]
+struct Task {
+    uuid: String,
+    description: String,
+    due: Option<String>,
+    project: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+// Runs task export and turns the tasks with a due date into read-only events
+// starting and ending at their due date
+pub fn tasks(config: &TaskwarriorConfig) -> Result<Vec<Event>> {
+    let output = Command::new(&config.command)
+        .args(config.filter.split_whitespace())
+        .arg("due.any:")
+        .arg("export")
+        .output()
+        .with_context(|| format!("Failed to run '{}'", config.command))?;
+
+    if !output.status.success() {
+        return Err(anyhow!("'{} export' failed", config.command));
+    }
+
+    let tasks: Vec<Task> = serde_json::from_slice(&output.stdout)
+        .with_context(|| format!("Failed to parse the output of '{} export'", config.command))?;
+
+    tasks
+        .into_iter()
+        .filter_map(|task| task.due.clone().map(|due| (task, due)))
+        .map(|(task, due)| {
+            // Taskwarrior exports dates in UTC
+            let due = NaiveDateTime::parse_from_str(&due, "%Y%m%dT%H%M%SZ")
+                .with_context(|| format!("Invalid due date '{}'", due))?;
+            let due = Local.from_utc_datetime(&due).naive_local();
+
+            Event::builder(task.description, due, due)
+                .description(task.project)
+                .tags(task.tags)
+                .id(task.uuid)
+                .calendar(CALENDAR)
+                .task(true)
+                .build()
+        })
+        .collect()
+}
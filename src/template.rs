@@ -0,0 +1,216 @@
+use crate::calendar::Event;
+use crate::config::{self, OpenConfig};
+use crate::locale::LocalFormat;
+use crate::open;
+use anyhow::{anyhow, Result};
+use chrono::format::{Item, StrftimeItems};
+use chrono::NaiveDateTime;
+
+// Used by list when no --format is given
+pub const DEFAULT_FORMAT: &str = "{start:%a %d %b} {time} - {name}{location: in %s}";
+
+// Used for the events under a day, by view, agenda, digest and motd
+pub const DAY_FORMAT: &str = "{time} - {name}{location: in %s}";
+
+// Used for the events next to the month grid of view
+pub const SIDEBAR_FORMAT: &str = "{start:%d %b} {time} - {name}{location: in %s}";
+
+// A named format of the config or a template, by default the format of the
+// config named after its use, or else the built-in one
+pub fn load(format: Option<String>, name: &str, fallback: &str) -> Result<Template> {
+    let config = config::load()?;
+    let mut formats = config.formats;
+    let format = match format {
+        Some(format) => formats.remove(&format).unwrap_or(format),
+        None => formats.remove(name).unwrap_or_else(|| fallback.to_string()),
+    };
+    Ok(Template::parse(&format)?.with_maps(config.open.maps))
+}
+
+// Shortens a text to a number of characters, ending it with an ellipsis
+pub fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    text.chars()
+        .take(width.saturating_sub(1))
+        .collect::<String>()
+        + "…"
+}
+
+#[derive(Debug)]
+pub struct Template {
+    parts: Vec<Part>,
+    // Template of the {map} links
+    maps: String,
+    // Whether the lines follow a column of ids
+    id: bool,
+}
+
+#[derive(Debug)]
+enum Part {
+    Literal(String),
+    Field(Field, Option<String>),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Field {
+    Id,
+    Name,
+    Calendar,
+    Start,
+    End,
+    Time,
+    Location,
+    Map,
+    Description,
+}
+
+impl Field {
+    fn parse(name: &str) -> Result<Self> {
+        match name {
+            "id" => Ok(Field::Id),
+            "name" => Ok(Field::Name),
+            "calendar" => Ok(Field::Calendar),
+            "start" => Ok(Field::Start),
+            "end" => Ok(Field::End),
+            "time" => Ok(Field::Time),
+            "location" => Ok(Field::Location),
+            "map" => Ok(Field::Map),
+            "description" => Ok(Field::Description),
+            _ => Err(anyhow!("Unknown field '{}' in format", name)),
+        }
+    }
+}
+
+impl Template {
+    // Fields are written as {field} or {field:spec}. For start and end the spec
+    // is a strftime format, for the other fields it is a pattern where %s is
+    // replaced by the value, omitted entirely when the value is empty.
+    // Literal braces are written {{ and }}.
+    pub fn parse(format: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = format.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut placeholder = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => placeholder.push(c),
+                            None => return Err(anyhow!("Unclosed '{{' in format")),
+                        }
+                    }
+
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+
+                    let (name, spec) = match placeholder.split_once(':') {
+                        Some((name, spec)) => (name, Some(spec.to_string())),
+                        None => (placeholder.as_str(), None),
+                    };
+                    let field = Field::parse(name.trim())?;
+                    if let (Field::Start | Field::End, Some(spec)) = (field, &spec) {
+                        // chrono panics when displaying an invalid format
+                        if StrftimeItems::new(spec).any(|item| item == Item::Error) {
+                            return Err(anyhow!("Invalid date format '{}'", spec));
+                        }
+                    }
+                    parts.push(Part::Field(field, spec));
+                }
+                '}' => return Err(anyhow!("Unmatched '}}' in format")),
+                _ => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+
+        Ok(Template {
+            parts,
+            maps: OpenConfig::default().maps,
+            id: false,
+        })
+    }
+
+    pub fn with_maps(mut self, maps: String) -> Self {
+        self.maps = maps;
+        self
+    }
+
+    // Prefixes the lines of the text output with the id of the event
+    pub fn with_id(mut self) -> Self {
+        self.id = true;
+        self
+    }
+
+    pub fn shows_id(&self) -> bool {
+        self.id
+    }
+
+    // Renders an event on at most width characters
+    pub fn render_width(&self, event: &Event, width: usize) -> String {
+        truncate(&self.render(event), width)
+    }
+
+    pub fn render(&self, event: &Event) -> String {
+        let mut output = String::new();
+
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => output.push_str(literal),
+                Part::Field(Field::Start, spec) => {
+                    output.push_str(&format_datetime(event.start, spec.as_deref()))
+                }
+                Part::Field(Field::End, spec) => {
+                    output.push_str(&format_datetime(event.end, spec.as_deref()))
+                }
+                Part::Field(field, spec) => {
+                    let time_range = event.time_range();
+                    let map = event
+                        .location
+                        .as_deref()
+                        .and_then(|location| open::map_link(location, &self.maps))
+                        .unwrap_or_default();
+                    let value = match field {
+                        Field::Id => event.id.as_str(),
+                        Field::Name => event.name.as_str(),
+                        Field::Calendar => event.calendar.as_str(),
+                        Field::Time => time_range.as_str(),
+                        Field::Location => event.location.as_deref().unwrap_or_default(),
+                        Field::Map => map.as_str(),
+                        Field::Description => event.description.as_deref().unwrap_or_default(),
+                        Field::Start | Field::End => unreachable!(),
+                    };
+
+                    match spec {
+                        Some(pattern) if !value.is_empty() => {
+                            output.push_str(&pattern.replace("%s", value))
+                        }
+                        Some(_) => {}
+                        None => output.push_str(value),
+                    }
+                }
+            }
+        }
+
+        output
+    }
+}
+
+fn format_datetime(datetime: NaiveDateTime, spec: Option<&str>) -> String {
+    datetime.format_local(spec.unwrap_or("%Y-%m-%d %H:%M"))
+}
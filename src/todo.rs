@@ -0,0 +1,112 @@
+use crate::calendar::{self, Todo};
+use crate::cli;
+use crate::display;
+use crate::event;
+use crate::locale::LocalFormat;
+use anyhow::Result;
+use chrono::Local;
+use colored::Colorize;
+
+pub fn add(cmd: cli::CalendarTodoAddArgs) -> Result<()> {
+    event::ensure_calendar(&cmd.calendar, cmd.create)?;
+
+    let mut calendar = calendar::load(&cmd.calendar)?;
+    let todo = Todo::new(cmd.name, cmd.due, cmd.all_day, cmd.desc);
+
+    calendar.add_todo(todo)?;
+
+    Ok(())
+}
+
+pub fn list(cmd: cli::CalendarTodoListArgs) -> Result<()> {
+    let mut todos: Vec<Todo> = match cmd.calendar {
+        Some(calendar_name) => {
+            if calendar_name == "personal" {
+                event::create_personal()?;
+            }
+            calendar::load(&calendar_name)?.todos
+        }
+        None => calendar::load_all()?
+            .into_iter()
+            .flat_map(|calendar| calendar.todos)
+            .collect(),
+    };
+
+    if !cmd.all {
+        todos.retain(|todo| !todo.done);
+    }
+
+    // Pending todos first, by due date, the ones without one last
+    todos.sort_by(|a, b| {
+        (a.done, a.due.is_none(), a.due, &a.name).cmp(&(b.done, b.due.is_none(), b.due, &b.name))
+    });
+
+    if todos.is_empty() {
+        println!("Nothing to do");
+    }
+
+    let id_width = cmd
+        .id
+        .then(|| display::short_id_length(todos.iter().map(|todo| todo.id.as_str())));
+    for todo in &todos {
+        print_todo(todo, id_width);
+    }
+
+    Ok(())
+}
+
+pub fn done(cmd: cli::CalendarTodoDoneArgs) -> Result<()> {
+    let mut calendar = calendar::load(&cmd.calendar)?;
+
+    let todo = calendar.todo_by_id(&cmd.todo_id)?;
+    if todo.done {
+        println!("'{}' is already done", todo.name);
+        return Ok(());
+    }
+    let name = todo.name.clone();
+
+    calendar.complete_todo(todo.id.clone())?;
+    println!("Marked '{}' as done", name);
+
+    Ok(())
+}
+
+// With the width of the id column when the ids are shown
+fn print_todo(todo: &Todo, id_width: Option<usize>) {
+    let id_part = match id_width {
+        Some(width) => display::id_column(&todo.id, width),
+        None => String::new(),
+    };
+    let checkbox = if todo.done { "[x]" } else { "[ ]" };
+
+    let due_part = match todo.due {
+        Some(due) => {
+            let format = if todo.all_day {
+                "%a %d %b"
+            } else {
+                "%a %d %b %H:%M"
+            };
+            let due_text = format!(" - due {}", due.format_local(format));
+            // All-day todos are late once their day is over
+            let now = Local::now().naive_local();
+            let late = if todo.all_day {
+                due.date() < now.date()
+            } else {
+                due < now
+            };
+            if late && !todo.done {
+                due_text.red().to_string()
+            } else {
+                due_text
+            }
+        }
+        None => String::new(),
+    };
+
+    let line = format!("{}{} {}{}", id_part, checkbox, todo.name, due_part);
+    if todo.done {
+        println!("{}", line.dimmed());
+    } else {
+        println!("{}", line);
+    }
+}
@@ -0,0 +1,171 @@
+use crate::calendar::{self, Event};
+use crate::cli;
+use crate::display;
+use crate::error::CalendarError;
+use crate::event;
+use crate::locale::LocalFormat;
+use crate::paths;
+use crate::storage;
+use anyhow::{anyhow, Context, Result};
+use chrono::NaiveDateTime;
+use colored::Colorize;
+use std::fs;
+use std::path::PathBuf;
+
+// An event of the trash, with the name of its file in the calendar
+struct Deleted {
+    event: Event,
+    file: PathBuf,
+    name: String,
+    deleted: NaiveDateTime,
+}
+
+pub fn list(cmd: cli::CalendarTrashListArgs) -> Result<()> {
+    let trash = load(cmd.calendar.as_deref())?;
+    if trash.is_empty() {
+        println!("The trash is empty");
+        return Ok(());
+    }
+
+    let id_width = cmd
+        .id
+        .then(|| display::short_id_length(trash.iter().map(|d| d.event.id.as_str())));
+    for deleted in &trash {
+        let id_part = match id_width {
+            Some(width) => display::id_column(&deleted.event.id, width),
+            None => String::new(),
+        };
+        let when = format!(
+            "(deleted {})",
+            deleted.deleted.format_local("%a %d %b %H:%M")
+        );
+        println!(
+            "{}{}: {} {}",
+            id_part,
+            deleted.event.calendar,
+            event::describe_event(&deleted.event),
+            when.dimmed()
+        );
+    }
+
+    Ok(())
+}
+
+pub fn restore(cmd: cli::CalendarTrashRestoreArgs) -> Result<()> {
+    let trash = load(cmd.calendar.as_deref())?;
+    let events: Vec<Event> = trash.iter().map(|d| d.event.clone()).collect();
+    let chosen = event::select_event(&events, &cmd.event_id)?;
+    // The last deletion of an event deleted several times
+    let deleted = trash
+        .iter()
+        .find(|d| d.event.id == chosen.id && d.event.calendar == chosen.calendar)
+        .ok_or_else(|| {
+            CalendarError::NotFound("Could not find event with this uuid".to_string())
+        })?;
+    let event = &deleted.event;
+
+    event::ensure_calendar(&event.calendar, false)?;
+    let calendar = calendar::load(&event.calendar)?;
+    if calendar.events.iter().any(|e| e.id == event.id) {
+        return Err(anyhow!(
+            "'{}' is already in '{}'",
+            event.name,
+            event.calendar
+        ));
+    }
+
+    let content = fs::read_to_string(&deleted.file)
+        .with_context(|| format!("Failed to read '{}'", deleted.file.display()))?;
+    let mut batch = storage::Batch::default();
+    batch.move_to(&deleted.file, calendar.path.join(&deleted.name), content)?;
+    batch.commit()?;
+    println!("Restored '{}' to '{}'", event.name, event.calendar);
+
+    Ok(())
+}
+
+pub fn empty(cmd: cli::CalendarTrashEmptyArgs) -> Result<()> {
+    let trash = load(cmd.calendar.as_deref())?;
+    if trash.is_empty() {
+        println!("The trash is empty");
+        return Ok(());
+    }
+
+    if !cmd.force {
+        let prompt = format!("Delete the {} events of the trash for good?", trash.len());
+        if !event::confirm(&prompt)? {
+            return Ok(());
+        }
+    }
+
+    for deleted in &trash {
+        fs::remove_file(&deleted.file)
+            .with_context(|| format!("Failed to remove '{}'", deleted.file.display()))?;
+        // The directory of a calendar goes once empty
+        if let Some(dir) = deleted.file.parent() {
+            let _ = fs::remove_dir(dir);
+        }
+    }
+    println!("Deleted {} events for good", trash.len());
+
+    Ok(())
+}
+
+// The events of the trash of a calendar, or of every calendar, the last
+// deleted first
+fn load(calendar: Option<&str>) -> Result<Vec<Deleted>> {
+    let trash_dir = paths::trash_dir()?;
+    let dirs: Vec<PathBuf> = match calendar {
+        Some(name) => vec![trash_dir.join(name)],
+        None => match fs::read_dir(&trash_dir) {
+            Ok(entries) => entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|path| path.is_dir())
+                .collect(),
+            Err(_) => Vec::new(),
+        },
+    };
+
+    let mut trash = Vec::new();
+    for dir in dirs {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let calendar = dir
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        for entry in entries {
+            let file = entry.context("Failed to read directory entry")?.path();
+            let Some(file_name) = file.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            // Files being written, hidden, are skipped with anything else
+            let Some((time, name)) = file_name.split_once('-') else {
+                continue;
+            };
+            let Ok(deleted) = NaiveDateTime::parse_from_str(time, storage::TRASH_TIME_FORMAT)
+            else {
+                continue;
+            };
+            if !name.ends_with(".ics") {
+                continue;
+            }
+
+            let content = fs::read_to_string(&file)?;
+            let mut event = storage::read_event(&content, &file)
+                .with_context(|| format!("Failed to read '{}'", file.display()))?;
+            event.calendar = calendar.clone();
+            event.file = Some(file.clone());
+            trash.push(Deleted {
+                event,
+                name: name.to_string(),
+                file,
+                deleted,
+            });
+        }
+    }
+    trash.sort_by_key(|deleted| std::cmp::Reverse(deleted.deleted));
+    Ok(trash)
+}
@@ -0,0 +1,75 @@
+use crate::paths;
+use crate::storage;
+use anyhow::Result;
+use chrono::{Local, Timelike};
+use console::Term;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+// How often the calendars are checked for changes
+pub const POLL: Duration = Duration::from_secs(1);
+
+// The calendars, their collections and the files of these
+const DEPTH: usize = 3;
+
+pub type Snapshot = Vec<(PathBuf, Option<SystemTime>)>;
+
+// Draws a view again and again, like in a pane of tmux: when the files of the
+// calendars change, by a sync or another command, and at each minute for the
+// time of the day. The loop ends with Ctrl-C.
+pub fn run(mut draw: impl FnMut() -> Result<()>) -> Result<()> {
+    let term = Term::stdout();
+    loop {
+        let state = snapshot()?;
+        let minute = Local::now().minute();
+
+        term.clear_screen()?;
+        // A file caught while being written fails a draw, not the watch
+        if let Err(err) = draw() {
+            eprintln!("Error: {:#}", err);
+        }
+        storage::report_skipped();
+
+        while snapshot()? == state && Local::now().minute() == minute {
+            thread::sleep(POLL);
+        }
+    }
+}
+
+// The modification times of the directories and files of the calendars of
+// every root and of the downloaded subscriptions, which any write, rename or
+// removal changes
+pub fn snapshot() -> Result<Snapshot> {
+    let mut entries = Vec::new();
+    for root in paths::calendar_roots()? {
+        walk(&root, DEPTH, &mut entries);
+    }
+    walk(&paths::subscriptions_dir()?, 1, &mut entries);
+    Ok(entries)
+}
+
+fn walk(dir: &Path, depth: usize, entries: &mut Snapshot) {
+    let modified = fs::metadata(dir)
+        .and_then(|metadata| metadata.modified())
+        .ok();
+    entries.push((dir.to_path_buf(), modified));
+    let Ok(children) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut children: Vec<_> = children.filter_map(Result::ok).collect();
+    children.sort_by_key(|child| child.file_name());
+    for child in children {
+        let path = child.path();
+        if depth > 1 && path.is_dir() {
+            walk(&path, depth - 1, entries);
+        } else {
+            let modified = child
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok();
+            entries.push((path, modified));
+        }
+    }
+}